@@ -162,7 +162,9 @@ fn setup_linker() {
         .expect("Could not find linker.ld");
 
     println!("cargo:rerun-if-changed={}", linker_script.display());
-    
+    // カーネルを上位半分 (KERNEL_BASE) にリンクする
+    println!("cargo:rustc-link-arg=-T{}", linker_script.display());
+
     // Rustcの情報を環境変数に設定
     set_rustc_env();
 }
@@ -0,0 +1,522 @@
+// kernel/src/arch/x86_64/acpi.rs
+//! ACPI テーブル解析
+//!
+//! ブートローダから渡された RSDP を起点に RSDT/XSDT をたどり、
+//! 割り込みルーティングに必要な MADT (APIC テーブル) と
+//! 電源管理に必要な FADT を解析します。
+//!
+//! # テーブル構造
+//!
+//! ```text
+//! RSDP ──> RSDT (32-bit pointers) / XSDT (64-bit pointers)
+//!            ├── "APIC" (MADT): Local APIC / IOAPIC / Interrupt Source Override
+//!            ├── "FACP" (FADT): 電源管理レジスタ ──> DSDT (\_S5 スリープ種別)
+//!            └── ...
+//! ```
+//!
+//! すべての物理アドレスはブートローダの物理メモリマッピング
+//! (`PHYS_MEM_OFFSET`) 経由で参照します。
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Once;
+
+use crate::debug_println;
+
+/// RSDP のシグネチャ
+const RSDP_SIGNATURE: &[u8; 8] = b"RSD PTR ";
+/// MADT のシグネチャ
+const MADT_SIGNATURE: &[u8; 4] = b"APIC";
+/// FADT のシグネチャ
+const FADT_SIGNATURE: &[u8; 4] = b"FACP";
+
+/// FADT Flags: RESET_REG_SUP (リセットレジスタが使用可能)
+const FADT_RESET_REG_SUPPORTED: u32 = 1 << 10;
+
+/// MADT エントリ種別
+mod madt_entry {
+    /// Processor Local APIC
+    pub const LOCAL_APIC: u8 = 0;
+    /// I/O APIC
+    pub const IO_APIC: u8 = 1;
+    /// Interrupt Source Override
+    pub const INTERRUPT_OVERRIDE: u8 = 2;
+    /// Local APIC Address Override
+    pub const LOCAL_APIC_OVERRIDE: u8 = 5;
+}
+
+/// ACPI 解析エラー
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcpiError {
+    /// RSDP が提供されていない
+    NoRsdp,
+    /// シグネチャが一致しない
+    BadSignature,
+    /// チェックサムが一致しない
+    BadChecksum,
+    /// 要求されたテーブルが存在しない
+    TableNotFound,
+}
+
+/// 全 ACPI テーブルに共通する SDT ヘッダ
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+pub struct SdtHeader {
+    /// テーブルシグネチャ
+    pub signature: [u8; 4],
+    /// ヘッダを含むテーブル全体の長さ
+    pub length: u32,
+    /// リビジョン
+    pub revision: u8,
+    /// チェックサム
+    pub checksum: u8,
+    /// OEM ID
+    pub oem_id: [u8; 6],
+    /// OEM テーブル ID
+    pub oem_table_id: [u8; 8],
+    /// OEM リビジョン
+    pub oem_revision: u32,
+    /// 作成者 ID
+    pub creator_id: u32,
+    /// 作成者リビジョン
+    pub creator_revision: u32,
+}
+
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+struct Rsdp {
+    signature: [u8; 8],
+    checksum: u8,
+    oem_id: [u8; 6],
+    revision: u8,
+    rsdt_address: u32,
+    // ACPI 2.0+
+    length: u32,
+    xsdt_address: u64,
+    extended_checksum: u8,
+    _reserved: [u8; 3],
+}
+
+/// MADT に記載された I/O APIC
+#[derive(Debug, Clone, Copy)]
+pub struct IoApicInfo {
+    /// I/O APIC ID
+    pub id: u8,
+    /// レジスタの物理アドレス
+    pub address: u32,
+    /// この I/O APIC が担当する最初の GSI
+    pub gsi_base: u32,
+}
+
+/// ISA IRQ から GSI への再マッピング (Interrupt Source Override)
+#[derive(Debug, Clone, Copy)]
+pub struct InterruptOverride {
+    /// ISA IRQ 番号
+    pub source: u8,
+    /// 対応する Global System Interrupt
+    pub gsi: u32,
+    /// MPS INTI フラグ (極性・トリガモード)
+    pub flags: u16,
+}
+
+impl InterruptOverride {
+    /// アクティブローかどうか
+    #[must_use]
+    pub const fn active_low(&self) -> bool {
+        self.flags & 0b11 == 0b11
+    }
+
+    /// レベルトリガかどうか
+    #[must_use]
+    pub const fn level_triggered(&self) -> bool {
+        (self.flags >> 2) & 0b11 == 0b11
+    }
+}
+
+/// 解析済み MADT
+#[derive(Debug, Clone)]
+pub struct MadtInfo {
+    /// Local APIC の物理アドレス
+    pub local_apic_address: u64,
+    /// 有効な Local APIC の ID 一覧
+    pub local_apic_ids: Vec<u8>,
+    /// I/O APIC 一覧
+    pub io_apics: Vec<IoApicInfo>,
+    /// ISA IRQ オーバーライド一覧
+    pub overrides: Vec<InterruptOverride>,
+}
+
+impl MadtInfo {
+    /// ISA IRQ を GSI に変換（オーバーライドがなければ恒等写像）
+    #[must_use]
+    pub fn isa_override(&self, irq: u8) -> Option<&InterruptOverride> {
+        self.overrides.iter().find(|o| o.source == irq)
+    }
+
+    /// 指定 GSI を担当する I/O APIC を検索
+    #[must_use]
+    pub fn io_apic_for_gsi(&self, gsi: u32) -> Option<&IoApicInfo> {
+        self.io_apics
+            .iter()
+            .filter(|io| io.gsi_base <= gsi)
+            .max_by_key(|io| io.gsi_base)
+    }
+}
+
+/// Generic Address Structure のアドレス空間
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressSpace {
+    /// システムメモリ
+    Memory,
+    /// システム I/O ポート
+    Io,
+    /// PCI コンフィギュレーション空間
+    PciConfig,
+    /// その他（未対応）
+    Other(u8),
+}
+
+/// Generic Address Structure (GAS)
+#[derive(Debug, Clone, Copy)]
+pub struct GenericAddress {
+    /// アドレス空間
+    pub space: AddressSpace,
+    /// レジスタのビット幅
+    pub bit_width: u8,
+    /// アドレス
+    pub address: u64,
+}
+
+impl GenericAddress {
+    fn parse(bytes: &[u8]) -> Self {
+        let space = match bytes[0] {
+            0 => AddressSpace::Memory,
+            1 => AddressSpace::Io,
+            2 => AddressSpace::PciConfig,
+            other => AddressSpace::Other(other),
+        };
+        Self {
+            space,
+            bit_width: bytes[1],
+            address: u64::from_le_bytes(bytes[4..12].try_into().unwrap_or([0; 8])),
+        }
+    }
+}
+
+/// 解析済み FADT（電源管理に必要な部分のみ）
+#[derive(Debug, Clone, Copy)]
+pub struct FadtInfo {
+    /// SMI コマンドポート（ACPI モード切り替え用、0 なら不要）
+    pub smi_command: u32,
+    /// SMI コマンドポートに書き込む ACPI 有効化値
+    pub acpi_enable: u8,
+    /// PM1a コントロールブロックの I/O ポート
+    pub pm1a_control: u16,
+    /// PM1b コントロールブロックの I/O ポート（0 なら存在しない）
+    pub pm1b_control: u16,
+    /// リセットレジスタ（RESET_REG_SUP がセットされている場合のみ）
+    pub reset_register: Option<GenericAddress>,
+    /// リセットレジスタに書き込む値
+    pub reset_value: u8,
+    /// DSDT の \_S5 オブジェクトから得た (SLP_TYPa, SLP_TYPb)
+    pub s5_sleep_type: Option<(u8, u8)>,
+}
+
+/// 物理メモリオフセット（init 時に設定）
+static PHYS_OFFSET: AtomicU64 = AtomicU64::new(0);
+/// RSDT/XSDT の物理アドレスとエントリ幅
+static ROOT_TABLE: Once<(u64, usize)> = Once::new();
+/// 解析済み MADT
+static MADT: Once<MadtInfo> = Once::new();
+/// 解析済み FADT
+static FADT: Once<FadtInfo> = Once::new();
+
+fn phys_to_virt(phys: u64) -> *const u8 {
+    (phys + PHYS_OFFSET.load(Ordering::Relaxed)) as *const u8
+}
+
+/// バイト列のチェックサムを検証（合計が 0 になる）
+fn checksum_ok(ptr: *const u8, len: usize) -> bool {
+    // SAFETY: 呼び出し元がマップ済みの ACPI 領域であることを保証している
+    let bytes = unsafe { core::slice::from_raw_parts(ptr, len) };
+    bytes.iter().fold(0u8, |acc, b| acc.wrapping_add(*b)) == 0
+}
+
+/// ACPI サブシステムを初期化
+///
+/// RSDP を検証してルートテーブルを記録し、MADT と FADT を解析します。
+///
+/// # Errors
+///
+/// RSDP またはルートテーブルが不正な場合はエラーを返します。
+pub fn init(rsdp_phys: Option<u64>, phys_mem_offset: u64) -> Result<(), AcpiError> {
+    PHYS_OFFSET.store(phys_mem_offset, Ordering::Relaxed);
+    let rsdp_phys = rsdp_phys.ok_or(AcpiError::NoRsdp)?;
+
+    // SAFETY: ブートローダが提供した RSDP アドレスは物理メモリマップ内にある
+    let rsdp = unsafe { core::ptr::read_unaligned(phys_to_virt(rsdp_phys) as *const Rsdp) };
+    if &rsdp.signature != RSDP_SIGNATURE {
+        return Err(AcpiError::BadSignature);
+    }
+    if !checksum_ok(phys_to_virt(rsdp_phys), 20) {
+        return Err(AcpiError::BadChecksum);
+    }
+
+    let root = if rsdp.revision >= 2 && rsdp.xsdt_address != 0 {
+        (rsdp.xsdt_address, 8)
+    } else {
+        (u64::from(rsdp.rsdt_address), 4)
+    };
+    let root_header = table_header(root.0);
+    if !checksum_ok(phys_to_virt(root.0), root_header.length as usize) {
+        return Err(AcpiError::BadChecksum);
+    }
+    ROOT_TABLE.call_once(|| root);
+
+    debug_println!(
+        "[ACPI] Revision {}, root table at {:#x} ({})",
+        rsdp.revision,
+        root.0,
+        if root.1 == 8 { "XSDT" } else { "RSDT" }
+    );
+
+    match parse_madt() {
+        Ok(madt) => {
+            debug_println!(
+                "[ACPI] MADT: {} CPU(s), {} IOAPIC(s), {} override(s)",
+                madt.local_apic_ids.len(),
+                madt.io_apics.len(),
+                madt.overrides.len()
+            );
+            MADT.call_once(|| madt);
+        }
+        Err(e) => debug_println!("[ACPI] MADT unavailable: {:?}", e),
+    }
+
+    match parse_fadt() {
+        Ok(fadt) => {
+            debug_println!(
+                "[ACPI] FADT: PM1a_CNT={:#x}, reset={}, S5={:?}",
+                fadt.pm1a_control,
+                fadt.reset_register.is_some(),
+                fadt.s5_sleep_type
+            );
+            FADT.call_once(|| fadt);
+        }
+        Err(e) => debug_println!("[ACPI] FADT unavailable: {:?}", e),
+    }
+
+    Ok(())
+}
+
+fn table_header(phys: u64) -> SdtHeader {
+    // SAFETY: ACPI テーブルは物理メモリマップ内にあり、ヘッダは常に存在する
+    unsafe { core::ptr::read_unaligned(phys_to_virt(phys) as *const SdtHeader) }
+}
+
+/// シグネチャでテーブルを検索し、物理アドレスを返す
+///
+/// # Errors
+///
+/// ACPI が未初期化か、テーブルが見つからない場合はエラーを返します。
+pub fn find_table(signature: &[u8; 4]) -> Result<u64, AcpiError> {
+    let &(root_phys, entry_size) = ROOT_TABLE.get().ok_or(AcpiError::NoRsdp)?;
+    let header = table_header(root_phys);
+    let header_len = core::mem::size_of::<SdtHeader>();
+    let entries = (header.length as usize).saturating_sub(header_len) / entry_size;
+    let base = phys_to_virt(root_phys).wrapping_add(header_len);
+
+    for i in 0..entries {
+        // SAFETY: エントリはルートテーブルの長さの範囲内
+        let table_phys = unsafe {
+            let ptr = base.add(i * entry_size);
+            if entry_size == 8 {
+                core::ptr::read_unaligned(ptr as *const u64)
+            } else {
+                u64::from(core::ptr::read_unaligned(ptr as *const u32))
+            }
+        };
+        let table = table_header(table_phys);
+        if &table.signature == signature {
+            if !checksum_ok(phys_to_virt(table_phys), table.length as usize) {
+                return Err(AcpiError::BadChecksum);
+            }
+            return Ok(table_phys);
+        }
+    }
+
+    Err(AcpiError::TableNotFound)
+}
+
+/// 物理アドレスのテーブル本体をバイト列として取得
+///
+/// # Safety
+///
+/// `phys` は `find_table` が返した有効なテーブルアドレスである必要があります。
+pub unsafe fn table_bytes(phys: u64) -> &'static [u8] {
+    let len = table_header(phys).length as usize;
+    // SAFETY: 呼び出し元が有効なテーブルであることを保証している
+    unsafe { core::slice::from_raw_parts(phys_to_virt(phys), len) }
+}
+
+fn read_u16(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+fn parse_madt() -> Result<MadtInfo, AcpiError> {
+    let phys = find_table(MADT_SIGNATURE)?;
+    // SAFETY: find_table が検証済みのアドレスを返した
+    let bytes = unsafe { table_bytes(phys) };
+
+    let mut info = MadtInfo {
+        local_apic_address: u64::from(read_u32(bytes, 36)),
+        local_apic_ids: Vec::new(),
+        io_apics: Vec::new(),
+        overrides: Vec::new(),
+    };
+
+    // ヘッダ (36) + Local APIC Address (4) + Flags (4)
+    let mut at = 44;
+    while at + 2 <= bytes.len() {
+        let kind = bytes[at];
+        let len = bytes[at + 1] as usize;
+        if len < 2 || at + len > bytes.len() {
+            break;
+        }
+        let entry = &bytes[at..at + len];
+
+        match kind {
+            madt_entry::LOCAL_APIC if len >= 8 => {
+                // bit 0: enabled, bit 1: online capable
+                if read_u32(entry, 4) & 0b11 != 0 {
+                    info.local_apic_ids.push(entry[3]);
+                }
+            }
+            madt_entry::IO_APIC if len >= 12 => info.io_apics.push(IoApicInfo {
+                id: entry[2],
+                address: read_u32(entry, 4),
+                gsi_base: read_u32(entry, 8),
+            }),
+            madt_entry::INTERRUPT_OVERRIDE if len >= 10 => {
+                info.overrides.push(InterruptOverride {
+                    source: entry[3],
+                    gsi: read_u32(entry, 4),
+                    flags: read_u16(entry, 8),
+                });
+            }
+            madt_entry::LOCAL_APIC_OVERRIDE if len >= 12 => {
+                info.local_apic_address =
+                    u64::from_le_bytes(entry[4..12].try_into().unwrap_or([0; 8]));
+            }
+            _ => {}
+        }
+
+        at += len;
+    }
+
+    Ok(info)
+}
+
+fn parse_fadt() -> Result<FadtInfo, AcpiError> {
+    let phys = find_table(FADT_SIGNATURE)?;
+    // SAFETY: find_table が検証済みのアドレスを返した
+    let bytes = unsafe { table_bytes(phys) };
+    // ACPI 1.0 の FADT は 116 バイト。それより後ろのフィールドは長さを確認してから読む
+    let has = |end: usize| bytes.len() >= end;
+
+    let flags = if has(116) { read_u32(bytes, 112) } else { 0 };
+    let reset_register = (has(129) && flags & FADT_RESET_REG_SUPPORTED != 0)
+        .then(|| GenericAddress::parse(&bytes[116..128]));
+    let reset_value = if has(129) { bytes[128] } else { 0 };
+
+    // DSDT: X_DSDT (ACPI 2.0+) を優先し、なければ 32-bit の DSDT を使う
+    let x_dsdt = if has(148) {
+        u64::from_le_bytes(bytes[140..148].try_into().unwrap_or([0; 8]))
+    } else {
+        0
+    };
+    let dsdt = if x_dsdt != 0 { x_dsdt } else { u64::from(read_u32(bytes, 40)) };
+
+    Ok(FadtInfo {
+        smi_command: read_u32(bytes, 48),
+        acpi_enable: bytes[52],
+        pm1a_control: read_u32(bytes, 64) as u16,
+        pm1b_control: read_u32(bytes, 68) as u16,
+        reset_register,
+        reset_value,
+        s5_sleep_type: (dsdt != 0).then(|| find_s5_sleep_type(dsdt)).flatten(),
+    })
+}
+
+/// DSDT の AML から `\_S5` パッケージを探し、SLP_TYPa/SLP_TYPb を取り出す
+///
+/// 完全な AML インタプリタは持たないため、以下の形だけを認識する:
+///
+/// ```text
+/// NameOp(0x08) "_S5_" PackageOp(0x12) PkgLength NumElements
+///     (BytePrefix(0x0A) value | ZeroOp(0x00) | OneOp(0x01)) × 2
+/// ```
+fn find_s5_sleep_type(dsdt_phys: u64) -> Option<(u8, u8)> {
+    let header = table_header(dsdt_phys);
+    if &header.signature != b"DSDT" {
+        return None;
+    }
+    // SAFETY: DSDT は FADT が指す物理メモリ上の ACPI テーブル
+    let aml = unsafe { table_bytes(dsdt_phys) };
+    let pos = aml.windows(4).position(|w| w == b"_S5_")?;
+
+    // 直前が NameOp (オプションでルートプレフィックス '\') であることを確認
+    let name_op = match pos {
+        p if p >= 1 && aml[p - 1] == 0x08 => true,
+        p if p >= 2 && aml[p - 1] == b'\\' && aml[p - 2] == 0x08 => true,
+        _ => false,
+    };
+    if !name_op || aml.get(pos + 4) != Some(&0x12) {
+        return None;
+    }
+
+    // PkgLength: 先頭バイトの上位 2 ビットが後続バイト数
+    let mut at = pos + 5;
+    at += 1 + ((*aml.get(at)? >> 6) & 0b11) as usize;
+    at += 1; // NumElements
+
+    let mut read_value = || -> Option<u8> {
+        let op = *aml.get(at)?;
+        at += 1;
+        match op {
+            0x0A => {
+                let value = *aml.get(at)?;
+                at += 1;
+                Some(value)
+            }
+            0x00 | 0x01 => Some(op),
+            _ => None,
+        }
+    };
+
+    let slp_typ_a = read_value()?;
+    let slp_typ_b = read_value()?;
+    Some((slp_typ_a, slp_typ_b))
+}
+
+/// 解析済み FADT を取得
+#[must_use]
+pub fn fadt() -> Option<&'static FadtInfo> {
+    FADT.get()
+}
+
+/// 解析済み MADT を取得
+#[must_use]
+pub fn madt() -> Option<&'static MadtInfo> {
+    MADT.get()
+}
+
+/// 物理メモリオフセットを取得（他の ACPI 利用者向け）
+#[must_use]
+pub fn phys_mem_offset() -> u64 {
+    PHYS_OFFSET.load(Ordering::Relaxed)
+}
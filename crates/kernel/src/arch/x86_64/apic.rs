@@ -0,0 +1,308 @@
+// kernel/src/arch/x86_64/apic.rs
+//! Local APIC タイマーと割り込みコントローラの切り替え
+//!
+//! 8259 PIC + PIT 構成から Local APIC + I/O APIC 構成へ移行します。
+//!
+//! # 初期化の流れ
+//!
+//! ```text
+//! acpi::init()          MADT を解析
+//!      ↓
+//! apic::init()          PIC をリマップしてから全マスク
+//!      │                LAPIC を有効化（スプリアスベクタ設定）
+//!      │                PIT チャンネル 2 で LAPIC タイマーを較正
+//!      ↓                I/O APIC 経由でキーボード・COM1・COM2 をルーティング
+//! apic::start_periodic()  ティック割り込み開始
+//! ```
+//!
+//! # Tickless idle
+//!
+//! アイドル時は周期割り込みを止め、次の `async::timer` の期限まで
+//! ワンショットでタイマーを設定します。復帰時には眠っていた分の
+//! ティックを `tick_n()` でまとめて反映し、周期モードに戻します。
+
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicU8, Ordering};
+
+use super::interrupts::{COM1_VECTOR, COM2_VECTOR, KEYBOARD_VECTOR, SPURIOUS_VECTOR, TIMER_VECTOR};
+use super::smp::{self, lapic, lapic_read, lapic_write, MAX_CPUS};
+use super::{acpi, ioapic, pic};
+use crate::debug_println;
+use crate::kernel::r#async::timer::{self, TICK_MS};
+
+/// LVT マスクビット
+const LVT_MASKED: u32 = 1 << 16;
+/// LVT タイマー: 周期モード
+const LVT_TIMER_PERIODIC: u32 = 1 << 17;
+/// 分周比 16 (Divide Configuration Register の値)
+const TIMER_DIVIDE_BY_16: u32 = 0x3;
+/// 較正に使う時間 (ms)
+const CALIBRATION_MS: u32 = 10;
+/// tickless idle で一度に眠る最大ティック数（カウンタのオーバーフロー防止）
+const MAX_IDLE_TICKS: u64 = 100;
+
+/// ISA IRQ 番号: キーボード
+const IRQ_KEYBOARD: u8 = 1;
+/// ISA IRQ 番号: COM2
+const IRQ_COM2: u8 = 3;
+/// ISA IRQ 番号: COM1
+const IRQ_COM1: u8 = 4;
+
+/// LAPIC タイマーの動作モード
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum TimerMode {
+    /// 停止中
+    Disabled = 0,
+    /// 周期モード（1 ティックごとに割り込み）
+    Periodic = 1,
+    /// ワンショット（明示的に設定された 1 回だけの割り込み）
+    OneShot = 2,
+    /// tickless idle 中のワンショット
+    TicklessIdle = 3,
+}
+
+impl TimerMode {
+    const fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Self::Periodic,
+            2 => Self::OneShot,
+            3 => Self::TicklessIdle,
+            _ => Self::Disabled,
+        }
+    }
+}
+
+/// 較正済みの LAPIC タイマーカウント / ms（CPU ごと、APIC ID でインデックス）
+static TICKS_PER_MS: [AtomicU32; MAX_CPUS] = [const { AtomicU32::new(0) }; MAX_CPUS];
+/// 現在のタイマーモード（CPU ごと）
+static TIMER_MODE: [AtomicU8; MAX_CPUS] = [const { AtomicU8::new(0) }; MAX_CPUS];
+/// tickless idle で設定したティック数（CPU ごと）
+static IDLE_TICKS: [AtomicU64; MAX_CPUS] = [const { AtomicU64::new(0) }; MAX_CPUS];
+
+/// APIC 割り込みモードが有効か（false なら 8259 PIC を使用中）
+static APIC_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// APIC 初期化エラー
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApicError {
+    /// MADT が解析されていない
+    NoMadt,
+    /// I/O APIC の設定に失敗
+    IoApic(ioapic::IoApicError),
+}
+
+impl From<ioapic::IoApicError> for ApicError {
+    fn from(e: ioapic::IoApicError) -> Self {
+        Self::IoApic(e)
+    }
+}
+
+fn cpu_index() -> usize {
+    smp::get_apic_id() as usize % MAX_CPUS
+}
+
+fn mode() -> TimerMode {
+    TimerMode::from_u8(TIMER_MODE[cpu_index()].load(Ordering::Relaxed))
+}
+
+fn set_mode(mode: TimerMode) {
+    TIMER_MODE[cpu_index()].store(mode as u8, Ordering::Relaxed);
+}
+
+/// APIC 割り込みモードが有効かどうか
+#[inline]
+#[must_use]
+pub fn is_enabled() -> bool {
+    APIC_ENABLED.load(Ordering::Acquire)
+}
+
+/// Local APIC に EOI を送る
+#[inline]
+pub fn eoi() {
+    // SAFETY: APIC モードでは LAPIC がマップ・有効化済み
+    unsafe { lapic_write(lapic::EOI, 0) };
+}
+
+/// 割り込みコントローラを APIC モードに切り替える
+///
+/// `acpi::init()` の後、割り込みを有効化する前に BSP で一度だけ呼び出します。
+/// 成功すると 8259 PIC は全マスクされ、キーボード (IRQ1) と COM1 (IRQ4) は
+/// I/O APIC 経由で BSP に配送されます。タイマーは `start_periodic()` で開始します。
+///
+/// # Errors
+///
+/// MADT または I/O APIC が利用できない場合はエラーを返し、PIC 構成のまま残ります。
+pub fn init(phys_mem_offset: u64) -> Result<(), ApicError> {
+    if acpi::madt().is_none() {
+        return Err(ApicError::NoMadt);
+    }
+
+    // PIC をリマップしてから全マスク（スプリアス割り込みが例外ベクタに来ないように）
+    // SAFETY: ブート時に一度だけ呼ばれる
+    unsafe {
+        let mut pics = pic::PICS.lock();
+        pics.initialize();
+        pics.disable();
+    }
+
+    if smp::state().lapic_base.load(Ordering::Acquire) == 0 {
+        smp::init_bsp_lapic(phys_mem_offset);
+    }
+    init_local();
+
+    ioapic::init()?;
+    let bsp = smp::get_apic_id();
+    ioapic::route_isa_irq(IRQ_KEYBOARD, KEYBOARD_VECTOR, bsp)?;
+    ioapic::route_isa_irq(IRQ_COM1, COM1_VECTOR, bsp)?;
+    ioapic::route_isa_irq(IRQ_COM2, COM2_VECTOR, bsp)?;
+
+    APIC_ENABLED.store(true, Ordering::Release);
+    debug_println!("[APIC] Interrupt routing switched from 8259 PIC to IOAPIC");
+    Ok(())
+}
+
+/// 現在の CPU の Local APIC を有効化し、タイマーを較正
+///
+/// BSP は `init()` から、AP は起動時に呼び出します。
+pub fn init_local() {
+    // SAFETY: LAPIC ベースは smp::init_bsp_lapic で設定済み
+    unsafe {
+        lapic_write(lapic::TPR, 0);
+        lapic_write(lapic::SIVR, 0x100 | u32::from(SPURIOUS_VECTOR));
+        lapic_write(lapic::LVT_TIMER, LVT_MASKED | u32::from(TIMER_VECTOR));
+    }
+    set_mode(TimerMode::Disabled);
+    calibrate();
+}
+
+/// PIT チャンネル 2 を基準に LAPIC タイマーの周波数を測定
+fn calibrate() {
+    use crate::kernel::driver::PIT;
+
+    // SAFETY: LAPIC は有効化済み、タイマーはマスク状態
+    let elapsed = unsafe {
+        lapic_write(lapic::TIMER_DIVIDE, TIMER_DIVIDE_BY_16);
+        lapic_write(lapic::TIMER_INITIAL_COUNT, u32::MAX);
+        PIT.lock().busy_wait_ms(CALIBRATION_MS);
+        let remaining = lapic_read(lapic::TIMER_CURRENT_COUNT);
+        lapic_write(lapic::TIMER_INITIAL_COUNT, 0);
+        u32::MAX - remaining
+    };
+
+    let per_ms = (elapsed / CALIBRATION_MS).max(1);
+    TICKS_PER_MS[cpu_index()].store(per_ms, Ordering::Relaxed);
+    debug_println!(
+        "[APIC] CPU {} timer calibrated: {} counts/ms (div 16)",
+        cpu_index(),
+        per_ms
+    );
+}
+
+fn counts_for_ms(ms: u64) -> u32 {
+    let per_ms = u64::from(TICKS_PER_MS[cpu_index()].load(Ordering::Relaxed));
+    (per_ms * ms).clamp(1, u64::from(u32::MAX)) as u32
+}
+
+/// 周期モードでティック割り込みを開始（1 ティック = `TICK_MS`）
+pub fn start_periodic() {
+    let count = counts_for_ms(TICK_MS);
+    // SAFETY: LAPIC は較正済み
+    unsafe {
+        lapic_write(lapic::TIMER_DIVIDE, TIMER_DIVIDE_BY_16);
+        lapic_write(lapic::LVT_TIMER, LVT_TIMER_PERIODIC | u32::from(TIMER_VECTOR));
+        lapic_write(lapic::TIMER_INITIAL_COUNT, count);
+    }
+    set_mode(TimerMode::Periodic);
+}
+
+/// 指定ミリ秒後に 1 回だけ割り込みを発生させる
+pub fn arm_oneshot(ms: u64) {
+    arm_oneshot_counts(counts_for_ms(ms));
+    set_mode(TimerMode::OneShot);
+}
+
+fn arm_oneshot_counts(count: u32) {
+    // SAFETY: LAPIC は較正済み
+    unsafe {
+        lapic_write(lapic::TIMER_DIVIDE, TIMER_DIVIDE_BY_16);
+        lapic_write(lapic::LVT_TIMER, u32::from(TIMER_VECTOR));
+        lapic_write(lapic::TIMER_INITIAL_COUNT, count);
+    }
+}
+
+/// タイマーを停止
+pub fn stop_timer() {
+    // SAFETY: LAPIC は有効化済み
+    unsafe {
+        lapic_write(lapic::LVT_TIMER, LVT_MASKED | u32::from(TIMER_VECTOR));
+        lapic_write(lapic::TIMER_INITIAL_COUNT, 0);
+    }
+    set_mode(TimerMode::Disabled);
+}
+
+/// タイマー割り込みの処理を行い、経過したティック数を返す
+///
+/// タイマー割り込みハンドラから呼び出します。tickless idle の
+/// ワンショットが満了した場合は眠っていたティック数を返し、周期モードに戻します。
+#[must_use]
+pub fn on_timer_interrupt() -> u64 {
+    match mode() {
+        TimerMode::TicklessIdle => {
+            let ticks = IDLE_TICKS[cpu_index()].swap(0, Ordering::Relaxed);
+            start_periodic();
+            ticks.max(1)
+        }
+        TimerMode::OneShot => {
+            set_mode(TimerMode::Disabled);
+            1
+        }
+        TimerMode::Periodic | TimerMode::Disabled => 1,
+    }
+}
+
+/// 次のタイマー期限まで CPU を停止する（tickless idle）
+///
+/// 周期タイマーが動作していない場合は単純に `hlt` します。
+/// タイマー以外の割り込みで早期に起きた場合は、経過時間を LAPIC の
+/// カウンタから求めてティックを進め、周期モードに戻します。
+pub fn idle_halt(next_deadline: Option<u64>) {
+    use x86_64::instructions::interrupts;
+
+    if !is_enabled() || mode() != TimerMode::Periodic {
+        x86_64::instructions::hlt();
+        return;
+    }
+
+    interrupts::disable();
+    let now = timer::current_ticks();
+    let sleep_ticks = next_deadline
+        .map_or(MAX_IDLE_TICKS, |deadline| deadline.saturating_sub(now))
+        .min(MAX_IDLE_TICKS);
+
+    if sleep_ticks <= 1 {
+        interrupts::enable_and_hlt();
+        return;
+    }
+
+    let count = counts_for_ms(sleep_ticks * TICK_MS);
+    IDLE_TICKS[cpu_index()].store(sleep_ticks, Ordering::Relaxed);
+    arm_oneshot(sleep_ticks * TICK_MS);
+    // 満了時に眠っていた分のティックを反映するよう、ワンショットのモードを上書き
+    set_mode(TimerMode::TicklessIdle);
+
+    interrupts::enable_and_hlt();
+    interrupts::disable();
+
+    // タイマー以外の割り込みで起こされた場合
+    if mode() == TimerMode::TicklessIdle {
+        // SAFETY: LAPIC は有効化済み
+        let remaining = unsafe { lapic_read(lapic::TIMER_CURRENT_COUNT) };
+        let per_tick = u64::from(counts_for_ms(TICK_MS));
+        let elapsed = u64::from(count - remaining.min(count)) / per_tick;
+        IDLE_TICKS[cpu_index()].store(0, Ordering::Relaxed);
+        timer::tick_n(elapsed);
+        start_periodic();
+    }
+
+    interrupts::enable();
+}
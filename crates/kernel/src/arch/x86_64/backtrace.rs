@@ -0,0 +1,196 @@
+// kernel/src/arch/x86_64/backtrace.rs
+//! フレームポインタによるスタックトレース
+//!
+//! ターゲット仕様で `"frame-pointer": "always"` を指定しているため、すべての
+//! 関数は `push rbp; mov rbp, rsp` で始まり、RBP がフレームの連結リストになります。
+//!
+//! ```text
+//! [rbp]      呼び出し元の RBP
+//! [rbp + 8]  戻りアドレス
+//! ```
+//!
+//! x86-interrupt ハンドラも同じプロローグを持つため、ハンドラ内で読んだ
+//! `[rbp]` は割り込まれたコードの RBP です（[`interrupted_frame_pointer`]）。
+//! ユーザーモードからの例外ではユーザースタックをたどれます。
+//!
+//! スタックの読み出しは `paging::translate_current` で変換してから行うため、
+//! 壊れたスタックをたどっても二次フォールトは起きません。
+//!
+//! カーネルアドレスは `ksyms` で関数名に解決し、シリアルとコンソールの
+//! 両方に出力します。
+
+use core::fmt;
+
+use crate::kernel::driver::{write_console_best_effort, write_debug};
+use crate::kernel::ksyms;
+use crate::kernel::mm::paging;
+
+/// たどるフレーム数の上限
+const MAX_FRAMES: usize = 32;
+/// カーネル空間の先頭（上位半分）
+const KERNEL_SPACE_START: u64 = 0xFFFF_8000_0000_0000;
+/// ユーザー空間の終端
+const USER_SPACE_END: u64 = 0x0000_8000_0000_0000;
+
+/// 現在の関数の RBP
+#[inline(always)]
+#[must_use]
+pub fn frame_pointer() -> u64 {
+    let rbp: u64;
+    // SAFETY: RBP を読むだけ
+    unsafe { core::arch::asm!("mov {}, rbp", out(reg) rbp, options(nomem, nostack, preserves_flags)) };
+    rbp
+}
+
+/// 例外ハンドラ内から、割り込まれたコードの RBP を取得
+///
+/// ハンドラ本体に直接インライン展開される必要があります。
+#[inline(always)]
+#[must_use]
+pub fn interrupted_frame_pointer() -> u64 {
+    read_u64(frame_pointer()).unwrap_or(0)
+}
+
+/// マップされていれば 8 バイト読む
+fn read_u64(addr: u64) -> Option<u64> {
+    if addr % 8 != 0 {
+        return None;
+    }
+    let ptr = paging::translate_current(addr)?;
+    // SAFETY: translate_current はマップ済みのアドレスのみ返し、
+    // 8 バイト境界なのでページをまたがない
+    Some(unsafe { ptr.cast::<u64>().read_volatile() })
+}
+
+/// スタック上の戻りアドレスを順に返すイテレータ
+pub struct Frames {
+    rbp: u64,
+    remaining: usize,
+    user: bool,
+}
+
+impl Frames {
+    fn in_range(&self, addr: u64) -> bool {
+        if self.user {
+            addr != 0 && addr < USER_SPACE_END
+        } else {
+            addr >= KERNEL_SPACE_START
+        }
+    }
+}
+
+impl Iterator for Frames {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        if self.remaining == 0 || !self.in_range(self.rbp) {
+            return None;
+        }
+        self.remaining -= 1;
+
+        let return_address = read_u64(self.rbp.checked_add(8)?)?;
+        let next_rbp = read_u64(self.rbp)?;
+        if return_address == 0 {
+            return None;
+        }
+        // スタックは下位に伸びるため、呼び出し元のフレームは必ず上位にある
+        // (ループや壊れたチェーンを打ち切る)
+        self.rbp = if next_rbp > self.rbp { next_rbp } else { 0 };
+        Some(return_address)
+    }
+}
+
+/// カーネルスタックをたどる
+#[must_use]
+pub const fn walk(rbp: u64) -> Frames {
+    Frames { rbp, remaining: MAX_FRAMES, user: false }
+}
+
+/// ユーザースタックをたどる（現在の CR3 のアドレス空間）
+#[must_use]
+pub const fn walk_user(rbp: u64) -> Frames {
+    Frames { rbp, remaining: MAX_FRAMES, user: true }
+}
+
+/// シリアルとコンソールの両方に出力
+fn emit(args: fmt::Arguments) {
+    write_debug(args);
+    write_console_best_effort(args);
+}
+
+/// 1 フレーム分を出力
+///
+/// 戻りアドレスは call 命令の次を指すため、`call` 自体が属する関数を
+/// 引けるよう 1 引いた位置で解決します。
+fn emit_frame(index: usize, addr: u64, is_return_address: bool) {
+    let lookup = if is_return_address { addr.saturating_sub(1) } else { addr };
+    match ksyms::resolve(lookup) {
+        Some(sym) => emit(format_args!(
+            "  #{:<2} {:#018x}  {}+{:#x}\n",
+            index,
+            addr,
+            sym.name(),
+            addr - sym.address
+        )),
+        None => emit(format_args!("  #{:<2} {:#018x}\n", index, addr)),
+    }
+}
+
+/// カーネルのバックトレースを出力
+///
+/// `rip` を指定した場合（例外ハンドラ）は、それを先頭フレームとして出力します。
+pub fn print_kernel(rip: Option<u64>, rbp: u64) {
+    emit(format_args!("Backtrace:\n"));
+    if ksyms::kernel_symbols().is_none() {
+        emit(format_args!("  (no symbol table, run tools/build/mksyms on the kernel image)\n"));
+    }
+    let mut index = 0;
+    if let Some(rip) = rip {
+        emit_frame(index, rip, false);
+        index += 1;
+    }
+    for addr in walk(rbp) {
+        emit_frame(index, addr, true);
+        index += 1;
+    }
+}
+
+/// 呼び出し元からのカーネルバックトレースを出力（パニックハンドラ用）
+#[inline(always)]
+pub fn print_current() {
+    print_kernel(None, frame_pointer());
+}
+
+/// ユーザースタックのバックトレースを出力
+///
+/// ユーザープログラムのシンボルは持っていないため、アドレスのみを出力します。
+pub fn print_user(rip: u64, rbp: u64) {
+    emit(format_args!("User backtrace:\n"));
+    emit(format_args!("  #0  {:#018x}\n", rip));
+    for (index, addr) in walk_user(rbp).enumerate() {
+        emit(format_args!("  #{:<2} {:#018x}\n", index + 1, addr));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[inline(never)]
+    fn nested() -> usize {
+        walk(frame_pointer()).count()
+    }
+
+    #[test_case]
+    fn test_walk_finds_callers() {
+        // test_runner -> このテスト -> nested の少なくとも 2 フレーム
+        assert!(nested() >= 2);
+    }
+
+    #[test_case]
+    fn test_walk_rejects_bad_frame_pointer() {
+        assert_eq!(walk(0).count(), 0);
+        assert_eq!(walk(0x1000).count(), 0);
+        assert_eq!(walk_user(KERNEL_SPACE_START).count(), 0);
+    }
+}
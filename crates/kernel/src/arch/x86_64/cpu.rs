@@ -116,3 +116,26 @@ where
 pub fn read_timestamp() -> u64 {
     unsafe { core::arch::x86_64::_rdtsc() }
 }
+
+/// Read a hardware random number with RDRAND.
+///
+/// Returns `None` if the instruction reports that no random number was
+/// available; callers should retry a few times. Only call this when
+/// [`CpuFeatures::has_rdrand`](super::cpu_features::CpuFeatures::has_rdrand)
+/// is set, otherwise the instruction raises #UD.
+#[must_use]
+pub fn read_random() -> Option<u64> {
+    let value: u64;
+    let ok: u8;
+    // SAFETY: RDRAND only writes its destination register and CF
+    unsafe {
+        core::arch::asm!(
+            "rdrand {value}",
+            "setc {ok}",
+            value = out(reg) value,
+            ok = out(reg_byte) ok,
+            options(nomem, nostack)
+        );
+    }
+    (ok != 0).then_some(value)
+}
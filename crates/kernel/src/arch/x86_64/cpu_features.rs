@@ -23,6 +23,18 @@ pub struct CpuFeatures {
     pub has_xsave: bool,
     /// Time Stamp Counter support
     pub has_tsc: bool,
+    /// RDRAND hardware random number support
+    pub has_rdrand: bool,
+    /// Process-context identifiers (CR4.PCIDE)
+    pub has_pcid: bool,
+    /// INVPCID instruction support
+    pub has_invpcid: bool,
+    /// Supervisor Mode Execution Prevention (CR4.SMEP)
+    pub has_smep: bool,
+    /// Supervisor Mode Access Prevention (CR4.SMAP, STAC/CLAC)
+    pub has_smap: bool,
+    /// User-Mode Instruction Prevention (CR4.UMIP)
+    pub has_umip: bool,
 }
 
 /// Cached CPU features
@@ -54,6 +66,7 @@ pub fn detect() -> CpuFeatures {
     
     // Get feature information once
     let feature_info = cpuid.get_feature_info();
+    let extended_info = cpuid.get_extended_feature_info();
     
     let features = CpuFeatures {
         has_fpu: feature_info.as_ref().map_or(false, |f| f.has_fpu()),
@@ -62,6 +75,12 @@ pub fn detect() -> CpuFeatures {
         has_avx: feature_info.as_ref().map_or(false, |f| f.has_avx()),
         has_xsave: feature_info.as_ref().map_or(false, |f| f.has_xsave()),
         has_tsc: feature_info.as_ref().map_or(false, |f| f.has_tsc()),
+        has_rdrand: feature_info.as_ref().map_or(false, |f| f.has_rdrand()),
+        has_pcid: feature_info.as_ref().map_or(false, |f| f.has_pcid()),
+        has_invpcid: extended_info.as_ref().map_or(false, |f| f.has_invpcid()),
+        has_smep: extended_info.as_ref().map_or(false, |f| f.has_smep()),
+        has_smap: extended_info.as_ref().map_or(false, |f| f.has_smap()),
+        has_umip: extended_info.as_ref().map_or(false, |f| f.has_umip()),
     };
     
     *cache = Some(features);
@@ -0,0 +1,697 @@
+// kernel/src/arch/x86_64/gdbstub.rs
+//! GDB リモートシリアルプロトコル (RSP) スタブ
+//!
+//! COM2 に接続した GDB からカーネルとユーザープロセスをデバッグできます。
+//!
+//! ```text
+//! qemu-system-x86_64 ... -serial stdio -serial tcp::1234,server,nowait
+//! gdb target/.../tiny_os -ex 'target remote :1234'
+//! ```
+//!
+//! # 構成
+//!
+//! ```text
+//! #BP (int3) ──┐
+//! #DB (TF)   ──┴─> trap_entry (全 GPR を TrapFrame に保存) ──> handle_trap
+//!                                                                   │
+//!                     COM2 でパケットを送受信 <─── session() <──────┘
+//! ```
+//!
+//! # 対応パケット
+//!
+//! | パケット          | 内容                                       |
+//! |-------------------|--------------------------------------------|
+//! | `?`               | 停止理由 (`S05`)                           |
+//! | `g` / `G`         | 全レジスタの読み書き                       |
+//! | `p n` / `P n=v`   | 単一レジスタの読み書き                     |
+//! | `m` / `M`         | メモリの読み書き（現在の CR3 で変換）      |
+//! | `Z0` / `z0`       | ソフトウェアブレークポイント (int3 を埋め込む) |
+//! | `c` / `s`         | 続行 / TF によるシングルステップ           |
+//! | `D` / `k`         | デタッチ（ブレークポイントを全て除去）     |
+//!
+//! # スタブに入る契機
+//!
+//! - `int3`（GDB が設定したブレークポイント、またはプログラム中の `int3`）
+//! - シングルステップ完了 (#DB)
+//! - カーネルパニック（パニックハンドラが [`breakpoint`] を呼ぶ）
+//! - COM2 で Ctrl-C (0x03) を受信（GDB の割り込み要求）
+//! - キーボードの F12（マジックキー）
+//!
+//! メモリアクセスは停止時の CR3 で変換するため、ユーザーモードで停止した
+//! 場合はそのプロセスのアドレス空間とカーネル空間の両方が見えます。
+//! 他の CPU は停止しません。
+
+#![allow(unsafe_op_in_unsafe_fn)] // naked_asm! requires this
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+
+use super::interrupts::COM2_VECTOR;
+use super::irq::{self, IrqError, IrqReturn};
+use crate::debug_println;
+use crate::kernel::core::{CharDevice, Device};
+use crate::kernel::driver::serial::{SerialPort, SERIAL2};
+use crate::kernel::mm::paging;
+
+/// デバッグ例外ベクタ (#DB)
+const DEBUG_VECTOR: u64 = 1;
+/// ブレークポイント例外ベクタ (#BP)
+const BREAKPOINT_VECTOR: u64 = 3;
+/// COM2 の ISA IRQ 番号
+const IRQ_COM2: u8 = 3;
+
+/// RFLAGS: トラップフラグ
+const RFLAGS_TF: u64 = 1 << 8;
+/// int3 命令
+const INT3: u8 = 0xCC;
+/// GDB の割り込み要求 (Ctrl-C)
+const GDB_INTERRUPT: u8 = 0x03;
+/// マジックキー (F12) のスキャンコード
+pub const MAGIC_SCANCODE: u8 = 0x58;
+
+/// パケットバッファのサイズ（`qSupported` で通知）
+const PACKET_SIZE: usize = 1024;
+/// ブレークポイントの最大数
+const MAX_BREAKPOINTS: usize = 32;
+/// `g` パケットのレジスタ数 (rax..gs)
+const GDB_REG_COUNT: usize = 24;
+/// 64 ビットレジスタの数 (rax..rip)
+const GDB_REG64_COUNT: usize = 17;
+
+/// スタブが有効か（COM2 が存在し `init` 済み）
+static ENABLED: AtomicBool = AtomicBool::new(false);
+/// GDB が接続済みか（`c`/`s` で再開した後は停止時に `S05` を送る）
+static ATTACHED: AtomicBool = AtomicBool::new(false);
+/// 設定中のブレークポイント (アドレス, 元のバイト)
+static BREAKPOINTS: Mutex<[Option<(u64, u8)>; MAX_BREAKPOINTS]> = Mutex::new([None; MAX_BREAKPOINTS]);
+
+/// 例外発生時の全レジスタ
+///
+/// `trap_entry!` のスタックレイアウトと一致させています。
+#[derive(Debug)]
+#[repr(C)]
+struct TrapFrame {
+    rax: u64,
+    rbx: u64,
+    rcx: u64,
+    rdx: u64,
+    rsi: u64,
+    rdi: u64,
+    rbp: u64,
+    r8: u64,
+    r9: u64,
+    r10: u64,
+    r11: u64,
+    r12: u64,
+    r13: u64,
+    r14: u64,
+    r15: u64,
+    vector: u64,
+    error_code: u64,
+    // CPU が積む割り込みフレーム
+    rip: u64,
+    cs: u64,
+    rflags: u64,
+    rsp: u64,
+    ss: u64,
+}
+
+impl TrapFrame {
+    /// GDB のレジスタ番号 (amd64) で読み出す
+    ///
+    /// セグメントレジスタ ds/es/fs/gs は保存していないため 0 を返します。
+    fn gdb_reg(&self, index: usize) -> Option<u64> {
+        Some(match index {
+            0 => self.rax,
+            1 => self.rbx,
+            2 => self.rcx,
+            3 => self.rdx,
+            4 => self.rsi,
+            5 => self.rdi,
+            6 => self.rbp,
+            7 => self.rsp,
+            8 => self.r8,
+            9 => self.r9,
+            10 => self.r10,
+            11 => self.r11,
+            12 => self.r12,
+            13 => self.r13,
+            14 => self.r14,
+            15 => self.r15,
+            16 => self.rip,
+            17 => self.rflags,
+            18 => self.cs,
+            19 => self.ss,
+            20..=23 => 0,
+            _ => return None,
+        })
+    }
+
+    /// GDB のレジスタ番号 (amd64) で書き込む
+    ///
+    /// cs/ss とセグメントレジスタへの書き込みは無視します（iretq で #GP になるため）。
+    fn set_gdb_reg(&mut self, index: usize, value: u64) -> bool {
+        let slot = match index {
+            0 => &mut self.rax,
+            1 => &mut self.rbx,
+            2 => &mut self.rcx,
+            3 => &mut self.rdx,
+            4 => &mut self.rsi,
+            5 => &mut self.rdi,
+            6 => &mut self.rbp,
+            7 => &mut self.rsp,
+            8 => &mut self.r8,
+            9 => &mut self.r9,
+            10 => &mut self.r10,
+            11 => &mut self.r11,
+            12 => &mut self.r12,
+            13 => &mut self.r13,
+            14 => &mut self.r14,
+            15 => &mut self.r15,
+            16 => &mut self.rip,
+            17 => &mut self.rflags,
+            18..=23 => return true,
+            _ => return false,
+        };
+        *slot = value;
+        true
+    }
+}
+
+/// 全 GPR を保存して `handle_trap` を呼ぶ例外エントリを定義
+macro_rules! trap_entry {
+    ($name:ident, $vector:expr) => {
+        #[unsafe(naked)]
+        pub(crate) unsafe extern "C" fn $name() {
+            core::arch::naked_asm!(
+                "push 0",           // エラーコード（#BP/#DB にはない）
+                "push {vector}",
+                "push r15", "push r14", "push r13", "push r12",
+                "push r11", "push r10", "push r9", "push r8",
+                "push rbp", "push rdi", "push rsi", "push rdx",
+                "push rcx", "push rbx", "push rax",
+                // IDT 経由では AC が下りないので、SMAP があれば下ろす
+                // (割り込まれた側の RFLAGS は iretq で戻る)
+                "cmp byte ptr [rip + {smap}], 0",
+                "je 2f",
+                "clac",
+                "2:",
+                "mov rdi, rsp",     // &mut TrapFrame
+                "mov rbx, rsp",     // rbx は callee-saved
+                "and rsp, -16",
+                "call {handler}",
+                "mov rsp, rbx",
+                "pop rax", "pop rbx", "pop rcx", "pop rdx",
+                "pop rsi", "pop rdi", "pop rbp", "pop r8",
+                "pop r9", "pop r10", "pop r11", "pop r12",
+                "pop r13", "pop r14", "pop r15",
+                "add rsp, 16",      // ベクタとエラーコード
+                "iretq",
+                vector = const $vector,
+                handler = sym handle_trap,
+                smap = sym super::protection::SMAP_ENABLED,
+            );
+        }
+    };
+}
+
+trap_entry!(breakpoint_entry, BREAKPOINT_VECTOR);
+trap_entry!(debug_entry, DEBUG_VECTOR);
+
+/// スタブが有効か
+#[must_use]
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// GDB スタブを初期化
+///
+/// COM2 が存在しなければ何もせず `false` を返します。その場合 `int3` は
+/// 従来通りログを出して続行します。
+pub fn init() -> bool {
+    {
+        let mut serial = SERIAL2.lock();
+        if !serial.is_present() {
+            return false;
+        }
+        if serial.init().is_err() {
+            return false;
+        }
+        serial.enable_rx_interrupt();
+    }
+    if let Err(e) = register_irq_handler() {
+        debug_println!("[GDB] Failed to register COM2 handler: {:?}", e);
+    }
+    irq::controller().set_isa_irq_masked(IRQ_COM2, false);
+
+    ENABLED.store(true, Ordering::Release);
+    debug_println!("[GDB] Remote stub listening on COM2");
+    true
+}
+
+/// スタブが有効ならブレークポイント例外を発生させて GDB に制御を渡す
+#[inline(always)]
+pub fn breakpoint() {
+    if is_enabled() {
+        // SAFETY: int3 は breakpoint_entry に入り、再開後ここに戻る
+        unsafe { core::arch::asm!("int3", options(nomem, nostack)) };
+    }
+}
+
+/// COM2 割り込みハンドラ: Ctrl-C を受けたらスタブに入る
+fn com2_irq(_context: usize) -> IrqReturn {
+    let interrupt = match SERIAL2.try_lock() {
+        Some(serial) => {
+            let mut interrupt = false;
+            while let Ok(Some(byte)) = serial.read_byte() {
+                interrupt |= byte == GDB_INTERRUPT;
+            }
+            interrupt
+        }
+        None => return IrqReturn::NotMine,
+    };
+    if interrupt {
+        breakpoint();
+    }
+    IrqReturn::Handled
+}
+
+fn register_irq_handler() -> Result<(), IrqError> {
+    irq::register_irq(COM2_VECTOR, com2_irq, 0)
+}
+
+/// #BP / #DB の共通ハンドラ
+extern "C" fn handle_trap(frame: &mut TrapFrame) {
+    if frame.vector == DEBUG_VECTOR {
+        frame.rflags &= !RFLAGS_TF;
+    }
+    if !is_enabled() {
+        if frame.vector == BREAKPOINT_VECTOR {
+            debug_println!("[EXCEPTION] BREAKPOINT at {:#x}", frame.rip);
+        }
+        return;
+    }
+    session(frame);
+}
+
+// ============================================================================
+// パケット入出力
+// ============================================================================
+
+/// 16 進数字 1 文字の値
+const fn hex_value(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+/// 16 進文字列を数値に変換
+fn parse_hex(s: &[u8]) -> Option<u64> {
+    if s.is_empty() || s.len() > 16 {
+        return None;
+    }
+    s.iter().try_fold(0u64, |acc, &c| Some((acc << 4) | u64::from(hex_value(c)?)))
+}
+
+/// 16 進 2 文字を 1 バイトに変換
+fn parse_hex_byte(s: &[u8]) -> Option<u8> {
+    Some((hex_value(*s.first()?)? << 4) | hex_value(*s.get(1)?)?)
+}
+
+/// パケットのチェックサム（バイトの総和 mod 256）
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+/// `addr,len` を解析
+fn parse_addr_len(s: &[u8]) -> Option<(u64, usize)> {
+    let comma = s.iter().position(|&c| c == b',')?;
+    Some((parse_hex(&s[..comma])?, parse_hex(&s[comma + 1..])? as usize))
+}
+
+/// 応答パケットの組み立てバッファ
+struct Reply {
+    buf: [u8; PACKET_SIZE],
+    len: usize,
+}
+
+impl Reply {
+    const fn new() -> Self {
+        Self { buf: [0; PACKET_SIZE], len: 0 }
+    }
+
+    fn push(&mut self, data: &[u8]) {
+        let n = data.len().min(PACKET_SIZE - self.len);
+        self.buf[self.len..self.len + n].copy_from_slice(&data[..n]);
+        self.len += n;
+    }
+
+    fn push_hex_byte(&mut self, byte: u8) {
+        self.push(&[HEX_DIGITS[usize::from(byte >> 4)], HEX_DIGITS[usize::from(byte & 0xF)]]);
+    }
+
+    /// リトルエンディアンで `size` バイト分の 16 進を追加
+    fn push_le(&mut self, value: u64, size: usize) {
+        for byte in &value.to_le_bytes()[..size] {
+            self.push_hex_byte(*byte);
+        }
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+/// COM2 上の RSP 接続
+struct Connection<'a> {
+    serial: &'a mut SerialPort,
+}
+
+impl Connection<'_> {
+    fn read_byte(&mut self) -> u8 {
+        loop {
+            if let Ok(Some(byte)) = self.serial.read_byte() {
+                return byte;
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    fn write_bytes(&mut self, data: &[u8]) {
+        for &byte in data {
+            let _ = self.serial.write_byte(byte);
+        }
+    }
+
+    /// `$<data>#<checksum>` を受信し、データ部の長さを返す
+    fn recv_packet(&mut self, buf: &mut [u8; PACKET_SIZE]) -> usize {
+        loop {
+            while self.read_byte() != b'$' {}
+
+            let mut len = 0;
+            loop {
+                let byte = self.read_byte();
+                if byte == b'#' {
+                    break;
+                }
+                if len < PACKET_SIZE {
+                    buf[len] = byte;
+                    len += 1;
+                }
+            }
+            let sum = [self.read_byte(), self.read_byte()];
+            if parse_hex_byte(&sum) == Some(checksum(&buf[..len])) {
+                self.write_bytes(b"+");
+                return len;
+            }
+            self.write_bytes(b"-");
+        }
+    }
+
+    /// パケットを送信し、`+` を受け取るまで再送する
+    fn send_packet(&mut self, data: &[u8]) {
+        let sum = checksum(data);
+        loop {
+            self.write_bytes(b"$");
+            self.write_bytes(data);
+            self.write_bytes(&[b'#', HEX_DIGITS[usize::from(sum >> 4)], HEX_DIGITS[usize::from(sum & 0xF)]]);
+            match self.read_byte() {
+                b'+' => return,
+                b'-' => {}
+                // 応答待ちの間に届いた Ctrl-C などは無視
+                _ => return,
+            }
+        }
+    }
+}
+
+// ============================================================================
+// メモリとブレークポイント
+// ============================================================================
+
+fn read_memory(addr: u64) -> Option<u8> {
+    // SAFETY: translate_current はマップ済みのアドレスのみ返す
+    paging::translate_current(addr).map(|ptr| unsafe { ptr.read_volatile() })
+}
+
+/// 物理マッピング経由で書き込む（読み取り専用ページにも書ける）
+fn write_memory(addr: u64, value: u8) -> bool {
+    match paging::translate_current(addr) {
+        Some(ptr) => {
+            // SAFETY: translate_current はマップ済みのアドレスのみ返す
+            unsafe { ptr.write_volatile(value) };
+            true
+        }
+        None => false,
+    }
+}
+
+fn insert_breakpoint(addr: u64) -> bool {
+    let mut breakpoints = BREAKPOINTS.lock();
+    if breakpoints.iter().flatten().any(|&(a, _)| a == addr) {
+        return true;
+    }
+    let Some(slot) = breakpoints.iter_mut().find(|slot| slot.is_none()) else {
+        return false;
+    };
+    let Some(original) = read_memory(addr) else {
+        return false;
+    };
+    if !write_memory(addr, INT3) {
+        return false;
+    }
+    *slot = Some((addr, original));
+    true
+}
+
+fn remove_breakpoint(addr: u64) -> bool {
+    let mut breakpoints = BREAKPOINTS.lock();
+    let Some(slot) = breakpoints.iter_mut().find(|slot| matches!(slot, Some((a, _)) if *a == addr)) else {
+        return false;
+    };
+    if let Some((addr, original)) = slot.take() {
+        write_memory(addr, original);
+    }
+    true
+}
+
+fn remove_all_breakpoints() {
+    let mut breakpoints = BREAKPOINTS.lock();
+    for (addr, original) in breakpoints.iter_mut().filter_map(Option::take) {
+        write_memory(addr, original);
+    }
+}
+
+// ============================================================================
+// コマンド処理
+// ============================================================================
+
+/// 1 パケット分の処理結果
+enum Action {
+    /// 応答を返して次のパケットを待つ
+    Reply,
+    /// 応答を返して実行を再開する
+    Resume,
+}
+
+/// GDB と対話し、`c`/`s`/`D` を受けたら戻る
+fn session(frame: &mut TrapFrame) {
+    let mut serial = SERIAL2.lock();
+    let mut conn = Connection { serial: &mut serial };
+    let mut packet = [0u8; PACKET_SIZE];
+
+    if ATTACHED.load(Ordering::Relaxed) {
+        conn.send_packet(b"S05");
+    }
+
+    loop {
+        let len = conn.recv_packet(&mut packet);
+        let mut reply = Reply::new();
+        let action = handle_packet(frame, &packet[..len], &mut reply);
+        match action {
+            Action::Reply => conn.send_packet(reply.as_bytes()),
+            Action::Resume => {
+                if reply.len > 0 {
+                    conn.send_packet(reply.as_bytes());
+                }
+                return;
+            }
+        }
+    }
+}
+
+fn handle_packet(frame: &mut TrapFrame, packet: &[u8], reply: &mut Reply) -> Action {
+    let Some((&command, args)) = packet.split_first() else {
+        return Action::Reply;
+    };
+    match command {
+        b'?' => reply.push(b"S05"),
+        b'g' => {
+            for index in 0..GDB_REG_COUNT {
+                let size = if index < GDB_REG64_COUNT { 8 } else { 4 };
+                reply.push_le(frame.gdb_reg(index).unwrap_or(0), size);
+            }
+        }
+        b'G' => {
+            let mut offset = 0;
+            for index in 0..GDB_REG_COUNT {
+                let size = if index < GDB_REG64_COUNT { 8 } else { 4 };
+                let Some(hex) = args.get(offset..offset + size * 2) else { break };
+                let value = hex
+                    .chunks(2)
+                    .rev()
+                    .try_fold(0u64, |acc, pair| Some((acc << 8) | u64::from(parse_hex_byte(pair)?)));
+                if let Some(value) = value {
+                    frame.set_gdb_reg(index, value);
+                }
+                offset += size * 2;
+            }
+            reply.push(b"OK");
+        }
+        b'p' => match parse_hex(args).and_then(|index| frame.gdb_reg(index as usize).map(|v| (index, v))) {
+            Some((index, value)) => {
+                let size = if (index as usize) < GDB_REG64_COUNT { 8 } else { 4 };
+                reply.push_le(value, size);
+            }
+            None => reply.push(b"E01"),
+        },
+        b'P' => {
+            let parsed = args.iter().position(|&c| c == b'=').and_then(|eq| {
+                let index = parse_hex(&args[..eq])? as usize;
+                let value = args[eq + 1..]
+                    .chunks(2)
+                    .rev()
+                    .try_fold(0u64, |acc, pair| Some((acc << 8) | u64::from(parse_hex_byte(pair)?)))?;
+                Some((index, value))
+            });
+            match parsed {
+                Some((index, value)) if frame.set_gdb_reg(index, value) => reply.push(b"OK"),
+                _ => reply.push(b"E01"),
+            }
+        }
+        b'm' => match parse_addr_len(args) {
+            Some((addr, len)) => {
+                let len = len.min(PACKET_SIZE / 2);
+                for i in 0..len as u64 {
+                    match read_memory(addr.wrapping_add(i)) {
+                        Some(byte) => reply.push_hex_byte(byte),
+                        None if i == 0 => {
+                            reply.push(b"E14");
+                            break;
+                        }
+                        // 途中までの読み取りを返す
+                        None => break,
+                    }
+                }
+            }
+            None => reply.push(b"E01"),
+        },
+        b'M' => {
+            let parsed = args.iter().position(|&c| c == b':').and_then(|colon| {
+                let (addr, len) = parse_addr_len(&args[..colon])?;
+                Some((addr, len, &args[colon + 1..]))
+            });
+            match parsed {
+                Some((addr, len, data)) if data.len() >= len * 2 => {
+                    let ok = data
+                        .chunks(2)
+                        .take(len)
+                        .enumerate()
+                        .all(|(i, pair)| parse_hex_byte(pair).is_some_and(|b| write_memory(addr.wrapping_add(i as u64), b)));
+                    reply.push(if ok { b"OK" } else { b"E14" });
+                }
+                _ => reply.push(b"E01"),
+            }
+        }
+        b'Z' | b'z' => {
+            // Z0,addr,kind: ソフトウェアブレークポイントのみ対応
+            let mut fields = args.split(|&c| c == b',');
+            let kind = fields.next();
+            let addr = fields.next().and_then(parse_hex);
+            match (kind, addr) {
+                (Some(b"0"), Some(addr)) => {
+                    let ok = if command == b'Z' { insert_breakpoint(addr) } else { remove_breakpoint(addr) };
+                    reply.push(if ok { b"OK" } else { b"E0E" });
+                }
+                // ハードウェアブレークポイント・ウォッチポイントは未対応
+                _ => {}
+            }
+        }
+        b'c' | b's' => {
+            if let Some(addr) = parse_hex(args) {
+                frame.rip = addr;
+            }
+            if command == b's' {
+                frame.rflags |= RFLAGS_TF;
+            } else {
+                frame.rflags &= !RFLAGS_TF;
+            }
+            ATTACHED.store(true, Ordering::Relaxed);
+            return Action::Resume;
+        }
+        b'D' | b'k' => {
+            remove_all_breakpoints();
+            frame.rflags &= !RFLAGS_TF;
+            ATTACHED.store(false, Ordering::Relaxed);
+            if command == b'D' {
+                reply.push(b"OK");
+            }
+            return Action::Resume;
+        }
+        b'q' => {
+            if args.starts_with(b"Supported") {
+                reply.push(b"PacketSize=400");
+            } else if args == b"Attached" {
+                reply.push(b"1");
+            }
+        }
+        // 未対応のパケットには空の応答を返す
+        _ => {}
+    }
+    Action::Reply
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_parse_hex() {
+        assert_eq!(parse_hex(b"ffff800000001000"), Some(0xffff_8000_0000_1000));
+        assert_eq!(parse_hex(b"1A"), Some(0x1a));
+        assert_eq!(parse_hex(b""), None);
+        assert_eq!(parse_hex(b"xyz"), None);
+        assert_eq!(parse_addr_len(b"401000,40"), Some((0x40_1000, 0x40)));
+    }
+
+    #[test_case]
+    fn test_checksum() {
+        // "$OK#9a"
+        assert_eq!(checksum(b"OK"), 0x9a);
+        assert_eq!(parse_hex_byte(b"9a"), Some(0x9a));
+    }
+
+    #[test_case]
+    fn test_register_packet_roundtrip() {
+        let mut frame = TrapFrame {
+            rax: 0x1122_3344_5566_7788, rbx: 0, rcx: 0, rdx: 0, rsi: 0, rdi: 0, rbp: 0,
+            r8: 0, r9: 0, r10: 0, r11: 0, r12: 0, r13: 0, r14: 0, r15: 0,
+            vector: BREAKPOINT_VECTOR, error_code: 0,
+            rip: 0x40_1000, cs: 0x08, rflags: 0x202, rsp: 0x7000, ss: 0x10,
+        };
+        let mut reply = Reply::new();
+        handle_packet(&mut frame, b"g", &mut reply);
+        assert_eq!(reply.len, (GDB_REG64_COUNT * 8 + (GDB_REG_COUNT - GDB_REG64_COUNT) * 4) * 2);
+        assert!(reply.as_bytes().starts_with(b"8877665544332211"));
+
+        let mut reply = Reply::new();
+        handle_packet(&mut frame, b"P10=0020400000000000", &mut reply);
+        assert_eq!(reply.as_bytes(), b"OK");
+        assert_eq!(frame.rip, 0x40_2000);
+    }
+}
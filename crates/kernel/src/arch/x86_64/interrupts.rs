@@ -5,23 +5,56 @@
 
 use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
 use x86_64::VirtAddr;
-use crate::arch::x86_64::gdt;
+use crate::arch::x86_64::{backtrace, gdbstub, gdt, irq, protection};
 use crate::arch::Cpu;
 use spin::Lazy;
 
+/// タイマー割り込みベクタ (PIC: IRQ0 / APIC: LAPIC タイマー)
+pub const TIMER_VECTOR: u8 = 32;
+/// キーボード割り込みベクタ (IRQ1)
+pub const KEYBOARD_VECTOR: u8 = 33;
+/// COM2 割り込みベクタ (IRQ3)
+pub const COM2_VECTOR: u8 = 35;
+/// COM1 割り込みベクタ (IRQ4)
+pub const COM1_VECTOR: u8 = 36;
+/// LAPIC スプリアス割り込みベクタ
+pub const SPURIOUS_VECTOR: u8 = 0xFF;
+
+/// 各ベクタに `irq_stub::<V>` を設定する
+macro_rules! set_irq_stubs {
+    ($idt:ident; $($vector:literal),* $(,)?) => {
+        $( $idt[$vector].set_handler_fn(irq_stub::<$vector>); )*
+    };
+}
+
 static IDT: Lazy<InterruptDescriptorTable> = Lazy::new(|| {
     let mut idt = InterruptDescriptorTable::new();
-    idt.breakpoint.set_handler_fn(breakpoint_handler);
+    // #BP/#DB は全レジスタを保存する GDB スタブのエントリを使う
+    // (int3 はユーザーモードからも使えるよう DPL=3)
+    unsafe {
+        idt.breakpoint.set_handler_addr(VirtAddr::new(gdbstub::breakpoint_entry as usize as u64))
+            .set_privilege_level(x86_64::PrivilegeLevel::Ring3);
+        idt.debug.set_handler_addr(VirtAddr::new(gdbstub::debug_entry as usize as u64));
+    }
     unsafe {
         idt.double_fault.set_handler_fn(double_fault_handler)
             .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
     }
     idt.general_protection_fault.set_handler_fn(general_protection_fault_handler);
     idt.page_fault.set_handler_fn(page_fault_handler);
-    // Timer Interrupt (IRQ0 -> 32)
-    idt[32].set_handler_fn(timer_interrupt_handler);
-    // Keyboard Interrupt (IRQ1 -> 33)
-    idt[33].set_handler_fn(keyboard_interrupt_handler);
+    // Timer Interrupt (IRQ0 / LAPIC timer -> 32)
+    idt[TIMER_VECTOR].set_handler_fn(timer_interrupt_handler);
+    // Legacy IRQs (33..=47) and dynamically allocated vectors (48..=111)
+    // are dispatched to handlers registered through `irq::register_irq`
+    set_irq_stubs!(idt;
+        33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 44, 45, 46, 47,
+        48, 49, 50, 51, 52, 53, 54, 55, 56, 57, 58, 59, 60, 61, 62, 63,
+        64, 65, 66, 67, 68, 69, 70, 71, 72, 73, 74, 75, 76, 77, 78, 79,
+        80, 81, 82, 83, 84, 85, 86, 87, 88, 89, 90, 91, 92, 93, 94, 95,
+        96, 97, 98, 99, 100, 101, 102, 103, 104, 105, 106, 107, 108, 109, 110, 111
+    );
+    // LAPIC spurious interrupt (EOI 不要)
+    idt[SPURIOUS_VECTOR].set_handler_fn(spurious_interrupt_handler);
     idt
 });
 
@@ -30,32 +63,37 @@ pub fn init_idt() {
     IDT.load();
 }
 
-extern "x86-interrupt" fn breakpoint_handler(_stack_frame: InterruptStackFrame) {
-    use crate::arch::x86_64::port::PortWriteOnly;
-    unsafe {
-        let mut serial = PortWriteOnly::<u8>::new(0x3F8);
-        for byte in b"[EXCEPTION] BREAKPOINT\n" {
-            serial.write(*byte);
-        }
-    }
-}
-
 extern "x86-interrupt" fn general_protection_fault_handler(
-    _stack_frame: InterruptStackFrame, _error_code: u64)
+    stack_frame: InterruptStackFrame, error_code: u64)
 {
     use crate::arch::x86_64::port::PortWriteOnly;
     use crate::arch::{Cpu, ArchCpu};
+    use crate::kernel::process::{coredump, lifecycle};
+    protection::clear_ac();
     
     ArchCpu::disable_interrupts();
+    let rbp = backtrace::interrupted_frame_pointer();
     
-    // Minimal output - just indicate GPF occurred
-    // DO NOT access stack_frame because it may cause another GPF
+    // A GPF raised in user mode kills only the faulting process
+    if stack_frame.code_segment.rpl() == x86_64::PrivilegeLevel::Ring3 {
+        crate::debug_println!(
+            "[GPF] User fault at {:#x}, error: {:#x}, terminating process",
+            stack_frame.instruction_pointer.as_u64(),
+            error_code
+        );
+        backtrace::print_user(stack_frame.instruction_pointer.as_u64(), rbp);
+        coredump::dump_current(&coredump::FaultInfo::from_frame(coredump::SIGSEGV, &stack_frame));
+        lifecycle::kill_current(128 + coredump::SIGSEGV);
+    }
+    
+    // Minimal output first, in case the report below faults again
     unsafe {
         let mut serial = PortWriteOnly::<u8>::new(0x3F8);
         for byte in b"[GPF!]\n" {
             serial.write(*byte);
         }
     }
+    backtrace::print_kernel(Some(stack_frame.instruction_pointer.as_u64()), rbp);
     
     loop {
         ArchCpu::halt();
@@ -63,42 +101,74 @@ extern "x86-interrupt" fn general_protection_fault_handler(
 }
 
 extern "x86-interrupt" fn double_fault_handler(
-    _stack_frame: InterruptStackFrame, _error_code: u64) -> !
+    stack_frame: InterruptStackFrame, _error_code: u64) -> !
 {
     use crate::arch::{Cpu, ArchCpu};
     use crate::arch::x86_64::port::PortWriteOnly;
+    protection::clear_ac();
     
     ArchCpu::disable_interrupts();
+    let rbp = backtrace::interrupted_frame_pointer();
     
-    // Minimal output - just indicate DF occurred
+    // Minimal output first, in case the report below faults again
     unsafe {
         let mut serial = PortWriteOnly::<u8>::new(0x3F8);
         for byte in b"[DF!]\n" {
             serial.write(*byte);
         }
     }
+    // A fault on a kernel stack guard page escalates to a double fault
+    // because the exception frame cannot be pushed onto the full stack
+    report_kernel_stack_overflow();
+    // Runs on the IST stack, so a broken kernel stack can still be walked
+    backtrace::print_kernel(Some(stack_frame.instruction_pointer.as_u64()), rbp);
     
     loop {
         ArchCpu::halt();
     }
 }
 
+/// CR2 がカーネルスタックのガードページを指していれば、あふれたプロセスを報告
+fn report_kernel_stack_overflow() {
+    use crate::kernel::driver::{write_console_best_effort, write_debug};
+    use x86_64::registers::control::Cr2;
+
+    let Ok(addr) = Cr2::read() else {
+        return;
+    };
+    if let Some(pid) = crate::kernel::mm::kstack::guard_owner(addr) {
+        let addr = addr.as_u64();
+        write_debug(format_args!("kernel stack overflow in pid {} (guard page {:#x})\n", pid, addr));
+        write_console_best_effort(format_args!("kernel stack overflow in pid {} (guard page {:#x})\n", pid, addr));
+    }
+}
+
 extern "x86-interrupt" fn page_fault_handler(
-    stack_frame: InterruptStackFrame,
+    mut stack_frame: InterruptStackFrame,
     error_code: PageFaultErrorCode,
 ) {
     use x86_64::registers::control::Cr2;
-    use crate::kernel::mm::page_fault::{is_user_space_address, handle_user_page_fault};
+    use crate::kernel::mm::page_fault::{is_user_space_address, handle_user_page_fault, PageFaultError};
+    use crate::kernel::mm::oom;
     use crate::kernel::process::PROCESS_TABLE;
     use crate::kernel::mm::allocator::BOOT_INFO_ALLOCATOR;
     use crate::kernel::mm::PHYS_MEM_OFFSET;
     use x86_64::structures::paging::OffsetPageTable;
+    protection::clear_ac();
     
     // [DEBUG] Immediate serial output removed to avoid spam
     // unsafe { ... }
     
     // Get the faulting address from CR2
     let fault_addr = Cr2::read().unwrap_or(VirtAddr::new(0));
+    let rbp = backtrace::interrupted_frame_pointer();
+
+    // Charge the interrupted user time before handling the fault
+    let from_user = error_code.contains(PageFaultErrorCode::USER_MODE);
+    if from_user {
+        crate::kernel::process::accounting::enter_kernel();
+        crate::kernel::process::accounting::count_page_fault();
+    }
     
     // Check if this is a user-space page fault
     if is_user_space_address(fault_addr) {
@@ -108,16 +178,22 @@ extern "x86-interrupt" fn page_fault_handler(
             error_code
         );
         
-        // Try to handle the user-space page fault
-        let handled = (|| -> Result<(), ()> {
+        // Try to handle the user-space page fault. The error tells whether
+        // the fault failed for lack of memory
+        let handled = (|| -> Result<(), bool> {
             crate::debug_println!("[PageFault] Handling user fault at {:#x}", fault_addr.as_u64());
-            // Get current process
-            let mut table = PROCESS_TABLE.lock();
-            let process = table.current_process_mut().ok_or(())?;
+            // Get current process. A kernel access may fault while the
+            // table is already held; fail instead of deadlocking
+            let table = if from_user {
+                PROCESS_TABLE.lock()
+            } else {
+                PROCESS_TABLE.try_lock().ok_or(false)?
+            };
+            let process = table.current_process().ok_or(false)?;
             
             // Get frame allocator and physical memory offset
             let mut allocator_lock = BOOT_INFO_ALLOCATOR.lock();
-            let frame_allocator = allocator_lock.as_mut().ok_or(())?;
+            let frame_allocator = allocator_lock.as_mut().ok_or(false)?;
             let phys_mem_offset = VirtAddr::new(PHYS_MEM_OFFSET.load(core::sync::atomic::Ordering::Relaxed));
             
             // Create mapper for the process's page table
@@ -126,24 +202,74 @@ extern "x86-interrupt" fn page_fault_handler(
             let mut mapper = unsafe { OffsetPageTable::new(l4_table, phys_mem_offset) };
             
             // Handle the page fault
-            handle_user_page_fault(fault_addr, error_code, &mut mapper, frame_allocator)
+            handle_user_page_fault(fault_addr, error_code, process.vmas(), &mut mapper, frame_allocator)
                 .map_err(|e| {
+                    if matches!(e, PageFaultError::StackOverflow) {
+                        crate::println!("user stack overflow in pid {}", process.pid().as_u64());
+                    }
                     crate::debug_println!("[PageFault] Failed to handle: {:?}", e);
-                    ()
-                })
+                    matches!(e, PageFaultError::OutOfMemory)
+                })?;
+
+            // Start reclaiming before the next fault runs dry
+            oom::balance(frame_allocator);
+            Ok(())
         })();
         
-        if handled.is_ok() {
-            crate::debug_println!("[PageFault] User-space page fault handled successfully");
-            return; // Successfully handled, return to user space
+        match handled {
+            Ok(()) => {
+                crate::debug_println!("[PageFault] User-space page fault handled successfully");
+                if from_user {
+                    crate::kernel::process::accounting::return_to_user();
+                }
+                return; // Successfully handled, return to user space
+            }
+            // Both locks are released here, so the OOM killer may take them.
+            // On retry the faulting instruction runs again
+            Err(true) => match oom::out_of_memory("page fault") {
+                oom::OomAction::Retry => {
+                    if from_user {
+                        crate::kernel::process::accounting::return_to_user();
+                    }
+                    return;
+                }
+                oom::OomAction::KillCurrent => {
+                    crate::kernel::process::lifecycle::kill_current(oom::OOM_EXIT_CODE)
+                }
+            },
+            Err(false) => {}
         }
         
         crate::debug_println!("[PageFault] Failed to handle user-space page fault, terminating process");
-        // Fall through to panic
     }
     
-    // Kernel page fault or unhandled user fault - panic
+    // A user-copy routine touched memory that demand paging could not
+    // provide: resume at its fixup, which reports how much was not copied
+    if !from_user {
+        let ip = stack_frame.instruction_pointer.as_u64();
+        if let Some(fixup) = super::usercopy::search_exception_table(ip) {
+            crate::debug_println!("[PageFault] User copy fault at {:#x}, fixup {:#x}", fault_addr.as_u64(), fixup);
+            // SAFETY: The fixup belongs to the same routine and expects the
+            // interrupted register state
+            unsafe {
+                stack_frame.as_mut().update(|frame| frame.instruction_pointer = VirtAddr::new(fixup));
+            }
+            return;
+        }
+    }
+
+    // Unhandled fault from user mode: dump core and kill the process
+    if from_user {
+        use crate::kernel::process::{coredump, lifecycle};
+        backtrace::print_user(stack_frame.instruction_pointer.as_u64(), rbp);
+        coredump::dump_current(&coredump::FaultInfo::from_frame(coredump::SIGSEGV, &stack_frame));
+        lifecycle::kill_current(128 + coredump::SIGSEGV);
+    }
+    
+    // Kernel page fault - panic
     use crate::arch::x86_64::port::PortWriteOnly;
+
+    report_kernel_stack_overflow();
     
     unsafe {
         let mut serial = PortWriteOnly::<u8>::new(0x3F8);
@@ -196,6 +322,7 @@ extern "x86-interrupt" fn page_fault_handler(
         print_u64(stack_frame.instruction_pointer.as_u64(), &mut serial);
         serial.write(b'\n');
     }
+    backtrace::print_kernel(Some(stack_frame.instruction_pointer.as_u64()), rbp);
     
     loop {
         crate::arch::ArchCpu::halt();
@@ -203,11 +330,23 @@ extern "x86-interrupt" fn page_fault_handler(
 }
 
 #[allow(clippy::missing_const_for_fn)]
-extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    use crate::arch::x86_64::pic::PICS;
-    
+extern "x86-interrupt" fn timer_interrupt_handler(stack_frame: InterruptStackFrame) {
+    use crate::kernel::process::accounting;
+    protection::clear_ac();
+
+    // Charge the interrupted user time before doing kernel work
+    let from_user = stack_frame.code_segment.rpl() == x86_64::PrivilegeLevel::Ring3;
+    if from_user {
+        accounting::enter_kernel();
+    }
+
     // Update async timer tick counter and wake sleeping tasks
-    crate::kernel::r#async::tick();
+    // (tickless idle から復帰した場合は眠っていた分をまとめて進める)
+    if crate::arch::x86_64::apic::is_enabled() {
+        crate::kernel::r#async::tick_n(crate::arch::x86_64::apic::on_timer_interrupt());
+    } else {
+        crate::kernel::r#async::tick();
+    }
 
     // Also poll SQPOLL contexts on each timer tick to ensure background
     // polling runs even when user-mode processes are active.
@@ -217,28 +356,38 @@ extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFr
     }
     
     // Send EOI first to allow nested interrupts if needed
-    unsafe {
-        PICS.lock().notify_end_of_interrupt(32);
-    }
+    irq::count(TIMER_VECTOR);
+    irq::controller().eoi(TIMER_VECTOR);
     
+    // Keep the current process's usage fresh even without context switches
+    accounting::flush_current_from_interrupt();
+
     // Trigger process scheduler
     // This will pick the next ready process and switch to it
     crate::kernel::process::schedule_next();
-}
 
-extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    use crate::arch::x86_64::pic::PICS;
-    use crate::kernel::driver::keyboard::{KEYBOARD, SCANCODE_QUEUE};
+    if from_user {
+        accounting::return_to_user();
+    }
+}
 
-    // キーボードからスキャンコードを読み取る
-    let scancode = KEYBOARD.lock().read_scancode();
+/// 登録済みハンドラへ配送する IRQ スタブ
+extern "x86-interrupt" fn irq_stub<const VECTOR: u8>(stack_frame: InterruptStackFrame) {
+    use crate::kernel::process::accounting;
+    protection::clear_ac();
 
-    if let Some(scancode) = scancode {
-        // キューに追加（Waker もここで呼ばれる）
-        SCANCODE_QUEUE.lock().add_scancode(scancode);
+    // ユーザーモードから割り込まれた場合は、そこまでをユーザー時間として計上する
+    let from_user = stack_frame.code_segment.rpl() == x86_64::PrivilegeLevel::Ring3;
+    if from_user {
+        accounting::enter_kernel();
     }
-
-    unsafe {
-        PICS.lock().notify_end_of_interrupt(33);
+    irq::dispatch(VECTOR);
+    if from_user {
+        accounting::return_to_user();
     }
 }
+
+extern "x86-interrupt" fn spurious_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    protection::clear_ac();
+    // スプリアス割り込みには EOI を送ってはいけない
+}
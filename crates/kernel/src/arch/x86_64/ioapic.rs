@@ -0,0 +1,213 @@
+// kernel/src/arch/x86_64/ioapic.rs
+//! I/O APIC
+//!
+//! MADT に記載された I/O APIC を初期化し、レガシー ISA IRQ
+//! (キーボード、COM1 など) を任意の IDT ベクタへルーティングします。
+//!
+//! # レジスタアクセス
+//!
+//! I/O APIC は間接アクセス方式です。`IOREGSEL` にレジスタ番号を書き込み、
+//! `IOWIN` から値を読み書きします。選択と読み書きの間に他の CPU が
+//! 割り込まないよう、全アクセスを `IO_APICS` のロック下で行います。
+
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::acpi;
+use crate::debug_println;
+
+/// レジスタ選択 (IOREGSEL) オフセット
+const IOREGSEL: u64 = 0x00;
+/// データウィンドウ (IOWIN) オフセット
+const IOWIN: u64 = 0x10;
+
+/// バージョンレジスタ
+const REG_VERSION: u32 = 0x01;
+/// 最初のリダイレクションテーブルレジスタ
+const REG_REDTBL_BASE: u32 = 0x10;
+
+/// リダイレクションエントリ: マスク
+const REDIR_MASKED: u64 = 1 << 16;
+/// リダイレクションエントリ: レベルトリガ
+const REDIR_LEVEL: u64 = 1 << 15;
+/// リダイレクションエントリ: アクティブロー
+const REDIR_ACTIVE_LOW: u64 = 1 << 13;
+
+/// I/O APIC のエラー
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoApicError {
+    /// MADT が解析されていない
+    NoMadt,
+    /// GSI を担当する I/O APIC が存在しない
+    NoIoApicForGsi(u32),
+}
+
+/// 単一の I/O APIC
+pub struct IoApic {
+    base: u64,
+    gsi_base: u32,
+    redirection_entries: u32,
+}
+
+impl IoApic {
+    /// 物理アドレスから I/O APIC を作成
+    ///
+    /// # Safety
+    ///
+    /// `base` は I/O APIC レジスタがマップされた仮想アドレスである必要があります。
+    unsafe fn new(base: u64, gsi_base: u32) -> Self {
+        let mut ioapic = Self { base, gsi_base, redirection_entries: 0 };
+        // SAFETY: 呼び出し元が base の有効性を保証している
+        let version = unsafe { ioapic.read(REG_VERSION) };
+        ioapic.redirection_entries = ((version >> 16) & 0xFF) + 1;
+        ioapic
+    }
+
+    unsafe fn read(&self, reg: u32) -> u32 {
+        // SAFETY: base は new() で検証済みの I/O APIC アドレス
+        unsafe {
+            core::ptr::write_volatile((self.base + IOREGSEL) as *mut u32, reg);
+            core::ptr::read_volatile((self.base + IOWIN) as *const u32)
+        }
+    }
+
+    unsafe fn write(&mut self, reg: u32, value: u32) {
+        // SAFETY: base は new() で検証済みの I/O APIC アドレス
+        unsafe {
+            core::ptr::write_volatile((self.base + IOREGSEL) as *mut u32, reg);
+            core::ptr::write_volatile((self.base + IOWIN) as *mut u32, value);
+        }
+    }
+
+    fn handles(&self, gsi: u32) -> bool {
+        gsi >= self.gsi_base && gsi < self.gsi_base + self.redirection_entries
+    }
+
+    fn write_redirection(&mut self, gsi: u32, entry: u64) {
+        let reg = REG_REDTBL_BASE + (gsi - self.gsi_base) * 2;
+        // SAFETY: handles() で範囲を確認済みのリダイレクションエントリ
+        unsafe {
+            // 上位 (宛先) を先に書き、最後に下位 (マスクビットを含む) を書く
+            self.write(reg + 1, (entry >> 32) as u32);
+            self.write(reg, entry as u32);
+        }
+    }
+
+    fn read_redirection(&self, gsi: u32) -> u64 {
+        let reg = REG_REDTBL_BASE + (gsi - self.gsi_base) * 2;
+        // SAFETY: handles() で範囲を確認済みのリダイレクションエントリ
+        unsafe { u64::from(self.read(reg)) | (u64::from(self.read(reg + 1)) << 32) }
+    }
+
+    fn mask_all(&mut self) {
+        for i in 0..self.redirection_entries {
+            self.write_redirection(self.gsi_base + i, REDIR_MASKED);
+        }
+    }
+}
+
+/// システム上の全 I/O APIC
+static IO_APICS: Mutex<Vec<IoApic>> = Mutex::new(Vec::new());
+
+/// MADT から I/O APIC を列挙して初期化
+///
+/// 全リダイレクションエントリをマスクした状態で登録します。
+/// 個々の IRQ は `route_isa_irq` / `route_gsi` で有効化してください。
+///
+/// # Errors
+///
+/// MADT が解析されていない場合は `IoApicError::NoMadt` を返します。
+pub fn init() -> Result<(), IoApicError> {
+    let madt = acpi::madt().ok_or(IoApicError::NoMadt)?;
+    let offset = acpi::phys_mem_offset();
+
+    let mut io_apics = IO_APICS.lock();
+    for info in &madt.io_apics {
+        // SAFETY: MADT の I/O APIC アドレスは物理メモリマッピング経由でアクセス可能
+        let mut ioapic = unsafe { IoApic::new(u64::from(info.address) + offset, info.gsi_base) };
+        ioapic.mask_all();
+        debug_println!(
+            "[IOAPIC] id={} at {:#x}, GSI {}..{}",
+            info.id,
+            info.address,
+            info.gsi_base,
+            info.gsi_base + ioapic.redirection_entries
+        );
+        io_apics.push(ioapic);
+    }
+
+    Ok(())
+}
+
+/// GSI を指定ベクタ・宛先 LAPIC にルーティング
+///
+/// # Errors
+///
+/// GSI を担当する I/O APIC が存在しない場合はエラーを返します。
+pub fn route_gsi(
+    gsi: u32,
+    vector: u8,
+    dest_apic_id: u32,
+    active_low: bool,
+    level_triggered: bool,
+) -> Result<(), IoApicError> {
+    let mut io_apics = IO_APICS.lock();
+    let ioapic = io_apics
+        .iter_mut()
+        .find(|io| io.handles(gsi))
+        .ok_or(IoApicError::NoIoApicForGsi(gsi))?;
+
+    // Fixed delivery, physical destination
+    let mut entry = u64::from(vector) | (u64::from(dest_apic_id & 0xFF) << 56);
+    if active_low {
+        entry |= REDIR_ACTIVE_LOW;
+    }
+    if level_triggered {
+        entry |= REDIR_LEVEL;
+    }
+    ioapic.write_redirection(gsi, entry);
+    Ok(())
+}
+
+/// ISA IRQ を指定ベクタにルーティング
+///
+/// MADT の Interrupt Source Override を適用して GSI と極性・トリガモードを決定します。
+/// オーバーライドがなければ ISA の既定値（エッジ・アクティブハイ、GSI = IRQ）を使用します。
+///
+/// # Errors
+///
+/// MADT がない、または担当 I/O APIC が存在しない場合はエラーを返します。
+pub fn route_isa_irq(irq: u8, vector: u8, dest_apic_id: u32) -> Result<u32, IoApicError> {
+    let madt = acpi::madt().ok_or(IoApicError::NoMadt)?;
+    let (gsi, active_low, level) = match madt.isa_override(irq) {
+        Some(o) => (o.gsi, o.active_low(), o.level_triggered()),
+        None => (u32::from(irq), false, false),
+    };
+    route_gsi(gsi, vector, dest_apic_id, active_low, level)?;
+    debug_println!("[IOAPIC] IRQ{} -> GSI {} -> vector {:#x}", irq, gsi, vector);
+    Ok(gsi)
+}
+
+/// GSI のマスクを設定・解除
+///
+/// # Errors
+///
+/// GSI を担当する I/O APIC が存在しない場合はエラーを返します。
+pub fn set_masked(gsi: u32, masked: bool) -> Result<(), IoApicError> {
+    let mut io_apics = IO_APICS.lock();
+    let ioapic = io_apics
+        .iter_mut()
+        .find(|io| io.handles(gsi))
+        .ok_or(IoApicError::NoIoApicForGsi(gsi))?;
+
+    let entry = ioapic.read_redirection(gsi);
+    let entry = if masked { entry | REDIR_MASKED } else { entry & !REDIR_MASKED };
+    ioapic.write_redirection(gsi, entry);
+    Ok(())
+}
+
+/// I/O APIC が 1 つ以上初期化済みかどうか
+#[must_use]
+pub fn is_available() -> bool {
+    !IO_APICS.lock().is_empty()
+}
@@ -0,0 +1,326 @@
+// kernel/src/arch/x86_64/irq.rs
+//! 割り込みベクタ管理とハンドラ登録 API
+//!
+//! ドライバが IDT を直接編集せずに割り込みハンドラを登録できるようにします。
+//!
+//! # 構成
+//!
+//! ```text
+//! IDT[33..=111] ──> irq_stub::<V>() ──> dispatch(V)
+//!                                         ├── COUNTS[V] += 1
+//!                                         ├── HANDLERS[V] を順に呼ぶ（共有 IRQ チェーン）
+//!                                         └── controller().eoi(V)
+//! ```
+//!
+//! - `33..=47`  : レガシー ISA IRQ 用（固定、`allocate_vector` の対象外）
+//! - `48..=111` : 動的割り当て用（MSI/MSI-X など）
+//!
+//! タイマー (32) は スケジューラと直結した専用ハンドラを使うため、
+//! このディスパッチャを経由しませんがカウンタは共有します。
+//!
+//! # Example
+//!
+//! ```ignore
+//! fn my_handler(context: usize) -> IrqReturn {
+//!     let dev = unsafe { &*(context as *const MyDevice) };
+//!     if !dev.interrupt_pending() {
+//!         return IrqReturn::NotMine;
+//!     }
+//!     dev.ack();
+//!     IrqReturn::Handled
+//! }
+//!
+//! let vector = irq::allocate_vector()?;
+//! irq::register_irq(vector, my_handler, &DEVICE as *const _ as usize)?;
+//! ```
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use super::{apic, pic};
+
+/// IDT のエントリ数
+const VECTOR_COUNT: usize = 256;
+/// ディスパッチャ経由で扱う最初のベクタ（レガシー IRQ1 = キーボード）
+pub const FIRST_ROUTED_VECTOR: u8 = 33;
+/// 動的割り当ての最初のベクタ
+pub const DYNAMIC_VECTOR_START: u8 = 48;
+/// 動的割り当ての最後のベクタ
+pub const DYNAMIC_VECTOR_END: u8 = 111;
+/// 8259 PIC のベクタオフセット（ISA IRQ0）
+const PIC_VECTOR_BASE: u8 = 32;
+
+/// 割り込みハンドラの戻り値
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqReturn {
+    /// このハンドラが割り込みを処理した
+    Handled,
+    /// このハンドラのデバイスからの割り込みではない（共有 IRQ で次へ）
+    NotMine,
+}
+
+/// 割り込みハンドラ
+///
+/// `context` は登録時に渡した値がそのまま渡されます（通常はデバイス構造体へのポインタ）。
+/// 割り込みコンテキストで実行されるため、ブロックするロックを取得してはいけません。
+pub type IrqHandler = fn(context: usize) -> IrqReturn;
+
+/// IRQ 管理のエラー
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqError {
+    /// ディスパッチャが扱わないベクタ
+    InvalidVector(u8),
+    /// 空きベクタがない
+    NoFreeVector,
+    /// 指定されたハンドラは登録されていない
+    NotRegistered,
+    /// 同じハンドラとコンテキストの組が既に登録されている
+    AlreadyRegistered,
+}
+
+/// 割り込みコントローラの抽象
+///
+/// EOI やマスク操作の違い（8259 PIC / APIC）を呼び出し元から隠蔽します。
+pub trait InterruptController: Sync {
+    /// コントローラ名
+    fn name(&self) -> &'static str;
+
+    /// 割り込み終了を通知
+    fn eoi(&self, vector: u8);
+
+    /// ISA IRQ のマスクを設定・解除
+    fn set_isa_irq_masked(&self, irq: u8, masked: bool);
+}
+
+/// 8259 PIC コントローラ
+pub struct PicController;
+
+impl InterruptController for PicController {
+    fn name(&self) -> &'static str {
+        "8259 PIC"
+    }
+
+    fn eoi(&self, vector: u8) {
+        // SAFETY: 割り込みハンドラ内から対応するベクタで呼ばれる
+        unsafe { pic::PICS.lock().notify_end_of_interrupt(vector) };
+    }
+
+    fn set_isa_irq_masked(&self, irq: u8, masked: bool) {
+        // SAFETY: PIC は初期化済み
+        unsafe {
+            let mut pics = pic::PICS.lock();
+            if masked {
+                pics.mask_irq(irq);
+            } else {
+                pics.unmask_irq(irq);
+            }
+        }
+    }
+}
+
+/// Local APIC + I/O APIC コントローラ
+pub struct ApicController;
+
+impl InterruptController for ApicController {
+    fn name(&self) -> &'static str {
+        "LAPIC/IOAPIC"
+    }
+
+    fn eoi(&self, _vector: u8) {
+        apic::eoi();
+    }
+
+    fn set_isa_irq_masked(&self, irq: u8, masked: bool) {
+        let gsi = super::acpi::madt()
+            .and_then(|madt| madt.isa_override(irq))
+            .map_or(u32::from(irq), |o| o.gsi);
+        let _ = super::ioapic::set_masked(gsi, masked);
+    }
+}
+
+static PIC_CONTROLLER: PicController = PicController;
+static APIC_CONTROLLER: ApicController = ApicController;
+
+/// 現在有効な割り込みコントローラを取得
+#[must_use]
+pub fn controller() -> &'static dyn InterruptController {
+    if apic::is_enabled() {
+        &APIC_CONTROLLER
+    } else {
+        &PIC_CONTROLLER
+    }
+}
+
+/// ベクタごとのハンドラチェーン
+static HANDLERS: [Mutex<Vec<(IrqHandler, usize)>>; VECTOR_COUNT] =
+    [const { Mutex::new(Vec::new()) }; VECTOR_COUNT];
+/// ベクタごとの割り込み回数
+static COUNTS: [AtomicU64; VECTOR_COUNT] = [const { AtomicU64::new(0) }; VECTOR_COUNT];
+/// どのハンドラも処理しなかった割り込みの回数
+static UNHANDLED: AtomicU64 = AtomicU64::new(0);
+/// 動的ベクタの割り当て状況 (bit n = DYNAMIC_VECTOR_START + n)
+static ALLOCATED: Mutex<u64> = Mutex::new(0);
+
+const fn is_routed(vector: u8) -> bool {
+    vector >= FIRST_ROUTED_VECTOR && vector <= DYNAMIC_VECTOR_END
+}
+
+/// ISA IRQ 番号に対応するベクタ
+#[must_use]
+pub const fn isa_vector(irq: u8) -> u8 {
+    PIC_VECTOR_BASE + irq
+}
+
+/// 動的ベクタを 1 つ割り当てる
+///
+/// # Errors
+///
+/// 空きがなければ `IrqError::NoFreeVector` を返します。
+pub fn allocate_vector() -> Result<u8, IrqError> {
+    let mut allocated = ALLOCATED.lock();
+    let free = (!*allocated).trailing_zeros();
+    if free >= u32::from(DYNAMIC_VECTOR_END - DYNAMIC_VECTOR_START + 1) {
+        return Err(IrqError::NoFreeVector);
+    }
+    *allocated |= 1 << free;
+    Ok(DYNAMIC_VECTOR_START + free as u8)
+}
+
+/// 動的ベクタを解放する
+///
+/// 登録済みのハンドラもすべて解除されます。
+///
+/// # Errors
+///
+/// 動的範囲外のベクタを指定した場合はエラーを返します。
+pub fn free_vector(vector: u8) -> Result<(), IrqError> {
+    if !(DYNAMIC_VECTOR_START..=DYNAMIC_VECTOR_END).contains(&vector) {
+        return Err(IrqError::InvalidVector(vector));
+    }
+    without_interrupts(|| HANDLERS[vector as usize].lock().clear());
+    *ALLOCATED.lock() &= !(1 << (vector - DYNAMIC_VECTOR_START));
+    Ok(())
+}
+
+/// 割り込みハンドラを登録する
+///
+/// 同じベクタに複数のハンドラを登録でき（共有 IRQ）、登録順に呼び出されます。
+///
+/// # Errors
+///
+/// - `IrqError::InvalidVector` - ディスパッチャが扱わないベクタ
+/// - `IrqError::AlreadyRegistered` - 同じハンドラ・コンテキストが登録済み
+pub fn register_irq(vector: u8, handler: IrqHandler, context: usize) -> Result<(), IrqError> {
+    if !is_routed(vector) {
+        return Err(IrqError::InvalidVector(vector));
+    }
+    // 同じ CPU 上の割り込みハンドラとのデッドロックを避けるため割り込み禁止で操作
+    without_interrupts(|| {
+        let mut chain = HANDLERS[vector as usize].lock();
+        if chain.iter().any(|&(h, c)| core::ptr::fn_addr_eq(h, handler) && c == context) {
+            return Err(IrqError::AlreadyRegistered);
+        }
+        chain.push((handler, context));
+        Ok(())
+    })
+}
+
+/// 割り込みハンドラの登録を解除する
+///
+/// # Errors
+///
+/// 指定した組が登録されていなければ `IrqError::NotRegistered` を返します。
+pub fn unregister_irq(vector: u8, handler: IrqHandler, context: usize) -> Result<(), IrqError> {
+    if !is_routed(vector) {
+        return Err(IrqError::InvalidVector(vector));
+    }
+    without_interrupts(|| {
+        let mut chain = HANDLERS[vector as usize].lock();
+        let before = chain.len();
+        chain.retain(|&(h, c)| !(core::ptr::fn_addr_eq(h, handler) && c == context));
+        if chain.len() == before {
+            Err(IrqError::NotRegistered)
+        } else {
+            Ok(())
+        }
+    })
+}
+
+/// 割り込み回数を記録する（専用ハンドラ用）
+#[inline]
+pub fn count(vector: u8) {
+    COUNTS[vector as usize].fetch_add(1, Ordering::Relaxed);
+}
+
+/// ベクタの割り込み回数を取得
+#[must_use]
+pub fn irq_count(vector: u8) -> u64 {
+    COUNTS[vector as usize].load(Ordering::Relaxed)
+}
+
+/// どのハンドラも処理しなかった割り込みの回数
+#[must_use]
+pub fn unhandled_count() -> u64 {
+    UNHANDLED.load(Ordering::Relaxed)
+}
+
+/// 割り込みをハンドラチェーンに配送し、EOI を送る
+///
+/// `interrupts` の IRQ スタブから呼ばれます。
+///
+/// チェーンを書き換える側は必ず割り込み禁止の中でロックを取るため、
+/// 同じ CPU でこのロックが保持されたまま割り込みが入ることはありません。
+/// 他の CPU が登録中であれば短時間スピンして待ちます（割り込みは落としません）。
+/// そのためハンドラの中から `register_irq`・`unregister_irq` を呼んではいけません。
+pub(crate) fn dispatch(vector: u8) {
+    count(vector);
+
+    let handled = HANDLERS[vector as usize]
+        .lock()
+        .iter()
+        .fold(false, |handled, &(handler, context)| {
+            handler(context) == IrqReturn::Handled || handled
+        });
+    if !handled {
+        UNHANDLED.fetch_add(1, Ordering::Relaxed);
+    }
+
+    controller().eoi(vector);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dummy_handler(_context: usize) -> IrqReturn {
+        IrqReturn::NotMine
+    }
+
+    #[test_case]
+    fn test_allocate_and_free_vector() {
+        let vector = allocate_vector().expect("vector");
+        assert!((DYNAMIC_VECTOR_START..=DYNAMIC_VECTOR_END).contains(&vector));
+        let other = allocate_vector().expect("vector");
+        assert_ne!(vector, other);
+        free_vector(vector).unwrap();
+        free_vector(other).unwrap();
+    }
+
+    #[test_case]
+    fn test_register_rejects_unrouted_vector() {
+        assert_eq!(register_irq(14, dummy_handler, 0), Err(IrqError::InvalidVector(14)));
+    }
+
+    #[test_case]
+    fn test_shared_irq_chain() {
+        let vector = allocate_vector().unwrap();
+        register_irq(vector, dummy_handler, 1).unwrap();
+        register_irq(vector, dummy_handler, 2).unwrap();
+        assert_eq!(register_irq(vector, dummy_handler, 1), Err(IrqError::AlreadyRegistered));
+        unregister_irq(vector, dummy_handler, 1).unwrap();
+        assert_eq!(unregister_irq(vector, dummy_handler, 1), Err(IrqError::NotRegistered));
+        free_vector(vector).unwrap();
+    }
+}
@@ -24,9 +24,27 @@ pub mod cpu_features;
 pub mod per_cpu;
 /// Symmetric Multi-Processing support (Phase 3: SMP)
 pub mod smp;
+/// ACPI table parsing (RSDP/XSDT/MADT)
+pub mod acpi;
+/// I/O APIC interrupt routing
+pub mod ioapic;
+/// Local APIC timer and APIC-mode interrupt controller
+pub mod apic;
+/// Interrupt vector allocation and handler registration
+pub mod irq;
+/// ACPI reset / S5 power-off
+pub mod power;
+/// GDB remote serial protocol stub on COM2
+pub mod gdbstub;
+/// Frame-pointer stack walking and symbolized backtraces
+pub mod backtrace;
+/// Fault-tolerant copies to and from user space
+pub mod usercopy;
+/// SMEP, SMAP and UMIP enforcement
+pub mod protection;
 
 pub use cpu::{X86Cpu, InterruptFlags, critical_section};
-pub use cpu::read_timestamp;
+pub use cpu::{read_random, read_timestamp};
 pub use qemu::write_debug_byte;
 pub use port::{Port, PortReadOnly, PortWriteOnly};
 pub use gdt::init as init_gdt;
@@ -118,6 +118,22 @@ impl ChainedPics {
     fn handles_interrupt(&self, interrupt_id: u8) -> bool {
         self.pics.iter().any(|p| p.handles_interrupt(interrupt_id))
     }
+
+    /// すべての IRQ をマスクして PIC を無効化
+    ///
+    /// APIC モードへ移行する際に使用します。リマップ済みでないと
+    /// スプリアス割り込みが例外ベクタに届くため、先に `initialize` を呼んでください。
+    ///
+    /// # Safety
+    ///
+    /// この関数はPICが適切に初期化された後に呼ばれる必要があります。
+    pub unsafe fn disable(&mut self) {
+        // SAFETY: 呼び出し元がPICマスク操作の安全性を保証している
+        unsafe {
+            self.pics[0].data.write(0xff);
+            self.pics[1].data.write(0xff);
+        }
+    }
     
     /// 特定の IRQ のマスクを解除
     ///
@@ -139,6 +155,27 @@ impl ChainedPics {
             }
         }
     }
+
+    /// 特定の IRQ をマスク
+    ///
+    /// # Safety
+    /// 
+    /// この関数はPICが適切に初期化された後に呼ばれる必要があります。
+    pub unsafe fn mask_irq(&mut self, irq: u8) {
+        // SAFETY: 呼び出し元がPICマスク操作の安全性を保証している
+        unsafe {
+            let mut port: Port<u8>;
+            if irq < 8 {
+                port = Port::new(PIC1_DATA);
+                let value = port.read();
+                port.write(value | (1 << irq));
+            } else {
+                port = Port::new(PIC2_DATA);
+                let value = port.read();
+                port.write(value | (1 << (irq - 8)));
+            }
+        }
+    }
 }
 
 struct Pic {
@@ -0,0 +1,141 @@
+// kernel/src/arch/x86_64/power.rs
+//! ハードウェアによる再起動と電源断
+//!
+//! FADT から得たレジスタを使って機械を止めます。どの手段も失敗した
+//! 場合は割り込みを禁止して CPU を停止します。
+//!
+//! # 再起動
+//!
+//! 1. FADT の RESET_REG (I/O ポートまたはメモリ) に RESET_VALUE を書き込む
+//! 2. キーボードコントローラ (0x64) にパルスコマンド 0xFE を送る
+//!
+//! # 電源断 (ACPI S5)
+//!
+//! 1. SCI_EN が立っていなければ SMI_CMD に ACPI_ENABLE を書いて ACPI モードへ移行
+//! 2. PM1a_CNT (と PM1b_CNT) に `SLP_TYPx << 10 | SLP_EN` を書き込む
+
+use x86_64::instructions::port::Port;
+
+use super::acpi::{self, AddressSpace};
+use crate::debug_println;
+
+/// PM1 コントロール: SCI_EN (ACPI モード有効)
+const PM1_SCI_EN: u16 = 1 << 0;
+/// PM1 コントロール: SLP_TYP フィールドのシフト量
+const PM1_SLP_TYP_SHIFT: u16 = 10;
+/// PM1 コントロール: SLP_EN (スリープ開始)
+const PM1_SLP_EN: u16 = 1 << 13;
+
+/// キーボードコントローラのコマンド/ステータスポート
+const KBC_COMMAND: u16 = 0x64;
+/// KBC ステータス: 入力バッファフル
+const KBC_INPUT_FULL: u8 = 1 << 1;
+/// KBC コマンド: CPU リセットラインをパルス
+const KBC_PULSE_RESET: u8 = 0xFE;
+
+/// ステータスレジスタをポーリングする最大回数
+const POLL_SPINS: u32 = 1_000_000;
+
+fn halt_forever() -> ! {
+    x86_64::instructions::interrupts::disable();
+    loop {
+        x86_64::instructions::hlt();
+    }
+}
+
+/// FADT のリセットレジスタで再起動を試みる
+fn reset_via_fadt() {
+    let Some(fadt) = acpi::fadt() else { return };
+    let Some(reg) = fadt.reset_register else { return };
+
+    match reg.space {
+        AddressSpace::Io => {
+            // SAFETY: FADT が示すリセットポートへの書き込み
+            unsafe { Port::<u8>::new(reg.address as u16).write(fadt.reset_value) };
+        }
+        AddressSpace::Memory => {
+            let virt = reg.address + acpi::phys_mem_offset();
+            // SAFETY: FADT が示すリセットレジスタは物理メモリマッピング経由でアクセス可能
+            unsafe { core::ptr::write_volatile(virt as *mut u8, fadt.reset_value) };
+        }
+        // PCI コンフィギュレーション空間経由のリセットは未対応
+        AddressSpace::PciConfig | AddressSpace::Other(_) => {}
+    }
+}
+
+/// キーボードコントローラで CPU リセットを試みる
+fn reset_via_kbc() {
+    let mut port = Port::<u8>::new(KBC_COMMAND);
+    // SAFETY: 0x64 は 8042 キーボードコントローラのコマンドポート
+    unsafe {
+        for _ in 0..POLL_SPINS {
+            if port.read() & KBC_INPUT_FULL == 0 {
+                break;
+            }
+            core::hint::spin_loop();
+        }
+        port.write(KBC_PULSE_RESET);
+    }
+}
+
+/// 機械を再起動する
+///
+/// FADT のリセットレジスタ、キーボードコントローラの順に試し、
+/// どちらも効かなければ CPU を停止します。
+pub fn reboot() -> ! {
+    x86_64::instructions::interrupts::disable();
+
+    debug_println!("[POWER] Rebooting via FADT reset register");
+    reset_via_fadt();
+
+    debug_println!("[POWER] Rebooting via keyboard controller");
+    reset_via_kbc();
+
+    debug_println!("[POWER] Reboot failed, halting");
+    halt_forever()
+}
+
+/// ACPI S5 で電源を切る
+///
+/// FADT または `\_S5` が見つからない場合、あるいは書き込み後も
+/// 停止しなかった場合は CPU を停止します。
+pub fn power_off() -> ! {
+    x86_64::instructions::interrupts::disable();
+
+    if let Some((fadt, (slp_typ_a, slp_typ_b))) =
+        acpi::fadt().and_then(|fadt| fadt.s5_sleep_type.map(|s5| (fadt, s5)))
+    {
+        let mut pm1a = Port::<u16>::new(fadt.pm1a_control);
+
+        // SAFETY: PM1a/PM1b と SMI_CMD は FADT が示す電源管理ポート
+        unsafe {
+            if pm1a.read() & PM1_SCI_EN == 0 && fadt.smi_command != 0 && fadt.acpi_enable != 0 {
+                Port::<u8>::new(fadt.smi_command as u16).write(fadt.acpi_enable);
+                for _ in 0..POLL_SPINS {
+                    if pm1a.read() & PM1_SCI_EN != 0 {
+                        break;
+                    }
+                    core::hint::spin_loop();
+                }
+            }
+
+            debug_println!("[POWER] Entering ACPI S5 (SLP_TYPa={})", slp_typ_a);
+            pm1a.write((u16::from(slp_typ_a) << PM1_SLP_TYP_SHIFT) | PM1_SLP_EN);
+            if fadt.pm1b_control != 0 {
+                Port::<u16>::new(fadt.pm1b_control)
+                    .write((u16::from(slp_typ_b) << PM1_SLP_TYP_SHIFT) | PM1_SLP_EN);
+            }
+        }
+    } else {
+        debug_println!("[POWER] ACPI S5 unavailable");
+    }
+
+    debug_println!("[POWER] Power-off failed, halting");
+    halt_forever()
+}
+
+/// 割り込みを禁止して CPU を停止する
+pub fn halt() -> ! {
+    debug_println!("[POWER] System halted");
+    halt_forever()
+}
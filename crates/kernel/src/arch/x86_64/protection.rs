@@ -0,0 +1,99 @@
+// kernel/src/arch/x86_64/protection.rs
+//! SMEP・SMAP・UMIP によるカーネルとユーザー空間の分離
+//!
+//! - **SMEP**: カーネルモードでユーザーページの命令を実行するとページフォルト
+//! - **SMAP**: カーネルモードでユーザーページを読み書きするとページフォルト。
+//!   RFLAGS.AC が立っている間だけアクセスが許されるので、`stac` は
+//!   [`super::usercopy`] のコピー用ルーチンの中だけで使います。IDT 経由の
+//!   割り込み・例外は AC を下ろさないため、各エントリの先頭で
+//!   [`clear_ac`] を呼びます（`syscall` は SFMASK で下ろす）
+//! - **UMIP**: ユーザーモードの SGDT・SIDT・SLDT・SMSW・STR を #GP にする
+//!
+//! どれも `cpu_features::detect` が対応を報告した場合だけ有効にします。
+//! ユーザーのマッピングはカーネルと共有しない PML4 エントリの下にあるので、
+//! カーネルのページテーブルに USER_ACCESSIBLE が付くことはありません。
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use x86_64::registers::control::{Cr4, Cr4Flags};
+
+use super::cpu_features;
+
+/// CR4.SMAP を有効にしたか
+///
+/// ユーザーコピーのルーチンはこれが立っているときだけ `stac`/`clac` を
+/// 実行します（SMAP のない CPU では #UD になるため）。
+pub(super) static SMAP_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// CPU が対応している保護機能を有効にする
+///
+/// ページングの初期化後、最初のユーザープロセスを作成する前に一度だけ
+/// 呼び出します。
+pub fn init() {
+    let features = cpu_features::get();
+    let mut flags = Cr4Flags::empty();
+    flags.set(Cr4Flags::SUPERVISOR_MODE_EXECUTION_PROTECTION, features.has_smep);
+    flags.set(Cr4Flags::SUPERVISOR_MODE_ACCESS_PREVENTION, features.has_smap);
+    flags.set(Cr4Flags::USER_MODE_INSTRUCTION_PREVENTION, features.has_umip);
+    // SAFETY: カーネルはユーザーページを実行せず、ユーザーメモリには
+    // コピー用ルーチンからしか触れない
+    unsafe {
+        Cr4::update(|cr4| cr4.insert(flags));
+    }
+    SMAP_ENABLED.store(features.has_smap, Ordering::Release);
+    log::info!(
+        target: "arch",
+        "SMEP: {}, SMAP: {}, UMIP: {}",
+        features.has_smep,
+        features.has_smap,
+        features.has_umip
+    );
+}
+
+/// SMAP を有効にしたか
+pub fn smap_enabled() -> bool {
+    SMAP_ENABLED.load(Ordering::Acquire)
+}
+
+/// 割り込み・例外のエントリで RFLAGS.AC を下ろす
+///
+/// ユーザーコピーの途中やユーザーモードで AC が立ったまま割り込まれても、
+/// ハンドラがユーザーページに触れられないようにします。割り込まれた側の
+/// AC は `iretq` が RFLAGS ごと戻します。
+#[inline(always)]
+pub fn clear_ac() {
+    if SMAP_ENABLED.load(Ordering::Relaxed) {
+        // SAFETY: SMAP があるので clac は #UD にならず、AC 以外に影響しない
+        unsafe { core::arch::asm!("clac", options(nomem, nostack)) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_cr4_matches_features() {
+        let features = cpu_features::get();
+        let cr4 = Cr4::read();
+        assert_eq!(smap_enabled(), cr4.contains(Cr4Flags::SUPERVISOR_MODE_ACCESS_PREVENTION));
+        if !features.has_smap {
+            assert!(!smap_enabled());
+        }
+        if !features.has_smep {
+            assert!(!cr4.contains(Cr4Flags::SUPERVISOR_MODE_EXECUTION_PROTECTION));
+        }
+    }
+
+    #[test_case]
+    fn test_clear_ac_closes_smap_window() {
+        use x86_64::registers::rflags::{self, RFlags};
+
+        if smap_enabled() {
+            // SAFETY: SMAP があるので stac は有効で、すぐに下ろす
+            unsafe { core::arch::asm!("stac", options(nomem, nostack)) };
+            assert!(rflags::read().contains(RFlags::ALIGNMENT_CHECK));
+        }
+        clear_ac();
+        assert!(!rflags::read().contains(RFlags::ALIGNMENT_CHECK));
+    }
+}
@@ -65,6 +65,14 @@ pub mod lapic {
     pub const ICR_LOW: u32 = 0x300;
     /// Interrupt Command Register (high)
     pub const ICR_HIGH: u32 = 0x310;
+    /// LVT Timer Register
+    pub const LVT_TIMER: u32 = 0x320;
+    /// Timer Initial Count Register
+    pub const TIMER_INITIAL_COUNT: u32 = 0x380;
+    /// Timer Current Count Register
+    pub const TIMER_CURRENT_COUNT: u32 = 0x390;
+    /// Timer Divide Configuration Register
+    pub const TIMER_DIVIDE: u32 = 0x3E0;
 }
 
 /// IPI delivery modes
@@ -144,7 +152,7 @@ pub fn state() -> &'static SmpState {
 /// # Safety
 /// 
 /// Requires valid LAPIC base address
-unsafe fn lapic_read(offset: u32) -> u32 {
+pub(crate) unsafe fn lapic_read(offset: u32) -> u32 {
     let base = SMP_STATE.lapic_base.load(Ordering::Relaxed);
     let addr = (base + u64::from(offset)) as *const u32;
     // SAFETY: Caller ensures LAPIC is mapped and offset is valid
@@ -156,7 +164,7 @@ unsafe fn lapic_read(offset: u32) -> u32 {
 /// # Safety
 /// 
 /// Requires valid LAPIC base address
-unsafe fn lapic_write(offset: u32, value: u32) {
+pub(crate) unsafe fn lapic_write(offset: u32, value: u32) {
     let base = SMP_STATE.lapic_base.load(Ordering::Relaxed);
     let addr = (base + u64::from(offset)) as *mut u32;
     // SAFETY: Caller ensures LAPIC is mapped and offset is valid
@@ -361,6 +369,9 @@ pub unsafe extern "C" fn ap_entry(cpu_id: u32) {
     // Initialize Per-CPU data for this AP
     // TODO: per_cpu::init_ap(cpu_id);
     
+    // Enable and calibrate this CPU's local APIC timer
+    super::apic::init_local();
+    
     // Set up GDT for this AP
     // TODO: Load AP-specific GDT
     
@@ -125,8 +125,9 @@ pub fn init_with_mode(mode: SyscallMode) {
         LStar::write(VirtAddr::new(entry_point));
         
         // Set up SFMASK register (RFLAGS bits to clear on syscall)
-        // We clear the interrupt flag to disable interrupts during syscall handling
-        SFMask::write(RFlags::INTERRUPT_FLAG);
+        // We clear the interrupt flag to disable interrupts during syscall handling,
+        // and AC so that user mode cannot open a SMAP window for the kernel
+        SFMask::write(RFlags::INTERRUPT_FLAG | RFlags::ALIGNMENT_CHECK);
         
         // Initialize Per-CPU data (Phase 3: swapgs-based)
         // This sets up IA32_KERNEL_GS_BASE for the boot CPU
@@ -504,20 +505,32 @@ extern "C" fn syscall_handler(
     unsafe {
         super::per_cpu::current().inc_syscall_count();
     }
+    crate::kernel::process::accounting::enter_kernel();
+    crate::kernel::process::accounting::count_syscall();
     
-    // Trace syscall entry in debug builds
-    debug_println!(
-        "[SYSCALL-ENTRY] num={}, args=({:#x}, {:#x}, {:#x}, {:#x}, {:#x}, {:#x})",
+    // Recorded into the kernel log only when the level is raised to trace
+    log::trace!(
+        target: "syscall",
+        "entry num={}, args=({:#x}, {:#x}, {:#x}, {:#x}, {:#x}, {:#x})",
         syscall_num, arg1, arg2, arg3, arg4, arg5, arg6
     );
     
+    #[cfg(feature = "syscall_trace")]
+    let start = super::read_timestamp();
+
     // Call the syscall dispatcher
     let result = crate::kernel::syscall::dispatch(
         syscall_num, arg1, arg2, arg3, arg4, arg5, arg6
     );
+
+    #[cfg(feature = "syscall_trace")]
+    crate::kernel::process::trace::record_syscall(
+        syscall_num, [arg1, arg2, arg3, arg4, arg5, arg6], result, start
+    );
     
-    // Trace syscall return
-    debug_println!("[SYSCALL-RESULT] num={syscall_num} returned {result}");
+    log::trace!(target: "syscall", "result num={syscall_num} returned {result}");
+
+    crate::kernel::process::accounting::return_to_user();
     
     // Convert i64 result to u64 for return
     result as u64
@@ -560,31 +573,18 @@ pub mod validation {
     /// 
     /// Returns `None` if:
     /// - Address is invalid (not in user space)
-    /// - Address is not readable (page fault would occur)
     /// - Page is not mapped or lacks read permission
     /// 
-    /// # Safety
-    /// 
-    /// This is still unsafe because:
-    /// - TOCTOU issues (page could be unmapped after check)
+    /// Faults are recovered through the user-copy exception table, so a
+    /// concurrent `munmap` cannot crash the kernel.
     /// 
-    /// # Phase 2 Implementation
+    /// # Safety
     /// 
-    /// This now properly checks if pages are mapped and readable.
+    /// Every bit pattern must be a valid `T`.
     #[must_use]
     #[allow(dead_code)]
-    #[allow(clippy::missing_const_for_fn)] // Unsafe fn cannot be const
     pub unsafe fn copy_from_user<T: Copy>(user_ptr: u64) -> Option<T> {
-        use crate::kernel::security::validate_user_read;
-        
-        // Validate that the pointer is in user space and the page is mapped/readable
-        if validate_user_read(user_ptr, core::mem::size_of::<T>() as u64).is_err() {
-            return None;
-        }
-        
-        // Safe to read from user space (validated above)
-        #[allow(clippy::cast_ptr_alignment)] // Caller ensures alignment
-        Some(*(user_ptr as *const T))
+        crate::kernel::security::read_user(user_ptr).ok()
     }
 }
 
@@ -32,8 +32,12 @@ use core::sync::atomic::{AtomicU32, AtomicU64, AtomicBool, AtomicUsize, Ordering
 use alloc::vec::Vec;
 use alloc::boxed::Box;
 use spin::Mutex;
+use x86_64::structures::paging::OffsetPageTable;
 use x86_64::VirtAddr;
 use crate::debug_println;
+use crate::kernel::mm::allocator::BOOT_INFO_ALLOCATOR;
+use crate::kernel::mm::mmap::collapse_huge_range;
+use crate::kernel::mm::vma::VmaTree;
 
 // =============================================================================
 // Constants
@@ -378,16 +382,37 @@ impl RegisteredBuffer {
         Ok(())
     }
     
-    /// Get a kernel pointer for the given offset
-    #[inline(always)]
-    pub unsafe fn get_ptr(&self, offset: u64) -> *const u8 {
-        (self.kernel_addr + offset) as *const u8
+    /// Copy `dst.len()` bytes at `offset` into `dst`
+    ///
+    /// The range must have passed [`Self::validate_access`]. The buffer is
+    /// user memory, so it is read through the user-copy routines.
+    pub fn read_at(&self, offset: u64, dst: &mut [u8]) -> Result<(), i64> {
+        crate::kernel::security::copy_from_user(dst, self.user_addr + offset)
     }
     
-    /// Get a mutable kernel pointer for the given offset
-    #[inline(always)]
-    pub unsafe fn get_ptr_mut(&self, offset: u64) -> *mut u8 {
-        (self.kernel_addr + offset) as *mut u8
+    /// Write `len` bytes at `offset` to the serial port
+    ///
+    /// Returns the number of bytes written, or `-EFAULT` if the buffer
+    /// could not be read.
+    fn write_to_serial(&self, offset: u64, len: u32) -> i64 {
+        use crate::kernel::core::traits::CharDevice;
+        use crate::kernel::driver::serial::SERIAL1;
+        
+        let mut chunk = [0u8; 256];
+        let mut done = 0;
+        while done < len as usize {
+            let n = (len as usize - done).min(chunk.len());
+            if let Err(e) = self.read_at(offset + done as u64, &mut chunk[..n]) {
+                return e;
+            }
+            if let Some(mut serial) = SERIAL1.try_lock() {
+                for &byte in &chunk[..n] {
+                    let _ = serial.write_byte(byte);
+                }
+            }
+            done += n;
+        }
+        len as i64
     }
 }
 
@@ -410,12 +435,17 @@ impl BufferRegistry {
     
     /// Register a new buffer
     ///
+    /// `space` is the address space (memory areas and mapper) of the ring's
+    /// process, locked by the caller. Without it the buffer stays on 4 KiB
+    /// pages.
+    ///
     /// Returns the buffer index on success.
     pub fn register(
         &mut self,
         user_addr: u64,
         size: u64,
         permissions: BufferPermissions,
+        space: Option<(&VmaTree, &mut OffsetPageTable<'_>)>,
     ) -> Result<u16, i64> {
         // Find a free slot
         for i in 0..MAX_REGISTERED_BUFFERS {
@@ -431,6 +461,14 @@ impl BufferRegistry {
                 if !super::syscall::validation::is_user_range(user_addr, size) {
                     return Err(-14); // EFAULT
                 }
+
+                // Huge pages for the aligned 2 MiB blocks of the buffer
+                if let Some((vmas, mapper)) = space {
+                    let mut allocator = BOOT_INFO_ALLOCATOR.lock();
+                    if let Some(frame_allocator) = allocator.as_mut() {
+                        collapse_huge_range(mapper, frame_allocator, vmas, user_addr, size);
+                    }
+                }
                 
                 self.buffers[idx] = RegisteredBuffer {
                     user_addr,
@@ -603,8 +641,11 @@ impl RingContext {
     
     /// Poll and process pending submissions
     ///
+    /// `space` is the locked address space of the ring's process, used when
+    /// buffers are registered (see [`BufferRegistry::register`]).
+    ///
     /// Returns the number of completions generated.
-    pub fn poll(&mut self) -> u32 {
+    pub fn poll(&mut self, mut space: Option<(&VmaTree, &mut OffsetPageTable<'_>)>) -> u32 {
         let mut completed = 0u32;
         
         loop {
@@ -620,7 +661,8 @@ impl RingContext {
             let sqe_copy = self.sq_entries[idx];  // Copy the entire entry
             
             // Process the entry (now we own a copy)
-            let result = self.process_sqe(&sqe_copy);
+            let space = space.as_mut().map(|(vmas, mapper)| (*vmas, &mut **mapper));
+            let result = self.process_sqe(&sqe_copy, space);
             
             // Write completion
             let cq_tail = self.cq_header.tail.load(Ordering::Acquire);
@@ -641,7 +683,7 @@ impl RingContext {
     }
     
     /// Process a single SQE
-    fn process_sqe(&mut self, sqe: &IdealSqe) -> i64 {
+    fn process_sqe(&mut self, sqe: &IdealSqe, space: Option<(&VmaTree, &mut OffsetPageTable<'_>)>) -> i64 {
         let opcode = RingOpcode::from(sqe.opcode);
         
         match opcode {
@@ -685,7 +727,7 @@ impl RingContext {
                     read: (sqe.flags & 1) != 0,
                     write: (sqe.flags & 2) != 0,
                 };
-                match self.buffers.register(sqe.arg1, sqe.len as u64, permissions) {
+                match self.buffers.register(sqe.arg1, sqe.len as u64, permissions, space) {
                     Ok(idx) => idx as i64,
                     Err(e) => e,
                 }
@@ -708,9 +750,6 @@ impl RingContext {
     
     /// Write using registered buffer
     fn do_write(&self, fd: u32, buf_index: u16, buf_offset: u32, len: u32) -> i64 {
-        use crate::kernel::core::traits::CharDevice;
-        use crate::kernel::driver::serial::SERIAL1;
-        
         // Get registered buffer
         let buf = match self.buffers.get(buf_index) {
             Some(b) => b,
@@ -725,16 +764,7 @@ impl RingContext {
         // No per-call validation needed! Buffer is pre-validated.
         if fd == 1 {
             // stdout -> serial
-            let ptr = unsafe { buf.get_ptr(buf_offset as u64) };
-            let slice = unsafe { core::slice::from_raw_parts(ptr, len as usize) };
-            
-            if let Some(mut serial) = SERIAL1.try_lock() {
-                for &byte in slice {
-                    let _ = serial.write_byte(byte);
-                }
-            }
-            
-            len as i64
+            buf.write_to_serial(buf_offset as u64, len)
         } else {
             -9 // EBADF
         }
@@ -748,9 +778,6 @@ impl RingContext {
     
     /// Console write using registered buffer
     fn do_console_write(&self, buf_index: u16, buf_offset: u32, len: u32) -> i64 {
-        use crate::kernel::core::traits::CharDevice;
-        use crate::kernel::driver::serial::SERIAL1;
-        
         let buf = match self.buffers.get(buf_index) {
             Some(b) => b,
             None => return -9,
@@ -760,16 +787,7 @@ impl RingContext {
             return -14;
         }
         
-        let ptr = unsafe { buf.get_ptr(buf_offset as u64) };
-        let slice = unsafe { core::slice::from_raw_parts(ptr, len as usize) };
-        
-        if let Some(mut serial) = SERIAL1.try_lock() {
-            for &byte in slice {
-                let _ = serial.write_byte(byte);
-            }
-        }
-        
-        len as i64
+        buf.write_to_serial(buf_offset as u64, len)
     }
     
     /// Get mapping info for userspace
@@ -882,32 +900,38 @@ pub unsafe extern "C" fn ideal_syscall_entry() {
 /// Returns the number of completions generated.
 #[unsafe(no_mangle)]
 extern "C" fn process_current_ring() -> u64 {
-    use crate::kernel::process::PROCESS_TABLE;
+    use crate::kernel::process::{accounting, PROCESS_TABLE};
     
     // Increment syscall counter
     unsafe {
         super::per_cpu::current().inc_syscall_count();
     }
+    accounting::enter_kernel();
+    accounting::count_syscall();
     
-    // Get current process's ring context
-    let mut table = PROCESS_TABLE.lock();
-    let process = match table.current_process_mut() {
-        Some(p) => p,
-        None => return (-3_i64) as u64, // ESRCH
+    let result = {
+        // Get current process's ring context
+        let mut table = PROCESS_TABLE.lock();
+        match table.current_process_mut() {
+            // Process the ring buffer
+            Some(process) if process.has_ring_context() => {
+                // SAFETY: PROCESS_TABLE serializes page table updates of the current process
+                let mut mapper = unsafe { crate::kernel::mm::mmap::current_mapper() };
+                let completed = process.ring_poll(&mut mapper);
+                process.usage().add_io_uring_sqes(u64::from(completed));
+                u64::from(completed)
+            }
+            Some(_) => {
+                // Ring context not initialized - return error
+                // The user should call ring_setup syscall first
+                (-38_i64) as u64 // ENOSYS - function not implemented
+            }
+            None => (-3_i64) as u64, // ESRCH
+        }
     };
     
-    // Process the ring buffer
-    match process.ring_context_mut() {
-        Some(ctx) => {
-            let completed = ctx.poll();
-            completed as u64
-        }
-        None => {
-            // Ring context not initialized - return error
-            // The user should call ring_setup syscall first
-            (-38_i64) as u64 // ENOSYS - function not implemented
-        }
-    }
+    accounting::return_to_user();
+    result
 }
 
 // =============================================================================
@@ -949,7 +973,9 @@ pub fn kernel_poll_all() -> u64 {
     for ctx_ptr in contexts.iter() {
         // SAFETY: Contexts are registered by kernel and remain valid
         let ctx = unsafe { &mut *ctx_ptr.0 };
-        total += ctx.poll() as u64;
+        // The poller does not run in the owner's address space, so
+        // buffers registered here stay on 4 KiB pages
+        total += ctx.poll(None) as u64;
     }
     
     total
@@ -1002,14 +1028,15 @@ pub unsafe fn cleanup_ring(ctx: Box<RingContext>) {
 /// Map the RingContext into user space
 ///
 /// This function maps the kernel's RingContext structure into the user's
-/// address space at USER_RING_CONTEXT_BASE, allowing direct access to
-/// the submission and completion queues.
+/// address space at `user_base`, allowing direct access to the submission
+/// and completion queues.
 ///
 /// # Arguments
 /// * `ctx` - The RingContext to map
+/// * `user_base` - Page-aligned user address of the mapping (the
+///   process's `UserLayout::ring_base`)
 /// * `user_mapper` - The user's page table mapper
 /// * `frame_allocator` - Frame allocator for intermediate page tables
-/// * `phys_offset` - Physical memory offset
 ///
 /// # Returns
 /// * Ok(user_address) - The user-space address where the context is mapped
@@ -1019,31 +1046,33 @@ pub unsafe fn cleanup_ring(ctx: Box<RingContext>) {
 /// The caller must ensure the page table mapper is valid.
 pub unsafe fn map_ring_to_user(
     ctx: &RingContext,
+    user_base: u64,
     user_mapper: &mut x86_64::structures::paging::OffsetPageTable,
     frame_allocator: &mut crate::kernel::mm::BootInfoFrameAllocator,
-    phys_offset: x86_64::VirtAddr,
 ) -> Result<u64, i64> {
-    use x86_64::structures::paging::{Page, PageTableFlags, Mapper, Size4KiB};
-    use crate::kernel::mm::user_paging::USER_RING_CONTEXT_BASE;
+    use x86_64::structures::paging::{Page, PageTableFlags, Mapper, Size4KiB, Translate};
     
-    // Calculate the physical address of the RingContext
-    // Since RingContext is in kernel heap, we use direct map formula
+    // The RingContext lives on the kernel heap, which is not part of the
+    // direct map; its frames are found through the page tables instead.
     let ctx_addr = ctx as *const RingContext as u64;
     let ctx_size = core::mem::size_of::<RingContext>();
     let num_pages = (ctx_size + 4095) / 4096;
     
     debug_println!(
         "[map_ring_to_user] Mapping RingContext at kernel {:#x} ({} bytes, {} pages) to user {:#x}",
-        ctx_addr, ctx_size, num_pages, USER_RING_CONTEXT_BASE
+        ctx_addr, ctx_size, num_pages, user_base
     );
     
     for i in 0..num_pages {
         let kernel_addr = ctx_addr + (i * 4096) as u64;
-        let user_addr = USER_RING_CONTEXT_BASE + (i * 4096) as u64;
+        let user_addr = user_base + (i * 4096) as u64;
         
-        // Calculate physical address from kernel virtual address
-        // Kernel heap uses direct physical map: virt = phys + phys_offset
-        let phys_addr = kernel_addr - phys_offset.as_u64();
+        // The heap region is shared by every address space, so the user
+        // mapper can translate it as well as the kernel's own tables
+        let Some(phys) = user_mapper.translate_addr(x86_64::VirtAddr::new(kernel_addr)) else {
+            return Err(-14); // EFAULT
+        };
+        let phys_addr = phys.as_u64();
         let phys_frame = x86_64::structures::paging::PhysFrame::<Size4KiB>::containing_address(
             x86_64::PhysAddr::new(phys_addr)
         );
@@ -1060,7 +1089,6 @@ pub unsafe fn map_ring_to_user(
         
         // Check if already mapped
         use x86_64::structures::paging::mapper::TranslateResult;
-        use x86_64::structures::paging::Translate;
         if let TranslateResult::Mapped { .. } = user_mapper.translate(x86_64::VirtAddr::new(user_addr)) {
             debug_println!(
                 "[map_ring_to_user] User address {:#x} already mapped, skipping",
@@ -1088,7 +1116,7 @@ pub unsafe fn map_ring_to_user(
     }
     
     debug_println!("[map_ring_to_user] Successfully mapped RingContext to user space");
-    Ok(USER_RING_CONTEXT_BASE)
+    Ok(user_base)
 }
 
 // =============================================================================
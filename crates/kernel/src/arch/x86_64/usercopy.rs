@@ -0,0 +1,183 @@
+// kernel/src/arch/x86_64/usercopy.rs
+//! ユーザー空間とのコピーと例外テーブル
+//!
+//! カーネルがユーザーのバッファを読み書きするときは、ここにあるアセンブリの
+//! ルーチンだけを使います。ユーザーのメモリに触れる命令は例外テーブルに
+//! 修正先と組で登録してあり、その命令のページフォルトをデマンドページングで
+//! 解決できなかった場合、ページフォルトハンドラは [`search_exception_table`]
+//! で修正先を引いて RIP を書き換えてから復帰します。修正先はコピーできなかった
+//! ことを戻り値で知らせて終わるので、他のスレッドがコピー中に munmap しても
+//! カーネルはパニックしません。
+//!
+//! SMAP が有効な場合、ユーザーのメモリに触れる間だけ `stac` で RFLAGS.AC を
+//! 立て、終わったら（フォルトした場合も）`clac` で戻します。カーネルの中で
+//! `stac` を使うのはここだけです（[`super::protection`]）。
+//!
+//! アドレスがユーザー空間にあるかどうかは呼び出し側
+//! （[`crate::kernel::security::usercopy`]）で確かめます。
+
+use core::arch::global_asm;
+
+/// 例外テーブルのエントリ
+#[repr(C)]
+struct ExceptionEntry {
+    /// フォルトを起こしうる命令のアドレス
+    insn: u64,
+    /// フォルト時に再開するアドレス
+    fixup: u64,
+}
+
+// System V の呼び出し規約に従うルーチン。DF は ABI によりクリアされている。
+// `stac`/`clac` は SMAP のない CPU では #UD になるので、有効なときだけ実行する
+global_asm!(
+    ".pushsection .text.usercopy, \"ax\"",
+    // rdi: コピー先, rsi: コピー元, rdx: バイト数
+    // 戻り値: コピーできなかったバイト数
+    ".global __usercopy_copy",
+    "__usercopy_copy:",
+    "    mov rcx, rdx",
+    "    cmp byte ptr [rip + {smap}], 0",
+    "    je .Lcopy_access",
+    "    stac",
+    ".Lcopy_access:",
+    "    rep movsb",
+    "    xor eax, eax",
+    "    jmp .Lusercopy_return",
+    ".Lcopy_fixup:",
+    "    mov rax, rcx",
+    "    jmp .Lusercopy_return",
+    // rdi: コピー先, rsi: コピー元, rdx: 最大バイト数
+    // 戻り値: NUL を除いた長さ（NUL がなければ最大バイト数）、フォルト時は -1
+    ".global __usercopy_strncpy",
+    "__usercopy_strncpy:",
+    "    xor eax, eax",
+    "    cmp byte ptr [rip + {smap}], 0",
+    "    je .Lstrncpy_loop",
+    "    stac",
+    ".Lstrncpy_loop:",
+    "    cmp rax, rdx",
+    "    je .Lusercopy_return",
+    ".Lstrncpy_access:",
+    "    movzx ecx, byte ptr [rsi + rax]",
+    "    mov byte ptr [rdi + rax], cl",
+    "    test cl, cl",
+    "    jz .Lusercopy_return",
+    "    inc rax",
+    "    jmp .Lstrncpy_loop",
+    ".Lstrncpy_fixup:",
+    "    mov rax, -1",
+    // 共通の出口: AC を下ろして戻る
+    ".Lusercopy_return:",
+    "    cmp byte ptr [rip + {smap}], 0",
+    "    je .Lusercopy_ret",
+    "    clac",
+    ".Lusercopy_ret:",
+    "    ret",
+    ".popsection",
+    ".pushsection .rodata.usercopy_ex_table, \"a\"",
+    ".balign 8",
+    ".global __usercopy_ex_table_start",
+    "__usercopy_ex_table_start:",
+    "    .quad .Lcopy_access, .Lcopy_fixup",
+    "    .quad .Lstrncpy_access, .Lstrncpy_fixup",
+    ".global __usercopy_ex_table_end",
+    "__usercopy_ex_table_end:",
+    ".popsection",
+    smap = sym super::protection::SMAP_ENABLED,
+);
+
+unsafe extern "C" {
+    fn __usercopy_copy(dst: *mut u8, src: *const u8, len: usize) -> usize;
+    fn __usercopy_strncpy(dst: *mut u8, src: *const u8, max: usize) -> isize;
+    static __usercopy_ex_table_start: ExceptionEntry;
+    static __usercopy_ex_table_end: ExceptionEntry;
+}
+
+/// 例外テーブル
+fn exception_table() -> &'static [ExceptionEntry] {
+    // SAFETY: 開始と終了のラベルは同じセクションで連続したエントリを囲んでいる
+    unsafe {
+        let start = &raw const __usercopy_ex_table_start;
+        let end = &raw const __usercopy_ex_table_end;
+        core::slice::from_raw_parts(start, end.offset_from(start) as usize)
+    }
+}
+
+/// `ip` で起きたフォルトの修正先を探す
+///
+/// 例外テーブルに登録された命令でなければ `None` を返します。
+pub fn search_exception_table(ip: u64) -> Option<u64> {
+    exception_table()
+        .iter()
+        .find(|entry| entry.insn == ip)
+        .map(|entry| entry.fixup)
+}
+
+/// `src` から `dst` へ `len` バイトをコピー
+///
+/// コピーできなかったバイト数を返します（成功なら 0）。フォルトした場合、
+/// それより前のバイトはコピー済みです。
+///
+/// # Safety
+/// `dst` と `src` のうちカーネル側のバッファは `len` バイト有効であること。
+/// ユーザー側のアドレスはユーザー空間内であること
+pub unsafe fn copy_user(dst: *mut u8, src: *const u8, len: usize) -> usize {
+    // SAFETY: 呼び出し側が保証し、ユーザー側のフォルトは例外テーブルで回復する
+    unsafe { __usercopy_copy(dst, src, len) }
+}
+
+/// NUL 終端の文字列を最大 `max` バイトまでコピー
+///
+/// NUL を含めてコピーし、NUL を除いた長さを返します。`max` バイト以内に
+/// NUL がなければ `max` を返します。フォルトした場合は `None` です。
+///
+/// # Safety
+/// `dst` は `max` バイト書き込めること。`src` から `max` バイトは
+/// ユーザー空間内であること
+pub unsafe fn strncpy_user(dst: *mut u8, src: *const u8, max: usize) -> Option<usize> {
+    // SAFETY: 呼び出し側が保証し、ユーザー側のフォルトは例外テーブルで回復する
+    let len = unsafe { __usercopy_strncpy(dst, src, max) };
+    usize::try_from(len).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// どの VMA にも属さないアドレス（NULL ページ）
+    const UNMAPPED: *const u8 = 0x10 as *const u8;
+
+    #[test_case]
+    fn test_exception_table_entries() {
+        let table = exception_table();
+        assert_eq!(table.len(), 2);
+        for entry in table {
+            assert_eq!(search_exception_table(entry.insn), Some(entry.fixup));
+        }
+        assert_eq!(search_exception_table(__usercopy_copy as usize as u64), None);
+    }
+
+    #[test_case]
+    fn test_copy_recovers_from_fault() {
+        let src = [1u8, 2, 3, 4];
+        let mut dst = [0u8; 4];
+        // SAFETY: どちらもカーネルのバッファ
+        assert_eq!(unsafe { copy_user(dst.as_mut_ptr(), src.as_ptr(), 4) }, 0);
+        assert_eq!(dst, src);
+
+        // SAFETY: フォルトは例外テーブルで回復する
+        assert_eq!(unsafe { copy_user(dst.as_mut_ptr(), UNMAPPED, 4) }, 4);
+    }
+
+    #[test_case]
+    fn test_strncpy_stops_at_nul() {
+        let mut dst = [0xffu8; 8];
+        // SAFETY: どちらもカーネルのバッファ
+        assert_eq!(unsafe { strncpy_user(dst.as_mut_ptr(), b"abc\0def".as_ptr(), 8) }, Some(3));
+        assert_eq!(&dst[..4], b"abc\0");
+        // SAFETY: 同上
+        assert_eq!(unsafe { strncpy_user(dst.as_mut_ptr(), b"abcdef".as_ptr(), 4) }, Some(4));
+        // SAFETY: フォルトは例外テーブルで回復する
+        assert_eq!(unsafe { strncpy_user(dst.as_mut_ptr(), UNMAPPED, 8) }, None);
+    }
+}
@@ -287,8 +287,8 @@ fn submit_op_to_ring(op: &IoUringOp, user_data: u64) -> i32 {
     use crate::kernel::process::PROCESS_TABLE;
     
     // Get the current process's capability table
-    let table = PROCESS_TABLE.lock();
-    let process = match table.current_process() {
+    let mut table = PROCESS_TABLE.lock();
+    let process = match table.current_process_mut() {
         Some(p) => p,
         None => {
             // No current process, return error
@@ -296,13 +296,12 @@ fn submit_op_to_ring(op: &IoUringOp, user_data: u64) -> i32 {
             return -3;
         }
     };
-    let cap_table = process.capability_table();
 
     // Handle V2 operations
     match op {
         IoUringOp::ReadV2 { capability_id, buf, len, offset } => {
             let sqe = SubmissionEntryV2::read_raw(*capability_id, *buf as u64, *len, *offset, user_data);
-            let cqe = dispatch_sqe_v2(&sqe, cap_table, None, true); // Allow raw addr for kernel
+            let cqe = dispatch_sqe_v2(&sqe, process, None, true); // Allow raw addr for kernel
             let result = match cqe.into_result() {
                 Ok(val) => val,
                 Err(e) => -(e as i32),
@@ -312,7 +311,7 @@ fn submit_op_to_ring(op: &IoUringOp, user_data: u64) -> i32 {
         }
         IoUringOp::WriteV2 { capability_id, buf, len, offset } => {
             let sqe = SubmissionEntryV2::write_raw(*capability_id, *buf as u64, *len, *offset, user_data);
-            let cqe = dispatch_sqe_v2(&sqe, cap_table, None, true); // Allow raw addr for kernel
+            let cqe = dispatch_sqe_v2(&sqe, process, None, true); // Allow raw addr for kernel
             let result = match cqe.into_result() {
                 Ok(val) => val,
                 Err(e) => -(e as i32),
@@ -322,7 +321,7 @@ fn submit_op_to_ring(op: &IoUringOp, user_data: u64) -> i32 {
         }
         IoUringOp::CloseV2 { capability_id } => {
             let sqe = SubmissionEntryV2::close(*capability_id, user_data);
-            let cqe = dispatch_sqe_v2(&sqe, cap_table, None, true);
+            let cqe = dispatch_sqe_v2(&sqe, process, None, true);
             let result = match cqe.into_result() {
                 Ok(val) => val,
                 Err(e) => -(e as i32),
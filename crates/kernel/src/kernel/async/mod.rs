@@ -51,7 +51,10 @@ pub use executor::{
     runtime_task_count,
 };
 pub use waker::{dummy_waker, WakerBuilder};
-pub use timer::{Timer, Yield, yield_now, TICKS, tick, timer_task, current_ticks, ticks_to_ms, ms_to_ticks};
+pub use timer::{
+    Timer, Yield, yield_now, TICKS, TICK_MS, tick, tick_n, next_deadline, timer_task,
+    current_ticks, ticks_to_ms, ms_to_ticks,
+};
 pub use io_uring_future::{
     IoUringOp, IoUringFuture,
     submit_async, write_async, read_async, close_async,
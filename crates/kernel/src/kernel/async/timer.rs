@@ -2,7 +2,7 @@
 //! 非同期タイマー
 //!
 //! 指定時間後に完了する Future。
-//! ハードウェア割り込み（PIT）と連携して動作します。
+//! ハードウェアタイマー割り込み（LAPIC タイマー、または PIT）と連携して動作します。
 //!
 //! # デッドロック回避設計
 //!
@@ -29,11 +29,20 @@ use core::sync::atomic::{AtomicU64, AtomicBool, Ordering};
 use alloc::vec::Vec;
 use spin::Mutex;
 
+/// 1 ティックの長さ (ミリ秒)
+pub const TICK_MS: u64 = 10;
+
 /// グローバルティックカウンタ (10ms 単位)
 /// 
 /// 割り込みハンドラからアトミックに更新される。
 pub static TICKS: AtomicU64 = AtomicU64::new(0);
 
+/// 最も近いタイマー期限 (ティック)。`u64::MAX` は期限なしを表す。
+///
+/// tickless idle が次の割り込みまでの時間を決めるために参照する。
+/// ロックフリーなので割り込みハンドラやアイドルループから読み取ってよい。
+static NEXT_DEADLINE: AtomicU64 = AtomicU64::new(u64::MAX);
+
 /// タイマー待機中の Waker リスト
 /// (deadline, waker) のペア
 /// 
@@ -61,6 +70,26 @@ pub fn tick() {
     // ここで WAKERS をロックしてはいけない！
 }
 
+/// 複数ティックをまとめて進める（ロックフリー）
+///
+/// tickless idle から復帰したときに、眠っていた間のティックを
+/// 一括で反映するために使用する。
+#[inline]
+pub fn tick_n(ticks: u64) {
+    TICKS.fetch_add(ticks, Ordering::Relaxed);
+}
+
+/// 最も近いタイマー期限を取得
+///
+/// 待機中のタイマーがなければ `None`。
+#[inline]
+pub fn next_deadline() -> Option<u64> {
+    match NEXT_DEADLINE.load(Ordering::Relaxed) {
+        u64::MAX => None,
+        deadline => Some(deadline),
+    }
+}
+
 /// タイマー管理タスク
 ///
 /// システム起動時に spawn され、期限切れタイマーの Waker を起こす。
@@ -103,6 +132,10 @@ pub async fn timer_task() {
             if wakers.is_empty() {
                 HAS_PENDING_TIMERS.store(false, Ordering::Relaxed);
             }
+
+            // 残っているタイマーから次の期限を再計算
+            let next = wakers.iter().map(|(deadline, _)| *deadline).min().unwrap_or(u64::MAX);
+            NEXT_DEADLINE.store(next, Ordering::Relaxed);
             
             drop(wakers); // 早期にロック解放
             
@@ -153,9 +186,7 @@ impl Future for Timer {
         
         // 初回ポーリング時に期限を設定
         if self.deadline.is_none() {
-            // 1 tick = 10ms (100Hz)
-            let ticks_needed = (self.duration_ms + 9) / 10; // 切り上げ
-            self.deadline = Some(current_ticks + ticks_needed);
+            self.deadline = Some(current_ticks + ms_to_ticks(self.duration_ms));
         }
 
         let deadline = self.deadline.unwrap();
@@ -170,6 +201,7 @@ impl Future for Timer {
             
             // ペンディングタイマーがあることをマーク
             HAS_PENDING_TIMERS.store(true, Ordering::Relaxed);
+            NEXT_DEADLINE.fetch_min(deadline, Ordering::Relaxed);
             
             Poll::Pending
         }
@@ -232,11 +264,11 @@ pub fn current_ticks() -> u64 {
 /// ティックをミリ秒に変換
 #[inline]
 pub const fn ticks_to_ms(ticks: u64) -> u64 {
-    ticks * 10 // 1 tick = 10ms
+    ticks * TICK_MS
 }
 
 /// ミリ秒をティックに変換
 #[inline]
 pub const fn ms_to_ticks(ms: u64) -> u64 {
-    (ms + 9) / 10 // 切り上げ
+    ms.div_ceil(TICK_MS)
 }
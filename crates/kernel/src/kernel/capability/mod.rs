@@ -149,6 +149,14 @@ impl Rights {
     /// Right to execute (for memory mappings)
     pub const EXEC: Self = Self(1 << 24);
 
+    // === System Rights ===
+
+    /// Right to reboot or power off the machine
+    pub const POWER: Self = Self(1 << 25);
+
+    /// Right to change the kernel log levels
+    pub const SYSLOG: Self = Self(1 << 26);
+
     // === Presets ===
 
     /// Read-only access
@@ -347,12 +355,28 @@ impl ResourceKind for EventResource {
 }
 
 /// Shared memory resource
+///
+/// Backed by a [`crate::kernel::ipc::shmem::ShmemObject`]. The creator may
+/// map, duplicate and hand it to other processes.
 pub struct ShmemResource;
 
 impl ResourceKind for ShmemResource {
     const TYPE_ID: u32 = 7;
     const NAME: &'static str = "shmem";
-    const DEFAULT_RIGHTS: Rights = Rights(Rights::READ.0 | Rights::WRITE.0 | Rights::MAP.0);
+    const DEFAULT_RIGHTS: Rights = Rights(
+        Rights::READ.0 | Rights::WRITE.0 | Rights::MAP.0 | Rights::DUP.0 | Rights::TRANSFER.0,
+    );
+}
+
+/// System control resource (reboot, power-off, kernel log levels)
+///
+/// Carries no kernel object; holding it with the right bits is the privilege.
+pub struct SystemResource;
+
+impl ResourceKind for SystemResource {
+    const TYPE_ID: u32 = 8;
+    const NAME: &'static str = "system";
+    const DEFAULT_RIGHTS: Rights = Rights(Rights::POWER.0 | Rights::SYSLOG.0);
 }
 
 /// Type-safe capability handle
@@ -491,6 +515,8 @@ pub type SocketHandle = Handle<SocketResource>;
 pub type PipeHandle = Handle<PipeResource>;
 pub type BufferHandle = Handle<BufferResource>;
 pub type DirectoryHandle = Handle<DirectoryResource>;
+/// Handle to a shared memory object
+pub type ShmemHandle = Handle<ShmemResource>;
 
 /// Global generation counter for capability IDs
 ///
@@ -556,5 +582,18 @@ mod tests {
         assert_eq!(PipeResource::TYPE_ID, 3);
         assert_eq!(BufferResource::TYPE_ID, 4);
         assert_eq!(DirectoryResource::TYPE_ID, 5);
+        assert_eq!(ShmemResource::TYPE_ID, 7);
+    }
+
+    #[test]
+    fn test_rights_match_abi() {
+        use crate::abi::rights::*;
+
+        assert_eq!(Rights::READ.bits(), RIGHT_READ);
+        assert_eq!(Rights::WRITE.bits(), RIGHT_WRITE);
+        assert_eq!(Rights::MAP.bits(), RIGHT_MAP);
+        assert_eq!(Rights::DUP.bits(), RIGHT_DUP);
+        assert_eq!(Rights::TRANSFER.bits(), RIGHT_TRANSFER);
+        assert_eq!(Rights::EXEC.bits(), RIGHT_EXEC);
     }
 }
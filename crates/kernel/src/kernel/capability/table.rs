@@ -350,9 +350,27 @@ impl CapabilityTable {
     ///
     /// Returns the entry if valid, or an error if the handle is invalid.
     pub fn get<R: ResourceKind>(&self, handle: &Handle<R>) -> Result<&CapabilityEntry, SyscallError> {
-        let index = handle.index() as usize;
-        let generation = handle.generation();
+        let entry = self.lookup(handle.index(), handle.generation())?;
 
+        // Verify type
+        if entry.type_id != R::TYPE_ID {
+            return Err(SyscallError::WrongCapabilityType);
+        }
+
+        Ok(entry)
+    }
+
+    /// Get a capability entry by raw handle value, whatever its type
+    ///
+    /// Used by syscalls that accept several resource types and dispatch on
+    /// [`CapabilityEntry::type_id`].
+    pub fn get_raw(&self, raw: u64) -> Result<&CapabilityEntry, SyscallError> {
+        self.lookup(raw as u32, (raw >> 32) as u32)
+    }
+
+    /// Find the entry in slot `index` if its generation matches
+    fn lookup(&self, index: u32, generation: u32) -> Result<&CapabilityEntry, SyscallError> {
+        let index = index as usize;
         let slots = self.slots.read();
 
         if index >= slots.len() {
@@ -368,11 +386,6 @@ impl CapabilityTable {
             return Err(SyscallError::CapabilityRevoked);
         }
 
-        // Verify type
-        if entry.type_id != R::TYPE_ID {
-            return Err(SyscallError::WrongCapabilityType);
-        }
-
         // Safety: The entry is valid and won't be modified while we hold the read lock
         // We return a reference with lifetime tied to self, which is safe because
         // the table outlives any operation using it.
@@ -401,9 +414,27 @@ impl CapabilityTable {
         &self,
         handle: Handle<R>,
     ) -> Result<Box<CapabilityEntry>, SyscallError> {
-        let index = handle.index() as usize;
-        let generation = handle.generation();
+        let entry = self.take(handle.index(), handle.generation(), Some(R::TYPE_ID))?;
+
+        // Forget the handle to prevent double-drop
+        core::mem::forget(handle);
+
+        Ok(entry)
+    }
 
+    /// Remove a capability by raw handle value, whatever its type
+    pub fn remove_raw(&self, raw: u64) -> Result<Box<CapabilityEntry>, SyscallError> {
+        self.take(raw as u32, (raw >> 32) as u32, None)
+    }
+
+    /// Empty slot `index` if its generation (and type, if given) match
+    fn take(
+        &self,
+        index: u32,
+        generation: u32,
+        type_id: Option<u32>,
+    ) -> Result<Box<CapabilityEntry>, SyscallError> {
+        let index = index as usize;
         let mut slots = self.slots.write();
 
         if index >= slots.len() {
@@ -420,7 +451,7 @@ impl CapabilityTable {
                 return Err(SyscallError::CapabilityRevoked);
             }
 
-            if entry.type_id != R::TYPE_ID {
+            if type_id.is_some_and(|id| entry.type_id != id) {
                 return Err(SyscallError::WrongCapabilityType);
             }
         }
@@ -435,9 +466,6 @@ impl CapabilityTable {
 
         self.count.fetch_sub(1, Ordering::Release);
 
-        // Forget the handle to prevent double-drop
-        core::mem::forget(handle);
-
         Ok(entry)
     }
 
@@ -682,4 +710,22 @@ mod tests {
         let result = table.get(&fake_handle);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_raw_access_ignores_type() {
+        let table = CapabilityTable::new();
+
+        let resource = Arc::new(TestResource { value: 42 });
+        let handle: Handle<FileResource> = table
+            .insert(resource, Rights::READ_WRITE)
+            .expect("insert failed");
+        let raw = handle.into_raw();
+
+        let entry = table.get_raw(raw).expect("get_raw failed");
+        assert_eq!(entry.type_id, FileResource::TYPE_ID);
+
+        table.remove_raw(raw).expect("remove_raw failed");
+        assert!(table.get_raw(raw).is_err());
+        assert_eq!(table.count(), 0);
+    }
 }
@@ -150,6 +150,23 @@ pub fn write_console(args: fmt::Arguments) {
     }
 }
 
+/// コンソールにベストエフォートで書き込む（パニック中も含む）
+///
+/// バックトレースなど、パニック時にも画面に残したい出力に使います。
+/// `try_lock()` のみを使うため、コンソールのロックを保持したまま
+/// パニックした場合は何も出力しません。
+pub fn write_console_best_effort(args: fmt::Arguments) {
+    use fmt::Write;
+
+    if PANIC_LEVEL.load(Ordering::Relaxed) > FIRST_PANIC {
+        return;
+    }
+    if let Some(mut guard) = CONSOLE.try_lock()
+        && let Some(ref mut console) = *guard {
+            let _ = console.write_fmt(args);
+        }
+}
+
 /// デバッグ出力（シリアルポート経由）
 /// 
 /// パニック時でもベストエフォートで出力を試みます。
@@ -53,6 +53,9 @@ impl Device for PS2Keyboard {
 
 use spin::Mutex;
 use alloc::collections::VecDeque;
+use crate::arch::x86_64::interrupts::KEYBOARD_VECTOR;
+use crate::arch::x86_64::irq::{self, IrqError, IrqReturn};
+use crate::arch::x86_64::gdbstub;
 use core::task::{Waker, Poll, Context};
 use core::pin::Pin;
 use core::future::Future;
@@ -125,6 +128,33 @@ pub static KEYBOARD: Mutex<PS2Keyboard> = Mutex::new(PS2Keyboard::new());
 /// グローバルスキャンコードキュー
 pub static SCANCODE_QUEUE: Mutex<ScancodeQueue> = Mutex::new(ScancodeQueue::new());
 
+/// キーボード割り込みハンドラ (IRQ1)
+fn keyboard_irq(_context: usize) -> IrqReturn {
+    // キーボードからスキャンコードを読み取る
+    let scancode = KEYBOARD.lock().read_scancode();
+
+    if let Some(scancode) = scancode {
+        // マジックキーで GDB スタブに入る（スタブ無効時は通常のキー入力）
+        if scancode == gdbstub::MAGIC_SCANCODE && gdbstub::is_enabled() {
+            gdbstub::breakpoint();
+            return IrqReturn::Handled;
+        }
+        // キューに追加（Waker もここで呼ばれる）
+        SCANCODE_QUEUE.lock().add_scancode(scancode);
+    }
+
+    IrqReturn::Handled
+}
+
+/// キーボード割り込みハンドラを登録
+///
+/// # Errors
+///
+/// ハンドラの登録に失敗した場合はエラーを返します。
+pub fn register_irq_handler() -> Result<(), IrqError> {
+    irq::register_irq(KEYBOARD_VECTOR, keyboard_irq, 0)
+}
+
 /// 次のスキャンコードを待つ Future
 /// 
 /// 1つのスキャンコードを非同期で待機します。
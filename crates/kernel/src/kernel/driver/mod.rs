@@ -10,6 +10,7 @@ pub mod framebuffer;
 
 pub use console::{
     ConsoleWriter, set_framebuffer_console, set_vga_console, write_console, write_debug,
+    write_console_best_effort,
     enter_panic, NORMAL, FIRST_PANIC, DOUBLE_PANIC, PanicLevel,
 };
 pub use serial::SerialPort;
@@ -17,6 +18,18 @@ pub use vga::VgaTextMode;
 pub use keyboard::PS2Keyboard;
 pub use pit::ProgrammableIntervalTimer;
 
-pub use serial::SERIAL1;
+pub use serial::{SERIAL1, SERIAL2};
 pub use vga::{init_vga, vga};
 pub use pit::PIT;
+
+/// レガシーデバイスの割り込みハンドラを登録
+///
+/// IDT 初期化後、割り込みを有効化する前に一度だけ呼び出します。
+pub fn register_irq_handlers() {
+    if let Err(e) = keyboard::register_irq_handler() {
+        crate::debug_println!("[IRQ] Failed to register keyboard handler: {:?}", e);
+    }
+    if let Err(e) = serial::register_irq_handler() {
+        crate::debug_println!("[IRQ] Failed to register COM1 handler: {:?}", e);
+    }
+}
@@ -12,13 +12,19 @@ const PIT_FREQUENCY: u32 = 1_193_182;
 
 /// チャンネル 0 データポート
 const CHANNEL0_DATA: u16 = 0x40;
+/// チャンネル 2 データポート
+const CHANNEL2_DATA: u16 = 0x42;
 /// コマンドポート
 const COMMAND_PORT: u16 = 0x43;
+/// NMI ステータス / スピーカ制御ポート（チャンネル 2 のゲートと出力）
+const SPEAKER_PORT: u16 = 0x61;
 
 /// Programmable Interval Timer
 pub struct ProgrammableIntervalTimer {
     channel0: Port<u8>,
+    channel2: Port<u8>,
     command: PortWriteOnly<u8>,
+    speaker: Port<u8>,
 }
 
 impl Default for ProgrammableIntervalTimer {
@@ -32,7 +38,9 @@ impl ProgrammableIntervalTimer {
     pub const fn new() -> Self {
         Self {
             channel0: Port::new(CHANNEL0_DATA),
+            channel2: Port::new(CHANNEL2_DATA),
             command: PortWriteOnly::new(COMMAND_PORT),
+            speaker: Port::new(SPEAKER_PORT),
         }
     }
 
@@ -58,6 +66,39 @@ impl ProgrammableIntervalTimer {
         
         Ok(())
     }
+
+    /// チャンネル 2 を使って指定ミリ秒だけビジーウェイト
+    ///
+    /// 割り込みを使わないため、LAPIC タイマーの較正など
+    /// 割り込み無効状態での時間計測に使用します。
+    /// チャンネル 2 の最大カウント（約 54ms）を超える値は切り詰められます。
+    pub fn busy_wait_ms(&mut self, ms: u32) {
+        let count = (PIT_FREQUENCY / 1000 * ms).min(0xFFFF);
+
+        // SAFETY: チャンネル 2 とポート 0x61 はスピーカ用で、他の用途には使っていない。
+        // ゲートを制御してモード 0 (interrupt on terminal count) でカウントし、
+        // OUT2 (ポート 0x61 の bit 5) がセットされるまで待つ標準的な手順。
+        unsafe {
+            // ゲート有効、スピーカ出力無効
+            let value = self.speaker.read();
+            self.speaker.write((value & !0x02) | 0x01);
+
+            // Channel 2, Access lo/hi, Mode 0, Binary
+            // 10 11 000 0 = 0xB0
+            self.command.write(0xB0);
+            self.channel2.write((count & 0xFF) as u8);
+            self.channel2.write((count >> 8) as u8);
+
+            // ゲートを一度落としてカウントを再開始
+            let value = self.speaker.read();
+            self.speaker.write(value & !0x01);
+            self.speaker.write(value | 0x01);
+
+            while self.speaker.read() & 0x20 == 0 {
+                core::hint::spin_loop();
+            }
+        }
+    }
 }
 
 impl Device for ProgrammableIntervalTimer {
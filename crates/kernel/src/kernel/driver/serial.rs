@@ -2,34 +2,79 @@
 //! Serial ポートドライバ (UART 16550)
 //!
 //! `CharDevice` trait に基づいた型安全な実装。
+//!
+//! - COM1 (`SERIAL1`): デバッグ出力
+//! - COM2 (`SERIAL2`): GDB リモートスタブ (`arch::x86_64::gdbstub`)
 
 use crate::kernel::core::{Device, CharDevice, KernelResult};
 use crate::kernel::core::result::DeviceError;
 use crate::arch::x86_64::port::{Port, PortReadOnly};
+use crate::arch::x86_64::interrupts::COM1_VECTOR;
+use crate::arch::x86_64::irq::{self, IrqError, IrqReturn};
 use spin::Mutex;
 
-/// Serial ポート (COM1)
+/// COM1 の I/O ベースアドレス
+const COM1_BASE: u16 = 0x3F8;
+/// COM2 の I/O ベースアドレス
+const COM2_BASE: u16 = 0x2F8;
+
+/// Serial ポート (UART 16550)
 pub struct SerialPort {
+    name: &'static str,
     data: Port<u8>,
     int_enable: Port<u8>,
     fifo_ctrl: Port<u8>,
     line_ctrl: Port<u8>,
     modem_ctrl: Port<u8>,
     line_status: PortReadOnly<u8>,
+    scratch: Port<u8>,
 }
 
 impl SerialPort {
+    /// 名前と I/O ベースアドレスを指定して作成
+    const fn new(name: &'static str, base: u16) -> Self {
+        Self {
+            name,
+            data: Port::new(base),
+            int_enable: Port::new(base + 1),
+            fifo_ctrl: Port::new(base + 2),
+            line_ctrl: Port::new(base + 3),
+            modem_ctrl: Port::new(base + 4),
+            line_status: PortReadOnly::new(base + 5),
+            scratch: Port::new(base + 7),
+        }
+    }
+
     /// COM1 を作成 (0x3F8)
     pub const fn com1() -> Self {
-        Self {
-            data: Port::new(0x3F8),
-            int_enable: Port::new(0x3F8 + 1),
-            fifo_ctrl: Port::new(0x3F8 + 2),
-            line_ctrl: Port::new(0x3F8 + 3),
-            modem_ctrl: Port::new(0x3F8 + 4),
-            line_status: PortReadOnly::new(0x3F8 + 5),
+        Self::new("COM1", COM1_BASE)
+    }
+
+    /// COM2 を作成 (0x2F8)
+    pub const fn com2() -> Self {
+        Self::new("COM2", COM2_BASE)
+    }
+
+    /// UART が存在するか確認
+    ///
+    /// スクラッチレジスタに書いた値が読み戻せるかで判定します。
+    /// デバイスがないポートは 0xFF を返します。
+    pub fn is_present(&mut self) -> bool {
+        // SAFETY: スクラッチレジスタ (base+7) は UART の動作に影響しない
+        unsafe {
+            self.scratch.write(0xAE);
+            self.scratch.read() == 0xAE
         }
     }
+
+    /// 受信データ割り込みを有効化
+    ///
+    /// `init` は割り込みを無効にするため、その後で呼び出します。
+    /// OUT2 (MCR ビット 3) は `init` で設定済みです。
+    pub fn enable_rx_interrupt(&mut self) {
+        // SAFETY: IER ビット 0 (受信データあり) の設定
+        unsafe { self.int_enable.write(0x01) };
+    }
     
     /// 送信バッファが空か確認
     fn is_tx_empty(&self) -> bool {
@@ -41,7 +86,7 @@ impl SerialPort {
 
 impl Device for SerialPort {
     fn name(&self) -> &'static str {
-        "COM1"
+        self.name
     }
     
     fn init(&mut self) -> KernelResult<()> {
@@ -113,3 +158,27 @@ impl fmt::Write for SerialPort {
 
 /// グローバル Serial ポート (const 初期化可能)
 pub static SERIAL1: Mutex<SerialPort> = Mutex::new(SerialPort::com1());
+
+/// GDB スタブ用の第 2 Serial ポート
+pub static SERIAL2: Mutex<SerialPort> = Mutex::new(SerialPort::com2());
+
+/// COM1 割り込みハンドラ (IRQ4)
+fn com1_irq(_context: usize) -> IrqReturn {
+    // 受信 FIFO を読み捨てて割り込み要因をクリアする
+    //
+    // SERIAL1 の書き込み側は割り込みを禁止せずにロックを持つため、ここでは
+    // ロックを取らずに LSR と RBR だけを読む。送信側が触るのは THR と LSR の
+    // 送信ビットだけなので、受信の読み出しとは干渉しない
+    let rx = SerialPort::com1();
+    while let Ok(Some(_)) = rx.read_byte() {}
+    IrqReturn::Handled
+}
+
+/// COM1 割り込みハンドラを登録
+///
+/// # Errors
+///
+/// ハンドラの登録に失敗した場合はエラーを返します。
+pub fn register_irq_handler() -> Result<(), IrqError> {
+    irq::register_irq(COM1_VECTOR, com1_irq, 0)
+}
@@ -1,10 +1,29 @@
 // kernel/src/kernel/fs/initrd.rs
-use super::FileSystem;
+//! Initial ramdisk (read-only CPIO newc archive)
+//!
+//! The archive stays where the bootloader loaded it. `mkcpio` pads file
+//! names so that file contents start on a page boundary, which lets
+//! `mmap` and the ELF loader map the ramdisk frames directly instead of
+//! copying them. Archives from other tools still work; their files are
+//! copied into fresh frames when first mapped.
+
+use super::{FileDescriptor, FileError, FileResult, FileSystem};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
 use core::str;
+use core::sync::atomic::Ordering;
+use x86_64::structures::paging::{PhysFrame, Size4KiB};
+
+use crate::kernel::ipc::shmem::ShmemObject;
+use crate::kernel::mm::allocator::BOOT_INFO_ALLOCATOR;
+use crate::kernel::mm::{paging, PHYS_MEM_OFFSET};
 
 /// CPIO Newc format magic
 const CPIO_MAGIC: &str = "070701";
 
+/// Page size of the mapped frames
+const PAGE_SIZE: u64 = 4096;
+
 /// Initrd filesystem (Read-only CPIO archive)
 pub struct InitrdFs {
     data: &'static [u8],
@@ -12,73 +31,240 @@ pub struct InitrdFs {
 
 impl InitrdFs {
     /// Create a new InitrdFs from a memory slice
-    /// 
+    ///
     /// # Safety
     /// The caller must ensure that the data slice is valid for the lifetime of the filesystem.
     pub unsafe fn new(data: &'static [u8]) -> Self {
         Self { data }
     }
-    
+
     fn parse_hex(s: &[u8]) -> Option<u32> {
         let s = str::from_utf8(s).ok()?;
         u32::from_str_radix(s, 16).ok()
     }
-}
 
-impl FileSystem for InitrdFs {
-    fn read_file(&self, path: &str) -> Option<&[u8]> {
+    /// Contents of the file at `path`, borrowed from the archive
+    fn lookup(&self, path: &str) -> Option<&'static [u8]> {
+        let data = self.data;
         let mut cursor = 0;
-        
+
         // Normalize path (remove leading /)
         let target_path = path.trim_start_matches('/');
-        
-        while cursor + 110 <= self.data.len() {
+
+        while cursor + 110 <= data.len() {
             // Check magic
-            if &self.data[cursor..cursor+6] != CPIO_MAGIC.as_bytes() {
+            if &data[cursor..cursor+6] != CPIO_MAGIC.as_bytes() {
                 break;
             }
-            
+
             // Parse header fields
             // Namesize is at offset 94, length 8
-            let namesize = Self::parse_hex(&self.data[cursor+94..cursor+102])? as usize;
+            let namesize = Self::parse_hex(&data[cursor+94..cursor+102])? as usize;
             // Filesize is at offset 54, length 8
-            let filesize = Self::parse_hex(&self.data[cursor+54..cursor+62])? as usize;
-            
+            let filesize = Self::parse_hex(&data[cursor+54..cursor+62])? as usize;
+
             // Header size is 110
             let header_end = cursor + 110;
-            
+
             // Read filename
-            if header_end + namesize > self.data.len() {
+            if namesize == 0 || header_end + namesize > data.len() {
                 break;
             }
-            
-            // namesize includes null terminator
-            let filename_bytes = &self.data[header_end..header_end+namesize-1]; 
+
+            // namesize includes the NUL terminator and any NUL padding
+            // that page-aligns the contents
+            let name_field = &data[header_end..header_end+namesize];
+            let filename_bytes = name_field.split(|&b| b == 0).next().unwrap_or_default();
             let filename = str::from_utf8(filename_bytes).ok()?;
-            
+
             // Calculate padding for header + name
             // The header + filename is padded to 4 byte boundary
             let total_header_size = 110 + namesize;
             let name_pad = (4 - (total_header_size % 4)) % 4;
             let content_start = total_header_size + name_pad + cursor;
-            
+
             if filename == "TRAILER!!!" {
                 break;
             }
-            
+
             if filename == target_path {
-                if content_start + filesize > self.data.len() {
+                if content_start + filesize > data.len() {
                     return None;
                 }
-                return Some(&self.data[content_start..content_start+filesize]);
+                return Some(&data[content_start..content_start+filesize]);
             }
-            
+
             // Skip to next file
             // File content is padded to 4 byte boundary
             let content_pad = (4 - (filesize % 4)) % 4;
             cursor = content_start + filesize + content_pad;
         }
-        
+
         None
     }
 }
+
+impl FileSystem for InitrdFs {
+    fn read_file(&self, path: &str) -> Option<&[u8]> {
+        self.lookup(path)
+    }
+
+    fn open(&self, path: &str) -> Option<Box<dyn FileDescriptor>> {
+        self.lookup(path).map(|data| Box::new(InitrdFile::new(data)) as Box<dyn FileDescriptor>)
+    }
+
+    fn file_frames(&self, path: &str) -> Option<Vec<PhysFrame<Size4KiB>>> {
+        ramdisk_frames(self.lookup(path)?)
+    }
+}
+
+/// Ramdisk frames holding `data` in page order
+///
+/// Returns `None` if `data` does not start on a page boundary or a page
+/// is not mapped.
+fn ramdisk_frames(data: &'static [u8]) -> Option<Vec<PhysFrame<Size4KiB>>> {
+    let start = data.as_ptr() as u64;
+    if start % PAGE_SIZE != 0 {
+        return None;
+    }
+    (0..(data.len() as u64).div_ceil(PAGE_SIZE))
+        .map(|i| paging::physical_address(start + i * PAGE_SIZE).map(PhysFrame::containing_address))
+        .collect()
+}
+
+/// An opened initrd file
+///
+/// Reads are served straight from the archive. For `mmap`, whole pages of
+/// page-aligned contents are the ramdisk frames themselves; the partial
+/// last page, and every page of unaligned contents, is copied once into
+/// frames owned by the file so that the tail past the end reads as zeroes.
+pub struct InitrdFile {
+    data: &'static [u8],
+    offset: usize,
+    /// Frames backing the file for `mmap`, built on first use
+    frames: Vec<PhysFrame<Size4KiB>>,
+    /// Copied pages among `frames`
+    copy: Option<ShmemObject>,
+}
+
+impl InitrdFile {
+    /// Open the archive contents `data`
+    #[must_use]
+    pub fn new(data: &'static [u8]) -> Self {
+        Self { data, offset: 0, frames: Vec::new(), copy: None }
+    }
+
+    /// Fill `frames` with the ramdisk pages and the copied ones
+    fn build_frames(&mut self) -> FileResult<()> {
+        let full_pages = match ramdisk_frames(self.data) {
+            Some(mut frames) => {
+                frames.truncate(self.data.len() / PAGE_SIZE as usize);
+                frames
+            }
+            None => Vec::new(),
+        };
+        let copied = &self.data[full_pages.len() * PAGE_SIZE as usize..];
+
+        if !copied.is_empty() {
+            let mut allocator = BOOT_INFO_ALLOCATOR.lock();
+            let allocator = allocator.as_mut().ok_or(FileError::OutOfMemory)?;
+            let copy = ShmemObject::new(copied.len() as u64, allocator).ok_or(FileError::OutOfMemory)?;
+            let phys_mem_offset = PHYS_MEM_OFFSET.load(Ordering::Relaxed);
+            for (frame, chunk) in copy.frames().iter().zip(copied.chunks(PAGE_SIZE as usize)) {
+                let dst = (phys_mem_offset + frame.start_address().as_u64()) as *mut u8;
+                // SAFETY: The frame belongs to the new object and is covered by the direct map
+                unsafe { core::ptr::copy_nonoverlapping(chunk.as_ptr(), dst, chunk.len()) };
+            }
+            self.copy = Some(copy);
+        }
+
+        self.frames = full_pages;
+        if let Some(copy) = &self.copy {
+            self.frames.extend_from_slice(copy.frames());
+        }
+        Ok(())
+    }
+}
+
+impl FileDescriptor for InitrdFile {
+    fn read(&mut self, buf: &mut [u8]) -> FileResult<usize> {
+        let rest = &self.data[self.offset..];
+        let len = rest.len().min(buf.len());
+        buf[..len].copy_from_slice(&rest[..len]);
+        self.offset += len;
+        Ok(len)
+    }
+
+    fn write(&mut self, _buf: &[u8]) -> FileResult<usize> {
+        Err(FileError::AccessDenied)
+    }
+
+    fn pages(&mut self, offset: u64, len: u64) -> FileResult<Vec<PhysFrame<Size4KiB>>> {
+        let size = (self.data.len() as u64).next_multiple_of(PAGE_SIZE);
+        if offset.checked_add(len).is_none_or(|end| end > size) {
+            return Err(FileError::InvalidArgument);
+        }
+        if self.frames.is_empty() {
+            self.build_frames()?;
+        }
+        let first = (offset / PAGE_SIZE) as usize;
+        Ok(self.frames[first..first + (len / PAGE_SIZE) as usize].to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::format;
+
+    /// Archive with the given files, names padded like `mkcpio` when `align` is set
+    fn archive(files: &[(&str, &[u8])], align: bool) -> &'static [u8] {
+        let mut out = Vec::new();
+        for (name, contents) in files.iter().copied().chain([("TRAILER!!!", &[][..])]) {
+            let mut namesize = name.len() + 1;
+            if align && !contents.is_empty() {
+                namesize += (PAGE_SIZE as usize - (out.len() + 110 + namesize) % PAGE_SIZE as usize) % PAGE_SIZE as usize;
+            }
+            out.extend_from_slice(format!("070701{:08X}{:08X}", 1, 0o100755).as_bytes());
+            out.extend_from_slice(format!("{:08X}{:08X}{:08X}{:08X}", 0, 0, 1, 0).as_bytes());
+            out.extend_from_slice(format!("{:08X}{:08X}{:08X}", contents.len(), 0, 0).as_bytes());
+            out.extend_from_slice(format!("{:08X}{:08X}{:08X}{:08X}", 0, 0, namesize, 0).as_bytes());
+            out.extend_from_slice(name.as_bytes());
+            out.resize(out.len() + namesize - name.len(), 0);
+            out.resize(out.len().next_multiple_of(4), 0);
+            out.extend_from_slice(contents);
+            out.resize(out.len().next_multiple_of(4), 0);
+        }
+        Box::leak(out.into_boxed_slice())
+    }
+
+    #[test_case]
+    fn test_lookup_plain_and_padded_names() {
+        let files: [(&str, &[u8]); 2] = [("bin/init", b"init"), ("bin/sh", b"shell")];
+        for align in [false, true] {
+            // SAFETY: The archive is leaked and lives forever
+            let fs = unsafe { InitrdFs::new(archive(&files, align)) };
+            assert_eq!(fs.read_file("/bin/init"), Some(&b"init"[..]));
+            assert_eq!(fs.read_file("bin/sh"), Some(&b"shell"[..]));
+            assert!(fs.read_file("/bin/missing").is_none());
+        }
+    }
+
+    #[test_case]
+    fn test_file_reads_and_rejects_writes() {
+        let mut file = InitrdFile::new(b"hello");
+        let mut buf = [0u8; 4];
+        assert_eq!(file.read(&mut buf).unwrap(), 4);
+        assert_eq!(file.read(&mut buf).unwrap(), 1);
+        assert_eq!(buf[0], b'o');
+        assert_eq!(file.read(&mut buf).unwrap(), 0);
+        assert!(file.write(b"x").is_err());
+    }
+
+    #[test_case]
+    fn test_pages_out_of_range() {
+        let mut file = InitrdFile::new(b"hello");
+        assert!(matches!(file.pages(PAGE_SIZE, PAGE_SIZE), Err(FileError::InvalidArgument)));
+        assert!(matches!(file.pages(0, 2 * PAGE_SIZE), Err(FileError::InvalidArgument)));
+    }
+}
@@ -1,15 +1,21 @@
 // kernel/src/kernel/fs/mod.rs
 //! Filesystem abstraction layer
 
-// pub mod initrd;  // TODO: Implement
-// pub mod vfs;     // TODO: Implement
+pub mod initrd;
 pub mod pipe;
+pub mod procfs;
 pub mod stdio;
+pub mod tmpfs;
+pub mod vfs;
 pub mod vfs_file;
 
 pub use vfs_file::{VfsFile, VfsFileType};
 pub use stdio::{Stdin, Stdout, Stderr, STDIN_CAP_ID, STDOUT_CAP_ID, STDERR_CAP_ID, FIRST_USER_CAP_ID};
 
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use x86_64::structures::paging::{PhysFrame, Size4KiB};
+
 /// Result type for file operations
 pub type FileResult<T> = Result<T, FileError>;
 
@@ -26,6 +32,10 @@ pub enum FileError {
     InvalidArgument,
     /// Permission denied
     AccessDenied,
+    /// The file does not support the operation
+    NotSupported,
+    /// No memory left for the file's pages
+    OutOfMemory,
 }
 
 /// File descriptor trait (stub for now)
@@ -40,6 +50,16 @@ pub trait FileDescriptor: Send + Sync {
     fn close(&mut self) -> Result<(), FileError> {
         Ok(()) // Default implementation does nothing
     }
+
+    /// Frames backing the pages `[offset, offset + len)` of the file, for `mmap`
+    ///
+    /// `offset` and `len` are page-aligned. The frames stay owned by the
+    /// file; each mapping takes its own references. Files without pages
+    /// (stdio, pipes, `/proc`) fail with `FileError::NotSupported`.
+    fn pages(&mut self, offset: u64, len: u64) -> FileResult<Vec<PhysFrame<Size4KiB>>> {
+        let _ = (offset, len);
+        Err(FileError::NotSupported)
+    }
 }
 
 /// Trait for filesystem implementations
@@ -51,4 +71,19 @@ pub trait FileSystem: Send + Sync {
     fn exists(&self, path: &str) -> bool {
         self.read_file(path).is_some()
     }
+
+    /// Open a file for `sys_open`
+    fn open(&self, path: &str) -> Option<Box<dyn FileDescriptor>> {
+        let _ = path;
+        None
+    }
+
+    /// Frames holding the file in page order, if its contents start on a
+    /// page boundary in memory and can be mapped without copying
+    ///
+    /// The last frame may continue past the end of the file.
+    fn file_frames(&self, path: &str) -> Option<Vec<PhysFrame<Size4KiB>>> {
+        let _ = path;
+        None
+    }
 }
@@ -0,0 +1,177 @@
+// kernel/src/kernel/fs/procfs.rs
+//! Process information pseudo-files (`/proc`)
+//!
+//! Contents are generated from the process table, so they cannot be served
+//! through `FileSystem::read_file` (which returns borrowed bytes). User space
+//! opens them with `sys_open`, which snapshots the file into a [`ProcFile`];
+//! kernel callers use [`read`] with an absolute `/proc/...` path.
+//!
+//! # Supported files
+//!
+//! - `/proc/<pid>/stat`
+//!
+//! ```text
+//! pid (name) state ppid utime_tsc stime_tsc nr_switches page_faults syscalls io_uring_sqes
+//! ```
+//!
+//! `state` uses the Linux letters: `R` (running/ready), `S` (blocked), `Z` (terminated).
+//!
+//! - `/proc/<pid>/maps`
+//!
+//! ```text
+//! start-end perms name
+//! 000000400000-000000402000 r-xp init
+//! 6ffffff00000-700000000000 rw-p [stack]
+//! ```
+//!
+//! One line per memory area. `perms` is `r`/`w`/`x` plus `p` (private) or
+//! `s` (shared); `name` is the program name for image areas, a bracketed
+//! tag for the stack and syscall ring, and empty for anonymous memory.
+
+use alloc::format;
+use alloc::string::String;
+use core::fmt::Write;
+
+use super::{FileDescriptor, FileError, FileResult};
+
+use crate::abi::mman::PROT_READ;
+use crate::kernel::mm::vma::VmaKind;
+use crate::kernel::process::{accounting, ProcessId, ProcessState, PROCESS_TABLE};
+
+/// Read a `/proc` file
+///
+/// Returns `None` if the path does not name an existing process file.
+#[must_use]
+pub fn read(path: &str) -> Option<String> {
+    let rest = path.strip_prefix("/proc/")?;
+    let (pid, file) = rest.split_once('/')?;
+    let pid = ProcessId::new(pid.parse().ok()?);
+
+    match file {
+        "stat" => stat(pid),
+        "maps" => maps(pid),
+        _ => None,
+    }
+}
+
+/// An opened `/proc` file
+///
+/// The contents are generated once at open time, so a sequence of reads
+/// sees one consistent snapshot.
+pub struct ProcFile {
+    contents: String,
+    offset: usize,
+}
+
+impl ProcFile {
+    /// Open a `/proc` file
+    ///
+    /// Returns `None` if the path does not name an existing process file.
+    #[must_use]
+    pub fn open(path: &str) -> Option<Self> {
+        read(path).map(|contents| Self { contents, offset: 0 })
+    }
+}
+
+impl FileDescriptor for ProcFile {
+    fn read(&mut self, buf: &mut [u8]) -> FileResult<usize> {
+        let rest = &self.contents.as_bytes()[self.offset..];
+        let len = rest.len().min(buf.len());
+        buf[..len].copy_from_slice(&rest[..len]);
+        self.offset += len;
+        Ok(len)
+    }
+
+    fn write(&mut self, _buf: &[u8]) -> FileResult<usize> {
+        Err(FileError::AccessDenied)
+    }
+}
+
+/// Generate `/proc/<pid>/stat`
+fn stat(pid: ProcessId) -> Option<String> {
+    let table = PROCESS_TABLE.lock();
+    let process = table.get_process(pid)?;
+
+    // The running process may still have counters pending on this CPU
+    if table.current_process().map(|p| p.pid()) == Some(pid) {
+        accounting::flush_into(process);
+    }
+
+    let state = match process.state() {
+        ProcessState::Running | ProcessState::Ready => 'R',
+        ProcessState::Blocked => 'S',
+        ProcessState::Terminated => 'Z',
+    };
+    let usage = process.usage().snapshot();
+
+    Some(format!(
+        "{} ({}) {} {} {} {} {} {} {} {}\n",
+        pid.as_u64(),
+        process.name(),
+        state,
+        process.parent_pid().map_or(0, |p| p.as_u64()),
+        usage.user_tsc,
+        usage.kernel_tsc,
+        usage.context_switches,
+        usage.page_faults,
+        usage.syscalls,
+        usage.io_uring_sqes,
+    ))
+}
+
+/// Generate `/proc/<pid>/maps`
+fn maps(pid: ProcessId) -> Option<String> {
+    let table = PROCESS_TABLE.lock();
+    let process = table.get_process(pid)?;
+
+    let mut out = String::new();
+    for vma in process.vmas().iter() {
+        let name = match vma.kind {
+            VmaKind::Image => process.name(),
+            kind => kind.label(),
+        };
+        let _ = writeln!(
+            out,
+            "{:012x}-{:012x} {}{}{}{} {}",
+            vma.start,
+            vma.end,
+            if vma.prot & PROT_READ != 0 { 'r' } else { '-' },
+            if vma.writable() { 'w' } else { '-' },
+            if vma.executable() { 'x' } else { '-' },
+            if vma.shared { 's' } else { 'p' },
+            name,
+        );
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_read_rejects_malformed_paths() {
+        assert!(read("/proc").is_none());
+        assert!(read("/proc/abc/stat").is_none());
+        assert!(read("/sys/1/stat").is_none());
+    }
+
+    #[test_case]
+    fn test_read_unknown_pid() {
+        assert!(read("/proc/999999/stat").is_none());
+        assert!(read("/proc/999999/maps").is_none());
+        assert!(ProcFile::open("/proc/999999/stat").is_none());
+    }
+
+    #[test_case]
+    fn test_proc_file_reads_snapshot_in_chunks() {
+        let mut file = ProcFile { contents: String::from("1 (init) R 0\n"), offset: 0 };
+        let mut buf = [0u8; 8];
+        assert_eq!(file.read(&mut buf).unwrap(), 8);
+        assert_eq!(&buf, b"1 (init)");
+        assert_eq!(file.read(&mut buf).unwrap(), 5);
+        assert_eq!(&buf[..5], b" R 0\n");
+        assert_eq!(file.read(&mut buf).unwrap(), 0);
+        assert!(file.write(b"x").is_err());
+    }
+}
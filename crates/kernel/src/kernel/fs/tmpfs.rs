@@ -0,0 +1,224 @@
+// kernel/src/kernel/fs/tmpfs.rs
+//! In-memory filesystem (`/tmp`)
+//!
+//! Each file lives in page frames owned by the file, and `mmap` maps those
+//! frames themselves. A `MAP_SHARED` mapping therefore sees `write` calls
+//! and the stores of every other shared mapping, and `read` sees stores
+//! made through the mappings. Private mappings are copy-on-write.
+//!
+//! Files are created by `sys_open` with `Rights::CREATE` and only grow;
+//! there is no unlink or truncate yet. A mapping may cover the page-rounded
+//! file size, so a file must be written to its final size before it is
+//! mapped.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::Ordering;
+use spin::Mutex;
+use x86_64::structures::paging::{FrameAllocator, PhysFrame, Size4KiB};
+
+use super::{FileDescriptor, FileError, FileResult};
+use crate::kernel::mm::allocator::BOOT_INFO_ALLOCATOR;
+use crate::kernel::mm::PHYS_MEM_OFFSET;
+
+/// Page size of the backing frames
+const PAGE_SIZE: u64 = 4096;
+
+/// Largest file size (16 MiB)
+pub const MAX_FILE_SIZE: u64 = 16 << 20;
+
+/// Global `/tmp` instance
+pub static TMPFS: Mutex<TmpFs> = Mutex::new(TmpFs::new());
+
+/// Flat directory of in-memory files
+pub struct TmpFs {
+    files: BTreeMap<String, Arc<Mutex<Node>>>,
+}
+
+impl TmpFs {
+    /// Create an empty filesystem
+    #[must_use]
+    pub const fn new() -> Self {
+        Self { files: BTreeMap::new() }
+    }
+
+    /// Open the file `name` (relative to `/tmp`), creating it if `create` is set
+    ///
+    /// Returns `None` if the file does not exist and `create` is not set,
+    /// or if `name` is empty or contains a `/`.
+    pub fn open(&mut self, name: &str, create: bool) -> Option<TmpFile> {
+        if name.is_empty() || name.contains('/') {
+            return None;
+        }
+        let node = match self.files.get(name) {
+            Some(node) => node.clone(),
+            None if create => {
+                let node = Arc::new(Mutex::new(Node { frames: Vec::new(), size: 0 }));
+                self.files.insert(String::from(name), node.clone());
+                node
+            }
+            None => return None,
+        };
+        Some(TmpFile { node, offset: 0 })
+    }
+}
+
+impl Default for TmpFs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Contents of one file
+struct Node {
+    /// Zeroed frames covering `size`, in page order
+    frames: Vec<PhysFrame<Size4KiB>>,
+    /// File size in bytes
+    size: u64,
+}
+
+impl Node {
+    /// Direct-map pointer to byte `offset`, which must lie in a backing frame
+    fn byte_ptr(&self, offset: u64) -> *mut u8 {
+        let frame = self.frames[(offset / PAGE_SIZE) as usize];
+        let phys_mem_offset = PHYS_MEM_OFFSET.load(Ordering::Relaxed);
+        (phys_mem_offset + frame.start_address().as_u64() + offset % PAGE_SIZE) as *mut u8
+    }
+
+    /// Add zeroed frames until the file can hold `size` bytes
+    fn reserve(&mut self, size: u64) -> FileResult<()> {
+        let pages = size.div_ceil(PAGE_SIZE) as usize;
+        if pages <= self.frames.len() {
+            return Ok(());
+        }
+        let mut allocator = BOOT_INFO_ALLOCATOR.lock();
+        let allocator = allocator.as_mut().ok_or(FileError::OutOfMemory)?;
+        while self.frames.len() < pages {
+            let frame = allocator.allocate_frame().ok_or(FileError::OutOfMemory)?;
+            let phys_mem_offset = PHYS_MEM_OFFSET.load(Ordering::Relaxed);
+            let ptr = (phys_mem_offset + frame.start_address().as_u64()) as *mut u8;
+            // SAFETY: The frame was just allocated and is covered by the direct map
+            unsafe { core::ptr::write_bytes(ptr, 0, PAGE_SIZE as usize) };
+            self.frames.push(frame);
+        }
+        Ok(())
+    }
+
+    /// Copy file bytes from `offset` into `buf`
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> usize {
+        let len = (self.size.saturating_sub(offset) as usize).min(buf.len());
+        let mut done = 0;
+        while done < len {
+            let at = offset + done as u64;
+            let chunk = (len - done).min((PAGE_SIZE - at % PAGE_SIZE) as usize);
+            // SAFETY: `at` is below the size, which the frames cover
+            unsafe { core::ptr::copy_nonoverlapping(self.byte_ptr(at), buf[done..].as_mut_ptr(), chunk) };
+            done += chunk;
+        }
+        len
+    }
+
+    /// Copy `buf` into the file at `offset`, growing it as needed
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> FileResult<usize> {
+        let end = offset + buf.len() as u64;
+        if end > MAX_FILE_SIZE {
+            return Err(FileError::InvalidArgument);
+        }
+        self.reserve(end)?;
+        let mut done = 0;
+        while done < buf.len() {
+            let at = offset + done as u64;
+            let chunk = (buf.len() - done).min((PAGE_SIZE - at % PAGE_SIZE) as usize);
+            // SAFETY: `reserve` made the frames cover `end`
+            unsafe { core::ptr::copy_nonoverlapping(buf[done..].as_ptr(), self.byte_ptr(at), chunk) };
+            done += chunk;
+        }
+        self.size = self.size.max(end);
+        Ok(buf.len())
+    }
+}
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        // Mappings hold their own references; like shared memory objects,
+        // the frames leak if the allocator is busy
+        if self.frames.is_empty() {
+            return;
+        }
+        if let Some(mut allocator) = BOOT_INFO_ALLOCATOR.try_lock() {
+            if let Some(ref mut alloc) = *allocator {
+                for &frame in &self.frames {
+                    // SAFETY: The node owns one reference to each frame
+                    unsafe { alloc.deallocate_frame(frame) };
+                }
+                return;
+            }
+        }
+        crate::debug_println!("[tmpfs] Allocator busy, leaking {} frames", self.frames.len());
+    }
+}
+
+/// An opened `/tmp` file with its own read/write position
+pub struct TmpFile {
+    node: Arc<Mutex<Node>>,
+    offset: u64,
+}
+
+impl TmpFile {
+    /// Current file size in bytes
+    #[must_use]
+    pub fn size(&self) -> u64 {
+        self.node.lock().size
+    }
+}
+
+impl FileDescriptor for TmpFile {
+    fn read(&mut self, buf: &mut [u8]) -> FileResult<usize> {
+        let len = self.node.lock().read_at(self.offset, buf);
+        self.offset += len as u64;
+        Ok(len)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> FileResult<usize> {
+        let len = self.node.lock().write_at(self.offset, buf)?;
+        self.offset += len as u64;
+        Ok(len)
+    }
+
+    fn pages(&mut self, offset: u64, len: u64) -> FileResult<Vec<PhysFrame<Size4KiB>>> {
+        let node = self.node.lock();
+        let size = node.size.next_multiple_of(PAGE_SIZE);
+        if offset.checked_add(len).is_none_or(|end| end > size) {
+            return Err(FileError::InvalidArgument);
+        }
+        let first = (offset / PAGE_SIZE) as usize;
+        Ok(node.frames[first..first + (len / PAGE_SIZE) as usize].to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_open_requires_create_and_flat_names() {
+        let mut fs = TmpFs::new();
+        assert!(fs.open("missing", false).is_none());
+        assert!(fs.open("", true).is_none());
+        assert!(fs.open("dir/file", true).is_none());
+
+        let created = fs.open("file", true).unwrap();
+        assert_eq!(created.size(), 0);
+        assert!(fs.open("file", false).is_some());
+    }
+
+    #[test_case]
+    fn test_empty_file_has_no_pages() {
+        let mut file = TmpFs::new().open("empty", true).unwrap();
+        let mut buf = [0u8; 4];
+        assert_eq!(file.read(&mut buf).unwrap(), 0);
+        assert!(matches!(file.pages(0, PAGE_SIZE), Err(FileError::InvalidArgument)));
+    }
+}
@@ -1,7 +1,14 @@
 // kernel/src/kernel/fs/vfs.rs
-use super::FileSystem;
+//! Virtual filesystem root
+//!
+//! The initrd is mounted at "/" during boot; `sys_open` and the process
+//! loader look files up here.
+
+use super::{FileDescriptor, FileSystem};
 use spin::{Mutex, Lazy};
 use alloc::boxed::Box;
+use alloc::vec::Vec;
+use x86_64::structures::paging::{PhysFrame, Size4KiB};
 
 /// Virtual Filesystem
 pub struct Vfs {
@@ -13,14 +20,19 @@ impl Vfs {
     pub fn new() -> Self {
         Self { root: None }
     }
-    
+
     /// Mount a filesystem
-    /// 
+    ///
     /// Currently only supports mounting at root "/"
     pub fn mount(&mut self, _path: &str, fs: impl FileSystem + 'static + Send + Sync) {
         self.root = Some(Box::new(fs));
     }
-    
+
+    /// Whether a filesystem is mounted at "/"
+    pub fn is_mounted(&self) -> bool {
+        self.root.is_some()
+    }
+
     /// Read a file
     pub fn read_file(&self, path: &str) -> Option<&[u8]> {
         if let Some(fs) = &self.root {
@@ -29,6 +41,16 @@ impl Vfs {
             None
         }
     }
+
+    /// Open a file
+    pub fn open(&self, path: &str) -> Option<Box<dyn FileDescriptor>> {
+        self.root.as_ref()?.open(path)
+    }
+
+    /// Frames holding a file, if it can be mapped without copying
+    pub fn file_frames(&self, path: &str) -> Option<Vec<PhysFrame<Size4KiB>>> {
+        self.root.as_ref()?.file_frames(path)
+    }
 }
 
 /// Global VFS instance
@@ -24,8 +24,10 @@
 
 use alloc::boxed::Box;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use spin::Mutex;
 use core::any::Any;
+use x86_64::structures::paging::{PhysFrame, Size4KiB};
 
 use super::{FileDescriptor, FileError};
 
@@ -140,6 +142,17 @@ impl VfsFile {
         inner.write(buf)
     }
 
+    /// Frames backing the pages `[offset, offset + len)`, for `mmap`
+    ///
+    /// # Errors
+    ///
+    /// Returns `FileError::NotSupported` for files without pages, and the
+    /// file's error if the range is invalid or its pages cannot be built.
+    pub fn pages(&self, offset: u64, len: u64) -> Result<Vec<PhysFrame<Size4KiB>>, FileError> {
+        let mut inner = self.inner.lock();
+        inner.pages(offset, len)
+    }
+
     /// Close the file
     ///
     /// This is called automatically when the capability is removed,
@@ -4,7 +4,10 @@
 //! Each process can have an io_uring context that manages its submission
 //! and completion queues, registered buffers, and SQPOLL configuration.
 
+#[cfg(feature = "syscall_trace")]
+use alloc::sync::Arc;
 use spin::Mutex;
+use x86_64::structures::paging::OffsetPageTable;
 
 use super::doorbell::Doorbell;
 use super::ring::IoUring;
@@ -12,8 +15,11 @@ use super::handlers_v2::dispatch_sqe_v2;
 use super::registered_buffers::{RegisteredBufferTable, RegisteredBufferStats};
 use crate::abi::io_uring_v2::SubmissionEntryV2;
 use crate::debug_println;
-use crate::kernel::capability::table::CapabilityTable;
+use crate::kernel::mm::vma::VmaTree;
 use crate::kernel::mm::BootInfoFrameAllocator;
+use crate::kernel::process::Process;
+#[cfg(feature = "syscall_trace")]
+use crate::kernel::process::trace::{self, ProcessTrace};
 use crate::kernel::syscall::SyscallResult;
 
 /// Per-process io_uring context
@@ -41,6 +47,10 @@ pub struct IoUringContext {
 
     /// Kernel virtual address of the optional doorbell page (shared with userspace)
     doorbell: Option<u64>,
+
+    /// Trace buffer of the owning process, if it is traced
+    #[cfg(feature = "syscall_trace")]
+    trace: Option<Arc<ProcessTrace>>,
 }
 
 impl IoUringContext {
@@ -61,6 +71,8 @@ impl IoUringContext {
             registered_buffers: RegisteredBufferTable::new(),
             sqpoll_enabled: false,
             doorbell: None,
+            #[cfg(feature = "syscall_trace")]
+            trace: None,
         })
     }
     
@@ -78,6 +90,7 @@ impl IoUringContext {
     /// * `len` - Buffer length in bytes
     /// * `readable` - Allow kernel to write to buffer
     /// * `writable` - Allow kernel to read from buffer
+    /// * `vmas`, `mapper` - Address space of the locked owning process
     ///
     /// # Returns
     /// * `Ok(index)` - Buffer index for use in SQEs
@@ -88,13 +101,21 @@ impl IoUringContext {
         len: usize,
         readable: bool,
         writable: bool,
+        vmas: &VmaTree,
+        mapper: &mut OffsetPageTable<'_>,
     ) -> Result<u32, SyscallResult> {
-        self.registered_buffers.register(user_addr, len, readable, writable)
+        self.registered_buffers.register(user_addr, len, readable, writable, vmas, mapper)
     }
     
-    /// Register multiple buffers from a user-provided iovec array
-    pub fn register_buffers(&mut self, user_iov: u64, count: usize) -> Result<u32, SyscallResult> {
-        self.registered_buffers.register_buffers(user_iov, count)
+    /// Register multiple buffers from an iovec array read by
+    /// [`read_iovecs`](super::registered_buffers::read_iovecs)
+    pub fn register_buffers(
+        &mut self,
+        iovecs: &[(u64, usize)],
+        vmas: &VmaTree,
+        mapper: &mut OffsetPageTable<'_>,
+    ) -> Result<u32, SyscallResult> {
+        self.registered_buffers.register_buffers(iovecs, vmas, mapper)
     }
     
     /// Unregister a buffer
@@ -145,11 +166,12 @@ impl IoUringContext {
     /// 3. Posts completions to CQ
     ///
     /// # Arguments
-    /// * `cap_table` - The process's capability table for I/O operations
+    /// * `process` - The owning process, detached from this context with
+    ///   [`Process::with_io_uring`]
     ///
     /// # Returns
     /// Number of operations completed
-    pub fn process(&mut self, cap_table: &CapabilityTable) -> u32 {
+    pub fn process(&mut self, process: &mut Process) -> u32 {
         // Harvest new submissions
         let harvested = self.ring.harvest_submissions();
         
@@ -164,7 +186,13 @@ impl IoUringContext {
             // Dispatch to V2 handler with capability table
             // dispatch_sqe_v2 handles validation internally
             // We allow_raw_addr = false for user-space requests for security
-            let completion = dispatch_sqe_v2(&sqe, cap_table, Some(&self.registered_buffers), false);
+            #[cfg(feature = "syscall_trace")]
+            let start = crate::arch::x86_64::read_timestamp();
+            let completion = dispatch_sqe_v2(&sqe, process, Some(&self.registered_buffers), false);
+            #[cfg(feature = "syscall_trace")]
+            if let Some(tracer) = &self.trace {
+                trace::record_sqe(tracer, &sqe, &completion, start);
+            }
             
             // Post completion
             if self.ring.post_completion(completion).is_ok() {
@@ -191,13 +219,13 @@ impl IoUringContext {
     ///
     /// # Arguments
     /// * `min_complete` - Minimum number of completions to wait for (0 = non-blocking)
-    /// * `cap_table` - The process's capability table for I/O operations
+    /// * `process` - The owning process (see [`Self::process`])
     ///
     /// # Returns
     /// Number of completions available in CQ
-    pub fn enter(&mut self, min_complete: u32, cap_table: &CapabilityTable) -> u32 {
+    pub fn enter(&mut self, min_complete: u32, process: &mut Process) -> u32 {
         // Process any pending submissions
-        self.process(cap_table);
+        self.process(process);
         
         // If min_complete > 0, we would wait for completions
         // For now, we just return the completion count
@@ -228,17 +256,23 @@ impl IoUringContext {
     ///
     /// # Arguments
     /// * `sqe` - V2 submission entry
-    /// * `cap_table` - Process capability table for permission verification
+    /// * `process` - The owning process (see [`Self::process`])
     ///
     /// # Returns
     /// V2 completion entry with result
     pub fn process_sqe_v2(
         &self,
         sqe: &SubmissionEntryV2,
-        cap_table: &CapabilityTable,
+        process: &mut Process,
     ) -> crate::abi::io_uring_v2::CompletionEntryV2 {
         // Use the registered buffer table from this context
-        dispatch_sqe_v2(sqe, cap_table, Some(&self.registered_buffers), false)
+        dispatch_sqe_v2(sqe, process, Some(&self.registered_buffers), false)
+    }
+
+    /// Record processed SQEs into `trace` (or stop recording with `None`)
+    #[cfg(feature = "syscall_trace")]
+    pub fn set_trace(&mut self, trace: Option<Arc<ProcessTrace>>) {
+        self.trace = trace;
     }
 
     /// Get a reference to the registered buffer table
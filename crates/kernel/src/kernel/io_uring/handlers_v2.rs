@@ -10,6 +10,7 @@
 //! File descriptors are no longer used for I/O operations.
 
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 
 use crate::abi::error::SyscallError;
 use crate::abi::io_uring_common::OpCode;
@@ -21,15 +22,20 @@ use crate::kernel::core::traits::CharDevice;
 use crate::kernel::driver::serial::SERIAL1;
 use crate::kernel::fs::VfsFile;
 use crate::kernel::io_uring::registered_buffers::RegisteredBufferTable;
-use crate::kernel::process::PROCESS_TABLE;
+use crate::kernel::mm::mmap;
+use crate::kernel::process::Process;
+use crate::kernel::syscall::{do_mmap, do_munmap, MmapArgs, MunmapArgs};
 
 /// Dispatch a V2 SQE to its handler
 ///
 /// This function validates the capability and dispatches to the appropriate handler.
 ///
+/// The caller holds `PROCESS_TABLE`; `process` need not be the one running
+/// on this CPU (SQPOLL polls rings of other processes).
+///
 /// # Arguments
 /// * `sqe` - The V2 submission entry
-/// * `cap_table` - The process's capability table
+/// * `process` - The process that submitted the entry
 /// * `buf_table` - The registered buffer table (optional)
 /// * `allow_raw_addr` - Whether to allow raw addresses (kernel mode only)
 ///
@@ -37,7 +43,7 @@ use crate::kernel::process::PROCESS_TABLE;
 /// A V2 completion entry with the result
 pub fn dispatch_sqe_v2(
     sqe: &SubmissionEntryV2,
-    cap_table: &CapabilityTable,
+    process: &mut Process,
     buf_table: Option<&RegisteredBufferTable>,
     allow_raw_addr: bool,
 ) -> CompletionEntryV2 {
@@ -48,13 +54,14 @@ pub fn dispatch_sqe_v2(
         None => return CompletionEntryV2::error(user_data, SyscallError::InvalidOpCode),
     };
 
+    let cap_table = process.capability_table();
     match op {
         OpCode::Nop => handle_nop_v2(sqe),
         OpCode::Read => handle_read_v2(sqe, cap_table, buf_table, allow_raw_addr),
         OpCode::Write => handle_write_v2(sqe, cap_table, buf_table, allow_raw_addr),
         OpCode::Close => handle_close_v2(sqe, cap_table),
-        OpCode::Mmap => handle_mmap_v2(sqe),
-        OpCode::Munmap => handle_munmap_v2(sqe),
+        OpCode::Mmap => handle_mmap_v2(sqe, process),
+        OpCode::Munmap => handle_munmap_v2(sqe, process),
 
         // Not yet implemented
         OpCode::Open
@@ -135,17 +142,25 @@ fn handle_read_v2(
         };
 
         // Validate buffer is readable (kernel can write to it)
-        let slice = match unsafe { buf_ref.as_mut_slice() } {
-            Some(s) => s,
-            None => {
+        if !buf_ref.is_readable() {
+            core::mem::forget(handle);
+            return CompletionEntryV2::error(user_data, SyscallError::InsufficientRights);
+        }
+        
+        // Limit read to requested length, read into a kernel buffer and
+        // copy the result out to user memory
+        let Some(mut data) = bounce_buffer((len as usize).min(buf_ref.len())) else {
+            core::mem::forget(handle);
+            return CompletionEntryV2::error(user_data, SyscallError::OutOfMemory);
+        };
+        let result = vfs_file.read(&mut data);
+        if let Ok(read) = result {
+            if buf_ref.copy_to(&data[..read]).is_err() {
                 core::mem::forget(handle);
-                return CompletionEntryV2::error(user_data, SyscallError::InsufficientRights);
+                return CompletionEntryV2::error(user_data, SyscallError::InvalidAddress);
             }
-        };
-        
-        // Limit read to requested length
-        let read_len = (len as usize).min(slice.len());
-        vfs_file.read(&mut slice[..read_len])
+        }
+        result
     } else if allow_raw_addr {
         // Kernel mode raw address support
         // Use aux1 as address
@@ -235,17 +250,21 @@ fn handle_write_v2(
         };
 
         // Validate buffer is writable (kernel can read from it)
-        let slice = match unsafe { buf_ref.as_slice() } {
-            Some(s) => s,
-            None => {
-                core::mem::forget(handle);
-                return CompletionEntryV2::error(user_data, SyscallError::InsufficientRights);
-            }
-        };
+        if !buf_ref.is_writable() {
+            core::mem::forget(handle);
+            return CompletionEntryV2::error(user_data, SyscallError::InsufficientRights);
+        }
         
-        // Limit write to requested length
-        let write_len = (len as usize).min(slice.len());
-        vfs_file.write(&slice[..write_len])
+        // Limit write to requested length and copy the data in from user memory
+        let Some(mut data) = bounce_buffer((len as usize).min(buf_ref.len())) else {
+            core::mem::forget(handle);
+            return CompletionEntryV2::error(user_data, SyscallError::OutOfMemory);
+        };
+        if buf_ref.copy_from(&mut data).is_err() {
+            core::mem::forget(handle);
+            return CompletionEntryV2::error(user_data, SyscallError::InvalidAddress);
+        }
+        vfs_file.write(&data)
     } else if allow_raw_addr {
         // Kernel mode raw address support
         // Use aux1 as address
@@ -283,6 +302,16 @@ fn handle_write_v2(
     }
 }
 
+/// Allocate a zeroed kernel buffer for copying to or from a registered buffer
+///
+/// Returns `None` if the allocation fails.
+fn bounce_buffer(len: usize) -> Option<Vec<u8>> {
+    let mut data = Vec::new();
+    data.try_reserve_exact(len).ok()?;
+    data.resize(len, 0);
+    Some(data)
+}
+
 /// Handle close operation with capability (V2)
 ///
 /// # Phase 1: Capability-based resource access
@@ -322,154 +351,52 @@ fn handle_close_v2(sqe: &SubmissionEntryV2, cap_table: &CapabilityTable) -> Comp
 
 /// Handle mmap operation (V2)
 ///
-/// Note: mmap doesn't use capabilities directly, but creates new memory mappings.
-fn handle_mmap_v2(sqe: &SubmissionEntryV2) -> CompletionEntryV2 {
-    use crate::kernel::mm::allocator::BOOT_INFO_ALLOCATOR;
-    use x86_64::structures::paging::{FrameAllocator, Mapper, Page, PageTableFlags, Size4KiB};
+/// Maps private anonymous read/write memory through the same path as the
+/// `mmap` syscall, so the mapping is tracked as a memory area of the process.
+/// The address does not fit the 32-bit result, so it is returned in `aux`.
+fn handle_mmap_v2(sqe: &SubmissionEntryV2, process: &mut Process) -> CompletionEntryV2 {
+    use crate::abi::mman::{MAP_ANONYMOUS, MAP_PRIVATE, PROT_READ, PROT_WRITE};
 
     let addr_hint = sqe.off; // Address hint is in `off` field for mmap
-    let len = sqe.len as u64;
-    let user_data = sqe.user_data;
-
-    if len == 0 {
-        return CompletionEntryV2::error(user_data, SyscallError::InvalidArgument);
-    }
-
-    // Align length to page size
-    let len_aligned = (len + 4095) & !4095;
-    let num_pages = (len_aligned / 4096) as usize;
-
-    // Get current process's mmap_top
-    let mut table = PROCESS_TABLE.lock();
-    let process = match table.current_process_mut() {
-        Some(p) => p,
-        None => return CompletionEntryV2::error(user_data, SyscallError::NoSuchProcess),
+    let args = match MmapArgs::new(
+        addr_hint,
+        u64::from(sqe.len),
+        PROT_READ | PROT_WRITE,
+        MAP_PRIVATE | MAP_ANONYMOUS,
+        0,
+        0,
+    ) {
+        Ok(args) => args,
+        Err(e) => return CompletionEntryV2::error(sqe.user_data, SyscallError::from_errno(e)),
     };
-
-    let start_addr = if addr_hint == 0 {
-        process.mmap_top()
-    } else {
-        // Fixed address not supported
-        return CompletionEntryV2::error(user_data, SyscallError::InvalidArgument);
-    };
-
-    // Update mmap_top
-    let new_top = start_addr + len_aligned;
-    process.set_mmap_top(new_top);
-    drop(table);
-
-    // Map memory
-    let phys_mem_offset = x86_64::VirtAddr::new(
-        crate::kernel::mm::PHYS_MEM_OFFSET.load(core::sync::atomic::Ordering::Relaxed),
-    );
-    let (l4_frame, _) = x86_64::registers::control::Cr3::read();
-    let l4_table_ptr = (phys_mem_offset + l4_frame.start_address().as_u64()).as_mut_ptr();
-    let l4_table = unsafe { &mut *l4_table_ptr };
-    let mut mapper =
-        unsafe { x86_64::structures::paging::OffsetPageTable::new(l4_table, phys_mem_offset) };
-
-    let mut allocator_lock = BOOT_INFO_ALLOCATOR.lock();
-    let frame_allocator = match allocator_lock.as_mut() {
-        Some(alloc) => alloc,
-        None => return CompletionEntryV2::error(user_data, SyscallError::OutOfMemory),
-    };
-
-    let start_page = Page::<Size4KiB>::containing_address(start_addr);
-    let flags =
-        PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
-
-    for i in 0..num_pages {
-        let page = start_page + i as u64;
-        let frame = match frame_allocator.allocate_frame() {
-            Some(f) => f,
-            None => {
-                // Rollback previous allocations
-                for j in 0..i {
-                    let page_to_unmap = start_page + j as u64;
-                    if let Ok((frame, _)) = mapper.unmap(page_to_unmap) {
-                        x86_64::instructions::tlb::flush(page_to_unmap.start_address());
-                        unsafe {
-                            frame_allocator.deallocate_frame(frame);
-                        }
-                    }
-                }
-                return CompletionEntryV2::error(user_data, SyscallError::OutOfMemory);
-            }
-        };
-
-        unsafe {
-            match mapper.map_to(page, frame, flags, frame_allocator) {
-                Ok(tlb) => tlb.flush(),
-                Err(_) => {
-                    frame_allocator.deallocate_frame(frame);
-                    // Rollback
-                    for j in 0..i {
-                        let page_to_unmap = start_page + j as u64;
-                        if let Ok((frame, _)) = mapper.unmap(page_to_unmap) {
-                            x86_64::instructions::tlb::flush(page_to_unmap.start_address());
-                            frame_allocator.deallocate_frame(frame);
-                        }
-                    }
-                    return CompletionEntryV2::error(user_data, SyscallError::MmapFailed);
-                }
-            }
-        }
-
-        // Zero the frame
-        if let Ok(frame) = mapper.translate_page(page) {
-            let frame_ptr =
-                (phys_mem_offset + frame.start_address().as_u64()).as_mut_ptr::<u8>();
-            unsafe {
-                core::ptr::write_bytes(frame_ptr, 0, 4096);
-            }
-        }
+    let l4_frame = process.page_table_frame();
+    // SAFETY: The caller holds PROCESS_TABLE, which serializes page table
+    // updates of every process
+    let mut mapper = unsafe { mmap::mapper_for(l4_frame) };
+    let result = do_mmap(process, &mut mapper, &args);
+    mmap::flush_if_inactive(l4_frame);
+    if result < 0 {
+        return CompletionEntryV2::error(sqe.user_data, SyscallError::from_errno(result));
     }
-
-    CompletionEntryV2::success(user_data, start_addr.as_u64() as i32)
+    CompletionEntryV2::success_with_aux(sqe.user_data, 0, result as u64)
 }
 
 /// Handle munmap operation (V2)
-fn handle_munmap_v2(sqe: &SubmissionEntryV2) -> CompletionEntryV2 {
-    use x86_64::structures::paging::{Mapper, Page, Size4KiB};
-
+fn handle_munmap_v2(sqe: &SubmissionEntryV2, process: &mut Process) -> CompletionEntryV2 {
     let addr = sqe.off; // Address is in `off` field
-    let len = sqe.len as u64;
-    let user_data = sqe.user_data;
-
-    if addr == 0 || len == 0 {
-        return CompletionEntryV2::error(user_data, SyscallError::InvalidArgument);
-    }
-
-    // Align length to page size
-    let len_aligned = (len + 4095) & !4095;
-
-    let phys_mem_offset = x86_64::VirtAddr::new(
-        crate::kernel::mm::PHYS_MEM_OFFSET.load(core::sync::atomic::Ordering::Relaxed),
-    );
-    let (l4_frame, _) = x86_64::registers::control::Cr3::read();
-    let l4_table_ptr = (phys_mem_offset + l4_frame.start_address().as_u64()).as_mut_ptr();
-    let l4_table = unsafe { &mut *l4_table_ptr };
-    let mut mapper =
-        unsafe { x86_64::structures::paging::OffsetPageTable::new(l4_table, phys_mem_offset) };
-
-    let start_addr = x86_64::VirtAddr::new(addr);
-    let start_page = Page::<Size4KiB>::containing_address(start_addr);
-    let end_page = Page::<Size4KiB>::containing_address(start_addr + len_aligned);
-
-    for page in Page::range(start_page, end_page) {
-        if let Ok((frame, _)) = mapper.unmap(page) {
-            x86_64::instructions::tlb::flush(page.start_address());
-
-            unsafe {
-                let mut allocator_lock = crate::kernel::mm::allocator::BOOT_INFO_ALLOCATOR.lock();
-                if let Some(frame_allocator) = allocator_lock.as_mut() {
-                    frame_allocator.deallocate_frame(frame);
-                }
-            }
-        }
+    let args = match MunmapArgs::new(addr, u64::from(sqe.len)) {
+        Ok(args) => args,
+        Err(e) => return CompletionEntryV2::error(sqe.user_data, SyscallError::from_errno(e)),
+    };
+    let l4_frame = process.page_table_frame();
+    // SAFETY: See handle_mmap_v2
+    let mut mapper = unsafe { mmap::mapper_for(l4_frame) };
+    let result = do_munmap(process, &mut mapper, &args);
+    mmap::flush_if_inactive(l4_frame);
+    if result < 0 {
+        return CompletionEntryV2::error(sqe.user_data, SyscallError::from_errno(result));
     }
-
-    CompletionEntryV2::success(user_data, 0)
+    CompletionEntryV2::success(sqe.user_data, 0)
 }
 
 
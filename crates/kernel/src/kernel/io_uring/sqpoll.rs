@@ -273,8 +273,8 @@ impl SqPollWorker {
                 use crate::kernel::process::PROCESS_TABLE;
                 let mut table = PROCESS_TABLE.lock();
                 if let Some(process) = table.get_process_mut(ctx.pid) {
-                    if let Some((iog_ctx, cap_table)) = process.io_uring_with_capabilities() {
-                        let processed = iog_ctx.process(cap_table);
+                    if let Some(processed) = process.with_io_uring(|iog_ctx, process| iog_ctx.process(process)) {
+                        process.usage().add_io_uring_sqes(u64::from(processed));
                         if processed > 0 {
                             // If ring has a doorbell, set CQ ready
                             if ctx.doorbell_addr != 0 {
@@ -437,6 +437,11 @@ pub fn init() {
     debug_println!("[SQPOLL] Subsystem initialized");
 }
 
+/// Stop the SQPOLL worker (used on shutdown)
+pub fn stop() {
+    SQPOLL_WORKER.stop();
+}
+
 /// Register a ring for SQPOLL
 pub fn register_ring(pid: ProcessId, ring_id: u32, sq_tail_addr: u64, doorbell_addr: u64) {
     let ctx = Arc::new(SqPollRingContext::new(pid, ring_id, sq_tail_addr, doorbell_addr));
@@ -1,2 +1,3 @@
 pub mod channel;
 pub mod pipe;
+pub mod shmem;
@@ -0,0 +1,83 @@
+// kernel/src/kernel/ipc/shmem.rs
+//! Shared memory objects
+//!
+//! A `ShmemObject` is a fixed set of zeroed physical frames reached through
+//! a `Handle<ShmemResource>` capability. Every process that maps the object
+//! takes a frame reference per mapped page, and the object itself holds
+//! one more, so the frames stay alive until the last mapping is gone and
+//! the last capability is closed.
+//!
+//! Access is governed by the capability rights: mapping needs `MAP` and
+//! `READ`, a writable shared mapping needs `WRITE` and an executable one
+//! `EXEC`. Private mappings are copy-on-write and never modify the object.
+
+use alloc::vec::Vec;
+use core::sync::atomic::Ordering;
+use x86_64::structures::paging::{FrameAllocator, PhysFrame, Size4KiB};
+
+use crate::kernel::mm::allocator::BOOT_INFO_ALLOCATOR;
+use crate::kernel::mm::{BootInfoFrameAllocator, PHYS_MEM_OFFSET};
+
+/// Page size of the backing frames
+const PAGE_SIZE: u64 = 4096;
+
+/// Refcounted set of frames shared between processes
+pub struct ShmemObject {
+    frames: Vec<PhysFrame<Size4KiB>>,
+}
+
+impl ShmemObject {
+    /// Allocate a zeroed object of `size` bytes, rounded up to whole pages
+    ///
+    /// Returns `None` if memory runs out; frames allocated so far are
+    /// released again.
+    pub fn new(size: u64, frame_allocator: &mut BootInfoFrameAllocator) -> Option<Self> {
+        let pages = size.div_ceil(PAGE_SIZE);
+        let phys_mem_offset = PHYS_MEM_OFFSET.load(Ordering::Relaxed);
+        let mut frames = Vec::with_capacity(pages as usize);
+
+        for _ in 0..pages {
+            let Some(frame) = frame_allocator.allocate_frame() else {
+                for frame in frames {
+                    // SAFETY: The frame was allocated above and never mapped
+                    unsafe { frame_allocator.deallocate_frame(frame) };
+                }
+                return None;
+            };
+            let frame_ptr = (phys_mem_offset + frame.start_address().as_u64()) as *mut u8;
+            // SAFETY: The frame was just allocated and is covered by the direct map
+            unsafe { core::ptr::write_bytes(frame_ptr, 0, PAGE_SIZE as usize) };
+            frames.push(frame);
+        }
+
+        Some(Self { frames })
+    }
+
+    /// Size in bytes (a multiple of the page size)
+    pub fn size(&self) -> u64 {
+        self.frames.len() as u64 * PAGE_SIZE
+    }
+
+    /// Backing frames in page order
+    pub fn frames(&self) -> &[PhysFrame<Size4KiB>] {
+        &self.frames
+    }
+}
+
+impl Drop for ShmemObject {
+    fn drop(&mut self) {
+        // Mappings hold their own references; only the object's is dropped
+        // here. Like process teardown, the frames leak if the allocator is
+        // busy.
+        if let Some(mut allocator) = BOOT_INFO_ALLOCATOR.try_lock() {
+            if let Some(ref mut alloc) = *allocator {
+                for &frame in &self.frames {
+                    // SAFETY: The object owns one reference to each frame
+                    unsafe { alloc.deallocate_frame(frame) };
+                }
+                return;
+            }
+        }
+        crate::debug_println!("[Shmem] Allocator busy, leaking {} frames", self.frames.len());
+    }
+}
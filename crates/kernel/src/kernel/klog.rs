@@ -0,0 +1,450 @@
+// kernel/src/kernel/klog.rs
+//! カーネルログリングバッファ
+//!
+//! `log` クレートのロガーとして登録され、`log::info!` などのマクロで出力された
+//! メッセージを固定長スロットのリングバッファに記録します。
+//! シリアルへの出力は `console_level` 以下のメッセージに限られるため、
+//! システムコールごとのトレースのような大量のログもメモリに残すだけで済みます。
+//!
+//! ```rust,ignore
+//! log::debug!(target: "mm", "mapped {:#x}", addr);   // タグ "mm"
+//! log::warn!("something odd");                          // タグはモジュール名の末尾
+//! ```
+//!
+//! # レベル
+//!
+//! - `record_level`: リングに記録するレベル（既定: Debug）
+//! - `console_level`: シリアルにも出力するレベル（既定: Info）
+//!
+//! どちらも `SYS_DEBUG_SET_LEVEL` で実行時に変更でき、記録内容は
+//! `SYS_DMESG` でユーザー空間から読み出せます。
+//!
+//! # ロックフリー
+//!
+//! 書き込み側は通し番号 (`seq`) を `fetch_add` で確保し、`seq % LOG_SLOTS`
+//! 番目のスロットにシーケンスロック方式で書き込みます。割り込みハンドラや
+//! パニック中でもロックを取らずに記録できます。
+//!
+//! 読み出し側はスロットの状態を前後で比較し、書き込み中や上書き済みの
+//! スロットを読み飛ばします。`LOG_SLOTS` 件以上離れた 2 つの書き込みが同じ
+//! スロットで同時に進行した場合のみ内容が混ざる可能性がありますが、
+//! 実用上は起こりません。
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{fence, AtomicU64, AtomicUsize, Ordering};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+/// リングバッファのスロット数
+pub const LOG_SLOTS: usize = 1024;
+/// タグの最大長（バイト）
+pub const MAX_TAG_LEN: usize = TAG_WORDS * 8;
+/// メッセージの最大長（バイト）。超えた分は切り詰める
+pub const MAX_TEXT_LEN: usize = TEXT_WORDS * 8;
+
+const TAG_WORDS: usize = 2;
+const TEXT_WORDS: usize = 22;
+
+/// 1 件分のスロット
+///
+/// `state` は未使用なら 0、`seq` の書き込み中は `seq * 2 + 1`、
+/// 書き込み完了後は `seq * 2 + 2` になります。
+struct Slot {
+    state: AtomicU64,
+    /// level | tag_len << 8 | text_len << 16 | cpu << 32
+    meta: AtomicU64,
+    ticks: AtomicU64,
+    tag: [AtomicU64; TAG_WORDS],
+    text: [AtomicU64; TEXT_WORDS],
+}
+
+impl Slot {
+    const fn new() -> Self {
+        Self {
+            state: AtomicU64::new(0),
+            meta: AtomicU64::new(0),
+            ticks: AtomicU64::new(0),
+            tag: [const { AtomicU64::new(0) }; TAG_WORDS],
+            text: [const { AtomicU64::new(0) }; TEXT_WORDS],
+        }
+    }
+}
+
+/// 読み出したログレコード
+#[derive(Clone)]
+pub struct LogRecord {
+    /// 通し番号
+    pub seq: u64,
+    /// ログレベル
+    pub level: Level,
+    /// 記録時のタイマーティック
+    pub ticks: u64,
+    /// 記録した CPU
+    pub cpu: u32,
+    tag: [u8; MAX_TAG_LEN],
+    tag_len: usize,
+    text: [u8; MAX_TEXT_LEN],
+    text_len: usize,
+}
+
+impl LogRecord {
+    /// サブシステムタグ
+    #[must_use]
+    pub fn tag(&self) -> &str {
+        core::str::from_utf8(&self.tag[..self.tag_len]).unwrap_or("?")
+    }
+
+    /// メッセージ本文
+    #[must_use]
+    pub fn text(&self) -> &str {
+        core::str::from_utf8(&self.text[..self.text_len]).unwrap_or("<invalid>")
+    }
+}
+
+/// 読み出しエラー
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadError {
+    /// まだ書き込まれていない（または書き込み中）
+    NotYet,
+    /// 上書きされて失われた
+    Lost,
+}
+
+/// ログリングバッファ
+pub struct KernelLog {
+    next: AtomicU64,
+    slots: [Slot; LOG_SLOTS],
+}
+
+impl KernelLog {
+    /// 空のリングバッファを作成
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            next: AtomicU64::new(0),
+            slots: [const { Slot::new() }; LOG_SLOTS],
+        }
+    }
+
+    /// 次に書き込まれる通し番号
+    pub fn next_seq(&self) -> u64 {
+        self.next.load(Ordering::Acquire)
+    }
+
+    /// まだ上書きされていない最古の通し番号
+    pub fn oldest_seq(&self) -> u64 {
+        self.next_seq().saturating_sub(LOG_SLOTS as u64)
+    }
+
+    /// メッセージを記録し、通し番号を返す
+    pub fn push(&self, level: Level, tag: &str, args: fmt::Arguments) -> u64 {
+        let mut text = Truncating::<MAX_TEXT_LEN>::new();
+        let _ = text.write_fmt(args);
+        let mut tag_buf = Truncating::<MAX_TAG_LEN>::new();
+        let _ = tag_buf.write_str(tag);
+
+        let seq = self.next.fetch_add(1, Ordering::Relaxed);
+        let slot = &self.slots[seq as usize % LOG_SLOTS];
+        let writing = seq * 2 + 1;
+
+        slot.state.store(writing, Ordering::Relaxed);
+        fence(Ordering::Release);
+
+        let cpu = u64::from(crate::arch::x86_64::smp::current_cpu_id());
+        let meta = level as u64
+            | (tag_buf.len as u64) << 8
+            | (text.len as u64) << 16
+            | cpu << 32;
+        slot.meta.store(meta, Ordering::Relaxed);
+        slot.ticks.store(crate::kernel::r#async::timer::current_ticks(), Ordering::Relaxed);
+        store_bytes(&slot.tag, &tag_buf.buf);
+        store_bytes(&slot.text, &text.buf);
+
+        // 同じスロットを後から確保した書き込みに追い越されていたら、
+        // こちらのレコードは破棄する
+        let _ = slot.state.compare_exchange(writing, writing + 1, Ordering::Release, Ordering::Relaxed);
+        seq
+    }
+
+    /// 通し番号 `seq` のレコードを読み出す
+    pub fn read(&self, seq: u64) -> Result<LogRecord, ReadError> {
+        if seq >= self.next_seq() {
+            return Err(ReadError::NotYet);
+        }
+        let slot = &self.slots[seq as usize % LOG_SLOTS];
+        let committed = seq * 2 + 2;
+
+        // 小さければ古いレコードが残っているか書き込み中、大きければ上書き済み
+        match slot.state.load(Ordering::Acquire) {
+            state if state < committed => return Err(ReadError::NotYet),
+            state if state > committed => return Err(ReadError::Lost),
+            _ => {}
+        }
+
+        let meta = slot.meta.load(Ordering::Relaxed);
+        let mut record = LogRecord {
+            seq,
+            level: level_from_u64(meta & 0xFF).unwrap_or(Level::Error),
+            ticks: slot.ticks.load(Ordering::Relaxed),
+            cpu: (meta >> 32) as u32,
+            tag: [0; MAX_TAG_LEN],
+            tag_len: (((meta >> 8) & 0xFF) as usize).min(MAX_TAG_LEN),
+            text: [0; MAX_TEXT_LEN],
+            text_len: (((meta >> 16) & 0xFFFF) as usize).min(MAX_TEXT_LEN),
+        };
+        load_bytes(&slot.tag, &mut record.tag);
+        load_bytes(&slot.text, &mut record.text);
+
+        fence(Ordering::Acquire);
+        if slot.state.load(Ordering::Relaxed) != committed {
+            return Err(ReadError::Lost);
+        }
+        Ok(record)
+    }
+}
+
+impl Default for KernelLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 固定長バッファへの切り詰め付き書き込み（文字境界で切る）
+struct Truncating<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> Truncating<N> {
+    const fn new() -> Self {
+        Self { buf: [0; N], len: 0 }
+    }
+}
+
+impl<const N: usize> Write for Truncating<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut take = s.len().min(N - self.len);
+        while !s.is_char_boundary(take) {
+            take -= 1;
+        }
+        self.buf[self.len..self.len + take].copy_from_slice(&s.as_bytes()[..take]);
+        self.len += take;
+        Ok(())
+    }
+}
+
+fn store_bytes(words: &[AtomicU64], bytes: &[u8]) {
+    for (word, chunk) in words.iter().zip(bytes.chunks(8)) {
+        let mut raw = [0u8; 8];
+        raw[..chunk.len()].copy_from_slice(chunk);
+        word.store(u64::from_le_bytes(raw), Ordering::Relaxed);
+    }
+}
+
+fn load_bytes(words: &[AtomicU64], bytes: &mut [u8]) {
+    for (word, chunk) in words.iter().zip(bytes.chunks_mut(8)) {
+        let raw = word.load(Ordering::Relaxed).to_le_bytes();
+        chunk.copy_from_slice(&raw[..chunk.len()]);
+    }
+}
+
+/// ABI のレベル番号 (`KLOG_*`) から `LevelFilter` へ変換
+#[must_use]
+pub const fn level_filter_from_u64(value: u64) -> Option<LevelFilter> {
+    match value {
+        0 => Some(LevelFilter::Off),
+        1 => Some(LevelFilter::Error),
+        2 => Some(LevelFilter::Warn),
+        3 => Some(LevelFilter::Info),
+        4 => Some(LevelFilter::Debug),
+        5 => Some(LevelFilter::Trace),
+        _ => None,
+    }
+}
+
+const fn level_from_u64(value: u64) -> Option<Level> {
+    match value {
+        1 => Some(Level::Error),
+        2 => Some(Level::Warn),
+        3 => Some(Level::Info),
+        4 => Some(Level::Debug),
+        5 => Some(Level::Trace),
+        _ => None,
+    }
+}
+
+/// カーネル全体のログ
+pub static KERNEL_LOG: KernelLog = KernelLog::new();
+
+static RECORD_LEVEL: AtomicUsize = AtomicUsize::new(LevelFilter::Debug as usize);
+static CONSOLE_LEVEL: AtomicUsize = AtomicUsize::new(LevelFilter::Info as usize);
+
+/// `log` クレートのロガー実装
+struct KernelLogger;
+
+static LOGGER: KernelLogger = KernelLogger;
+
+impl Log for KernelLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        let level = record.level() as usize;
+        let tag = short_tag(record.target());
+
+        if level <= RECORD_LEVEL.load(Ordering::Relaxed) {
+            KERNEL_LOG.push(record.level(), tag, *record.args());
+        }
+        if level <= CONSOLE_LEVEL.load(Ordering::Relaxed) {
+            crate::kernel::driver::write_debug(format_args!("[{}] {}: {}\n", record.level(), tag, record.args()));
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// モジュールパス (`tiny_os::kernel::mm::paging`) の末尾をタグにする
+fn short_tag(target: &str) -> &str {
+    target.rsplit("::").next().unwrap_or(target)
+}
+
+/// ロガーを登録
+///
+/// ヒープ不要のため、起動直後に呼び出せます。
+pub fn init() {
+    if log::set_logger(&LOGGER).is_ok() {
+        update_max_level();
+    }
+}
+
+fn update_max_level() {
+    let record = RECORD_LEVEL.load(Ordering::Relaxed);
+    let console = CONSOLE_LEVEL.load(Ordering::Relaxed);
+    log::set_max_level(level_filter_from_u64(record.max(console) as u64).unwrap_or(LevelFilter::Trace));
+}
+
+/// 現在の記録レベルとシリアル出力レベル
+#[must_use]
+pub fn levels() -> (LevelFilter, LevelFilter) {
+    let get = |level: &AtomicUsize| level_filter_from_u64(level.load(Ordering::Relaxed) as u64).unwrap_or(LevelFilter::Off);
+    (get(&RECORD_LEVEL), get(&CONSOLE_LEVEL))
+}
+
+/// 記録レベルとシリアル出力レベルを設定（`None` は変更しない）
+pub fn set_levels(record: Option<LevelFilter>, console: Option<LevelFilter>) {
+    if let Some(level) = record {
+        RECORD_LEVEL.store(level as usize, Ordering::Relaxed);
+    }
+    if let Some(level) = console {
+        CONSOLE_LEVEL.store(level as usize, Ordering::Relaxed);
+    }
+    update_max_level();
+}
+
+/// `dmesg` の 1 行の最大長
+const MAX_LINE_LEN: usize = 64 + MAX_TAG_LEN + MAX_TEXT_LEN;
+
+/// レコードを `dmesg` 形式の 1 行に整形
+///
+/// ```text
+/// 42 [   12.340] INFO syscall: message
+/// ```
+fn format_record(record: &LogRecord, out: &mut impl Write) -> fmt::Result {
+    let ms = crate::kernel::r#async::timer::ticks_to_ms(record.ticks);
+    writeln!(
+        out,
+        "{} [{:>5}.{:03}] {} {}: {}",
+        record.seq,
+        ms / 1000,
+        ms % 1000,
+        record.level,
+        record.tag(),
+        record.text()
+    )
+}
+
+/// `*cursor` 以降のレコードを `dmesg` 形式で `out` に書き込む
+///
+/// 行単位で書き込み、収まらない行の手前で止めます。`*cursor` は最後に
+/// 書き込んだレコードの次へ進みます。上書き済みのレコードは読み飛ばします。
+///
+/// 書き込んだバイト数を返します。
+pub fn read_lines(log: &KernelLog, cursor: &mut u64, out: &mut [u8]) -> usize {
+    let mut written = 0;
+    let mut seq = (*cursor).max(log.oldest_seq());
+
+    while seq < log.next_seq() {
+        let record = match log.read(seq) {
+            Ok(record) => record,
+            Err(ReadError::Lost) => {
+                seq += 1;
+                continue;
+            }
+            Err(ReadError::NotYet) => break,
+        };
+
+        let mut line = Truncating::<MAX_LINE_LEN>::new();
+        let _ = format_record(&record, &mut line);
+        let Some(dest) = out.get_mut(written..written + line.len) else {
+            break;
+        };
+        dest.copy_from_slice(&line.buf[..line.len]);
+        written += line.len;
+        seq += 1;
+    }
+
+    *cursor = seq;
+    written
+}
+
+/// 1 行分を受け取るのに必要なバッファサイズ
+pub const DMESG_MIN_BUFFER: usize = MAX_LINE_LEN;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_push_and_read() {
+        // スタックに置くには大きすぎるため static にする
+        static LOG: KernelLog = KernelLog::new();
+        let log = &LOG;
+        let seq = log.push(Level::Warn, "mm", format_args!("value={}", 42));
+        assert_eq!(seq, 0);
+
+        let record = log.read(seq).expect("committed record");
+        assert_eq!(record.level, Level::Warn);
+        assert_eq!(record.tag(), "mm");
+        assert_eq!(record.text(), "value=42");
+        assert_eq!(log.read(1).err(), Some(ReadError::NotYet));
+    }
+
+    #[test_case]
+    fn test_overwritten_records_are_lost() {
+        static LOG: KernelLog = KernelLog::new();
+        let log = &LOG;
+        for i in 0..=LOG_SLOTS {
+            log.push(Level::Info, "test", format_args!("{}", i));
+        }
+        assert_eq!(log.oldest_seq(), 1);
+        assert_eq!(log.read(0).err(), Some(ReadError::Lost));
+        assert_eq!(log.read(LOG_SLOTS as u64).expect("latest").text(), "1024");
+
+        // 失われたレコードを読み飛ばし、収まる行だけを書き込む
+        let mut cursor = 0;
+        let mut out = [0u8; 64];
+        let written = read_lines(log, &mut cursor, &mut out);
+        assert!(written > 0);
+        assert!(out[..written].ends_with(b"\n"));
+        assert_eq!(cursor, 3);
+    }
+
+    #[test_case]
+    fn test_truncates_on_char_boundary() {
+        let mut buf = Truncating::<4>::new();
+        let _ = buf.write_str("abあ");
+        assert_eq!(buf.len, 2);
+        assert_eq!(short_tag("tiny_os::kernel::syscall"), "syscall");
+    }
+}
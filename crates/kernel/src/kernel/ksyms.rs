@@ -0,0 +1,204 @@
+// kernel/src/kernel/ksyms.rs
+//! カーネルシンボルテーブル
+//!
+//! バックトレースのアドレスを関数名に変換するための圧縮シンボルテーブルです。
+//! カーネル自身のシンボルはリンク後にしか分からないため、イメージ内に
+//! `.ksyms` セクションを予約しておき、リンク後に `tools/build/mksyms` が
+//! ELF の `.symtab` から生成したテーブルを書き込みます。
+//!
+//! `tools/build/builder` はカーネルのビルド後に自動で `mksyms` を実行します。
+//! 手動でビルドした場合は次のように書き込みます。
+//!
+//! ```text
+//! cd tools/build/mksyms
+//! CARGO_BUILD_TARGET= cargo run --release -- ../../../target/x86_64-rany_os/debug/tiny_os
+//! ```
+//!
+//! 書き込まれていない場合はアドレスのみを表示します。
+//!
+//! # フォーマット (リトルエンディアン)
+//!
+//! ```text
+//! 0   magic   "KSYM"
+//! 4   count   u32       シンボル数
+//! 8   base    u64       最初のシンボルのアドレス
+//! 16  entries           アドレス順
+//!       delta   LEB128  前のシンボルからのアドレス差分（最初は base から）
+//!       shared  u8      前のシンボル名と共通する先頭バイト数
+//!       len     u8      残りの名前の長さ
+//!       name    [u8]    残りの名前
+//! ```
+//!
+//! 名前は前方一致圧縮 (front coding) されているため、先頭から順に復元します。
+//! 参照はパニック時などに限られるので線形探索で十分です。
+
+/// `.ksyms` セクションのサイズ
+pub const KSYMS_CAPACITY: usize = 1024 * 1024;
+/// テーブルのマジック
+pub const KSYMS_MAGIC: &[u8; 4] = b"KSYM";
+/// ヘッダのサイズ
+const HEADER_SIZE: usize = 16;
+/// シンボル名の最大長
+pub const MAX_NAME_LEN: usize = 255;
+
+/// リンク後に `mksyms` が書き込むシンボルテーブル
+#[used]
+#[unsafe(link_section = ".ksyms")]
+static KSYMS: [u8; KSYMS_CAPACITY] = [0; KSYMS_CAPACITY];
+
+/// 解決されたシンボル
+#[derive(Clone)]
+pub struct Symbol {
+    name: [u8; MAX_NAME_LEN],
+    name_len: usize,
+    /// シンボルの先頭アドレス
+    pub address: u64,
+    /// シンボル先頭からのオフセット
+    pub offset: u64,
+}
+
+impl Symbol {
+    /// シンボル名
+    #[must_use]
+    pub fn name(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.name_len]).unwrap_or("<invalid>")
+    }
+}
+
+/// シンボルテーブル
+pub struct SymbolTable<'a> {
+    count: u32,
+    base: u64,
+    entries: &'a [u8],
+}
+
+impl<'a> SymbolTable<'a> {
+    /// バイト列からテーブルを解析
+    ///
+    /// マジックが一致しなければ `None` を返します。
+    #[must_use]
+    pub fn parse(data: &'a [u8]) -> Option<Self> {
+        if data.len() < HEADER_SIZE || &data[..4] != KSYMS_MAGIC {
+            return None;
+        }
+        Some(Self {
+            count: u32::from_le_bytes(data[4..8].try_into().ok()?),
+            base: u64::from_le_bytes(data[8..16].try_into().ok()?),
+            entries: &data[HEADER_SIZE..],
+        })
+    }
+
+    /// シンボル数
+    #[must_use]
+    pub const fn len(&self) -> u32 {
+        self.count
+    }
+
+    /// シンボルがないか
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// `addr` を含むシンボルを探す
+    ///
+    /// `addr` 以下で最大のアドレスを持つシンボルを返します。最後のシンボルより
+    /// 後ろのアドレスは、どこまでが関数か分からないため解決しません
+    /// (`mksyms` は `.text` の末尾に名前が空の終端シンボルを置きます)。
+    #[must_use]
+    pub fn lookup(&self, addr: u64) -> Option<Symbol> {
+        let mut current = Symbol { name: [0; MAX_NAME_LEN], name_len: 0, address: self.base, offset: 0 };
+        let mut best: Option<Symbol> = None;
+        let mut pos = 0;
+
+        for _ in 0..self.count {
+            let (delta, used) = read_uleb128(self.entries.get(pos..)?)?;
+            pos += used;
+            let shared = usize::from(*self.entries.get(pos)?);
+            let len = usize::from(*self.entries.get(pos + 1)?);
+            let suffix = self.entries.get(pos + 2..pos + 2 + len)?;
+            pos += 2 + len;
+
+            let shared = shared.min(MAX_NAME_LEN);
+            let take = len.min(MAX_NAME_LEN - shared);
+            current.name[shared..shared + take].copy_from_slice(&suffix[..take]);
+            current.name_len = shared + take;
+            current.address = current.address.wrapping_add(delta);
+
+            if current.address > addr {
+                // 次のシンボルが見つかった時点で直前のシンボルが確定する
+                // (名前が空のものは .text 末尾を示す終端シンボル)
+                return best.filter(|sym| sym.name_len > 0);
+            }
+            best = Some(Symbol { offset: addr - current.address, ..current.clone() });
+        }
+        None
+    }
+}
+
+/// LEB128 (符号なし) を読み、値と消費したバイト数を返す
+fn read_uleb128(data: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0u64;
+    for (i, &byte) in data.iter().enumerate().take(10) {
+        value |= u64::from(byte & 0x7F) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+/// カーネルに埋め込まれたシンボルテーブル
+///
+/// `mksyms` で書き込まれていなければ `None` を返します。
+#[must_use]
+pub fn kernel_symbols() -> Option<SymbolTable<'static>> {
+    // コンパイラは KSYMS を全ゼロの定数として扱うため、
+    // リンク後の書き換えが見えるよう black_box で最適化を止める
+    let data: &'static [u8; KSYMS_CAPACITY] = core::hint::black_box(&KSYMS);
+    SymbolTable::parse(data)
+}
+
+/// カーネルアドレスをシンボルに解決
+#[must_use]
+pub fn resolve(addr: u64) -> Option<Symbol> {
+    kernel_symbols()?.lookup(addr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2 シンボルのテーブル: 0x1000 "foo::bar", 0x1040 "foo::baz"
+    const TABLE: &[u8] = &[
+        b'K', b'S', b'Y', b'M', 2, 0, 0, 0,
+        0x00, 0x10, 0, 0, 0, 0, 0, 0,
+        0x00, 0, 8, b'f', b'o', b'o', b':', b':', b'b', b'a', b'r',
+        0x40, 7, 1, b'z',
+    ];
+
+    #[test_case]
+    fn test_lookup_front_coded_names() {
+        let table = SymbolTable::parse(TABLE).expect("valid table");
+        assert_eq!(table.len(), 2);
+
+        let sym = table.lookup(0x1010).expect("foo::bar");
+        assert_eq!(sym.name(), "foo::bar");
+        assert_eq!(sym.offset, 0x10);
+
+        // 最後のシンボルより後ろは解決しない
+        assert!(table.lookup(0x1044).is_none());
+        assert!(table.lookup(0x0FFF).is_none());
+    }
+
+    #[test_case]
+    fn test_uleb128() {
+        assert_eq!(read_uleb128(&[0xE5, 0x8E, 0x26]), Some((624_485, 3)));
+        assert_eq!(read_uleb128(&[0x80]), None);
+    }
+
+    #[test_case]
+    fn test_unpatched_table_is_rejected() {
+        assert!(SymbolTable::parse(&[0; HEADER_SIZE]).is_none());
+    }
+}
@@ -3,7 +3,11 @@
 
 use x86_64::VirtAddr;
 // use crate::kernel::process::{Process, ProcessId};
-use crate::kernel::mm::user_paging::{map_user_code, map_user_stack, USER_CODE_BASE, DEFAULT_USER_STACK_SIZE};
+use crate::kernel::mm::user_paging::{map_user_code, map_user_stack, user_stack_area, user_stack_limit, UserLayout, USER_CODE_BASE, DEFAULT_USER_STACK_SIZE};
+use crate::kernel::mm::vma::{Vma, VmaKind};
+use crate::abi::mman::{PROT_EXEC, PROT_READ};
+use alloc::vec;
+use alloc::vec::Vec;
 use x86_64::structures::paging::{OffsetPageTable, FrameAllocator, Size4KiB};
 
 /// Loaded program information
@@ -12,6 +16,8 @@ pub struct LoadedProgram {
     pub entry_point: VirtAddr,
     /// Top of the user stack
     pub stack_top: VirtAddr,
+    /// Memory areas of the image and stack
+    pub areas: Vec<Vma>,
 }
 
 /// Load error types
@@ -30,10 +36,14 @@ pub enum LoadError {
 /// 
 /// Load embedded user program into a new process
 ///
+/// Flat binaries are position-dependent, so the code always goes to
+/// `USER_CODE_BASE`; only the stack follows `layout`.
+///
 /// # Arguments
 /// * `data` - Program binary data
 /// * `mapper` - User page table mapper
 /// * `frame_allocator` - Frame allocator
+/// * `layout` - Address-space layout of the new process
 ///
 /// # Returns
 /// LoadedProgram info
@@ -41,6 +51,7 @@ pub fn load_user_program<A>(
     data: &[u8],
     mapper: &mut OffsetPageTable,
     frame_allocator: &mut A,
+    layout: &UserLayout,
 ) -> Result<LoadedProgram, LoadError>
 where
     A: FrameAllocator<Size4KiB>,
@@ -62,12 +73,19 @@ where
     
     // Map stack
     let stack_top = unsafe {
-        map_user_stack(mapper, DEFAULT_USER_STACK_SIZE, frame_allocator)
+        map_user_stack(mapper, VirtAddr::new(layout.stack_top), DEFAULT_USER_STACK_SIZE, frame_allocator)
             .map_err(|_| LoadError::MappingFailure)?
     };
     
+    let code_end = (USER_CODE_BASE + code.len() as u64 + 4095) & !4095;
+    let areas = vec![
+        Vma::new(USER_CODE_BASE, code_end.max(USER_CODE_BASE + 4096), PROT_READ | PROT_EXEC, VmaKind::Image),
+        user_stack_area(layout.stack_top, user_stack_limit()),
+    ];
+
     Ok(LoadedProgram {
         entry_point,
         stack_top,
+        areas,
     })
 }
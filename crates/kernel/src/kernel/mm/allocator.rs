@@ -3,6 +3,9 @@
 //!
 //! ヒープ割り当てを管理します。
 //! リンクリストベースのアロケータを実装しています。
+//!
+//! 拡張ハンドラ（[`LockedHeap::set_grow_handler`]）が設定されていれば、
+//! 空きが足りないときにヒープの末尾へ領域を追加してから再試行します。
 
 use core::alloc::{GlobalAlloc, Layout};
 use core::ptr;
@@ -32,6 +35,12 @@ pub struct HeapStats {
     pub allocation_count: usize,
     /// 解放回数
     pub deallocation_count: usize,
+    /// ヒープを拡張した回数
+    pub grow_count: usize,
+    /// 拡張によって増えたバイト数
+    pub grown_bytes: LayoutSize,
+    /// 拡張に失敗した回数（上限到達またはメモリ不足）
+    pub grow_failures: usize,
 }
 
 impl HeapStats {
@@ -44,6 +53,9 @@ impl HeapStats {
             peak_usage: LayoutSize::zero(),
             allocation_count: 0,
             deallocation_count: 0,
+            grow_count: 0,
+            grown_bytes: LayoutSize::zero(),
+            grow_failures: 0,
         }
     }
     
@@ -98,10 +110,33 @@ impl ListNode {
     }
 }
 
+/// ヒープ拡張ハンドラ
+///
+/// `[start, start + size)` を書き込み可能なメモリとして用意できたら `true` を
+/// 返します。`start` は常に現在のヒープの末尾で、`size` はページの倍数です。
+/// アロケータのロックを保持したまま呼ばれるため、ヒープを使ってはいけません。
+pub type HeapGrowHandler = fn(VirtAddr, LayoutSize) -> bool;
+
+/// ヒープの OOM ハンドラ
+///
+/// 拡張できずに割り当てが失敗したとき、アロケータのロックをすべて手放して
+/// から呼ばれます。メモリを空けられて割り当てをやり直せるなら `true` を
+/// 返します。
+pub type HeapOomHandler = fn() -> bool;
+
+/// 1 回の拡張で追加する最小サイズ
+const GROW_MIN: usize = 256 * 1024;
+/// 拡張サイズの単位
+const GROW_GRANULE: usize = 4096;
+
 /// リンクリストベースのヒープアロケータ
 pub struct LinkedListAllocator {
     head: ListNode,
     stats: HeapStats,
+    /// ヒープ末尾（次に拡張する位置）
+    heap_end: usize,
+    /// 拡張ハンドラ（`None` なら固定サイズ）
+    grow_handler: Option<HeapGrowHandler>,
 }
 
 impl Default for LinkedListAllocator {
@@ -116,6 +151,8 @@ impl LinkedListAllocator {
         Self {
             head: ListNode::new(LayoutSize::zero()),
             stats: HeapStats::new(),
+            heap_end: 0,
+            grow_handler: None,
         }
     }
 
@@ -153,6 +190,7 @@ impl LinkedListAllocator {
 
         // stats.heap_capacity は実際に使えるサイズを記録する
         self.stats.heap_capacity = usable_size;
+        self.heap_end = aligned_start.as_usize() + usable_size.as_usize();
 
         // 実際に free リージョンを追加（aligned_start, usable_size）
         // Safety: aligned_start は align_up によって ListNode のアラインに整えられている
@@ -320,6 +358,35 @@ impl LinkedListAllocator {
         None
     }
 
+    /// `size` バイトを `align` で割り当てられるだけヒープを拡張
+    ///
+    /// 末尾の空きブロックとは結合されるため、拡張分だけで足りなくても
+    /// 直前の空きと合わせて割り当てられます。
+    fn grow(&mut self, size: usize, align: usize) -> bool {
+        let Some(handler) = self.grow_handler else {
+            return false;
+        };
+        let needed = size.saturating_add(align).saturating_add(mem::size_of::<ListNode>());
+        let bytes = needed.max(GROW_MIN).next_multiple_of(GROW_GRANULE);
+        let start = VirtAddr::new(self.heap_end);
+        if !handler(start, LayoutSize::new(bytes)) {
+            self.stats.grow_failures += 1;
+            return false;
+        }
+
+        // SAFETY: ハンドラが [heap_end, heap_end + bytes) を用意した
+        unsafe {
+            self.add_free_region(start, LayoutSize::new(bytes));
+        }
+        self.heap_end += bytes;
+        self.stats.heap_capacity = self.stats.heap_capacity.checked_add(LayoutSize::new(bytes))
+            .expect("Heap capacity overflow");
+        self.stats.grown_bytes = self.stats.grown_bytes.checked_add(LayoutSize::new(bytes))
+            .expect("Heap grown bytes overflow");
+        self.stats.grow_count += 1;
+        true
+    }
+
     /// 指定された領域から割り当てを試みる
     fn alloc_from_region(region: &ListNode, size: usize, align: usize) -> Result<usize, ()> {
         let region_start = region.start_addr();
@@ -447,6 +514,14 @@ impl LockedHeap {
     pub fn stats(&self) -> HeapStats {
         self.inner.lock().stats()
     }
+
+    /// 空きが足りないときに呼ぶ拡張ハンドラを設定
+    ///
+    /// ハンドラは [`init`](Self::init) で渡した領域の末尾から順に
+    /// 連続した領域を用意する必要があります。
+    pub fn set_grow_handler(&self, handler: HeapGrowHandler) {
+        self.inner.lock().grow_handler = Some(handler);
+    }
 }
 
 unsafe impl GlobalAlloc for LockedHeap {
@@ -458,7 +533,12 @@ unsafe impl GlobalAlloc for LockedHeap {
         
         let mut allocator = self.inner.lock();
 
-        if let Some((region_start, region_end, region_size, alloc_start)) = allocator.find_region(size, align) {
+        let found = match allocator.find_region(size, align) {
+            Some(found) => Some(found),
+            None if allocator.grow(size, align) => allocator.find_region(size, align),
+            None => None,
+        };
+        if let Some((region_start, region_end, region_size, alloc_start)) = found {
             // Prefixの処理
             let region_start_val = region_start.as_usize();
             if alloc_start > region_start_val {
@@ -663,4 +743,41 @@ mod tests {
             assert!(ptr_full.is_null());
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_grow_on_demand() {
+        const INITIAL: usize = 4096;
+        #[repr(C, align(4096))]
+        struct Memory([u8; INITIAL + GROW_MIN]);
+        static mut HEAP_MEM: Memory = Memory([0; INITIAL + GROW_MIN]);
+
+        fn grow_into_static(start: VirtAddr, size: LayoutSize) -> bool {
+            let base = core::ptr::addr_of!(HEAP_MEM) as usize;
+            start.as_usize() + size.as_usize() <= base + INITIAL + GROW_MIN
+        }
+
+        let heap = LockedHeap::new();
+        unsafe {
+            let start = core::ptr::addr_of_mut!(HEAP_MEM) as usize;
+            heap.init(VirtAddr::new(start), LayoutSize::new(INITIAL)).unwrap();
+        }
+        let layout = Layout::from_size_align(2 * INITIAL, 8).unwrap();
+
+        // 拡張ハンドラがなければ失敗する
+        assert!(unsafe { heap.alloc(layout) }.is_null());
+
+        heap.set_grow_handler(grow_into_static);
+        let ptr = unsafe { heap.alloc(layout) };
+        assert!(!ptr.is_null());
+        let stats = heap.stats();
+        assert_eq!(stats.grow_count, 1);
+        assert_eq!(stats.grown_bytes.as_usize(), GROW_MIN);
+        assert_eq!(stats.heap_capacity.as_usize(), INITIAL + GROW_MIN);
+
+        // 上限を超える拡張は失敗として数える
+        let huge = Layout::from_size_align(2 * GROW_MIN, 8).unwrap();
+        assert!(unsafe { heap.alloc(huge) }.is_null());
+        assert_eq!(heap.stats().grow_failures, 1);
+        unsafe { heap.dealloc(ptr, layout) };
+    }
+}
@@ -0,0 +1,358 @@
+// kernel/src/kernel/mm/buddy.rs
+//! バディアロケータ
+//!
+//! 物理フレームを 2 の累乗個（オーダー）のブロック単位で管理します。
+//! オーダー `k` のブロックは `2^k` フレームで、先頭 PFN が `2^k` に
+//! 整列しています。割り当てでは大きいブロックを半分ずつ分割し、解放では
+//! 相方（バディ、`pfn ^ (1 << k)`）が空いていれば結合するため、どちらも
+//! O(MAX_ORDER) で終わります。
+//!
+//! ゾーンごとに独立したフリーリストを持ちます。4 GiB 境界は最大ブロック
+//! より大きい単位で整列しているため、ブロックがゾーンをまたぐことは
+//! ありません。
+//!
+//! フリーリストのリンクは空きブロックの先頭フレーム自体に書き込み
+//! （ダイレクトマップ経由）、フレームごとのメタデータは参照カウント
+//! (`u16`) と空きブロックのオーダー (`u8`) の 3 バイトだけです。
+//! ヒープは使いません。
+
+use core::ptr;
+
+/// 最大オーダー（4 MiB ブロック）
+pub const MAX_ORDER: usize = 10;
+
+/// ページサイズ
+const PAGE_SIZE: u64 = 4096;
+
+/// DMA32 ゾーンの上限 PFN (4 GiB)
+const DMA32_LIMIT_PFN: u64 = (1 << 32) / PAGE_SIZE;
+
+/// `order` 配列で「空きブロックの先頭ではない」ことを表す値
+const NOT_FREE: u8 = u8::MAX;
+
+/// フリーリストの終端
+const NIL: u64 = u64::MAX;
+
+/// ゾーン数
+pub const ZONE_COUNT: usize = 2;
+
+/// 物理メモリゾーン
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Zone {
+    /// 4 GiB 未満（32 ビット DMA デバイスから到達可能）
+    Dma32,
+    /// 4 GiB 以上
+    Normal,
+}
+
+impl Zone {
+    /// `pfn` が属するゾーン
+    #[must_use]
+    pub const fn of(pfn: u64) -> Self {
+        if pfn < DMA32_LIMIT_PFN { Self::Dma32 } else { Self::Normal }
+    }
+
+    const fn index(self) -> usize {
+        match self {
+            Self::Dma32 => 0,
+            Self::Normal => 1,
+        }
+    }
+}
+
+/// 空きブロックの先頭フレームに書き込むリンク
+#[repr(C)]
+struct FreeNode {
+    next: u64,
+    prev: u64,
+}
+
+/// バディアロケータ本体
+pub struct BuddyAllocator {
+    /// メタデータ配列の先頭に対応する PFN
+    base_pfn: u64,
+    /// フレームごとの参照カウント
+    refs: &'static mut [u16],
+    /// 空きブロックの先頭ならそのオーダー、それ以外は `NOT_FREE`
+    order: &'static mut [u8],
+    /// ゾーン・オーダーごとのフリーリストの先頭 PFN
+    heads: [[u64; MAX_ORDER + 1]; ZONE_COUNT],
+    /// ゾーンごとの空きフレーム数
+    free: [usize; ZONE_COUNT],
+    /// 物理アドレスから仮想アドレスへのオフセット（ダイレクトマップ）
+    phys_offset: u64,
+}
+
+impl BuddyAllocator {
+    /// 空のアロケータを作成（すべてのフレームは使用中扱い）
+    ///
+    /// `refs[i]` と `order[i]` が PFN `base_pfn + i` のメタデータになります。
+    ///
+    /// # Safety
+    /// 後で [`add_free_range`](Self::add_free_range) に渡すフレームは、
+    /// `phys_offset` を足した仮想アドレスで書き込み可能であること。
+    ///
+    /// # Panics
+    /// 2 つの配列の長さが異なる場合
+    pub unsafe fn new(
+        base_pfn: u64,
+        refs: &'static mut [u16],
+        order: &'static mut [u8],
+        phys_offset: u64,
+    ) -> Self {
+        assert_eq!(refs.len(), order.len());
+        refs.fill(0);
+        order.fill(NOT_FREE);
+        Self {
+            base_pfn,
+            refs,
+            order,
+            heads: [[NIL; MAX_ORDER + 1]; ZONE_COUNT],
+            free: [0; ZONE_COUNT],
+            phys_offset,
+        }
+    }
+
+    /// `[start_pfn, end_pfn)` を空きとして追加
+    ///
+    /// # Safety
+    /// 範囲のフレームが他で使われておらず、メタデータの範囲内であること
+    pub unsafe fn add_free_range(&mut self, start_pfn: u64, end_pfn: u64) {
+        let mut pfn = start_pfn;
+        while pfn < end_pfn {
+            // 整列と残りの長さが許す最大のブロックで追加する
+            let align = if pfn == 0 { MAX_ORDER } else { pfn.trailing_zeros() as usize };
+            let fit = (end_pfn - pfn).ilog2() as usize;
+            let order = align.min(fit).min(MAX_ORDER);
+            // SAFETY: 呼び出し元が範囲の未使用を保証している
+            unsafe { self.free_block(pfn, order) };
+            pfn += 1 << order;
+        }
+    }
+
+    /// オーダー `order` のブロックを割り当て、先頭 PFN を返す
+    ///
+    /// `Zone::Normal` を指定した場合は Normal を優先し、足りなければ
+    /// DMA32 から割り当てます。各フレームの参照カウントは 1 になります。
+    pub fn allocate(&mut self, order: usize, zone: Zone) -> Option<u64> {
+        if order > MAX_ORDER {
+            return None;
+        }
+        let pfn = match zone {
+            Zone::Normal => self
+                .allocate_in(order, Zone::Normal)
+                .or_else(|| self.allocate_in(order, Zone::Dma32)),
+            Zone::Dma32 => self.allocate_in(order, Zone::Dma32),
+        }?;
+        let start = self.index(pfn)?;
+        self.refs[start..start + (1 << order)].fill(1);
+        Some(pfn)
+    }
+
+    /// 指定ゾーンからの割り当て（分割込み）
+    fn allocate_in(&mut self, order: usize, zone: Zone) -> Option<u64> {
+        let z = zone.index();
+        let found = (order..=MAX_ORDER).find(|&o| self.heads[z][o] != NIL)?;
+        let pfn = self.heads[z][found];
+        self.unlink(pfn, found);
+
+        // 余った上半分をひとつずつ下のオーダーへ戻す
+        let mut current = found;
+        while current > order {
+            current -= 1;
+            self.link(pfn + (1 << current), current);
+        }
+        self.free[z] -= 1 << order;
+        Some(pfn)
+    }
+
+    /// ブロックを解放し、バディと結合する
+    ///
+    /// 参照カウントは見ません（呼び出し元で 0 にしておく）。
+    ///
+    /// # Safety
+    /// ブロックが割り当て済みで、以後使われないこと
+    pub unsafe fn free_block(&mut self, pfn: u64, order: usize) {
+        let z = Zone::of(pfn).index();
+        self.free[z] += 1 << order;
+
+        let mut pfn = pfn;
+        let mut order = order;
+        while order < MAX_ORDER {
+            let buddy = pfn ^ (1 << order);
+            match self.index(buddy) {
+                Some(i) if self.order[i] == order as u8 => {}
+                _ => break,
+            }
+            self.unlink(buddy, order);
+            pfn = pfn.min(buddy);
+            order += 1;
+        }
+        self.link(pfn, order);
+    }
+
+    /// 参照カウントを 1 増やす
+    ///
+    /// 割り当てられていないフレームには何もせず `false` を返します。
+    /// 上限に達したフレームは以後解放されません。
+    pub fn add_reference(&mut self, pfn: u64) -> bool {
+        match self.index(pfn) {
+            Some(i) if self.refs[i] != 0 => {
+                self.refs[i] = self.refs[i].saturating_add(1);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// 参照カウントを 1 減らし、0 になったら `true` を返す
+    ///
+    /// 割り当てられていないフレームと上限に達したフレームは `false` です。
+    pub fn release(&mut self, pfn: u64) -> bool {
+        match self.index(pfn) {
+            Some(i) if self.refs[i] != 0 && self.refs[i] != u16::MAX => {
+                self.refs[i] -= 1;
+                self.refs[i] == 0
+            }
+            _ => false,
+        }
+    }
+
+    /// フレームがこのアロケータの管理範囲にあるか
+    #[must_use]
+    pub fn manages(&self, pfn: u64) -> bool {
+        self.index(pfn).is_some()
+    }
+
+    /// 参照カウント（管理外のフレームは 0）
+    #[must_use]
+    pub fn reference_count(&self, pfn: u64) -> u16 {
+        self.index(pfn).map_or(0, |i| self.refs[i])
+    }
+
+    /// ゾーンの空きフレーム数
+    #[must_use]
+    pub const fn free_frames(&self, zone: Zone) -> usize {
+        self.free[zone.index()]
+    }
+
+    /// メタデータ配列のインデックス
+    fn index(&self, pfn: u64) -> Option<usize> {
+        let i = usize::try_from(pfn.checked_sub(self.base_pfn)?).ok()?;
+        (i < self.refs.len()).then_some(i)
+    }
+
+    /// 空きブロックの先頭フレームのリンク
+    fn node(&self, pfn: u64) -> *mut FreeNode {
+        (self.phys_offset + pfn * PAGE_SIZE) as *mut FreeNode
+    }
+
+    /// フリーリストの先頭に追加
+    fn link(&mut self, pfn: u64, order: usize) {
+        let z = Zone::of(pfn).index();
+        let head = self.heads[z][order];
+        // SAFETY: 空きブロックの先頭フレームはアロケータだけが書き込む
+        unsafe {
+            ptr::write(self.node(pfn), FreeNode { next: head, prev: NIL });
+            if head != NIL {
+                (*self.node(head)).prev = pfn;
+            }
+        }
+        self.heads[z][order] = pfn;
+        if let Some(i) = self.index(pfn) {
+            self.order[i] = order as u8;
+        }
+    }
+
+    /// フリーリストから取り除く
+    fn unlink(&mut self, pfn: u64, order: usize) {
+        let z = Zone::of(pfn).index();
+        // SAFETY: `pfn` はフリーリスト上のブロック
+        unsafe {
+            let FreeNode { next, prev } = ptr::read(self.node(pfn));
+            if prev == NIL {
+                self.heads[z][order] = next;
+            } else {
+                (*self.node(prev)).next = next;
+            }
+            if next != NIL {
+                (*self.node(next)).prev = prev;
+            }
+        }
+        if let Some(i) = self.index(pfn) {
+            self.order[i] = NOT_FREE;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// テスト用の「物理メモリ」フレーム数
+    const FRAMES: usize = 64;
+
+    #[repr(C, align(4096))]
+    struct Memory([u8; FRAMES * PAGE_SIZE as usize]);
+
+    /// `base_pfn` から `FRAMES` 個を静的バッファで模したアロケータ
+    fn buddy(memory: &'static mut Memory, base_pfn: u64) -> BuddyAllocator {
+        let refs = alloc::vec![0u16; FRAMES].leak();
+        let order = alloc::vec![0u8; FRAMES].leak();
+        let phys_offset = (memory as *mut Memory as u64).wrapping_sub(base_pfn * PAGE_SIZE);
+        // SAFETY: バッファは 'static で、テストだけが使う
+        let mut buddy = unsafe { BuddyAllocator::new(base_pfn, refs, order, phys_offset) };
+        unsafe { buddy.add_free_range(base_pfn, base_pfn + FRAMES as u64) };
+        buddy
+    }
+
+    #[test_case]
+    fn test_split_and_coalesce() {
+        static mut MEMORY: Memory = Memory([0; FRAMES * PAGE_SIZE as usize]);
+        // SAFETY: このテストだけが使う
+        let mut buddy = buddy(unsafe { &mut *core::ptr::addr_of_mut!(MEMORY) }, 0x100);
+        assert_eq!(buddy.free_frames(Zone::Dma32), FRAMES);
+
+        let a = buddy.allocate(0, Zone::Dma32).unwrap();
+        let b = buddy.allocate(0, Zone::Dma32).unwrap();
+        assert_eq!(a ^ b, 1, "two single frames come from one split pair");
+        let big = buddy.allocate(4, Zone::Normal).unwrap();
+        assert_eq!(big % 16, 0, "blocks are naturally aligned");
+        assert_eq!(buddy.free_frames(Zone::Dma32), FRAMES - 2 - 16);
+
+        unsafe {
+            buddy.free_block(a, 0);
+            buddy.free_block(b, 0);
+            buddy.free_block(big, 4);
+        }
+        assert_eq!(buddy.free_frames(Zone::Dma32), FRAMES);
+        // 結合されていれば全体をひとつのブロックとして取り直せる
+        assert_eq!(buddy.allocate(6, Zone::Dma32), Some(0x100));
+    }
+
+    #[test_case]
+    fn test_reference_counts() {
+        static mut MEMORY: Memory = Memory([0; FRAMES * PAGE_SIZE as usize]);
+        // SAFETY: このテストだけが使う
+        let mut buddy = buddy(unsafe { &mut *core::ptr::addr_of_mut!(MEMORY) }, 0x200);
+
+        let pfn = buddy.allocate(0, Zone::Dma32).unwrap();
+        assert_eq!(buddy.reference_count(pfn), 1);
+        assert!(buddy.add_reference(pfn));
+        assert!(!buddy.release(pfn));
+        assert!(buddy.release(pfn));
+
+        // 空きフレームと管理外のフレームは参照を持てない
+        assert!(!buddy.add_reference(pfn));
+        assert!(!buddy.release(0x10));
+        assert!(buddy.manages(pfn));
+        assert!(!buddy.manages(0x10));
+    }
+
+    #[test_case]
+    fn test_zone_of() {
+        assert_eq!(Zone::of(0), Zone::Dma32);
+        assert_eq!(Zone::of(DMA32_LIMIT_PFN - 1), Zone::Dma32);
+        assert_eq!(Zone::of(DMA32_LIMIT_PFN), Zone::Normal);
+    }
+}
@@ -2,44 +2,164 @@
 //! 物理フレーム管理
 //!
 //! ブートローダから渡されたメモリマップに基づいて、物理メモリフレームを管理します。
+//!
+//! 1 MiB 以上の利用可能 (Usable) 領域はすべてバディアロケータ
+//! ([`super::buddy`]) に渡されます。初期化時に指定された予約範囲
+//! （ファームウェアが使う範囲など）とメタデータ自身の領域は除外され、
+//! [`FrameStats::reserved`] として数えられます。
+//!
+//! 空きフレーム数には [`Watermarks`] で 3 段階のしきい値を設けます。
+//! `low` を下回るとメモリ回収 ([`super::oom`]) を始め、`min` を下回ったまま
+//! 回収できなければ OOM キラーが動きます。
 
+use core::ops::Range;
+use core::sync::atomic::Ordering;
 use bootloader_api::info::{MemoryRegionKind, MemoryRegions};
 use x86_64::structures::paging::{FrameAllocator, FrameDeallocator, PhysFrame, Size4KiB};
 use x86_64::PhysAddr;
-use alloc::collections::{VecDeque, BTreeMap};
+
+use super::buddy::{BuddyAllocator, MAX_ORDER};
+pub use super::buddy::Zone;
+use super::PHYS_MEM_OFFSET;
+
+/// ページサイズ
+const PAGE_SIZE: u64 = 4096;
+
+/// 低位メモリ (< 1MB) は管理しない
+///
+/// - NULL pointer (0x0)
+/// - BIOS data area
+/// - Real mode IVT
+/// - Video memory
+const SAFE_MEMORY_START: u64 = 0x100000; // 1MB
+
+/// 予約範囲の最大数（メタデータの分を含む）
+const MAX_RESERVED: usize = 8;
+
+/// `min` ウォーターマークの下限（フレーム数）
+const MIN_WATERMARK_FLOOR: usize = 32;
+/// `min` ウォーターマークの上限（フレーム数、64 MiB）
+const MIN_WATERMARK_CEILING: usize = 16384;
+
+/// 空きフレーム数のしきい値
+///
+/// `min <= low <= high` を満たします。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Watermarks {
+    /// これを下回ると OOM キラーの対象になる
+    pub min: usize,
+    /// これを下回るとメモリ回収を始める
+    pub low: usize,
+    /// 回収はここまで空きを増やす
+    pub high: usize,
+}
+
+impl Watermarks {
+    /// 空きフレーム数から既定のしきい値を計算
+    ///
+    /// `min` は空きの 1/128 を [`MIN_WATERMARK_FLOOR`] と
+    /// [`MIN_WATERMARK_CEILING`] の間に収めたもの（ただし空きの 1/4 以下）で、
+    /// `low` と `high` はその 1.25 倍と 1.5 倍です。
+    pub const fn for_free_frames(free: usize) -> Self {
+        let mut min = free / 128;
+        if min < MIN_WATERMARK_FLOOR {
+            min = MIN_WATERMARK_FLOOR;
+        }
+        if min > MIN_WATERMARK_CEILING {
+            min = MIN_WATERMARK_CEILING;
+        }
+        if min > free / 4 {
+            min = free / 4;
+        }
+        Watermarks {
+            min,
+            low: min + min / 4,
+            high: min + min / 2,
+        }
+    }
+
+    /// 空きフレーム数に対する逼迫度
+    pub const fn pressure(&self, free: usize) -> Pressure {
+        if free < self.min {
+            Pressure::Critical
+        } else if free < self.low {
+            Pressure::Low
+        } else {
+            Pressure::None
+        }
+    }
+}
+
+/// メモリの逼迫度
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Pressure {
+    /// 空きが `low` 以上
+    None,
+    /// 空きが `low` 未満（回収する）
+    Low,
+    /// 空きが `min` 未満（回収できなければプロセスを殺す）
+    Critical,
+}
+
+/// フレーム統計情報
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameStats {
+    /// 管理対象の総フレーム数（予約分を含む）
+    pub total: usize,
+    /// 空きフレーム数
+    pub free: usize,
+    /// 予約済みフレーム数（予約範囲、アロケータのメタデータ）
+    pub reserved: usize,
+    /// DMA32 ゾーンの空きフレーム数
+    pub dma32_free: usize,
+}
+
+impl FrameStats {
+    /// 割り当て中のフレーム数
+    pub const fn allocated(&self) -> usize {
+        self.total - self.free - self.reserved
+    }
+}
 
 /// ブート情報（メモリマップ）に基づくフレームアロケータ
 ///
-/// バンプアロケータ + フリーリストのハイブリッド実装。
-/// 解放されたフレームはフリーリストで再利用されます。
+/// バディアロケータでフレームを管理し、フレームごとの参照カウントで
+/// 共有（Copy-on-Write、共有メモリ）を扱います。
 pub struct BootInfoFrameAllocator {
-    memory_map: &'static MemoryRegions,
-    next: usize,
-    /// 解放されたフレームのリスト（再利用可能）
-    free_frames: VecDeque<PhysFrame<Size4KiB>>,
-    /// フレームの参照カウント
-    references: BTreeMap<PhysFrame<Size4KiB>, usize>,
+    buddy: BuddyAllocator,
+    total: usize,
+    reserved: usize,
+    watermarks: Watermarks,
 }
 
 impl BootInfoFrameAllocator {
     /// メモリマップからフレームアロケータを初期化
     ///
+    /// `reserved` の物理アドレス範囲は割り当て対象から除外されます。
+    /// メタデータ（フレームあたり 3 バイト）は利用可能領域から確保し、
+    /// ダイレクトマップ経由でアクセスするため、`PHYS_MEM_OFFSET` を
+    /// 先に設定しておく必要があります。ヒープは使いません。
+    ///
     /// # Safety
     ///
     /// この関数を呼び出すには、以下の条件を満たす必要があります:
-    /// 
+    ///
     /// - `memory_map` が有効なメモリ領域情報を含むこと
     /// - `memory_map` のライフタイムが 'static であり、プログラム全体で有効であること
     /// - メモリマップ内の各領域が有効なアドレス範囲を指していること
     /// - この関数は一度だけ呼び出されるべきであること
-    /// - 割り当てられるフレームが他の目的で使用中でないこと
-    pub unsafe fn init(memory_map: &'static MemoryRegions) -> Self {
+    /// - `reserved` 以外の利用可能領域が他の目的で使用中でないこと
+    ///
+    /// # Panics
+    ///
+    /// 予約範囲が多すぎる場合、またはメタデータを置く領域がない場合
+    pub unsafe fn init(memory_map: &'static MemoryRegions, reserved: &[Range<u64>]) -> Self {
         // メモリマップが空でないことを確認（デバッグビルドのみ）
         debug_assert!(
             memory_map.iter().count() > 0,
             "Memory map must not be empty"
         );
-        
+
         // 各メモリ領域の基本的な妥当性を確認（デバッグビルドのみ）
         #[cfg(debug_assertions)]
         for region in memory_map.iter() {
@@ -52,102 +172,212 @@ impl BootInfoFrameAllocator {
                 "Memory region must not overflow"
             );
         }
-        
+
+        let phys_offset = PHYS_MEM_OFFSET.load(Ordering::Relaxed);
+
+        // 管理対象の PFN 範囲
+        let (min_pfn, max_pfn) = usable_ranges(memory_map).fold((u64::MAX, 0), |(lo, hi), r| {
+            (lo.min(r.start / PAGE_SIZE), hi.max(r.end / PAGE_SIZE))
+        });
+        assert!(min_pfn < max_pfn, "no usable memory");
+        let frames = (max_pfn - min_pfn) as usize;
+
+        // 参照カウント (u16) とオーダー (u8) の配列を置く場所を探す
+        assert!(reserved.len() < MAX_RESERVED, "too many reserved ranges");
+        let mut holes: [Range<u64>; MAX_RESERVED] = core::array::from_fn(|_| 0..0);
+        holes[..reserved.len()].clone_from_slice(reserved);
+        let meta_size = (frames as u64 * 3).next_multiple_of(PAGE_SIZE);
+        let mut meta_start = None;
+        for_each_free_range(memory_map, &holes[..reserved.len()], |range| {
+            if meta_start.is_none() && range.end - range.start >= meta_size {
+                meta_start = Some(range.start);
+            }
+        });
+        let meta_start = meta_start.expect("no room for frame allocator metadata");
+        holes[reserved.len()] = meta_start..meta_start + meta_size;
+        let holes = &holes[..=reserved.len()];
+
+        // SAFETY: メタデータ領域は予約済みで、ダイレクトマップされている
+        let (refs, order) = unsafe {
+            let refs = (phys_offset + meta_start) as *mut u16;
+            let order = refs.add(frames) as *mut u8;
+            (
+                core::slice::from_raw_parts_mut(refs, frames),
+                core::slice::from_raw_parts_mut(order, frames),
+            )
+        };
+        // SAFETY: 利用可能領域はダイレクトマップ経由で書き込める
+        let mut buddy = unsafe { BuddyAllocator::new(min_pfn, refs, order, phys_offset) };
+
+        let total = usable_ranges(memory_map)
+            .map(|r| ((r.end - r.start) / PAGE_SIZE) as usize)
+            .sum();
+        for_each_free_range(memory_map, holes, |range| {
+            // SAFETY: 予約範囲を除いた利用可能領域で、まだ誰も使っていない
+            unsafe { buddy.add_free_range(range.start / PAGE_SIZE, range.end / PAGE_SIZE) };
+        });
+
+        let free = buddy.free_frames(Zone::Dma32) + buddy.free_frames(Zone::Normal);
         BootInfoFrameAllocator {
-            memory_map,
-            next: 0,
-            free_frames: VecDeque::new(),
-            references: BTreeMap::new(),
-        }
-    }
-
-    /// 利用可能なフレームのイテレータを返す
-    fn usable_frames(&self) -> impl Iterator<Item = PhysFrame> {
-        // CRITICAL: Skip low memory (< 1MB) to avoid:
-        // - NULL pointer (0x0)
-        // - BIOS data area
-        // - Real mode IVT
-        // - Video memory
-        // - DMA zones
-        const SAFE_MEMORY_START: u64 = 0x100000; // 1MB
-        
-        // 利用可能な領域（Usable）のみを抽出し、低位メモリをスキップ
-        let regions = self.memory_map.iter();
-        let usable_regions = regions
-            .filter(|r| r.kind == MemoryRegionKind::Usable)
-            .filter(|r| r.end > SAFE_MEMORY_START); // Skip regions entirely below 1MB
-        
-        // 各領域をフレームのアドレス範囲に変換
-        let addr_ranges = usable_regions
-            .map(|r| {
-                // Clamp start address to SAFE_MEMORY_START
-                let start_addr = r.start.max(SAFE_MEMORY_START);
-                let start = PhysFrame::containing_address(PhysAddr::new(start_addr));
-                let end = PhysFrame::containing_address(PhysAddr::new(r.end - 1));
-                PhysFrame::range_inclusive(start, end)
-            });
-        
-        // 範囲をフラットなフレーム列に変換
-        addr_ranges.flatten()
-    }
-    
-    /// フレームの参照カウントを増やす
-    pub fn add_reference(&mut self, frame: PhysFrame<Size4KiB>) {
-        let count = self.references.entry(frame).or_insert(0);
-        *count += 1;
+            buddy,
+            total,
+            reserved: total - free,
+            watermarks: Watermarks::for_free_frames(free),
+        }
+    }
+
+    /// `2^order` 個の連続したフレームを割り当てる
+    ///
+    /// 先頭フレームは `2^order` フレーム境界に整列しています。
+    /// `Zone::Normal` は DMA32 にフォールバックしますが、`Zone::Dma32`
+    /// は 4 GiB 未満からのみ割り当てます。
+    pub fn allocate_pages(&mut self, order: usize, zone: Zone) -> Option<PhysFrame<Size4KiB>> {
+        let pfn = self.buddy.allocate(order, zone)?;
+        Some(PhysFrame::containing_address(PhysAddr::new(pfn * PAGE_SIZE)))
     }
 
-    /// フレームの参照カウントを減らす
+    /// [`allocate_pages`](Self::allocate_pages) で確保したフレームを解放
+    ///
+    /// 各フレームの参照を 1 つずつ落とします。どのフレームも共有されて
+    /// いなければブロックごと一度に解放します。
     ///
-    /// 参照カウントが0になった場合はtrueを返し、呼び出し元で解放処理を行う必要があることを示します。
-    /// (このメソッド自体は解放を行いません)
-    pub fn remove_reference(&mut self, frame: PhysFrame<Size4KiB>) -> bool {
-        if let Some(count) = self.references.get_mut(&frame) {
-            *count -= 1;
-            if *count == 0 {
-                self.references.remove(&frame);
-                return true;
+    /// # Safety
+    ///
+    /// `frame` と `order` が割り当て時と同じで、呼び出し側の参照が
+    /// 以後使われないこと
+    pub unsafe fn deallocate_pages(&mut self, frame: PhysFrame<Size4KiB>, order: usize) {
+        if order > MAX_ORDER {
+            return;
+        }
+        let pfn = frame.start_address().as_u64() / PAGE_SIZE;
+        let count = 1u64 << order;
+        if (pfn..pfn + count).all(|p| self.buddy.reference_count(p) == 1) {
+            for p in pfn..pfn + count {
+                self.buddy.release(p);
             }
-            return false;
+            // SAFETY: すべての参照がなくなったブロック
+            unsafe { self.buddy.free_block(pfn, order) };
+            return;
+        }
+        for p in pfn..pfn + count {
+            let frame = PhysFrame::containing_address(PhysAddr::new(p * PAGE_SIZE));
+            // SAFETY: 呼び出し側の参照を落とすだけ
+            unsafe { self.deallocate_frame(frame) };
         }
-        // 参照がない場合は既に解放されているとみなす（または管理外）
-        true
     }
 
-    /// フレームを解放してフリーリストに追加
+    /// フレームの参照カウントを増やす
+    ///
+    /// 管理外のフレーム（initrd など）や割り当てられていないフレームは
+    /// 無視します。
+    pub fn add_reference(&mut self, frame: PhysFrame<Size4KiB>) {
+        let pfn = frame.start_address().as_u64() / PAGE_SIZE;
+        if self.buddy.manages(pfn) && !self.buddy.add_reference(pfn) {
+            crate::debug_println!("[Frame] add_reference on unallocated frame {:#x}", pfn * PAGE_SIZE);
+        }
+    }
+
+    /// フレームの参照カウント（割り当てられていなければ 0）
+    pub fn reference_count(&self, frame: PhysFrame<Size4KiB>) -> usize {
+        usize::from(self.buddy.reference_count(frame.start_address().as_u64() / PAGE_SIZE))
+    }
+
+    /// フレームの参照を 1 つ落とし、最後の参照なら解放する
+    ///
+    /// 管理外のフレーム（ブートローダのページテーブルなど）や
+    /// 割り当てられていないフレームは無視します。
     ///
     /// # Safety
     ///
     /// 呼び出し側は以下を保証する必要があります:
     /// - `frame` がこのアロケータから割り当てられたものであること
-    /// - `frame` が他の場所で使用されていないこと
-    /// - `frame` が二重解放されないこと
+    /// - 呼び出し側の参照が以後使われないこと
     pub unsafe fn deallocate_frame(&mut self, frame: PhysFrame<Size4KiB>) {
-        // 参照カウントを減らす
-        if self.remove_reference(frame) {
-            // 参照がなくなった場合のみフリーリストに追加
-            self.free_frames.push_back(frame);
+        let pfn = frame.start_address().as_u64() / PAGE_SIZE;
+        if self.buddy.release(pfn) {
+            // SAFETY: 最後の参照が落ちたフレーム
+            unsafe { self.buddy.free_block(pfn, 0) };
+        }
+    }
+
+    /// 現在のしきい値
+    pub const fn watermarks(&self) -> Watermarks {
+        self.watermarks
+    }
+
+    /// しきい値を設定
+    ///
+    /// # Errors
+    /// `min <= low <= high` を満たさない場合、または `high` が管理対象の
+    /// フレーム数を超える場合
+    pub fn set_watermarks(&mut self, watermarks: Watermarks) -> Result<(), &'static str> {
+        if watermarks.min > watermarks.low || watermarks.low > watermarks.high {
+            return Err("watermarks must satisfy min <= low <= high");
+        }
+        if watermarks.high > self.total - self.reserved {
+            return Err("high watermark exceeds managed memory");
+        }
+        self.watermarks = watermarks;
+        Ok(())
+    }
+
+    /// 現在の逼迫度
+    pub fn pressure(&self) -> Pressure {
+        self.watermarks.pressure(self.free_frames())
+    }
+
+    /// 空きフレーム数
+    pub fn free_frames(&self) -> usize {
+        self.buddy.free_frames(Zone::Dma32) + self.buddy.free_frames(Zone::Normal)
+    }
+
+    /// 統計情報を取得
+    pub fn stats(&self) -> FrameStats {
+        let dma32_free = self.buddy.free_frames(Zone::Dma32);
+        FrameStats {
+            total: self.total,
+            free: dma32_free + self.buddy.free_frames(Zone::Normal),
+            reserved: self.reserved,
+            dma32_free,
+        }
+    }
+}
+
+/// 1 MiB 以上の利用可能領域（ページ境界に丸めたもの）
+fn usable_ranges(memory_map: &MemoryRegions) -> impl Iterator<Item = Range<u64>> + '_ {
+    memory_map
+        .iter()
+        .filter(|r| r.kind == MemoryRegionKind::Usable)
+        .map(|r| r.start.max(SAFE_MEMORY_START).next_multiple_of(PAGE_SIZE)..r.end & !(PAGE_SIZE - 1))
+        .filter(|r| r.start < r.end)
+}
+
+/// 利用可能領域から `holes` を除いた範囲（ページ境界）ごとに `f` を呼ぶ
+fn for_each_free_range(memory_map: &MemoryRegions, holes: &[Range<u64>], mut f: impl FnMut(Range<u64>)) {
+    for range in usable_ranges(memory_map) {
+        let mut cursor = range.start;
+        while cursor < range.end {
+            // カーソル以降で最初に重なる穴
+            let hole = holes
+                .iter()
+                .filter(|h| h.start < range.end && h.end > cursor)
+                .min_by_key(|h| h.start);
+            let Some(hole) = hole else {
+                f(cursor..range.end);
+                break;
+            };
+            let piece_end = (hole.start & !(PAGE_SIZE - 1)).max(cursor);
+            if piece_end > cursor {
+                f(cursor..piece_end);
+            }
+            cursor = hole.end.next_multiple_of(PAGE_SIZE);
         }
     }
 }
 
 unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
     fn allocate_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
-        // まずフリーリストから取得を試みる
-        if let Some(frame) = self.free_frames.pop_front() {
-            self.add_reference(frame);
-            return Some(frame);
-        }
-        
-        // フリーリストが空の場合は通常の割り当て
-        let frame = self.usable_frames().nth(self.next);
-        self.next += 1;
-        
-        if let Some(frame) = frame {
-            self.add_reference(frame);
-        }
-        
-        frame
+        self.allocate_pages(0, Zone::Normal)
     }
 }
 
@@ -161,7 +391,7 @@ impl FrameDeallocator<Size4KiB> for BootInfoFrameAllocator {
     }
 }
 
-// Safety: BootInfoFrameAllocator only reads from MemoryRegions which is static and immutable after boot.
+// Safety: The metadata arrays and free lists are owned by the allocator alone.
 // Access to the allocator itself is synchronized via Mutex in BOOT_INFO_ALLOCATOR.
 unsafe impl Send for BootInfoFrameAllocator {}
 unsafe impl Sync for BootInfoFrameAllocator {}
@@ -175,4 +405,3 @@ unsafe impl FrameAllocator<Size4KiB> for EmptyFrameAllocator {
         None
     }
 }
-
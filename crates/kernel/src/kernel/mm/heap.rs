@@ -0,0 +1,231 @@
+// kernel/src/kernel/mm/heap.rs
+//! カーネルヒープ領域
+//!
+//! カーネルヒープを専用の仮想領域（PML4 エントリ 1 つ分）に置きます。
+//! 起動時は [`INITIAL_HEAP_SIZE`] だけマップし、足りなくなると
+//! [`grow`] がフレームアロケータからフレームを取ってきて末尾に追加します。
+//!
+//! ```text
+//! base                      base + size           base + ceiling
+//! [ マップ済み (ヒープ) ][ 未マップ (拡張用) ... ]|
+//! ```
+//!
+//! 上限は [`set_heap_ceiling`] で変更できます。
+//!
+//! 拡張はアロケータのロックを保持したまま行うため、フレームアロケータの
+//! ロックを `lock` で待つと、フレームアロケータを保持したままヒープを使う
+//! コードとの間でデッドロックします。そこで
+//! [`oom::try_lock_spinning`] で取り直し、それでも取れない場合（この CPU
+//! 自身が保持している場合など）は予備フレームから拡張します。予備はロックを
+//! 取れた拡張のたびに [`RESERVE_FRAMES`] 枚まで補充します。
+//!
+//! 回収してもフレームが足りず拡張できなかった場合、割り当ては
+//! アロケータのロックを手放してから [`out_of_memory`] を呼び、OOM キラーで
+//! プロセスを終了させてやり直します。
+
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use spin::Mutex;
+use x86_64::structures::paging::{FrameAllocator, Mapper, Page, PageTableFlags, PhysFrame, Size4KiB};
+
+use super::allocator::BOOT_INFO_ALLOCATOR;
+use super::mmap::{current_mapper, map_anonymous};
+use super::oom::{self, OomAction};
+use super::paging::{reserve_kernel_region, PML4_ENTRY_SIZE};
+use super::types::{LayoutSize, VirtAddr};
+use super::BootInfoFrameAllocator;
+
+/// 起動時にマップするヒープサイズ (4 MiB)
+pub const INITIAL_HEAP_SIZE: usize = 4 * 1024 * 1024;
+/// ヒープサイズ上限の既定値 (256 MiB)
+pub const DEFAULT_HEAP_CEILING: usize = 256 * 1024 * 1024;
+
+/// ページサイズ
+const PAGE_SIZE: usize = 4096;
+
+/// 予備フレームの枚数（最小拡張 256 KiB 分とページテーブル分）
+const RESERVE_FRAMES: usize = 72;
+
+/// 領域の先頭アドレス（[`init`] 前は 0）
+static REGION_BASE: AtomicU64 = AtomicU64::new(0);
+/// ヒープサイズの上限
+static HEAP_CEILING: AtomicUsize = AtomicUsize::new(DEFAULT_HEAP_CEILING);
+/// マップ済みのヒープサイズ
+static MAPPED_SIZE: AtomicUsize = AtomicUsize::new(0);
+/// 直前の拡張がフレーム不足で失敗したか
+static OUT_OF_FRAMES: AtomicBool = AtomicBool::new(false);
+/// フレームアロケータを使えないときの拡張に使う予備フレーム
+static RESERVE: Mutex<Reserve> = Mutex::new(Reserve::new());
+
+/// 予備フレームの置き場
+struct Reserve {
+    frames: [Option<PhysFrame<Size4KiB>>; RESERVE_FRAMES],
+    len: usize,
+}
+
+impl Reserve {
+    const fn new() -> Self {
+        Self { frames: [None; RESERVE_FRAMES], len: 0 }
+    }
+
+    /// [`RESERVE_FRAMES`] 枚まで補充
+    fn refill(&mut self, frame_allocator: &mut BootInfoFrameAllocator) {
+        while self.len < RESERVE_FRAMES {
+            let Some(frame) = frame_allocator.allocate_frame() else {
+                break;
+            };
+            self.frames[self.len] = Some(frame);
+            self.len += 1;
+        }
+    }
+}
+
+// SAFETY: 予備のフレームはアロケータから取ったもので、取り出すまで誰も使わない
+unsafe impl FrameAllocator<Size4KiB> for Reserve {
+    fn allocate_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
+        self.len = self.len.checked_sub(1)?;
+        self.frames[self.len].take()
+    }
+}
+
+/// ヒープ領域を確保し、初期サイズ分をマップする
+///
+/// 返した範囲をグローバルアロケータの初期化に渡し、その後 [`grow`] を
+/// 拡張ハンドラとして設定します。最初のプロセスを作成する前に一度だけ
+/// 呼び出します。
+///
+/// # Errors
+/// 空き PML4 エントリがない場合、またはメモリ不足の場合
+pub fn init(frame_allocator: &mut BootInfoFrameAllocator) -> Result<(VirtAddr, LayoutSize), &'static str> {
+    let base = reserve_kernel_region(frame_allocator)?;
+    let size = INITIAL_HEAP_SIZE.min(HEAP_CEILING.load(Ordering::Relaxed));
+    map_pages(frame_allocator, base.as_u64(), size).map_err(|()| "failed to map initial heap")?;
+    RESERVE.lock().refill(frame_allocator);
+
+    REGION_BASE.store(base.as_u64(), Ordering::Relaxed);
+    MAPPED_SIZE.store(size, Ordering::Relaxed);
+    Ok((VirtAddr::new(base.as_u64() as usize), LayoutSize::new(size)))
+}
+
+/// ヒープ拡張ハンドラ
+///
+/// `[start, start + size)` をマップします。フレームが足りなければ
+/// [`oom::reclaim`] で回収してからやり直します。フレームアロケータの
+/// ロックを取れない場合は予備フレームを使います。上限を超える場合、
+/// 回収してもメモリが足りない場合、または予備も足りない場合は `false` を
+/// 返します。
+pub fn grow(start: VirtAddr, size: LayoutSize) -> bool {
+    let base = REGION_BASE.load(Ordering::Relaxed);
+    let mapped = MAPPED_SIZE.load(Ordering::Relaxed);
+    if base == 0 || start.as_usize() as u64 != base + mapped as u64 {
+        return false;
+    }
+    let new_size = match mapped.checked_add(size.as_usize()) {
+        Some(new_size) if new_size <= HEAP_CEILING.load(Ordering::Relaxed) => new_size,
+        _ => return false,
+    };
+
+    let start = start.as_usize() as u64;
+    let mut reserve = RESERVE.lock();
+    let grown = match oom::try_lock_spinning(&BOOT_INFO_ALLOCATOR) {
+        Some(mut allocator) => match allocator.as_mut() {
+            Some(frame_allocator) => {
+                let grown = map_with_reclaim(frame_allocator, start, size.as_usize());
+                OUT_OF_FRAMES.store(!grown, Ordering::Relaxed);
+                reserve.refill(frame_allocator);
+                grown
+            }
+            None => false,
+        },
+        None => map_from_reserve(&mut reserve, start, size.as_usize()),
+    };
+    if !grown {
+        return false;
+    }
+    MAPPED_SIZE.store(new_size, Ordering::Relaxed);
+    crate::debug_println!("[Heap] Grew by {} KiB to {} KiB", size.as_usize() / 1024, new_size / 1024);
+    true
+}
+
+/// ヒープの OOM ハンドラ
+///
+/// 拡張できずに割り当てが失敗したとき、アロケータのロックをすべて
+/// 手放してから呼ばれます。直前の拡張がフレーム不足で失敗していれば
+/// [`oom::try_out_of_memory`] で回収と OOM キラーを試し、割り当てを
+/// やり直せるなら `true` を返します。上限に達した場合や、ロックを
+/// 取れない場合、現在のプロセスが選ばれた場合（割り当ての途中では
+/// 終了させられない）は `false` を返し、割り当ては失敗します。
+pub fn out_of_memory() -> bool {
+    OUT_OF_FRAMES.swap(false, Ordering::Relaxed)
+        && oom::try_out_of_memory("kernel heap") == Some(OomAction::Retry)
+}
+
+/// ヒープサイズの上限
+#[must_use]
+pub fn heap_ceiling() -> usize {
+    HEAP_CEILING.load(Ordering::Relaxed)
+}
+
+/// ヒープサイズの上限を設定
+///
+/// # Errors
+/// 現在のヒープサイズより小さい場合、または領域の大きさを超える場合
+pub fn set_heap_ceiling(ceiling: usize) -> Result<(), &'static str> {
+    if ceiling < MAPPED_SIZE.load(Ordering::Relaxed) {
+        return Err("heap ceiling below current heap size");
+    }
+    if ceiling as u64 > PML4_ENTRY_SIZE {
+        return Err("heap ceiling exceeds the heap region");
+    }
+    HEAP_CEILING.store(ceiling, Ordering::Relaxed);
+    Ok(())
+}
+
+/// `[start, start + size)` を書き込み可能・実行不可でマップ
+fn map_pages(frame_allocator: &mut BootInfoFrameAllocator, start: u64, size: usize) -> Result<(), ()> {
+    let page = Page::<Size4KiB>::containing_address(x86_64::VirtAddr::new(start));
+    let count = size.div_ceil(PAGE_SIZE) as u64;
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+    // SAFETY: ヒープ領域の L3 はすべてのアドレス空間で共有されており、
+    // 末尾より先のページはこの関数だけがマップする
+    let mut mapper = unsafe { current_mapper() };
+    map_anonymous(&mut mapper, frame_allocator, page, count, flags).map_err(|_| ())
+}
+
+/// [`map_pages`] し、失敗したら回収してやり直す
+fn map_with_reclaim(frame_allocator: &mut BootInfoFrameAllocator, start: u64, size: usize) -> bool {
+    if map_pages(frame_allocator, start, size).is_ok() {
+        return true;
+    }
+    // ヒープのロックを手放せないので、OOM キラーではなく回収だけを試す
+    let needed = size.div_ceil(PAGE_SIZE);
+    oom::reclaim(frame_allocator, needed) >= needed && map_pages(frame_allocator, start, size).is_ok()
+}
+
+/// `[start, start + size)` を予備フレームでマップ
+///
+/// ページテーブルの分も含めて予備が足りない場合は何もせず `false` を返すので、
+/// 途中までマップされたまま残ることはありません。
+fn map_from_reserve(reserve: &mut Reserve, start: u64, size: usize) -> bool {
+    let count = size.div_ceil(PAGE_SIZE);
+    // L1 テーブルは 512 ページごとに 1 枚と境界をまたぐ分、L2 テーブルは最大 1 枚
+    let tables = count.div_ceil(512) + 2;
+    if reserve.len < count + tables {
+        return false;
+    }
+    let first = Page::<Size4KiB>::containing_address(x86_64::VirtAddr::new(start));
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+    // SAFETY: map_pages と同じ
+    let mut mapper = unsafe { current_mapper() };
+    for page in Page::range(first, first + count as u64) {
+        let Some(frame) = reserve.allocate_frame() else {
+            return false;
+        };
+        // SAFETY: 末尾より先の未使用ページに、予備から取った未使用フレームをマップする
+        match unsafe { mapper.map_to(page, frame, flags, reserve) } {
+            Ok(flush) => flush.flush(),
+            Err(_) => return false,
+        }
+    }
+    crate::debug_println!("[Heap] Frame allocator busy, grew from the reserve ({} left)", reserve.len);
+    true
+}
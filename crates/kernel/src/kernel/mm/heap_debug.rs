@@ -0,0 +1,414 @@
+// kernel/src/kernel/mm/heap_debug.rs
+//! ヒープデバッグモード（`heap_debug` feature）
+//!
+//! すべての割り当てを次の形に包んで [`LockedHeap`] から確保します。
+//!
+//! ```text
+//! [AllocHeader][red zone (>= 16)][user data][red zone (16)]
+//! ```
+//!
+//! - 解放時にヘッダのマジックと前後のレッドゾーンを検査し、二重解放・
+//!   範囲外書き込みを検出します。
+//! - 解放した領域はポイズン値で埋めて隔離リストに入れ、すぐにはヒープへ
+//!   返しません。隔離リストから追い出すときにポイズンが残っているかを検査し、
+//!   解放後の書き込み (use-after-free) を検出します。
+//! - ヘッダには割り当て元の戻りアドレスを記録し、[`dump`] で未解放の
+//!   割り当てを呼び出し元ごとに集計して出力します（リーク調査用）。
+//!
+//! 異常を検出すると、割り当て元を出力してからパニックします。
+//! 有効時はスラブキャッシュを経由せず、すべての割り当てがここを通ります。
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::mem;
+use core::ptr;
+use spin::Mutex;
+
+use super::allocator::LockedHeap;
+use crate::arch::x86_64::backtrace;
+use crate::kernel::ksyms;
+
+/// レッドゾーンの最小サイズ
+const REDZONE: usize = 16;
+/// ブロックの最小アラインメント
+const MIN_ALIGN: usize = 16;
+/// 記録する戻りアドレスの数
+const SITE_DEPTH: usize = 4;
+/// 記録時に飛ばすフレーム数（このモジュールの alloc、
+/// `SlabAllocator::alloc`、`__rust_alloc`）
+const SITE_SKIP: usize = 3;
+/// 隔離リストの長さ
+const QUARANTINE_LEN: usize = 256;
+/// [`dump`] で集計する呼び出し元の最大数
+const MAX_SITES: usize = 32;
+
+const LIVE_MAGIC: u64 = 0xA110_C8ED_A110_C8ED;
+const FREED_MAGIC: u64 = 0xF4EE_D0F4_EED0_F4EE;
+const REDZONE_BYTE: u8 = 0xFD;
+const POISON_BYTE: u8 = 0x6B;
+
+/// 割り当てごとのヘッダ（ブロックの先頭に置く）
+#[repr(C)]
+struct AllocHeader {
+    magic: u64,
+    size: usize,
+    sites: [u64; SITE_DEPTH],
+    /// 未解放の割り当てのリスト
+    prev: *mut AllocHeader,
+    next: *mut AllocHeader,
+}
+
+/// 未解放の割り当てと隔離リスト
+pub struct DebugState {
+    live: *mut AllocHeader,
+    live_count: usize,
+    live_bytes: usize,
+    quarantine: [Option<(usize, Layout)>; QUARANTINE_LEN],
+    quarantine_next: usize,
+}
+
+// SAFETY: 生ポインタは自分が管理するブロックだけを指し、
+// アクセスは外側の Mutex で直列化される
+unsafe impl Send for DebugState {}
+
+impl Default for DebugState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DebugState {
+    /// 空の状態
+    pub const fn new() -> Self {
+        Self {
+            live: ptr::null_mut(),
+            live_count: 0,
+            live_bytes: 0,
+            quarantine: [None; QUARANTINE_LEN],
+            quarantine_next: 0,
+        }
+    }
+
+    fn link(&mut self, header: *mut AllocHeader) {
+        // SAFETY: header は初期化済みでどのリストにも入っていない
+        unsafe {
+            (*header).prev = ptr::null_mut();
+            (*header).next = self.live;
+            if !self.live.is_null() {
+                (*self.live).prev = header;
+            }
+            self.live = header;
+            self.live_count += 1;
+            self.live_bytes += (*header).size;
+        }
+    }
+
+    fn unlink(&mut self, header: *mut AllocHeader) {
+        // SAFETY: header は live リストに入っている
+        unsafe {
+            let (prev, next) = ((*header).prev, (*header).next);
+            if prev.is_null() {
+                self.live = next;
+            } else {
+                (*prev).next = next;
+            }
+            if !next.is_null() {
+                (*next).prev = prev;
+            }
+            self.live_count -= 1;
+            self.live_bytes -= (*header).size;
+        }
+    }
+}
+
+/// 検出した異常
+struct Violation {
+    kind: &'static str,
+    ptr: usize,
+    size: usize,
+    sites: [u64; SITE_DEPTH],
+}
+
+/// 利用者のレイアウトから (ブロックのレイアウト, ユーザー領域のオフセット) を求める
+fn block_layout(layout: Layout) -> Option<(Layout, usize)> {
+    let align = layout.align().max(MIN_ALIGN);
+    let front = (mem::size_of::<AllocHeader>() + REDZONE).next_multiple_of(align);
+    let size = front.checked_add(layout.size())?.checked_add(REDZONE)?;
+    Layout::from_size_align(size, align).ok().map(|block| (block, front))
+}
+
+/// 割り当て元の戻りアドレスを記録
+#[inline(never)]
+fn capture_sites() -> [u64; SITE_DEPTH] {
+    let mut sites = [0; SITE_DEPTH];
+    // 先頭はこの関数の呼び出し元（alloc）なので 1 つ余分に飛ばす
+    for (slot, addr) in sites.iter_mut().zip(backtrace::walk(backtrace::frame_pointer()).skip(SITE_SKIP + 1)) {
+        *slot = addr;
+    }
+    sites
+}
+
+/// `[start, start + len)` がすべて `byte` か
+///
+/// # Safety
+/// 範囲が読み出し可能であること
+unsafe fn filled_with(start: *const u8, len: usize, byte: u8) -> bool {
+    // SAFETY: 呼び出し元が保証する
+    unsafe { core::slice::from_raw_parts(start, len) }.iter().all(|&b| b == byte)
+}
+
+/// デバッグ用ヘッダとレッドゾーン付きで割り当てる
+///
+/// # Safety
+/// [`GlobalAlloc::alloc`] と同じ
+#[inline(never)]
+pub(super) unsafe fn alloc(state: &Mutex<DebugState>, heap: &LockedHeap, layout: Layout) -> *mut u8 {
+    let Some((outer, front)) = block_layout(layout) else {
+        return ptr::null_mut();
+    };
+    // SAFETY: ブロックのサイズは 0 ではない
+    let block = unsafe { heap.alloc(outer) };
+    if block.is_null() {
+        return ptr::null_mut();
+    }
+
+    let header = block.cast::<AllocHeader>();
+    let header_size = mem::size_of::<AllocHeader>();
+    // SAFETY: block は block_layout の大きさで確保したばかり
+    unsafe {
+        header.write(AllocHeader {
+            magic: LIVE_MAGIC,
+            size: layout.size(),
+            sites: capture_sites(),
+            prev: ptr::null_mut(),
+            next: ptr::null_mut(),
+        });
+        ptr::write_bytes(block.add(header_size), REDZONE_BYTE, front - header_size);
+        ptr::write_bytes(block.add(front + layout.size()), REDZONE_BYTE, REDZONE);
+    }
+    state.lock().link(header);
+    // SAFETY: front はブロック内
+    unsafe { block.add(front) }
+}
+
+/// ヘッダとレッドゾーンを検査してから隔離リストに入れる
+///
+/// # Safety
+/// [`GlobalAlloc::dealloc`] と同じ（二重解放などは検出してパニックする）
+pub(super) unsafe fn dealloc(state: &Mutex<DebugState>, heap: &LockedHeap, ptr: *mut u8, layout: Layout) {
+    let Some((_, front)) = block_layout(layout) else {
+        return;
+    };
+    let evicted = {
+        let mut state = state.lock();
+        // SAFETY: ptr はこのモジュールの alloc が返したポインタ
+        match unsafe { retire(&mut state, ptr, layout, front) } {
+            Ok(evicted) => evicted,
+            Err(violation) => {
+                drop(state);
+                report(&violation);
+            }
+        }
+    };
+
+    if let Some((old_ptr, old_layout)) = evicted {
+        // 隔離中は誰も触っていないはずなので、ポイズンが崩れていれば UAF
+        // SAFETY: 隔離リストのブロックは解放前のまま確保されている
+        if let Err(violation) = unsafe { check_poison(old_ptr as *mut u8, old_layout) } {
+            report(&violation);
+        }
+        let (old_block, old_front) = block_layout(old_layout).expect("quarantined layout is valid");
+        // SAFETY: 隔離リストから外したブロックは誰も参照していない
+        unsafe { heap.dealloc((old_ptr as *mut u8).sub(old_front), old_block) };
+    }
+}
+
+/// 検査してポイズンし、隔離リストに入れる。追い出されたエントリを返す
+///
+/// # Safety
+/// `ptr` の前 `front` バイトがヘッダとして読めること
+unsafe fn retire(
+    state: &mut DebugState,
+    ptr: *mut u8,
+    layout: Layout,
+    front: usize,
+) -> Result<Option<(usize, Layout)>, Violation> {
+    // SAFETY: 呼び出し元が保証する
+    let block = unsafe { ptr.sub(front) };
+    let header = block.cast::<AllocHeader>();
+    let header_size = mem::size_of::<AllocHeader>();
+    // SAFETY: ヘッダはブロックの先頭にある
+    let (magic, size, sites) = unsafe { ((*header).magic, (*header).size, (*header).sites) };
+    let violation = |kind| Violation { kind, ptr: ptr as usize, size, sites };
+
+    match magic {
+        LIVE_MAGIC => {}
+        FREED_MAGIC => return Err(violation("double free")),
+        _ => return Err(Violation { kind: "free of unknown pointer or header overwritten", ptr: ptr as usize, size: layout.size(), sites: [0; SITE_DEPTH] }),
+    }
+    if size != layout.size() {
+        return Err(violation("free with mismatched layout"));
+    }
+    // SAFETY: レッドゾーンはブロック内
+    unsafe {
+        if !filled_with(block.add(header_size), front - header_size, REDZONE_BYTE) {
+            return Err(violation("buffer underflow (front red zone overwritten)"));
+        }
+        if !filled_with(ptr.add(size), REDZONE, REDZONE_BYTE) {
+            return Err(violation("buffer overflow (rear red zone overwritten)"));
+        }
+    }
+
+    state.unlink(header);
+    // SAFETY: ブロックはまだ確保されたまま
+    unsafe {
+        (*header).magic = FREED_MAGIC;
+        ptr::write_bytes(ptr, POISON_BYTE, size);
+    }
+    let slot = state.quarantine_next;
+    state.quarantine_next = (slot + 1) % QUARANTINE_LEN;
+    Ok(state.quarantine[slot].replace((ptr as usize, layout)))
+}
+
+/// 隔離していたブロックのポイズンとレッドゾーンを検査
+///
+/// # Safety
+/// `ptr` は隔離リストに入っていたブロックのユーザー領域であること
+unsafe fn check_poison(ptr: *mut u8, layout: Layout) -> Result<(), Violation> {
+    let (_, front) = block_layout(layout).expect("quarantined layout is valid");
+    // SAFETY: 呼び出し元が保証する
+    let header = unsafe { ptr.sub(front) }.cast::<AllocHeader>();
+    let header_size = mem::size_of::<AllocHeader>();
+    // SAFETY: ブロック全体がまだ確保されている
+    unsafe {
+        let intact = (*header).magic == FREED_MAGIC
+            && filled_with(header.cast::<u8>().add(header_size), front - header_size, REDZONE_BYTE)
+            && filled_with(ptr, layout.size(), POISON_BYTE)
+            && filled_with(ptr.add(layout.size()), REDZONE, REDZONE_BYTE);
+        if intact {
+            Ok(())
+        } else {
+            Err(Violation { kind: "write after free", ptr: ptr as usize, size: layout.size(), sites: (*header).sites })
+        }
+    }
+}
+
+/// 戻りアドレスを関数名付きで出力
+fn print_sites(sites: &[u64; SITE_DEPTH]) {
+    for &addr in sites.iter().take_while(|&&addr| addr != 0) {
+        match ksyms::resolve(addr.saturating_sub(1)) {
+            Some(sym) => crate::debug_println!("    {:#018x}  {}+{:#x}", addr, sym.name(), addr - sym.address),
+            None => crate::debug_println!("    {:#018x}", addr),
+        }
+    }
+}
+
+/// 異常を出力してパニック
+fn report(violation: &Violation) -> ! {
+    crate::debug_println!(
+        "[HeapDebug] {}: ptr={:#x} size={}",
+        violation.kind, violation.ptr, violation.size
+    );
+    if violation.sites[0] != 0 {
+        crate::debug_println!("  allocated at:");
+        print_sites(&violation.sites);
+    }
+    panic!("heap debug: {}", violation.kind);
+}
+
+/// 呼び出し元ごとの集計
+#[derive(Clone, Copy)]
+struct SiteSummary {
+    sites: [u64; SITE_DEPTH],
+    count: usize,
+    bytes: usize,
+}
+
+/// 未解放の割り当てを呼び出し元ごとに集計して出力
+///
+/// 集計はロック中にスタック上の固定長配列で行い、出力はロックを
+/// 離してから行います（集計しきれなかった分は "other" にまとめます）。
+pub(super) fn dump(state: &Mutex<DebugState>) {
+    let empty = SiteSummary { sites: [0; SITE_DEPTH], count: 0, bytes: 0 };
+    let mut summaries = [empty; MAX_SITES];
+    let mut used = 0;
+    let mut other = empty;
+
+    let (live_count, live_bytes) = {
+        let state = state.lock();
+        let mut header = state.live;
+        while !header.is_null() {
+            // SAFETY: live リストのヘッダは有効
+            let (sites, size, next) = unsafe { ((*header).sites, (*header).size, (*header).next) };
+            let summary = match summaries[..used].iter().position(|s| s.sites == sites) {
+                Some(i) => &mut summaries[i],
+                None if used < MAX_SITES => {
+                    summaries[used].sites = sites;
+                    used += 1;
+                    &mut summaries[used - 1]
+                }
+                None => &mut other,
+            };
+            summary.count += 1;
+            summary.bytes += size;
+            header = next;
+        }
+        (state.live_count, state.live_bytes)
+    };
+
+    summaries[..used].sort_unstable_by(|a, b| b.bytes.cmp(&a.bytes));
+    crate::debug_println!(
+        "[HeapDebug] {} outstanding allocations, {} bytes, {} call sites",
+        live_count, live_bytes, used
+    );
+    for summary in &summaries[..used] {
+        crate::debug_println!("  {} allocations, {} bytes", summary.count, summary.bytes);
+        print_sites(&summary.sites);
+    }
+    if other.count > 0 {
+        crate::debug_println!("  other: {} allocations, {} bytes", other.count, other.bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::mm::types::{LayoutSize, VirtAddr};
+
+    #[test_case]
+    fn test_block_layout_keeps_alignment() {
+        let (block, front) = block_layout(Layout::from_size_align(10, 64).unwrap()).unwrap();
+        assert_eq!(front % 64, 0);
+        assert!(front >= mem::size_of::<AllocHeader>() + REDZONE);
+        assert_eq!(block.size(), front + 10 + REDZONE);
+        assert_eq!(block.align(), 64);
+    }
+
+    #[test_case]
+    fn test_free_poisons_and_quarantines() {
+        #[repr(C, align(4096))]
+        struct Memory([u8; 8192]);
+        static mut MEM: Memory = Memory([0; 8192]);
+
+        let heap = LockedHeap::new();
+        let state = Mutex::new(DebugState::new());
+        unsafe {
+            let start = core::ptr::addr_of_mut!(MEM) as usize;
+            heap.init(VirtAddr::new(start), LayoutSize::new(8192)).unwrap();
+        }
+
+        let layout = Layout::from_size_align(32, 8).unwrap();
+        let ptr = unsafe { alloc(&state, &heap, layout) };
+        assert!(!ptr.is_null());
+        assert_eq!(state.lock().live_count, 1);
+
+        unsafe {
+            ptr.write_bytes(0xAA, 32);
+            dealloc(&state, &heap, ptr, layout);
+            assert!(filled_with(ptr, 32, POISON_BYTE));
+        }
+        assert_eq!(state.lock().live_count, 0);
+        // 隔離中なのでヒープにはまだ返っていない
+        assert!(heap.stats().current_usage.as_usize() > 0);
+        assert!(unsafe { check_poison(ptr, layout) }.is_ok());
+    }
+}
@@ -0,0 +1,165 @@
+// kernel/src/kernel/mm/kstack.rs
+//! カーネルスタック領域
+//!
+//! プロセスごとのカーネルスタックを専用の仮想領域に確保します。
+//! 領域は固定サイズのスロットに分かれ、各スロットの下端 1 ページは
+//! マップしないガードページです。
+//!
+//! ```text
+//! slot i:  [guard (未マップ)][stack: STACK_PAGES ページ] <- top
+//! ```
+//!
+//! スタックがあふれるとヒープを壊す代わりにガードページでフォールトします。
+//! 例外フレームもあふれたスタックには積めないため、CPU はダブルフォールトに
+//! 昇格し、IST スタック上のハンドラが [`guard_owner`] で持ち主の PID を
+//! 報告します。
+//!
+//! 領域にはカーネル PML4 の空きエントリを 1 つ使います
+//! （[`reserve_kernel_region`] を参照）。最初のプロセスより前に [`init`] を
+//! 呼ぶため、スタックはすべてのアドレス空間から同じアドレスで見えます。
+
+use core::sync::atomic::{AtomicU64, Ordering};
+use x86_64::structures::paging::{FrameAllocator, Page, PageTableFlags, Size4KiB};
+use x86_64::VirtAddr;
+
+use crate::kernel::mm::mmap::{current_mapper, map_anonymous, unmap_range};
+use crate::kernel::mm::paging::reserve_kernel_region;
+use crate::kernel::mm::{pcid, BootInfoFrameAllocator};
+
+/// ページサイズ
+const PAGE_SIZE: u64 = 4096;
+/// スタック本体のページ数 (16 KiB)
+pub const STACK_PAGES: u64 = 4;
+/// スタックサイズ（バイト）
+pub const STACK_SIZE: u64 = STACK_PAGES * PAGE_SIZE;
+/// ガードページを含むスロットのサイズ
+const SLOT_SIZE: u64 = STACK_SIZE + PAGE_SIZE;
+/// 同時に存在できるカーネルスタックの最大数
+pub const MAX_STACKS: usize = 1024;
+
+/// 未使用スロットを表す所有者の値
+const FREE: u64 = u64::MAX;
+
+/// 領域の先頭アドレス（[`init`] 前は 0）
+static REGION_BASE: AtomicU64 = AtomicU64::new(0);
+
+/// スロットごとの所有者 PID
+///
+/// ダブルフォールトハンドラからロックなしで読めるようアトミックにしています。
+static OWNERS: [AtomicU64; MAX_STACKS] = [const { AtomicU64::new(FREE) }; MAX_STACKS];
+
+/// カーネルスタック領域を初期化
+///
+/// 最初のプロセスを作成する前に一度だけ呼び出します。
+///
+/// # Errors
+/// 空きエントリがない場合、またはフレームを確保できない場合
+pub fn init<A>(frame_allocator: &mut A) -> Result<(), &'static str>
+where
+    A: FrameAllocator<Size4KiB>,
+{
+    let base = reserve_kernel_region(frame_allocator)?.as_u64();
+    REGION_BASE.store(base, Ordering::Relaxed);
+    crate::debug_println!("[KStack] Region at {:#x}", base);
+    Ok(())
+}
+
+/// `pid` のカーネルスタックを確保し、スタックトップを返す
+///
+/// スタックは 0 で初期化され、実行不可でマップされます。
+/// 解放は [`free`] で行います。
+///
+/// # Errors
+/// 領域が未初期化、スロットが満杯、またはメモリ不足の場合
+pub fn allocate(pid: u64, frame_allocator: &mut BootInfoFrameAllocator) -> Result<VirtAddr, &'static str> {
+    let base = REGION_BASE.load(Ordering::Relaxed);
+    if base == 0 {
+        return Err("kernel stack region not initialized");
+    }
+    let slot = OWNERS
+        .iter()
+        .position(|owner| {
+            owner
+                .compare_exchange(FREE, pid, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+        })
+        .ok_or("out of kernel stack slots")?;
+
+    let bottom = base + slot as u64 * SLOT_SIZE + PAGE_SIZE;
+    let start = Page::<Size4KiB>::containing_address(VirtAddr::new(bottom));
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+    // SAFETY: 領域の L3 テーブルはすべてのアドレス空間で共有されており、
+    // このスロットのページはスロットを確保した呼び出し元だけが触れる
+    let mut mapper = unsafe { current_mapper() };
+
+    if map_anonymous(&mut mapper, frame_allocator, start, STACK_PAGES, flags).is_err() {
+        // map_anonymous はマップ済みのページを解放してから失敗する
+        OWNERS[slot].store(FREE, Ordering::Release);
+        return Err("failed to map kernel stack");
+    }
+    Ok(VirtAddr::new(bottom + STACK_SIZE))
+}
+
+/// [`allocate`] で確保したスタックを解放
+///
+/// # Safety
+/// `top` のスタックがどの CPU でも使用されていないこと
+pub unsafe fn free(top: VirtAddr, frame_allocator: &mut BootInfoFrameAllocator) {
+    let base = REGION_BASE.load(Ordering::Relaxed);
+    let slot = match locate(base, top.as_u64().wrapping_sub(1)) {
+        Some((slot, false)) if top.as_u64() == base + (slot as u64 + 1) * SLOT_SIZE => slot,
+        _ => {
+            crate::debug_println!("[KStack] {:#x} is not a kernel stack top", top.as_u64());
+            return;
+        }
+    };
+
+    let start = Page::<Size4KiB>::containing_address(VirtAddr::new(top.as_u64() - STACK_SIZE));
+    // SAFETY: スロットの持ち主だけがページテーブルのこの範囲を変更する
+    let mut mapper = unsafe { current_mapper() };
+    unmap_range(&mut mapper, frame_allocator, start, STACK_PAGES);
+    // 切り替え直後は前のプロセスのスタックを次のプロセスの PCID で使うため、
+    // 他の PCID にもこのスタックの TLB エントリが残っている可能性がある
+    pcid::flush_all_contexts();
+    OWNERS[slot].store(FREE, Ordering::Release);
+}
+
+/// `addr` がカーネルスタックのガードページ内なら、そのスタックの持ち主の PID を返す
+///
+/// ロックを取らないため、ダブルフォールトハンドラから呼び出せます。
+#[must_use]
+pub fn guard_owner(addr: VirtAddr) -> Option<u64> {
+    let base = REGION_BASE.load(Ordering::Relaxed);
+    match locate(base, addr.as_u64()) {
+        Some((slot, true)) => Some(OWNERS[slot].load(Ordering::Relaxed)).filter(|&pid| pid != FREE),
+        _ => None,
+    }
+}
+
+/// 領域内のアドレスを (スロット番号, ガードページか) に変換
+fn locate(base: u64, addr: u64) -> Option<(usize, bool)> {
+    if base == 0 || addr < base {
+        return None;
+    }
+    let offset = addr - base;
+    let slot = usize::try_from(offset / SLOT_SIZE).ok().filter(|&s| s < MAX_STACKS)?;
+    Some((slot, offset % SLOT_SIZE < PAGE_SIZE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: u64 = 0xFFFF_C000_0000_0000;
+
+    #[test_case]
+    fn test_locate_guard_and_stack() {
+        assert_eq!(locate(BASE, BASE), Some((0, true)));
+        assert_eq!(locate(BASE, BASE + PAGE_SIZE), Some((0, false)));
+        assert_eq!(locate(BASE, BASE + SLOT_SIZE - 1), Some((0, false)));
+        assert_eq!(locate(BASE, BASE + SLOT_SIZE), Some((1, true)));
+        assert_eq!(locate(BASE, BASE - 1), None);
+        assert_eq!(locate(BASE, BASE + SLOT_SIZE * MAX_STACKS as u64), None);
+        assert_eq!(locate(0, BASE), None);
+    }
+}
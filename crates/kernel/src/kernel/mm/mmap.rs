@@ -0,0 +1,619 @@
+// kernel/src/kernel/mm/mmap.rs
+//! Page-level helpers behind `mmap`, `munmap` and `mprotect`
+//!
+//! These functions operate on the page table of the running process
+//! (the one in CR3) and take the frame allocator as an argument so that
+//! callers control the lock order with `PROCESS_TABLE`.
+//!
+//! `PROT_*` bits map to page table flags as follows:
+//!
+//! | prot            | PRESENT | USER | WRITABLE | NO_EXECUTE |
+//! |-----------------|---------|------|----------|------------|
+//! | `PROT_NONE`     | ✓       |      |          | ✓          |
+//! | `PROT_READ`     | ✓       | ✓    |          | ✓          |
+//! | `+ PROT_WRITE`  | ✓       | ✓    | ✓        | ✓          |
+//! | `+ PROT_EXEC`   | ✓       | ✓    |          |            |
+//!
+//! `PROT_NONE` pages stay present but lose `USER_ACCESSIBLE`, so any user
+//! access faults while the backing frame remains owned by the mapping and
+//! is released normally by `munmap`.
+//!
+//! # Huge pages
+//!
+//! Areas created with `MAP_HUGE` and registered io_uring buffers are
+//! backed by 2 MiB pages wherever they cover an aligned 2 MiB block. A
+//! huge page is one page directory entry over an order-9 block of frames,
+//! but every 4 KiB frame in it carries its own reference count. Splitting
+//! a huge page into 512 page table entries therefore needs no reference
+//! changes, and [`unmap_range`] and [`protect_range`] split the huge pages
+//! they only partially cover.
+//!
+//! # TLB flushes
+//!
+//! Ranges of up to [`FLUSH_PAGE_CEILING`] pages are flushed page by page.
+//! Longer ranges drop the whole address space from the TLB once at the end
+//! ([`pcid::flush_address_space`]), which with PCIDs leaves the entries of
+//! other processes in place.
+
+use x86_64::instructions::tlb;
+use x86_64::structures::paging::mapper::{MapToError, MapperFlush, TranslateResult};
+use x86_64::structures::paging::page_table::PageTableEntry;
+use x86_64::structures::paging::{
+    FrameAllocator, Mapper, OffsetPageTable, Page, PageSize, PageTable, PageTableFlags, PhysFrame,
+    Size2MiB, Size4KiB, Translate,
+};
+use x86_64::VirtAddr;
+
+use crate::abi::mman::{violates_wx, PROT_EXEC, PROT_NONE, PROT_WRITE};
+use crate::kernel::mm::paging::COW_FLAG;
+use crate::kernel::mm::vma::{VmaKind, VmaTree};
+use crate::kernel::mm::{pcid, BootInfoFrameAllocator, Zone, PHYS_MEM_OFFSET};
+
+/// Size of a huge page (2 MiB)
+pub const HUGE_PAGE_SIZE: u64 = Size2MiB::SIZE;
+
+/// Longest range flushed page by page; longer ones flush the address space
+pub const FLUSH_PAGE_CEILING: u64 = 32;
+
+/// 4 KiB frames per huge page
+const FRAMES_PER_HUGE_PAGE: u64 = HUGE_PAGE_SIZE / Size4KiB::SIZE;
+
+/// Buddy order of the frame block behind a huge page
+const HUGE_PAGE_ORDER: usize = 9;
+
+/// Flags of the page tables that hold user leaf entries
+///
+/// Permissions are decided by the leaf entries alone.
+const USER_TABLE_FLAGS: PageTableFlags = PageTableFlags::PRESENT
+    .union(PageTableFlags::WRITABLE)
+    .union(PageTableFlags::USER_ACCESSIBLE);
+
+/// Errors from the mapping helpers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapError {
+    /// No physical frame (or page table frame) available
+    OutOfMemory,
+    /// A page in the range is already mapped
+    AlreadyMapped,
+}
+
+impl<S: PageSize> From<MapToError<S>> for MapError {
+    fn from(e: MapToError<S>) -> Self {
+        match e {
+            MapToError::PageAlreadyMapped(_) => Self::AlreadyMapped,
+            _ => Self::OutOfMemory,
+        }
+    }
+}
+
+/// Page table flags for a `PROT_*` combination
+///
+/// Returns `None` when the combination violates W^X.
+#[must_use]
+pub fn prot_to_flags(prot: u64) -> Option<PageTableFlags> {
+    if violates_wx(prot) {
+        return None;
+    }
+
+    let mut flags = PageTableFlags::PRESENT;
+    if prot == PROT_NONE {
+        return Some(flags | PageTableFlags::NO_EXECUTE);
+    }
+    flags |= PageTableFlags::USER_ACCESSIBLE;
+    if prot & PROT_WRITE != 0 {
+        flags |= PageTableFlags::WRITABLE;
+    }
+    if prot & PROT_EXEC == 0 {
+        flags |= PageTableFlags::NO_EXECUTE;
+    }
+    Some(flags)
+}
+
+/// Mapper for the address space currently loaded in CR3
+///
+/// # Safety
+/// The caller must not create another mutable view of the same page table
+/// while the returned mapper is alive.
+pub unsafe fn current_mapper() -> OffsetPageTable<'static> {
+    let (l4_frame, _) = x86_64::registers::control::Cr3::read();
+    // SAFETY: Forwarded to the caller
+    unsafe { mapper_for(l4_frame) }
+}
+
+/// Mapper for the address space whose top-level table is `l4_frame`
+///
+/// Used to change a process that is not loaded in CR3, such as the owner
+/// of a ring polled by SQPOLL. Afterwards call [`flush_if_inactive`].
+///
+/// # Safety
+/// `l4_frame` must be a live PML4, and the caller must not create another
+/// mutable view of the same page table while the returned mapper is alive.
+pub unsafe fn mapper_for(l4_frame: PhysFrame) -> OffsetPageTable<'static> {
+    let phys_mem_offset = VirtAddr::new(PHYS_MEM_OFFSET.load(core::sync::atomic::Ordering::Relaxed));
+    let l4_table = (phys_mem_offset + l4_frame.start_address().as_u64()).as_mut_ptr();
+    // SAFETY: The direct map covers all page table frames; exclusivity is
+    // guaranteed by the caller
+    unsafe { OffsetPageTable::new(&mut *l4_table, phys_mem_offset) }
+}
+
+/// Flush stale translations after changing the address space `l4_frame`
+///
+/// Flushes in [`unmap_range`] and friends only reach the address space in
+/// CR3. If `l4_frame` is not loaded, its PCID may still cache the old
+/// entries, so all PCIDs are flushed.
+pub fn flush_if_inactive(l4_frame: PhysFrame) {
+    let (current, _) = x86_64::registers::control::Cr3::read();
+    if current != l4_frame {
+        pcid::flush_all_contexts();
+    }
+}
+
+/// Map `count` zeroed pages starting at `start`
+///
+/// All pages must be unmapped. On failure, pages mapped so far are
+/// released again and the range is left unmapped.
+pub fn map_anonymous(
+    mapper: &mut OffsetPageTable<'_>,
+    frame_allocator: &mut BootInfoFrameAllocator,
+    start: Page<Size4KiB>,
+    count: u64,
+    flags: PageTableFlags,
+) -> Result<(), MapError> {
+    let phys_mem_offset = PHYS_MEM_OFFSET.load(core::sync::atomic::Ordering::Relaxed);
+
+    for i in 0..count {
+        let page = start + i;
+        let Some(frame) = frame_allocator.allocate_frame() else {
+            unmap_range(mapper, frame_allocator, start, i);
+            return Err(MapError::OutOfMemory);
+        };
+
+        // Zero through the direct map before the page becomes visible
+        let frame_ptr = (phys_mem_offset + frame.start_address().as_u64()) as *mut u8;
+        // SAFETY: The frame was just allocated and is covered by the direct map
+        unsafe { core::ptr::write_bytes(frame_ptr, 0, 4096) };
+
+        // SAFETY: The frame is unused and the page belongs to user space
+        let result = unsafe { mapper.map_to(page, frame, flags, frame_allocator) };
+        match result {
+            Ok(tlb) => tlb.flush(),
+            Err(e) => {
+                // SAFETY: The frame was never mapped
+                unsafe { frame_allocator.deallocate_frame(frame) };
+                unmap_range(mapper, frame_allocator, start, i);
+                return Err(e.into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Map existing frames at consecutive pages starting at `start`
+///
+/// Each mapped page takes its own reference on the frame, released again
+/// by [`unmap_range`]. With `private`, pages are mapped copy-on-write so
+/// that writes (also after a later `mprotect`) never reach the frames.
+/// All pages must be unmapped; on failure the range is left unmapped.
+pub fn map_frames(
+    mapper: &mut OffsetPageTable<'_>,
+    frame_allocator: &mut BootInfoFrameAllocator,
+    start: Page<Size4KiB>,
+    frames: &[PhysFrame<Size4KiB>],
+    flags: PageTableFlags,
+    private: bool,
+) -> Result<(), MapError> {
+    let flags = if private {
+        (flags - PageTableFlags::WRITABLE) | COW_FLAG
+    } else {
+        flags
+    };
+
+    for (i, &frame) in frames.iter().enumerate() {
+        let page = start + i as u64;
+        frame_allocator.add_reference(frame);
+        // SAFETY: The frame is kept alive by the reference taken above
+        let result = unsafe { mapper.map_to(page, frame, flags, frame_allocator) };
+        match result {
+            Ok(tlb) => tlb.flush(),
+            Err(e) => {
+                // SAFETY: Drops the reference taken above; the frame stays owned elsewhere
+                unsafe { frame_allocator.deallocate_frame(frame) };
+                unmap_range(mapper, frame_allocator, start, i as u64);
+                return Err(e.into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Unmap every mapped page in `[start, start + count)` and free its frame
+///
+/// Frames still referenced elsewhere (shared memory, copy-on-write) are
+/// only released by their last user. Unmapped pages in the range are
+/// skipped. Huge pages covered completely are unmapped as a whole; huge
+/// pages covered in part are split first (see [`split_huge_boundaries`]
+/// for callers that must not fail halfway). Returns the number of pages
+/// that were released.
+pub fn unmap_range(
+    mapper: &mut OffsetPageTable<'_>,
+    frame_allocator: &mut BootInfoFrameAllocator,
+    start: Page<Size4KiB>,
+    count: u64,
+) -> u64 {
+    let end = start + count;
+    let batched = count > FLUSH_PAGE_CEILING;
+    let mut released = 0;
+    let mut page = start;
+    while page < end {
+        if let Some(huge) = huge_page_at(mapper, page) {
+            let huge_end = Page::containing_address(huge.start_address() + HUGE_PAGE_SIZE);
+            if huge.start_address() == page.start_address() && huge_end <= end {
+                if let Ok((frame, tlb)) = Mapper::<Size2MiB>::unmap(mapper, huge) {
+                    flush_unless(tlb, batched);
+                    release_huge_frames(frame_allocator, frame);
+                    released += FRAMES_PER_HUGE_PAGE;
+                }
+                page = huge_end;
+                continue;
+            }
+            if split_huge_page(mapper, frame_allocator, page.start_address()).is_err() {
+                crate::debug_println!("[mmap] Cannot split huge page at {:#x}", huge.start_address().as_u64());
+                page = huge_end.min(end);
+                continue;
+            }
+        }
+        if let Ok((frame, tlb)) = mapper.unmap(page) {
+            flush_unless(tlb, batched);
+            // SAFETY: The frame was owned by this mapping only
+            unsafe { frame_allocator.deallocate_frame(frame) };
+            released += 1;
+        }
+        page += 1;
+    }
+    if batched {
+        pcid::flush_address_space();
+    }
+    released
+}
+
+/// Change the flags of the mapped pages in `[start, start + count)`
+///
+/// Pages that are not mapped yet are skipped; they pick up the area's
+/// permissions when they are filled. Copy-on-write pages keep their
+/// `COW_FLAG` and stay read-only until the next write fault copies them.
+/// Huge pages covered in part are split first. The TLB is flushed as
+/// described in the [module documentation](self#tlb-flushes).
+pub fn protect_range(
+    mapper: &mut OffsetPageTable<'_>,
+    frame_allocator: &mut BootInfoFrameAllocator,
+    start: Page<Size4KiB>,
+    count: u64,
+    flags: PageTableFlags,
+) {
+    let end = start + count;
+    let batched = count > FLUSH_PAGE_CEILING;
+    let mut page = start;
+    while page < end {
+        if let Some(huge) = huge_page_at(mapper, page) {
+            let huge_end = Page::containing_address(huge.start_address() + HUGE_PAGE_SIZE);
+            if huge.start_address() == page.start_address() && huge_end <= end {
+                if let TranslateResult::Mapped { flags: old, .. } = mapper.translate(page.start_address()) {
+                    // SAFETY: The page is mapped and only its permission bits change
+                    if let Ok(tlb) = unsafe { Mapper::<Size2MiB>::update_flags(mapper, huge, keep_cow(old, flags)) } {
+                        flush_unless(tlb, batched);
+                    }
+                }
+                page = huge_end;
+                continue;
+            }
+            if split_huge_page(mapper, frame_allocator, page.start_address()).is_err() {
+                crate::debug_println!("[mmap] Cannot split huge page at {:#x}", huge.start_address().as_u64());
+                page = huge_end.min(end);
+                continue;
+            }
+        }
+        if let TranslateResult::Mapped { flags: old, .. } = mapper.translate(page.start_address()) {
+            // SAFETY: The page is mapped and only its permission bits change
+            if let Ok(tlb) = unsafe { mapper.update_flags(page, keep_cow(old, flags)) } {
+                flush_unless(tlb, batched);
+            }
+        }
+        page += 1;
+    }
+    if batched {
+        pcid::flush_address_space();
+    }
+}
+
+/// Flush one page now, or leave it to the address-space flush of a batch
+fn flush_unless<S: PageSize>(tlb: MapperFlush<S>, batched: bool) {
+    if batched {
+        tlb.ignore();
+    } else {
+        tlb.flush();
+    }
+}
+
+/// `flags` for a page that currently has `old`, keeping a pending copy-on-write
+fn keep_cow(old: PageTableFlags, flags: PageTableFlags) -> PageTableFlags {
+    if old.contains(COW_FLAG) {
+        (flags - PageTableFlags::WRITABLE) | COW_FLAG
+    } else {
+        flags
+    }
+}
+
+/// Map one zeroed 2 MiB page at `page`
+///
+/// Fails with [`MapError::AlreadyMapped`] when part of the block is
+/// already mapped with 4 KiB pages or as a huge page, and with
+/// [`MapError::OutOfMemory`] when no free 2 MiB block of frames is left.
+pub fn map_zeroed_huge_page(
+    mapper: &mut OffsetPageTable<'_>,
+    frame_allocator: &mut BootInfoFrameAllocator,
+    page: Page<Size2MiB>,
+    flags: PageTableFlags,
+) -> Result<(), MapError> {
+    if pd_entry(mapper, page.start_address()).is_some_and(|entry| !entry.is_unused()) {
+        return Err(MapError::AlreadyMapped);
+    }
+    let first = frame_allocator
+        .allocate_pages(HUGE_PAGE_ORDER, Zone::Normal)
+        .ok_or(MapError::OutOfMemory)?;
+
+    // Zero through the direct map before the page becomes visible
+    let block_ptr = (mapper.phys_offset() + first.start_address().as_u64()).as_mut_ptr::<u8>();
+    // SAFETY: The block was just allocated and is covered by the direct map
+    unsafe { core::ptr::write_bytes(block_ptr, 0, HUGE_PAGE_SIZE as usize) };
+
+    let frame = PhysFrame::<Size2MiB>::containing_address(first.start_address());
+    // SAFETY: The block is unused and the page belongs to user space
+    let result = unsafe { mapper.map_to_with_table_flags(page, frame, flags, USER_TABLE_FLAGS, frame_allocator) };
+    match result {
+        Ok(tlb) => {
+            tlb.flush();
+            Ok(())
+        }
+        Err(e) => {
+            // SAFETY: The block was never mapped
+            unsafe { frame_allocator.deallocate_pages(first, HUGE_PAGE_ORDER) };
+            Err(e.into())
+        }
+    }
+}
+
+/// [`map_anonymous`] with 2 MiB pages for every aligned 2 MiB block
+///
+/// The unaligned edges of the range, and blocks for which no free 2 MiB
+/// block of frames is available, are mapped with 4 KiB pages. On failure
+/// the range is left unmapped.
+pub fn map_anonymous_huge(
+    mapper: &mut OffsetPageTable<'_>,
+    frame_allocator: &mut BootInfoFrameAllocator,
+    start: Page<Size4KiB>,
+    count: u64,
+    flags: PageTableFlags,
+) -> Result<(), MapError> {
+    let mut done = 0;
+    while done < count {
+        let page = start + done;
+        let remaining = count - done;
+        let addr = page.start_address();
+
+        let (step, result) = if addr.is_aligned(HUGE_PAGE_SIZE) && remaining >= FRAMES_PER_HUGE_PAGE {
+            let result = match map_zeroed_huge_page(mapper, frame_allocator, Page::containing_address(addr), flags) {
+                Err(MapError::OutOfMemory) => {
+                    map_anonymous(mapper, frame_allocator, page, FRAMES_PER_HUGE_PAGE, flags)
+                }
+                other => other,
+            };
+            (FRAMES_PER_HUGE_PAGE, result)
+        } else {
+            // 4 KiB pages up to the next 2 MiB boundary
+            let to_boundary = (addr.align_up(HUGE_PAGE_SIZE) - addr) / Size4KiB::SIZE;
+            let step = if to_boundary == 0 { remaining } else { to_boundary.min(remaining) };
+            (step, map_anonymous(mapper, frame_allocator, page, step, flags))
+        };
+        if let Err(e) = result {
+            unmap_range(mapper, frame_allocator, start, done);
+            return Err(e);
+        }
+        done += step;
+    }
+    Ok(())
+}
+
+/// Replace the huge page containing `addr` by 512 4 KiB pages
+///
+/// The new page table maps the same frames with the same flags, so the
+/// contents, frame references and copy-on-write state do not change.
+/// Returns `Ok(false)` when no huge page is mapped at `addr`. Only used
+/// for user mappings.
+pub fn split_huge_page(
+    mapper: &mut OffsetPageTable<'_>,
+    frame_allocator: &mut BootInfoFrameAllocator,
+    addr: VirtAddr,
+) -> Result<bool, MapError> {
+    let phys_offset = mapper.phys_offset();
+    let Some(entry) = pd_entry(mapper, addr) else {
+        return Ok(false);
+    };
+    let flags = entry.flags();
+    if !flags.contains(PageTableFlags::PRESENT | PageTableFlags::HUGE_PAGE) {
+        return Ok(false);
+    }
+
+    let table_frame = frame_allocator.allocate_frame().ok_or(MapError::OutOfMemory)?;
+    // SAFETY: The frame was just allocated and is covered by the direct map
+    let table = unsafe { &mut *(phys_offset + table_frame.start_address().as_u64()).as_mut_ptr::<PageTable>() };
+    let base = entry.addr();
+    let leaf_flags = flags - PageTableFlags::HUGE_PAGE;
+    for (i, pte) in table.iter_mut().enumerate() {
+        pte.set_addr(base + i as u64 * Size4KiB::SIZE, leaf_flags);
+    }
+    entry.set_frame(table_frame, USER_TABLE_FLAGS);
+    // Invalidating any address of a huge page drops its whole TLB entry
+    tlb::flush(addr.align_down(HUGE_PAGE_SIZE));
+    Ok(true)
+}
+
+/// Split the huge pages that straddle `start` or `end`
+///
+/// Lets `munmap` and `mprotect` fail with `ENOMEM` before they change
+/// anything, rather than when a split runs out of memory halfway through
+/// [`unmap_range`] or [`protect_range`].
+pub fn split_huge_boundaries(
+    mapper: &mut OffsetPageTable<'_>,
+    frame_allocator: &mut BootInfoFrameAllocator,
+    start: VirtAddr,
+    end: VirtAddr,
+) -> Result<(), MapError> {
+    for addr in [start, end] {
+        if !addr.is_aligned(HUGE_PAGE_SIZE) {
+            split_huge_page(mapper, frame_allocator, addr)?;
+        }
+    }
+    Ok(())
+}
+
+/// Back the 2 MiB-aligned blocks of `[start, start + len)` with huge pages
+///
+/// Only blocks lying completely inside one private anonymous area are
+/// touched. Present pages are copied and missing ones zero-filled, so the
+/// contents seen by user space do not change. Blocks for which no free
+/// 2 MiB block of frames is available stay as they are. Returns the
+/// number of blocks backed by huge pages afterwards.
+pub fn collapse_huge_range(
+    mapper: &mut OffsetPageTable<'_>,
+    frame_allocator: &mut BootInfoFrameAllocator,
+    vmas: &VmaTree,
+    start: u64,
+    len: u64,
+) -> u64 {
+    let Some(end) = start.checked_add(len) else {
+        return 0;
+    };
+    let mut huge = 0;
+    let mut block = start.next_multiple_of(HUGE_PAGE_SIZE);
+    while block.checked_add(HUGE_PAGE_SIZE).is_some_and(|block_end| block_end <= end) {
+        let area = vmas
+            .find(block)
+            .filter(|vma| vma.kind == VmaKind::Anonymous && !vma.shared && vma.end - block >= HUGE_PAGE_SIZE);
+        if let Some(flags) = area.and_then(|vma| prot_to_flags(vma.prot)) {
+            if collapse_block(mapper, frame_allocator, VirtAddr::new(block), flags).is_ok() {
+                huge += 1;
+            }
+        }
+        block += HUGE_PAGE_SIZE;
+    }
+    huge
+}
+
+/// Replace the 4 KiB pages of the block at `addr` by one huge page
+fn collapse_block(
+    mapper: &mut OffsetPageTable<'_>,
+    frame_allocator: &mut BootInfoFrameAllocator,
+    addr: VirtAddr,
+    flags: PageTableFlags,
+) -> Result<(), MapError> {
+    let phys_offset = mapper.phys_offset();
+    let entry = match pd_entry(mapper, addr) {
+        Some(entry) if entry.flags().contains(PageTableFlags::HUGE_PAGE) => return Ok(()),
+        Some(entry) if !entry.is_unused() => entry,
+        _ => return map_zeroed_huge_page(mapper, frame_allocator, Page::containing_address(addr), flags),
+    };
+
+    let first = frame_allocator
+        .allocate_pages(HUGE_PAGE_ORDER, Zone::Normal)
+        .ok_or(MapError::OutOfMemory)?;
+    let block_ptr = (phys_offset + first.start_address().as_u64()).as_mut_ptr::<u8>();
+    let table_frame = PhysFrame::<Size4KiB>::containing_address(entry.addr());
+    // SAFETY: Present entries point at page tables covered by the direct map
+    let table = unsafe { &mut *(phys_offset + table_frame.start_address().as_u64()).as_mut_ptr::<PageTable>() };
+
+    for (i, pte) in table.iter_mut().enumerate() {
+        // SAFETY: `i` indexes one 4 KiB slice of the new block
+        let dst = unsafe { block_ptr.add(i * Size4KiB::SIZE as usize) };
+        if pte.flags().contains(PageTableFlags::PRESENT) {
+            let src = (phys_offset + pte.addr().as_u64()).as_ptr::<u8>();
+            // SAFETY: Both frames are covered by the direct map and do not overlap
+            unsafe { core::ptr::copy_nonoverlapping(src, dst, Size4KiB::SIZE as usize) };
+            // SAFETY: The entry is cleared below, dropping this mapping's reference
+            unsafe { frame_allocator.deallocate_frame(PhysFrame::containing_address(pte.addr())) };
+        } else {
+            // SAFETY: As above
+            unsafe { core::ptr::write_bytes(dst, 0, Size4KiB::SIZE as usize) };
+        }
+        pte.set_unused();
+    }
+
+    entry.set_addr(first.start_address(), flags | PageTableFlags::HUGE_PAGE);
+    // SAFETY: The page table is no longer referenced by the page directory
+    unsafe { frame_allocator.deallocate_frame(table_frame) };
+    // Up to 512 small TLB entries may still point at the old frames
+    pcid::flush_address_space();
+    Ok(())
+}
+
+/// Huge page mapped at `page`, if any
+fn huge_page_at(mapper: &mut OffsetPageTable<'_>, page: Page<Size4KiB>) -> Option<Page<Size2MiB>> {
+    let huge = pd_entry(mapper, page.start_address())?
+        .flags()
+        .contains(PageTableFlags::PRESENT | PageTableFlags::HUGE_PAGE);
+    huge.then(|| Page::containing_address(page.start_address()))
+}
+
+/// Drop the mapping's reference on each frame of a huge page
+fn release_huge_frames(frame_allocator: &mut BootInfoFrameAllocator, frame: PhysFrame<Size2MiB>) {
+    let first = PhysFrame::<Size4KiB>::containing_address(frame.start_address());
+    // SAFETY: Huge pages are always backed by an order-9 block; frames
+    // still shared with another mapping are only dereferenced
+    unsafe { frame_allocator.deallocate_pages(first, HUGE_PAGE_ORDER) };
+}
+
+/// Page directory entry covering `addr`
+///
+/// Returns `None` when the page table walk does not reach a page
+/// directory.
+fn pd_entry<'a>(mapper: &'a mut OffsetPageTable<'_>, addr: VirtAddr) -> Option<&'a mut PageTableEntry> {
+    let phys_offset = mapper.phys_offset();
+    let l4_entry = &mapper.level_4_table_mut()[addr.p4_index()];
+    if !l4_entry.flags().contains(PageTableFlags::PRESENT) {
+        return None;
+    }
+    // SAFETY: Present entries point at page tables covered by the direct map
+    let l3 = unsafe { &mut *(phys_offset + l4_entry.addr().as_u64()).as_mut_ptr::<PageTable>() };
+    let l3_entry = &l3[addr.p3_index()];
+    let flags = l3_entry.flags();
+    if !flags.contains(PageTableFlags::PRESENT) || flags.contains(PageTableFlags::HUGE_PAGE) {
+        return None;
+    }
+    // SAFETY: As above
+    let l2 = unsafe { &mut *(phys_offset + l3_entry.addr().as_u64()).as_mut_ptr::<PageTable>() };
+    Some(&mut l2[addr.p2_index()])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::abi::mman::PROT_READ;
+
+    #[test_case]
+    fn test_prot_to_flags_nx_and_wx() {
+        let rw = prot_to_flags(PROT_READ | PROT_WRITE).unwrap();
+        assert!(rw.contains(PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE));
+
+        let rx = prot_to_flags(PROT_READ | PROT_EXEC).unwrap();
+        assert!(!rx.contains(PageTableFlags::NO_EXECUTE));
+        assert!(!rx.contains(PageTableFlags::WRITABLE));
+
+        assert!(prot_to_flags(PROT_WRITE | PROT_EXEC).is_none());
+    }
+
+    #[test_case]
+    fn test_prot_none_is_not_user_accessible() {
+        let none = prot_to_flags(PROT_NONE).unwrap();
+        assert!(none.contains(PageTableFlags::PRESENT));
+        assert!(!none.contains(PageTableFlags::USER_ACCESSIBLE));
+    }
+}
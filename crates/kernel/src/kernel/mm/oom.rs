@@ -0,0 +1,305 @@
+// kernel/src/kernel/mm/oom.rs
+//! メモリ逼迫時の回収と OOM キラー
+//!
+//! 空きフレームが [`Watermarks`](super::Watermarks) を下回ったときの処理をまとめます。
+//!
+//! 1. **回収**: [`register_reclaimer`] で登録された回収関数を順に呼び、
+//!    空きを `high` まで増やそうとします。ページキャッシュのように捨てても
+//!    よいメモリを持つサブシステムが登録する想定で、まだ登録するものは
+//!    ありません。回収関数はフレームアロケータのロックを保持したまま
+//!    呼ばれるため、ヒープ拡張のようにロックを手放せない経路からも使えます。
+//! 2. **OOM キラー**: 回収しても足りなければ、常駐フレーム数が最も多い
+//!    プロセスを終了させます。理由はカーネルログに残します。
+//!
+//! ```text
+//! 空き >= low          何もしない
+//! min <= 空き < low    balance() が回収
+//! 割り当て失敗         out_of_memory() が回収 → だめなら OOM キラー
+//! ```
+//!
+//! OOM キラーは `PROCESS_TABLE` とフレームアロケータのロックを取るため、
+//! [`out_of_memory`] はどちらも保持せずに呼び出します。システムコールの
+//! フレーム割り当てとユーザーのページフォルトがこれを使います。
+//! カーネルヒープのように呼び出し側がどちらかを保持しているかもしれない
+//! 経路では、ロックを取れたときだけ進む [`try_out_of_memory`] を使います。
+
+use spin::{Mutex, MutexGuard};
+use x86_64::structures::paging::{PageTable, PageTableFlags};
+
+use super::allocator::BOOT_INFO_ALLOCATOR;
+use super::frame::Pressure;
+use super::vma::VmaTree;
+use super::{BootInfoFrameAllocator, PHYS_MEM_OFFSET};
+use crate::kernel::process::{lifecycle, ProcessId, ProcessState, PROCESS_TABLE};
+
+/// 登録できる回収関数の最大数
+const MAX_RECLAIMERS: usize = 8;
+
+/// OOM キラーに終了させられたプロセスの終了コード (128 + SIGKILL)
+pub const OOM_EXIT_CODE: i32 = 128 + 9;
+
+/// ページサイズ
+const PAGE_SIZE: u64 = 4096;
+
+/// [`try_lock_spinning`] がロックを取り直す回数
+const LOCK_RETRIES: usize = 10_000;
+
+/// 回収関数
+///
+/// 最大 `target` フレームを解放し、解放したフレーム数を返します。
+/// フレームアロケータのロックを保持したまま呼ばれるので、ロックを
+/// 取り直したり、ヒープを拡張させるような割り当てをしてはいけません。
+pub type ReclaimFn = fn(&mut BootInfoFrameAllocator, usize) -> usize;
+
+/// 登録済みの回収関数（名前と関数）
+static RECLAIMERS: Mutex<[Option<(&'static str, ReclaimFn)>; MAX_RECLAIMERS]> =
+    Mutex::new([None; MAX_RECLAIMERS]);
+
+/// [`out_of_memory`] の結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OomAction {
+    /// メモリを回収したか他のプロセスを終了させたので、割り当てをやり直す
+    Retry,
+    /// 現在のプロセスを [`OOM_EXIT_CODE`] で終了させる
+    KillCurrent,
+}
+
+/// 回収関数を登録
+///
+/// # Errors
+/// 登録数が [`MAX_RECLAIMERS`] に達している場合
+pub fn register_reclaimer(name: &'static str, reclaim: ReclaimFn) -> Result<(), &'static str> {
+    let mut reclaimers = RECLAIMERS.lock();
+    let slot = reclaimers
+        .iter_mut()
+        .find(|slot| slot.is_none())
+        .ok_or("too many reclaimers")?;
+    *slot = Some((name, reclaim));
+    Ok(())
+}
+
+/// 登録済みの回収関数で最大 `target` フレームを解放
+///
+/// 解放したフレーム数を返します。
+pub fn reclaim(frame_allocator: &mut BootInfoFrameAllocator, target: usize) -> usize {
+    // 回収関数が登録を行っても止まらないよう、コピーしてからロックを外す
+    let reclaimers = *RECLAIMERS.lock();
+    let mut freed = 0;
+    for (name, reclaim) in reclaimers.iter().flatten() {
+        if freed >= target {
+            break;
+        }
+        let n = reclaim(frame_allocator, target - freed);
+        if n > 0 {
+            crate::debug_println!("[OOM] Reclaimer {} freed {} frames", name, n);
+        }
+        freed += n;
+    }
+    freed
+}
+
+/// 空きが `low` を下回っていれば `high` まで回収
+///
+/// 解放したフレーム数を返します。
+pub fn balance(frame_allocator: &mut BootInfoFrameAllocator) -> usize {
+    if frame_allocator.pressure() == Pressure::None {
+        return 0;
+    }
+    let target = frame_allocator.watermarks().high.saturating_sub(frame_allocator.free_frames());
+    reclaim(frame_allocator, target)
+}
+
+/// 割り当てに失敗したときの処理
+///
+/// まず `high` まで回収し、何か解放できれば [`OomAction::Retry`] を返します。
+/// 解放できなければ常駐フレーム数が最も多いプロセスを選び、理由を
+/// ログに残して終了させます。選ばれたのが現在のプロセスであれば
+/// [`OomAction::KillCurrent`] を返し、終了は呼び出し側に任せます。
+///
+/// `PROCESS_TABLE` とフレームアロケータのロックを保持せずに呼び出すこと。
+pub fn out_of_memory(reason: &str) -> OomAction {
+    handle_out_of_memory(reason, true).unwrap_or(OomAction::KillCurrent)
+}
+
+/// ロックを待たない [`out_of_memory`]
+///
+/// フレームアロケータと `PROCESS_TABLE` を [`try_lock_spinning`] で取り、
+/// 取れなければ何もせず `None` を返します。取れた場合はこの CPU が
+/// どちらも保持していないことがわかるので、プロセスの終了まで進めます。
+pub fn try_out_of_memory(reason: &str) -> Option<OomAction> {
+    handle_out_of_memory(reason, false)
+}
+
+/// [`out_of_memory`] の本体（`wait` でなければロックを待たない）
+fn handle_out_of_memory(reason: &str, wait: bool) -> Option<OomAction> {
+    {
+        let mut allocator = acquire(&BOOT_INFO_ALLOCATOR, wait)?;
+        let Some(frame_allocator) = allocator.as_mut() else {
+            return Some(OomAction::KillCurrent);
+        };
+        let target = frame_allocator.watermarks().high.saturating_sub(frame_allocator.free_frames());
+        if reclaim(frame_allocator, target.max(1)) > 0 {
+            return Some(OomAction::Retry);
+        }
+    }
+
+    let (current, victim) = {
+        let table = acquire(&*PROCESS_TABLE, wait)?;
+        let victim = pick_victim(
+            table
+                .processes()
+                .filter(|p| p.state() != ProcessState::Terminated)
+                .map(|p| (p.pid(), resident_frames(p.page_table_frame().start_address().as_u64(), p.vmas()))),
+        )
+        .map(|(pid, resident)| {
+            let name = table.get_process(pid).map_or("?", |p| p.name());
+            log::error!(
+                target: "oom",
+                "out of memory ({}): killing pid {} ({}) with {} resident frames",
+                reason,
+                pid.as_u64(),
+                name,
+                resident
+            );
+            pid
+        });
+        (table.current_process().map(|p| p.pid()), victim)
+    };
+
+    match victim {
+        Some(pid) if Some(pid) != current => {
+            lifecycle::terminate_process(pid, OOM_EXIT_CODE);
+            Some(OomAction::Retry)
+        }
+        _ => Some(OomAction::KillCurrent),
+    }
+}
+
+/// `wait` なら `lock`、そうでなければ [`try_lock_spinning`] でロックを取る
+fn acquire<T>(mutex: &Mutex<T>, wait: bool) -> Option<MutexGuard<'_, T>> {
+    if wait {
+        Some(mutex.lock())
+    } else {
+        try_lock_spinning(mutex)
+    }
+}
+
+/// `try_lock` を [`LOCK_RETRIES`] 回まで繰り返してロックを取る
+///
+/// 他の CPU が短時間保持している場合は待てますが、この CPU 自身が
+/// 保持している場合は取れないので `None` を返します。ロックを待つと
+/// デッドロックしうる経路（ヒープ拡張など）で使います。
+pub fn try_lock_spinning<T>(mutex: &Mutex<T>) -> Option<MutexGuard<'_, T>> {
+    for _ in 0..LOCK_RETRIES {
+        if let Some(guard) = mutex.try_lock() {
+            return Some(guard);
+        }
+        core::hint::spin_loop();
+    }
+    None
+}
+
+/// 常駐フレーム数が最も多いプロセスを選ぶ
+///
+/// 常駐フレームのないプロセス（カーネルスレッドなど）は選びません。
+/// 同数の場合は後に現れた（新しい）プロセスを選びます。
+fn pick_victim(candidates: impl Iterator<Item = (ProcessId, usize)>) -> Option<(ProcessId, usize)> {
+    candidates
+        .filter(|&(_, resident)| resident > 0)
+        .max_by_key(|&(_, resident)| resident)
+}
+
+/// 常駐フレーム数
+///
+/// メモリ領域ごとにページテーブルをたどり、マップされているページを
+/// 数えます。Copy-on-Write で共有しているフレームは共有している
+/// 各プロセスで数えます。
+fn resident_frames(l4_phys: u64, vmas: &VmaTree) -> usize {
+    let phys_offset = PHYS_MEM_OFFSET.load(core::sync::atomic::Ordering::Relaxed);
+    let mut count = 0;
+    for vma in vmas.iter() {
+        let mut addr = vma.start;
+        while addr < vma.end {
+            // SAFETY: ページテーブルはダイレクトマップ経由で読める
+            let (present, size) = unsafe { probe(l4_phys, addr, phys_offset) };
+            let next = (addr & !(size - 1)).saturating_add(size).min(vma.end);
+            if present {
+                count += ((next - addr) / PAGE_SIZE) as usize;
+            }
+            addr = next;
+        }
+    }
+    count
+}
+
+/// `addr` を含むブロックを調べる
+///
+/// マップされているかどうかと、同じ結果になるブロックの大きさ
+/// （末端ページの大きさ、または存在しないエントリが覆う範囲）を返します。
+///
+/// # Safety
+/// `l4_phys` が有効な PML4 を指していること
+unsafe fn probe(l4_phys: u64, addr: u64, phys_offset: u64) -> (bool, u64) {
+    /// 各レベルのエントリが覆う大きさ (PML4, PDPT, PD, PT)
+    const LEVEL_SIZE: [u64; 4] = [1 << 39, 1 << 30, 1 << 21, 1 << 12];
+
+    let mut table_phys = l4_phys;
+    for (level, &size) in LEVEL_SIZE.iter().enumerate() {
+        let index = ((addr / size) & 0x1ff) as usize;
+        // SAFETY: 呼び出し側が保証し、下位のテーブルはエントリが指すもの
+        let table = unsafe { &*((phys_offset + table_phys) as *const PageTable) };
+        let entry = &table[index];
+        let flags = entry.flags();
+        if !flags.contains(PageTableFlags::PRESENT) {
+            return (false, size);
+        }
+        if level == LEVEL_SIZE.len() - 1 || flags.contains(PageTableFlags::HUGE_PAGE) {
+            return (true, size);
+        }
+        table_phys = entry.addr().as_u64();
+    }
+    (false, PAGE_SIZE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::mm::frame::Watermarks;
+
+    #[test_case]
+    fn test_watermarks_scale_with_memory() {
+        let small = Watermarks::for_free_frames(1024);
+        assert_eq!(small.min, 32);
+        assert!(small.min <= small.low && small.low <= small.high);
+
+        let large = Watermarks::for_free_frames(1 << 20);
+        assert_eq!(large.min, 8192);
+        assert_eq!((large.low, large.high), (10240, 12288));
+
+        let huge = Watermarks::for_free_frames(1 << 30);
+        assert_eq!(huge.min, 16384);
+
+        // 小さなメモリでも min は空きの 1/4 まで
+        assert_eq!(Watermarks::for_free_frames(64).min, 16);
+    }
+
+    #[test_case]
+    fn test_pressure_levels() {
+        let marks = Watermarks { min: 10, low: 20, high: 30 };
+        assert_eq!(marks.pressure(5), Pressure::Critical);
+        assert_eq!(marks.pressure(10), Pressure::Low);
+        assert_eq!(marks.pressure(20), Pressure::None);
+    }
+
+    #[test_case]
+    fn test_pick_victim_largest_resident() {
+        let candidates = [
+            (ProcessId::new(1), 10),
+            (ProcessId::new(2), 300),
+            (ProcessId::new(3), 0),
+            (ProcessId::new(4), 300),
+        ];
+        assert_eq!(pick_victim(candidates.into_iter()), Some((ProcessId::new(4), 300)));
+        assert_eq!(pick_victim([(ProcessId::new(5), 0)].into_iter()), None);
+    }
+}
@@ -3,17 +3,30 @@
 //!
 //! This module provides page fault handling for user-space processes,
 //! including lazy allocation, copy-on-write, and stack growth.
+//!
+//! Anonymous mappings and the user stack are only reserved as memory
+//! areas; their frames are allocated and zeroed on first touch. The stack
+//! area spans the configured stack limit, and the unmapped guard gap below
+//! it turns runaway growth into [`PageFaultError::StackOverflow`]. Kernel
+//! code that accesses user memory calls [`fault_in_current`] first, so
+//! syscalls see the same pages user space would.
+//!
+//! In areas created with `MAP_HUGE`, a fault in a 2 MiB block that lies
+//! completely inside the area fills the whole block with one huge page.
+//! A write to a copy-on-write huge page splits it and copies only the
+//! 4 KiB page that was written.
 
 use x86_64::structures::idt::PageFaultErrorCode;
-use x86_64::{VirtAddr, structures::paging::{Page, PageTableFlags, Mapper, Size4KiB, FrameAllocator, Translate, mapper::TranslateResult}};
-use crate::kernel::mm::user_paging::{USER_STACK_TOP, USER_CODE_BASE};
+use x86_64::{VirtAddr, structures::paging::{Page, PageTableFlags, Mapper, OffsetPageTable, Size4KiB, FrameAllocator, Translate, mapper::TranslateResult}};
 use crate::kernel::mm::paging::COW_FLAG;
+use crate::kernel::mm::mmap::{self, prot_to_flags, HUGE_PAGE_SIZE};
+use crate::kernel::mm::vma::VmaTree;
 use crate::kernel::mm::BootInfoFrameAllocator;
-
-/// User stack size (64 KiB)
-const USER_STACK_SIZE: u64 = 64 * 1024;
 use crate::debug_println;
 
+/// End (exclusive) of the user half of the address space
+const USER_SPACE_END: u64 = 0x0000_8000_0000_0000;
+
 /// Result type for page fault handling
 pub type PageFaultResult<T> = Result<T, PageFaultError>;
 
@@ -36,31 +49,31 @@ pub enum PageFaultError {
 
 /// Page fault handler for user-space addresses
 ///
-/// This function handles page faults that occur in user space. It implements:
-/// - Lazy stack allocation (allocate stack pages on demand)
-/// - Stack growth (expand stack when needed)
-/// - Access violation detection
-/// - Copy-on-Write (CoW) handling
+/// The faulting address is looked up in the process's memory areas:
+/// - Guard gap below a stack area: stack overflow
+/// - No area, or an access the area does not permit: segfault
+/// - Write to a present copy-on-write page of a writable area: copy it
+/// - Missing page in a demand-zero area (stack, anonymous mmap): map a
+///   zeroed page, or a zeroed 2 MiB page in a huge area
 ///
 /// # Arguments
 ///
 /// * `fault_addr` - The virtual address that caused the page fault
 /// * `error_code` - The page fault error code from the CPU
+/// * `vmas` - Memory areas of the faulting process
 /// * `mapper` - Page table mapper for the faulting process
 /// * `frame_allocator` - Frame allocator for allocating physical memory
 ///
 /// # Returns
 ///
 /// `Ok(())` if the page fault was successfully handled, `Err(PageFaultError)` otherwise
-pub fn handle_user_page_fault<M>(
+pub fn handle_user_page_fault(
     fault_addr: VirtAddr,
     error_code: PageFaultErrorCode,
-    mapper: &mut M,
+    vmas: &VmaTree,
+    mapper: &mut OffsetPageTable<'_>,
     frame_allocator: &mut BootInfoFrameAllocator,
-) -> PageFaultResult<()>
-where
-    M: Mapper<Size4KiB> + Translate,
-{
+) -> PageFaultResult<()> {
     let fault_page = Page::containing_address(fault_addr);
     let fault_addr_u64 = fault_addr.as_u64();
     
@@ -70,105 +83,95 @@ where
         error_code
     );
     
-    // Check if the fault is in user stack region
-    let stack_top = USER_STACK_TOP;
-    let stack_bottom = stack_top - USER_STACK_SIZE;
-    
-    if fault_addr_u64 >= stack_bottom && fault_addr_u64 < stack_top {
-        // This is a stack access
-        
-        // Check if it's a protection violation (page is present but access denied)
-        if error_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION) {
-            // Check for CoW
-            if handle_cow_fault(fault_page, error_code, mapper, frame_allocator)? {
-                return Ok(());
-            }
-            
-            debug_println!("[PageFault] Stack protection violation");
-            return Err(PageFaultError::AccessViolation);
-        }
-        
-        // Check if it's a write to a not-present page (lazy allocation)
-        if !error_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION) {
-            // Page not present - allocate it
-            debug_println!("[PageFault] Lazy stack allocation at page {:#x}", fault_page.start_address().as_u64());
-            
-            // Allocate a physical frame
-            let frame = frame_allocator
-                .allocate_frame()
-                .ok_or(PageFaultError::OutOfMemory)?;
-            
-            // Map the page with user-accessible, writable flags
-            let flags = PageTableFlags::PRESENT 
-                | PageTableFlags::WRITABLE 
-                | PageTableFlags::USER_ACCESSIBLE;
-            
-            unsafe {
-                mapper
-                    .map_to(fault_page, frame, flags, frame_allocator)
-                    .map_err(|_| PageFaultError::OutOfMemory)?
-                    .flush();
-            }
-            
-            // Zero-initialize the new stack page
-            unsafe {
-                let phys_offset = VirtAddr::new(crate::kernel::mm::PHYS_MEM_OFFSET.load(core::sync::atomic::Ordering::Relaxed));
-                let frame_ptr = (phys_offset + frame.start_address().as_u64()).as_mut_ptr::<u8>();
-                core::ptr::write_bytes(frame_ptr, 0, 4096);
-            }
-            
-            debug_println!("[PageFault] Stack page allocated successfully");
-            return Ok(());
-        }
-    }
-    
-    // Check if the fault is in code region (should already be mapped)
-    let code_end = USER_CODE_BASE + (1024 * 1024); // 1 MB max program size
-    if fault_addr_u64 >= USER_CODE_BASE && fault_addr_u64 < code_end {
-        // Check for CoW
-        if error_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION) {
-             if handle_cow_fault(fault_page, error_code, mapper, frame_allocator)? {
-                return Ok(());
-            }
-            // Trying to write to code segment
-            return Err(PageFaultError::AccessViolation);
+    let Some(vma) = vmas.find(fault_addr_u64) else {
+        if vmas.in_stack_guard(fault_addr_u64) {
+            debug_println!("[PageFault] Stack guard hit at {:#x}", fault_addr_u64);
+            return Err(PageFaultError::StackOverflow);
         }
-        
-        debug_println!("[PageFault] Code region fault - likely protection violation");
-        
-        // Code page not present - this shouldn't happen
-        return Err(PageFaultError::InvalidAccess);
+        debug_println!("[PageFault] No memory area at {:#x}", fault_addr_u64);
+        return Err(PageFaultError::InvalidAddress);
+    };
+
+    // Check the access against the area's permissions
+    let write = error_code.contains(PageFaultErrorCode::CAUSED_BY_WRITE);
+    let fetch = error_code.contains(PageFaultErrorCode::INSTRUCTION_FETCH);
+    let permitted = if write {
+        vma.writable()
+    } else if fetch {
+        vma.executable()
+    } else {
+        vma.readable()
+    };
+    if !permitted {
+        debug_println!("[PageFault] Access not permitted by area {:#x}-{:#x}", vma.start, vma.end);
+        return Err(PageFaultError::AccessViolation);
     }
-    
-    // Check for generic CoW in other regions (heap, etc.)
+
     if error_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION) {
+        // The page is present, so only a pending copy-on-write can be resolved
         if handle_cow_fault(fault_page, error_code, mapper, frame_allocator)? {
             return Ok(());
         }
+        return Err(PageFaultError::AccessViolation);
     }
-    
-    // Fault is outside valid user memory regions
-    debug_println!("[PageFault] Invalid user address: {:#x}", fault_addr_u64);
-    Err(PageFaultError::InvalidAddress)
+
+    if !vma.kind.is_demand_zero() {
+        debug_println!("[PageFault] Missing page in non-demand area at {:#x}", fault_addr_u64);
+        return Err(PageFaultError::InvalidAccess);
+    }
+
+    // Page not present - allocate a zeroed page with the area's permissions
+    debug_println!("[PageFault] Demand-zero fill at page {:#x}", fault_page.start_address().as_u64());
+    let flags = prot_to_flags(vma.prot).ok_or(PageFaultError::AccessViolation)?;
+
+    // Huge areas fill whole 2 MiB blocks; blocks reaching past the area,
+    // already holding 4 KiB pages, or without free 2 MiB of frames fall
+    // back to a single page
+    let block = fault_addr.align_down(HUGE_PAGE_SIZE);
+    if vma.huge
+        && block.as_u64() >= vma.start
+        && vma.end - block.as_u64() >= HUGE_PAGE_SIZE
+        && mmap::map_zeroed_huge_page(mapper, frame_allocator, Page::containing_address(block), flags).is_ok()
+    {
+        debug_println!("[PageFault] Huge page filled at {:#x}", block.as_u64());
+        return Ok(());
+    }
+
+    let frame = frame_allocator
+        .allocate_frame()
+        .ok_or(PageFaultError::OutOfMemory)?;
+
+    // Zero-initialize the page before it becomes visible
+    unsafe {
+        let phys_offset = VirtAddr::new(crate::kernel::mm::PHYS_MEM_OFFSET.load(core::sync::atomic::Ordering::Relaxed));
+        let frame_ptr = (phys_offset + frame.start_address().as_u64()).as_mut_ptr::<u8>();
+        core::ptr::write_bytes(frame_ptr, 0, 4096);
+    }
+
+    unsafe {
+        mapper
+            .map_to(fault_page, frame, flags, frame_allocator)
+            .map_err(|_| PageFaultError::OutOfMemory)?
+            .flush();
+    }
+
+    Ok(())
 }
 
 /// Handle Copy-on-Write fault
-fn handle_cow_fault<M>(
+fn handle_cow_fault(
     page: Page<Size4KiB>,
     error_code: PageFaultErrorCode,
-    mapper: &mut M,
+    mapper: &mut OffsetPageTable<'_>,
     frame_allocator: &mut BootInfoFrameAllocator,
-) -> PageFaultResult<bool>
-where
-    M: Mapper<Size4KiB> + Translate,
-{
+) -> PageFaultResult<bool> {
     // CoW only applies to write violations
     if !error_code.contains(PageFaultErrorCode::CAUSED_BY_WRITE) {
         return Ok(false);
     }
 
     // Get current flags and frame
-    let (phys_frame, flags) = match mapper.translate(page.start_address()) {
+    let (mut phys_frame, mut flags) = match mapper.translate(page.start_address()) {
         TranslateResult::Mapped { frame, flags, .. } => (frame, flags),
         _ => return Err(PageFaultError::TranslationFailed),
     };
@@ -177,6 +180,16 @@ where
     if !flags.contains(COW_FLAG) {
         return Ok(false);
     }
+
+    // A shared huge page is split so that only the written page is copied
+    if flags.contains(PageTableFlags::HUGE_PAGE) {
+        mmap::split_huge_page(mapper, frame_allocator, page.start_address())
+            .map_err(|_| PageFaultError::OutOfMemory)?;
+        (phys_frame, flags) = match mapper.translate(page.start_address()) {
+            TranslateResult::Mapped { frame, flags, .. } => (frame, flags),
+            _ => return Err(PageFaultError::TranslationFailed),
+        };
+    }
     
     debug_println!("[PageFault] Handling CoW for page {:#x}", page.start_address().as_u64());
     
@@ -217,7 +230,80 @@ where
     Ok(true)
 }
 
-/// Check if a virtual address is in valid user space range
+/// Fill in the missing pages of `[addr, addr + len)` as if user space had
+/// touched them
+///
+/// Each page that is not present is faulted in with a read (or write)
+/// access; with `write`, present copy-on-write pages are copied as well.
+/// Stops at the first page the process may not access that way.
+pub fn populate_user_range(
+    addr: u64,
+    len: u64,
+    write: bool,
+    vmas: &VmaTree,
+    mapper: &mut OffsetPageTable<'_>,
+    frame_allocator: &mut BootInfoFrameAllocator,
+) -> PageFaultResult<()> {
+    if len == 0 {
+        return Ok(());
+    }
+    let end = addr.checked_add(len - 1).ok_or(PageFaultError::InvalidAddress)?;
+    let first = Page::<Size4KiB>::containing_address(VirtAddr::try_new(addr).map_err(|_| PageFaultError::InvalidAddress)?);
+    let last = Page::<Size4KiB>::containing_address(VirtAddr::try_new(end).map_err(|_| PageFaultError::InvalidAddress)?);
+
+    let mut access = PageFaultErrorCode::USER_MODE;
+    if write {
+        access |= PageFaultErrorCode::CAUSED_BY_WRITE;
+    }
+
+    for page in Page::range_inclusive(first, last) {
+        let error_code = match mapper.translate(page.start_address()) {
+            TranslateResult::Mapped { flags, .. } => {
+                if !write || flags.contains(PageTableFlags::WRITABLE) {
+                    continue;
+                }
+                access | PageFaultErrorCode::PROTECTION_VIOLATION
+            }
+            _ => access,
+        };
+        handle_user_page_fault(page.start_address(), error_code, vmas, mapper, frame_allocator)?;
+    }
+    Ok(())
+}
+
+/// [`populate_user_range`] for the running process
+///
+/// Used before the kernel reads or writes a user buffer. Callers that
+/// already hold `PROCESS_TABLE` cannot populate; the range is then left
+/// as it is and the caller's own checks decide. Failures are not
+/// reported either, for the same reason.
+pub fn fault_in_current(addr: u64, len: u64, write: bool) {
+    use crate::kernel::mm::allocator::BOOT_INFO_ALLOCATOR;
+    use crate::kernel::mm::mmap::current_mapper;
+    use crate::kernel::process::PROCESS_TABLE;
+
+    let Some(table) = PROCESS_TABLE.try_lock() else {
+        return;
+    };
+    let Some(process) = table.current_process() else {
+        return;
+    };
+    let mut allocator_lock = BOOT_INFO_ALLOCATOR.lock();
+    let Some(frame_allocator) = allocator_lock.as_mut() else {
+        return;
+    };
+    // SAFETY: PROCESS_TABLE serializes page table updates of the current process
+    let mut mapper = unsafe { current_mapper() };
+    if let Err(e) = populate_user_range(addr, len, write, process.vmas(), &mut mapper, frame_allocator) {
+        debug_println!("[PageFault] Could not populate {:#x}+{:#x}: {:?}", addr, len, e);
+    }
+}
+
+/// Check if a virtual address lies in the user half of the address space
+///
+/// The null page is excluded. Whether the address is actually part of a
+/// mapping is decided by the process's memory areas in
+/// [`handle_user_page_fault`].
 ///
 /// # Arguments
 ///
@@ -227,33 +313,13 @@ where
 ///
 /// `true` if the address is in user space, `false` otherwise
 pub fn is_user_space_address(addr: VirtAddr) -> bool {
-    let addr_u64 = addr.as_u64();
-    
-    // User space is typically 0x0000_0000_0000_0000 to 0x0000_7FFF_FFFF_FFFF
-    // We use a more conservative range for our OS
-    
-    // Check code region (4 MiB to 5 MiB)
-    let code_start = USER_CODE_BASE;
-    let code_end = code_start + (1024 * 1024); // 1 MB
-    
-    if addr_u64 >= code_start && addr_u64 < code_end {
-        return true;
-    }
-    
-    // Check stack region
-    let stack_top = USER_STACK_TOP;
-    let stack_bottom = stack_top - USER_STACK_SIZE;
-    
-    if addr_u64 >= stack_bottom && addr_u64 < stack_top {
-        return true;
-    }
-    
-    false
+    (0x1000..USER_SPACE_END).contains(&addr.as_u64())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::kernel::mm::user_paging::{DEFAULT_USER_STACK_SIZE, USER_CODE_BASE, USER_STACK_TOP};
     
     #[test]
     fn test_is_user_space_address() {
@@ -263,7 +329,7 @@ mod tests {
         
         // Stack region
         assert!(is_user_space_address(VirtAddr::new(USER_STACK_TOP - 0x1000)));
-        assert!(is_user_space_address(VirtAddr::new(USER_STACK_TOP - USER_STACK_SIZE + 1)));
+        assert!(is_user_space_address(VirtAddr::new(USER_STACK_TOP - DEFAULT_USER_STACK_SIZE as u64 + 1)));
         
         // Invalid addresses
         assert!(!is_user_space_address(VirtAddr::new(0)));
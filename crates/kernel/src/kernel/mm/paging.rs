@@ -3,8 +3,10 @@
 //!
 //! x86_64のページテーブル操作を提供します。
 
-use x86_64::structures::paging::{OffsetPageTable, PageTable, PageTableFlags};
-use x86_64::VirtAddr;
+use core::sync::atomic::Ordering;
+use x86_64::registers::control::Cr3;
+use x86_64::structures::paging::{FrameAllocator, OffsetPageTable, PageTable, PageTableFlags, Size4KiB};
+use x86_64::{PhysAddr, VirtAddr};
 
 /// Copy-on-Write flag (Bit 9, available for OS use)
 pub const COW_FLAG: PageTableFlags = PageTableFlags::BIT_9;
@@ -69,3 +71,147 @@ unsafe fn active_level_4_table(physical_memory_offset: VirtAddr)
     // - 仮想アドレスの計算結果は有効なページテーブルを指している
     unsafe { &mut *page_table_ptr }
 }
+
+/// 現在の CR3 で仮想アドレスを変換し、物理メモリマッピング上のポインタを返します。
+///
+/// マップされていないアドレスや非正規アドレスには `None` を返すため、
+/// 例外ハンドラやデバッガから任意のアドレスを安全に読み書きするのに使えます。
+/// 書き込みは物理マッピング経由になるため、読み取り専用ページにも書けます。
+#[must_use]
+pub fn translate_current(addr: u64) -> Option<*mut u8> {
+    let offset = super::PHYS_MEM_OFFSET.load(core::sync::atomic::Ordering::Relaxed);
+    physical_address(addr).map(|phys| (offset + phys.as_u64()) as *mut u8)
+}
+
+/// 現在の CR3 で仮想アドレスを物理アドレスに変換します。
+///
+/// マップされていないアドレスや非正規アドレス、物理メモリマッピングの
+/// 初期化前には `None` を返します。
+#[must_use]
+pub fn physical_address(addr: u64) -> Option<PhysAddr> {
+    use x86_64::structures::paging::Translate;
+
+    let offset = super::PHYS_MEM_OFFSET.load(core::sync::atomic::Ordering::Relaxed);
+    if offset == 0 {
+        return None;
+    }
+    let virt = VirtAddr::try_new(addr).ok()?;
+    // SAFETY: 物理メモリ全体が offset にマッピングされており、
+    // ページテーブルは読み取りにのみ使う
+    let mapper = unsafe { OffsetPageTable::new(active_level_4_table(VirtAddr::new(offset)), VirtAddr::new(offset)) };
+    mapper.translate_addr(virt)
+}
+
+/// PML4 エントリ 1 つがカバーする範囲 (512 GiB)
+pub const PML4_ENTRY_SIZE: u64 = 1 << 39;
+
+/// カーネル専用の仮想領域として上位半分の空き PML4 エントリを 1 つ確保します。
+///
+/// 空の L3 テーブルを割り当ててエントリに設定し、領域の先頭アドレスを返します。
+/// ユーザーページテーブルは作成時にカーネルの PML4 エントリをコピーするため、
+/// 最初のプロセスを作成する前に呼び出せば、以後この領域のマッピングは
+/// すべてのアドレス空間から同じアドレスで見えます。
+///
+/// # Errors
+/// 空きエントリがない場合、またはフレームを確保できない場合
+pub fn reserve_kernel_region<A>(frame_allocator: &mut A) -> Result<VirtAddr, &'static str>
+where
+    A: FrameAllocator<Size4KiB>,
+{
+    let phys_mem_offset = super::PHYS_MEM_OFFSET.load(Ordering::Relaxed);
+    let (l4_frame, _) = Cr3::read();
+    // SAFETY: 物理メモリ全体がダイレクトマップされており、起動処理中は
+    // 他にページテーブルを書き換えるコードがない
+    let l4_table = unsafe {
+        &mut *((phys_mem_offset + l4_frame.start_address().as_u64()) as *mut PageTable)
+    };
+
+    let index = (256..512)
+        .find(|&i| l4_table[i].is_unused())
+        .ok_or("no free PML4 entry")?;
+
+    let l3_frame = frame_allocator
+        .allocate_frame()
+        .ok_or("failed to allocate L3 table")?;
+    // SAFETY: 割り当てたばかりのフレームで、ダイレクトマップ経由でのみ触れる
+    unsafe {
+        let l3_table = (phys_mem_offset + l3_frame.start_address().as_u64()) as *mut PageTable;
+        (*l3_table).zero();
+    }
+    l4_table[index].set_frame(l3_frame, PageTableFlags::PRESENT | PageTableFlags::WRITABLE);
+
+    // 上位半分のアドレスは符号拡張する
+    Ok(VirtAddr::new(0xFFFF_0000_0000_0000 | (index as u64 * PML4_ENTRY_SIZE)))
+}
+
+/// ダイレクトマップ（物理メモリのオフセットマッピング）を 2 MiB ページにまとめます。
+///
+/// `[0, phys_end)` のうち 4 KiB ページでマップされている 2 MiB ブロックを
+/// 調べ、512 エントリがすべて存在し、連続した物理アドレスを同じフラグで
+/// マップしていれば 1 つの 2 MiB ページに置き換えます。仮想アドレスと
+/// 物理アドレスの対応は変わらないため、`PHYS_MEM_OFFSET` を使うコードには
+/// 影響しません。置き換えたブロック数を返します（ブートローダが最初から
+/// 2 MiB ページでマップしていれば 0）。
+///
+/// 不要になった L1 テーブルはブートローダのフレームなので解放しません。
+/// 最初のプロセスを作成する前に呼び出します。
+pub fn collapse_direct_map(phys_end: u64) -> usize {
+    use x86_64::structures::paging::{PageSize, Size2MiB};
+    use x86_64::PhysAddr;
+
+    // ページごとに変わるビットは比較しない
+    let volatile = PageTableFlags::ACCESSED | PageTableFlags::DIRTY;
+
+    let phys_mem_offset = super::PHYS_MEM_OFFSET.load(Ordering::Relaxed);
+    if phys_mem_offset == 0 {
+        return 0;
+    }
+    let table_at = |phys: PhysAddr| (phys_mem_offset + phys.as_u64()) as *mut PageTable;
+    let (l4_frame, _) = Cr3::read();
+
+    let mut collapsed = 0;
+    for block in (0..phys_end).step_by(Size2MiB::SIZE as usize) {
+        let virt = VirtAddr::new(phys_mem_offset + block);
+        // SAFETY: ダイレクトマップのページテーブルはダイレクトマップ自身から
+        // 見えており、起動処理中は他に書き換えるコードがない
+        let pd_entry = unsafe {
+            let l4 = &*table_at(l4_frame.start_address());
+            let l4_entry = &l4[virt.p4_index()];
+            if !l4_entry.flags().contains(PageTableFlags::PRESENT) {
+                continue;
+            }
+            let l3 = &*table_at(l4_entry.addr());
+            let l3_entry = &l3[virt.p3_index()];
+            if !l3_entry.flags().contains(PageTableFlags::PRESENT)
+                || l3_entry.flags().contains(PageTableFlags::HUGE_PAGE)
+            {
+                continue;
+            }
+            let pd = &mut *table_at(l3_entry.addr());
+            &mut pd[virt.p2_index()]
+        };
+        let pd_flags = pd_entry.flags();
+        if !pd_flags.contains(PageTableFlags::PRESENT) || pd_flags.contains(PageTableFlags::HUGE_PAGE) {
+            continue;
+        }
+
+        // SAFETY: 上と同じ
+        let pt = unsafe { &*table_at(pd_entry.addr()) };
+        let flags = pt[0].flags() - volatile;
+        // L1 のビット 7 は PAT で、2 MiB エントリでは位置が変わる
+        let contiguous = !flags.contains(PageTableFlags::HUGE_PAGE)
+            && pt.iter().enumerate().all(|(i, entry)| {
+                entry.flags().contains(PageTableFlags::PRESENT)
+                    && entry.addr().as_u64() == block + i as u64 * 4096
+                    && entry.flags() - volatile == flags
+            });
+        if !contiguous {
+            continue;
+        }
+
+        pd_entry.set_addr(PhysAddr::new(block), flags | PageTableFlags::HUGE_PAGE);
+        x86_64::instructions::tlb::flush(virt);
+        collapsed += 1;
+    }
+    collapsed
+}
@@ -0,0 +1,241 @@
+// kernel/src/kernel/mm/pcid.rs
+//! PCID によるアドレス空間の識別
+//!
+//! CR4.PCIDE を有効にすると TLB エントリに 12 ビットの PCID が付き、
+//! CR3 を書き換えても他のアドレス空間のエントリが残るようになります。
+//! プロセスのアドレス空間ごとに [`AddressSpaceTag`] を持たせ、切り替えの
+//! たびに PCID を確かめてから CR3 をフラッシュなしで書き込みます。
+//!
+//! # 世代による再利用
+//!
+//! PCID は 1 から順に配り、使い切ったら世代を進め、すべての PCID の TLB を
+//! フラッシュしてから 1 に戻ります。タグの世代が現在の世代と違えば新しい
+//! PCID を割り当て直します。終了したプロセスの PCID は世代が進むまで
+//! 再利用しないので、プロセスの終了時にフラッシュは要りません。
+//! PCID 0 はブート時のカーネルページテーブルが使います。
+//!
+//! 現在のアドレス空間以外のページテーブルを書き換えた場合は
+//! [`flush_all_contexts`] を呼ぶ必要があります（カーネル領域のアンマップなど）。
+//!
+//! # フォールバック
+//!
+//! PCID のない CPU では従来どおり CR3 を書き込むたびに TLB 全体が
+//! フラッシュされます。INVPCID がない場合、単一アドレス空間のフラッシュは
+//! 同じ PCID での CR3 の再書き込みで、全 PCID のフラッシュは CR4.PGE の
+//! 切り替えで代用します。
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+use x86_64::instructions::tlb::{self, InvPcidCommand};
+use x86_64::registers::control::{Cr3, Cr3Flags, Cr4, Cr4Flags};
+use x86_64::structures::paging::PhysFrame;
+
+use crate::arch::x86_64::{cpu_features, critical_section};
+
+/// 割り当てる最初の PCID（0 はカーネル用）
+const FIRST_PCID: u16 = 1;
+/// PCID の最大値
+const MAX_PCID: u16 = 4095;
+/// CR3 のビット 63: 書き込み時に PCID のエントリを残す
+const CR3_NOFLUSH: u64 = 1 << 63;
+
+/// CR4.PCIDE を有効にしたか
+static ENABLED: AtomicBool = AtomicBool::new(false);
+/// INVPCID を使えるか
+static HAS_INVPCID: AtomicBool = AtomicBool::new(false);
+/// PCID の割り当て状態
+static ALLOCATOR: Mutex<PcidAllocator> = Mutex::new(PcidAllocator::new(MAX_PCID));
+
+/// アドレス空間に割り当てた PCID と、その世代
+///
+/// 既定値は未割り当て（世代 0）です。ページテーブルを作り直したときは
+/// 既定値に戻し、古い PCID のエントリを引き継がないようにします。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AddressSpaceTag {
+    pcid: u16,
+    generation: u64,
+}
+
+impl AddressSpaceTag {
+    /// 割り当てられている PCID（未割り当てなら 0）
+    pub const fn pcid(&self) -> u16 {
+        self.pcid
+    }
+}
+
+/// 世代付きの PCID 割り当て
+struct PcidAllocator {
+    generation: u64,
+    next: u16,
+    max: u16,
+}
+
+impl PcidAllocator {
+    const fn new(max: u16) -> Self {
+        PcidAllocator {
+            generation: 1,
+            next: FIRST_PCID,
+            max,
+        }
+    }
+
+    /// 現在の世代の PCID を `tag` に割り当てる
+    ///
+    /// 使い切って世代を進めた場合は `true` を返します。このとき呼び出し側は
+    /// すべての PCID の TLB をフラッシュする必要があります。
+    fn assign(&mut self, tag: &mut AddressSpaceTag) -> bool {
+        if tag.generation == self.generation {
+            return false;
+        }
+        let rolled_over = self.next > self.max;
+        if rolled_over {
+            self.generation += 1;
+            self.next = FIRST_PCID;
+        }
+        tag.pcid = self.next;
+        tag.generation = self.generation;
+        self.next += 1;
+        rolled_over
+    }
+}
+
+/// CPU が対応していれば PCID を有効にする
+///
+/// ページングの初期化後、最初のプロセスを作成する前に一度だけ呼び出します。
+pub fn init() {
+    let features = cpu_features::get();
+    if !features.has_pcid {
+        log::info!(target: "mm", "PCID not supported, every CR3 write flushes the TLB");
+        return;
+    }
+    // PCIDE を立てるには CR3 の下位 12 ビットが 0 である必要がある
+    let (frame, low_bits) = Cr3::read_raw();
+    // SAFETY: 同じページテーブルを読み込み直すだけ
+    unsafe {
+        if low_bits != 0 {
+            Cr3::write(frame, Cr3Flags::empty());
+        }
+        Cr4::update(|flags| flags.insert(Cr4Flags::PCID));
+    }
+    HAS_INVPCID.store(features.has_invpcid, Ordering::Relaxed);
+    ENABLED.store(true, Ordering::Release);
+    log::info!(target: "mm", "PCID enabled (INVPCID: {})", features.has_invpcid);
+}
+
+/// PCID を使っているか
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Acquire)
+}
+
+/// `frame` をルートとするアドレス空間の CR3 の値
+///
+/// PCID が有効なら `tag` に PCID を割り当て、フラッシュしない指定を付けます。
+/// 無効ならページテーブルの物理アドレスそのものです。
+pub fn cr3_value(frame: PhysFrame, tag: &mut AddressSpaceTag) -> u64 {
+    let addr = frame.start_address().as_u64();
+    if !is_enabled() {
+        return addr;
+    }
+    let rolled_over = critical_section(|| ALLOCATOR.lock().assign(tag));
+    if rolled_over {
+        flush_all_contexts();
+    }
+    CR3_NOFLUSH | addr | u64::from(tag.pcid)
+}
+
+/// `frame` をルートとするアドレス空間に切り替える
+///
+/// # Safety
+/// `frame` がカーネル領域をマップした有効な PML4 であること
+pub unsafe fn switch_to(frame: PhysFrame, tag: &mut AddressSpaceTag) {
+    let value = cr3_value(frame, tag);
+    // SAFETY: 呼び出し側が保証し、PCID のビットは PCIDE 有効時にしか立たない
+    unsafe {
+        core::arch::asm!("mov cr3, {}", in(reg) value, options(nostack, preserves_flags));
+    }
+}
+
+/// 現在のアドレス空間の TLB エントリをすべてフラッシュ
+///
+/// 他のアドレス空間のエントリは残ります。
+pub fn flush_address_space() {
+    if !is_enabled() {
+        tlb::flush_all();
+        return;
+    }
+    let (frame, pcid) = Cr3::read_pcid();
+    // SAFETY: INVPCID の有無は確認済みで、CR3 は同じページテーブルに戻すだけ
+    unsafe {
+        if HAS_INVPCID.load(Ordering::Relaxed) {
+            tlb::flush_pcid(InvPcidCommand::Single(pcid));
+        } else {
+            Cr3::write_pcid(frame, pcid);
+        }
+    }
+}
+
+/// すべての PCID の TLB エントリをフラッシュ
+pub fn flush_all_contexts() {
+    if !is_enabled() {
+        tlb::flush_all();
+        return;
+    }
+    // SAFETY: INVPCID の有無は確認済み。PGE の切り替えはフラッシュ以外の
+    // 効果を持たない
+    unsafe {
+        if HAS_INVPCID.load(Ordering::Relaxed) {
+            tlb::flush_pcid(InvPcidCommand::All);
+        } else {
+            let flags = Cr4::read();
+            Cr4::write(flags ^ Cr4Flags::PAGE_GLOBAL);
+            Cr4::write(flags);
+        }
+    }
+}
+
+/// 現在の PCID（無効なら 0）
+pub fn current_pcid() -> u16 {
+    if is_enabled() {
+        Cr3::read_pcid().1.value()
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_tag_keeps_pcid_within_generation() {
+        let mut allocator = PcidAllocator::new(MAX_PCID);
+        let mut a = AddressSpaceTag::default();
+        let mut b = AddressSpaceTag::default();
+        assert!(!allocator.assign(&mut a));
+        assert!(!allocator.assign(&mut b));
+        assert_eq!((a.pcid(), b.pcid()), (1, 2));
+
+        // 同じ世代なら割り当て直さない
+        assert!(!allocator.assign(&mut a));
+        assert_eq!(a.pcid(), 1);
+    }
+
+    #[test_case]
+    fn test_generation_rollover_recycles_pcids() {
+        let mut allocator = PcidAllocator::new(2);
+        let mut a = AddressSpaceTag::default();
+        let mut b = AddressSpaceTag::default();
+        let mut c = AddressSpaceTag::default();
+        allocator.assign(&mut a);
+        allocator.assign(&mut b);
+
+        // 使い切ったら世代を進めて 1 から配り直す
+        assert!(allocator.assign(&mut c));
+        assert_eq!(c.pcid(), 1);
+
+        // 古い世代のタグは次の切り替えで割り当て直される
+        assert!(!allocator.assign(&mut a));
+        assert_eq!(a.pcid(), 2);
+        assert_ne!(a.generation, b.generation);
+    }
+}
@@ -0,0 +1,644 @@
+// kernel/src/kernel/mm/slab.rs
+//! スラブアロケータ
+//!
+//! 小さな固定サイズのオブジェクトをサイズ別のキャッシュから割り当てます。
+//! 各キャッシュは 4 KiB のスラブ（ヒープから確保したページ）を持ち、
+//! スラブ内の空きオブジェクトを侵入型リストで管理するため、割り当てと解放は
+//! O(1) です。[`MAX_OBJECT_SIZE`] を超える割り当ては従来どおり
+//! [`LockedHeap`] の first-fit に回します。
+//!
+//! ```text
+//! slab (4 KiB, 4 KiB アライン):
+//!   [Slab ヘッダ][pad][obj 0][obj 1] ... [obj n-1]
+//! ```
+//!
+//! スラブはページ境界にアラインされているため、解放時はポインタの下位
+//! ビットを落とすだけでヘッダ（と所属するキャッシュ）が分かります。
+//!
+//! 頻繁に割り当てる型には [`SlabAllocator::register_cache`] で名前付き
+//! キャッシュを登録できます。同じレイアウトの割り当ては以後そのキャッシュ
+//! から行われ、統計も型ごとに分かれます。
+//!
+//! `heap_debug` feature を有効にすると、スラブを使わずすべての割り当てを
+//! [`super::heap_debug`] 経由でヒープから行います。
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use spin::Mutex;
+
+use super::allocator::{HeapGrowHandler, HeapOomHandler, HeapStats, LockedHeap};
+use super::types::{LayoutSize, MemoryError, VirtAddr};
+
+/// スラブ 1 枚のサイズ
+const SLAB_SIZE: usize = 4096;
+/// スラブから割り当てる最大のオブジェクトサイズ
+pub const MAX_OBJECT_SIZE: usize = 512;
+/// 最小のオブジェクトサイズ（空きリストのポインタを格納できる大きさ）
+const MIN_OBJECT_SIZE: usize = 8;
+/// サイズ別キャッシュの数 (8, 16, ..., 512)
+const SIZE_CLASS_COUNT: usize = 7;
+/// 名前付きキャッシュの最大数
+pub const MAX_NAMED_CACHES: usize = 8;
+/// スラブヘッダの破損検出用マジック
+const SLAB_MAGIC: u32 = 0x5AB5_AB00;
+
+/// スラブ内の空きオブジェクト
+struct FreeObject {
+    next: *mut FreeObject,
+}
+
+/// スラブヘッダ（スラブページの先頭に置く）
+struct Slab {
+    magic: u32,
+    /// 所属するキャッシュの番号
+    cache: u16,
+    /// 使用中のオブジェクト数
+    in_use: u16,
+    /// 空きオブジェクトのリスト
+    free: *mut FreeObject,
+    /// 部分スラブリストの前後
+    prev: *mut Slab,
+    next: *mut Slab,
+}
+
+impl Slab {
+    /// `ptr` を含むスラブのヘッダ
+    fn containing(ptr: *mut u8) -> *mut Slab {
+        (ptr as usize & !(SLAB_SIZE - 1)) as *mut Slab
+    }
+}
+
+/// キャッシュごとの統計情報
+#[derive(Debug, Clone, Copy)]
+pub struct SlabStats {
+    /// キャッシュ名
+    pub name: &'static str,
+    /// オブジェクト 1 個のサイズ（パディング込み）
+    pub object_size: usize,
+    /// スラブ 1 枚あたりのオブジェクト数
+    pub objects_per_slab: usize,
+    /// 保持しているスラブ数
+    pub slabs: usize,
+    /// 使用中のオブジェクト数
+    pub active_objects: usize,
+    /// 割り当て回数
+    pub allocation_count: usize,
+    /// 解放回数
+    pub deallocation_count: usize,
+}
+
+/// 1 つのオブジェクトサイズを受け持つキャッシュ
+struct SlabCache {
+    name: &'static str,
+    object_size: usize,
+    /// スラブ内で最初のオブジェクトのオフセット
+    first_offset: usize,
+    /// 空きのあるスラブのリスト（満杯のスラブはリストから外す）
+    partial: *mut Slab,
+    slabs: usize,
+    active: usize,
+    allocs: usize,
+    frees: usize,
+}
+
+// SAFETY: 生ポインタはキャッシュが所有するスラブだけを指し、
+// アクセスは外側の Mutex で直列化される
+unsafe impl Send for SlabCache {}
+
+impl SlabCache {
+    const fn new(name: &'static str, object_size: usize, align: usize) -> Self {
+        let header = core::mem::size_of::<Slab>();
+        Self {
+            name,
+            object_size,
+            first_offset: (header + align - 1) & !(align - 1),
+            partial: ptr::null_mut(),
+            slabs: 0,
+            active: 0,
+            allocs: 0,
+            frees: 0,
+        }
+    }
+
+    /// 未登録の名前付きキャッシュ
+    const fn unused() -> Self {
+        Self::new("", 0, MIN_OBJECT_SIZE)
+    }
+
+    fn objects_per_slab(&self) -> usize {
+        if self.object_size == 0 {
+            return 0;
+        }
+        (SLAB_SIZE - self.first_offset) / self.object_size
+    }
+
+    fn stats(&self) -> SlabStats {
+        SlabStats {
+            name: self.name,
+            object_size: self.object_size,
+            objects_per_slab: self.objects_per_slab(),
+            slabs: self.slabs,
+            active_objects: self.active,
+            allocation_count: self.allocs,
+            deallocation_count: self.frees,
+        }
+    }
+
+    /// オブジェクトを 1 つ割り当てる（空きがなければスラブを追加）
+    ///
+    /// # Safety
+    /// `index` はこのキャッシュの番号であること
+    unsafe fn allocate(&mut self, index: usize, backing: &LockedHeap) -> *mut u8 {
+        if self.partial.is_null() {
+            // SAFETY: 呼び出し元の保証をそのまま引き継ぐ
+            let slab = unsafe { self.grow(index, backing) };
+            if slab.is_null() {
+                return ptr::null_mut();
+            }
+            self.push_partial(slab);
+        }
+
+        let slab = self.partial;
+        // SAFETY: partial リストのスラブは有効で、必ず空きオブジェクトを持つ
+        unsafe {
+            let object = (*slab).free;
+            (*slab).free = (*object).next;
+            (*slab).in_use += 1;
+            if (*slab).free.is_null() {
+                self.unlink(slab);
+            }
+            self.active += 1;
+            self.allocs += 1;
+            object.cast()
+        }
+    }
+
+    /// オブジェクトをスラブに戻す
+    ///
+    /// 空になったスラブは、他にも部分スラブがあればヒープに返します。
+    /// 割り当てと解放が交互に続く場合のページの出し入れを避けるため、
+    /// 最後の 1 枚は保持します。
+    ///
+    /// # Safety
+    /// `object` はこのキャッシュから割り当てた使用中のオブジェクトであること
+    unsafe fn free(&mut self, slab: *mut Slab, object: *mut u8, backing: &LockedHeap) {
+        // SAFETY: 呼び出し元が slab と object の正当性を保証する
+        unsafe {
+            let was_full = (*slab).free.is_null();
+            let node = object.cast::<FreeObject>();
+            (*node).next = (*slab).free;
+            (*slab).free = node;
+            (*slab).in_use -= 1;
+            self.active -= 1;
+            self.frees += 1;
+
+            if was_full {
+                self.push_partial(slab);
+            }
+            if (*slab).in_use == 0 && !(self.partial == slab && (*slab).next.is_null()) {
+                self.unlink(slab);
+                (*slab).magic = 0;
+                self.slabs -= 1;
+                backing.dealloc(slab.cast(), Self::slab_layout());
+            }
+        }
+    }
+
+    /// 新しいスラブをヒープから確保し、空きリストを作る
+    ///
+    /// # Safety
+    /// `index` はこのキャッシュの番号であること
+    unsafe fn grow(&mut self, index: usize, backing: &LockedHeap) -> *mut Slab {
+        let count = self.objects_per_slab();
+        if count == 0 {
+            return ptr::null_mut();
+        }
+        // SAFETY: レイアウトはサイズ 0 ではない
+        let page = unsafe { backing.alloc(Self::slab_layout()) };
+        if page.is_null() {
+            return ptr::null_mut();
+        }
+
+        let slab = page.cast::<Slab>();
+        // SAFETY: page はこのスラブ専用に確保したばかりの 4 KiB
+        unsafe {
+            let mut free: *mut FreeObject = ptr::null_mut();
+            for i in (0..count).rev() {
+                let node = page.add(self.first_offset + i * self.object_size).cast::<FreeObject>();
+                (*node).next = free;
+                free = node;
+            }
+            slab.write(Slab {
+                magic: SLAB_MAGIC,
+                cache: index as u16,
+                in_use: 0,
+                free,
+                prev: ptr::null_mut(),
+                next: ptr::null_mut(),
+            });
+        }
+        self.slabs += 1;
+        slab
+    }
+
+    fn push_partial(&mut self, slab: *mut Slab) {
+        // SAFETY: slab はこのキャッシュのスラブで、どのリストにも入っていない
+        unsafe {
+            (*slab).prev = ptr::null_mut();
+            (*slab).next = self.partial;
+            if !self.partial.is_null() {
+                (*self.partial).prev = slab;
+            }
+        }
+        self.partial = slab;
+    }
+
+    fn unlink(&mut self, slab: *mut Slab) {
+        // SAFETY: slab は partial リストに入っている
+        unsafe {
+            let (prev, next) = ((*slab).prev, (*slab).next);
+            if prev.is_null() {
+                self.partial = next;
+            } else {
+                (*prev).next = next;
+            }
+            if !next.is_null() {
+                (*next).prev = prev;
+            }
+            (*slab).prev = ptr::null_mut();
+            (*slab).next = ptr::null_mut();
+        }
+    }
+
+    const fn slab_layout() -> Layout {
+        // SAFETY: SLAB_SIZE は 0 でない 2 の累乗
+        unsafe { Layout::from_size_align_unchecked(SLAB_SIZE, SLAB_SIZE) }
+    }
+}
+
+/// 名前付きキャッシュのキー（0 は未登録）
+fn layout_key(layout: Layout) -> u64 {
+    ((layout.size() as u64) << 32) | layout.align() as u64
+}
+
+/// スラブ層付きのヒープアロケータ
+///
+/// 小さな割り当てをスラブキャッシュから、それ以外を [`LockedHeap`] から
+/// 行います。スラブ自体も [`LockedHeap`] から確保するため、ヒープ容量は
+/// 両者で共有されます。
+pub struct SlabAllocator {
+    backing: LockedHeap,
+    classes: [Mutex<SlabCache>; SIZE_CLASS_COUNT],
+    named: [Mutex<SlabCache>; MAX_NAMED_CACHES],
+    /// 名前付きキャッシュのレイアウト（ロックなしで引くためアトミック）
+    named_keys: [AtomicU64; MAX_NAMED_CACHES],
+    named_count: AtomicUsize,
+    /// OOM ハンドラ（`None` なら失敗した割り当てをやり直さない）
+    oom_handler: Mutex<Option<HeapOomHandler>>,
+    /// デバッグモードの状態
+    #[cfg(feature = "heap_debug")]
+    debug: Mutex<super::heap_debug::DebugState>,
+}
+
+impl Default for SlabAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SlabAllocator {
+    /// 新しいアロケータを作成
+    pub const fn new() -> Self {
+        Self {
+            backing: LockedHeap::new(),
+            classes: [
+                Mutex::new(SlabCache::new("kmalloc-8", 8, 8)),
+                Mutex::new(SlabCache::new("kmalloc-16", 16, 16)),
+                Mutex::new(SlabCache::new("kmalloc-32", 32, 32)),
+                Mutex::new(SlabCache::new("kmalloc-64", 64, 64)),
+                Mutex::new(SlabCache::new("kmalloc-128", 128, 128)),
+                Mutex::new(SlabCache::new("kmalloc-256", 256, 256)),
+                Mutex::new(SlabCache::new("kmalloc-512", 512, 512)),
+            ],
+            named: [const { Mutex::new(SlabCache::unused()) }; MAX_NAMED_CACHES],
+            named_keys: [const { AtomicU64::new(0) }; MAX_NAMED_CACHES],
+            named_count: AtomicUsize::new(0),
+            oom_handler: Mutex::new(None),
+            #[cfg(feature = "heap_debug")]
+            debug: Mutex::new(super::heap_debug::DebugState::new()),
+        }
+    }
+
+    /// ヒープを初期化
+    ///
+    /// # Safety
+    ///
+    /// [`LockedHeap::init`] と同じ
+    ///
+    /// # Errors
+    ///
+    /// 既に初期化されている場合は `Err(MemoryError::InvalidAddress)` を返します
+    pub unsafe fn init(&self, heap_start: VirtAddr, heap_size: LayoutSize) -> Result<(), MemoryError> {
+        // SAFETY: 呼び出し元が保証する
+        unsafe { self.backing.init(heap_start, heap_size) }
+    }
+
+    /// ヒープの拡張ハンドラを設定（[`LockedHeap::set_grow_handler`] を参照）
+    pub fn set_grow_handler(&self, handler: HeapGrowHandler) {
+        self.backing.set_grow_handler(handler);
+    }
+
+    /// OOM ハンドラを設定
+    ///
+    /// 以後、失敗した割り当てはキャッシュとヒープのロックを手放してから
+    /// `handler` を呼び、`true` ならやり直します。
+    pub fn set_oom_handler(&self, handler: HeapOomHandler) {
+        *self.oom_handler.lock() = Some(handler);
+    }
+
+    /// ヒープ統計情報を取得（スラブページは使用中として数える）
+    pub fn heap_stats(&self) -> HeapStats {
+        self.backing.stats()
+    }
+
+    /// 各キャッシュの統計情報を `f` に渡す（未使用の名前付きキャッシュは除く）
+    pub fn for_each_cache(&self, mut f: impl FnMut(&SlabStats)) {
+        for cache in &self.classes {
+            f(&cache.lock().stats());
+        }
+        let registered = self.named_count.load(Ordering::Acquire).min(MAX_NAMED_CACHES);
+        for cache in &self.named[..registered] {
+            let stats = cache.lock().stats();
+            if stats.object_size != 0 {
+                f(&stats);
+            }
+        }
+    }
+
+    /// `layout` 専用の名前付きキャッシュを登録
+    ///
+    /// 登録後、同じサイズとアラインメントの割り当てはすべてこのキャッシュから
+    /// 行われます。登録前に割り当てたオブジェクトは元のキャッシュに戻るため、
+    /// いつ登録しても安全です。
+    ///
+    /// # Errors
+    /// レイアウトが大きすぎる、登録済み、または登録数の上限に達した場合
+    pub fn register_cache(&self, name: &'static str, layout: Layout) -> Result<(), &'static str> {
+        if !Self::fits_slab(layout) {
+            return Err("layout too large for a slab cache");
+        }
+        let key = layout_key(layout);
+        if self.named_keys.iter().any(|k| k.load(Ordering::Acquire) == key) {
+            return Err("slab cache already registered for this layout");
+        }
+        let slot = self.named_count.fetch_add(1, Ordering::AcqRel);
+        if slot >= MAX_NAMED_CACHES {
+            self.named_count.fetch_sub(1, Ordering::AcqRel);
+            return Err("too many named slab caches");
+        }
+
+        let align = layout.align().max(MIN_OBJECT_SIZE);
+        let size = layout.size().max(MIN_OBJECT_SIZE).next_multiple_of(align);
+        *self.named[slot].lock() = SlabCache::new(name, size, align);
+        self.named_keys[slot].store(key, Ordering::Release);
+        Ok(())
+    }
+
+    /// スラブに収まるレイアウトか
+    fn fits_slab(layout: Layout) -> bool {
+        layout.size() <= MAX_OBJECT_SIZE && layout.align() <= MAX_OBJECT_SIZE
+    }
+
+    /// スラブから割り当てるレイアウトか
+    ///
+    /// 名前付きキャッシュの登録に依存しないため、割り当てと解放で
+    /// 判定が食い違うことはありません。
+    fn is_small(layout: Layout) -> bool {
+        !cfg!(feature = "heap_debug") && Self::fits_slab(layout)
+    }
+
+    /// OOM ハンドラを呼ばずに 1 回だけ割り当てる
+    ///
+    /// # Safety
+    /// [`GlobalAlloc::alloc`] と同じ
+    unsafe fn alloc_once(&self, layout: Layout) -> *mut u8 {
+        if !Self::is_small(layout) {
+            // SAFETY: 呼び出し元が保証する
+            return unsafe { self.alloc_large(layout) };
+        }
+        let index = self.cache_index(layout);
+        // SAFETY: index は cache_index が返したキャッシュ自身の番号
+        unsafe { self.cache(index).lock().allocate(index, &self.backing) }
+    }
+
+    /// スラブを使わない割り当て
+    ///
+    /// # Safety
+    /// [`GlobalAlloc::alloc`] と同じ
+    unsafe fn alloc_large(&self, layout: Layout) -> *mut u8 {
+        #[cfg(feature = "heap_debug")]
+        // SAFETY: 呼び出し元が保証する
+        let ptr = unsafe { super::heap_debug::alloc(&self.debug, &self.backing, layout) };
+        #[cfg(not(feature = "heap_debug"))]
+        // SAFETY: 呼び出し元が保証する
+        let ptr = unsafe { self.backing.alloc(layout) };
+        ptr
+    }
+
+    /// [`alloc_large`](Self::alloc_large) で割り当てた領域を解放
+    ///
+    /// # Safety
+    /// [`GlobalAlloc::dealloc`] と同じ
+    unsafe fn dealloc_large(&self, ptr: *mut u8, layout: Layout) {
+        #[cfg(feature = "heap_debug")]
+        // SAFETY: 呼び出し元が保証する
+        unsafe { super::heap_debug::dealloc(&self.debug, &self.backing, ptr, layout) };
+        #[cfg(not(feature = "heap_debug"))]
+        // SAFETY: 呼び出し元が保証する
+        unsafe { self.backing.dealloc(ptr, layout) };
+    }
+
+    /// 未解放の割り当てを呼び出し元ごとに出力（`heap_debug` feature）
+    #[cfg(feature = "heap_debug")]
+    pub fn dump_allocations(&self) {
+        super::heap_debug::dump(&self.debug);
+    }
+
+    /// 小さなレイアウトを受け持つキャッシュの番号
+    fn cache_index(&self, layout: Layout) -> usize {
+        let key = layout_key(layout);
+        if let Some(i) = self.named_keys.iter().position(|k| k.load(Ordering::Acquire) == key) {
+            return SIZE_CLASS_COUNT + i;
+        }
+        let size = layout.size().max(layout.align()).max(MIN_OBJECT_SIZE).next_power_of_two();
+        (size.trailing_zeros() - MIN_OBJECT_SIZE.trailing_zeros()) as usize
+    }
+
+    fn cache(&self, index: usize) -> &Mutex<SlabCache> {
+        match index.checked_sub(SIZE_CLASS_COUNT) {
+            None => &self.classes[index],
+            Some(named) => &self.named[named],
+        }
+    }
+}
+
+unsafe impl GlobalAlloc for SlabAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        loop {
+            // SAFETY: GlobalAlloc の契約をそのまま引き継ぐ
+            let ptr = unsafe { self.alloc_once(layout) };
+            if !ptr.is_null() {
+                return ptr;
+            }
+            // ハンドラはメモリを解放しうるので、ロックを外してから呼ぶ
+            let handler = *self.oom_handler.lock();
+            if !handler.is_some_and(|handler| handler()) {
+                return ptr;
+            }
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if !Self::is_small(layout) {
+            // SAFETY: GlobalAlloc の契約をそのまま引き継ぐ
+            return unsafe { self.dealloc_large(ptr, layout) };
+        }
+        let slab = Slab::containing(ptr);
+        // SAFETY: 小さな割り当ては必ずスラブ内にあり、ヘッダはページ先頭にある
+        let (magic, index) = unsafe { ((*slab).magic, usize::from((*slab).cache)) };
+        if magic != SLAB_MAGIC || index >= SIZE_CLASS_COUNT + MAX_NAMED_CACHES {
+            crate::debug_println!("[ERROR] Slab corruption: ptr={:#x}, magic={:#x}", ptr as usize, magic);
+            panic!("Slab corruption detected: invalid slab header");
+        }
+        // SAFETY: ヘッダが示すキャッシュから割り当てたオブジェクト
+        unsafe { self.cache(index).lock().free(slab, ptr, &self.backing) }
+    }
+}
+
+#[cfg(all(test, not(feature = "heap_debug")))]
+mod tests {
+    use super::*;
+
+    #[repr(C, align(4096))]
+    struct Memory([u8; 4 * SLAB_SIZE]);
+
+    fn stats_of(heap: &SlabAllocator, name: &str) -> SlabStats {
+        let mut found = None;
+        heap.for_each_cache(|s| {
+            if s.name == name {
+                found = Some(*s);
+            }
+        });
+        found.expect("cache not found")
+    }
+
+    #[test_case]
+    fn test_size_class_selection() {
+        let heap = SlabAllocator::new();
+        assert_eq!(heap.cache_index(Layout::from_size_align(1, 1).unwrap()), 0);
+        assert_eq!(heap.cache_index(Layout::from_size_align(8, 8).unwrap()), 0);
+        assert_eq!(heap.cache_index(Layout::from_size_align(9, 8).unwrap()), 1);
+        assert_eq!(heap.cache_index(Layout::from_size_align(24, 8).unwrap()), 2);
+        assert_eq!(heap.cache_index(Layout::from_size_align(8, 64).unwrap()), 3);
+        assert_eq!(heap.cache_index(Layout::from_size_align(512, 8).unwrap()), 6);
+        assert!(!SlabAllocator::is_small(Layout::from_size_align(513, 8).unwrap()));
+    }
+
+    #[test_case]
+    fn test_allocate_and_release_slabs() {
+        static mut MEM: Memory = Memory([0; 4 * SLAB_SIZE]);
+        let heap = SlabAllocator::new();
+        unsafe {
+            let start = core::ptr::addr_of_mut!(MEM) as usize;
+            heap.init(VirtAddr::new(start), LayoutSize::new(4 * SLAB_SIZE)).unwrap();
+        }
+
+        let layout = Layout::from_size_align(256, 8).unwrap();
+        let per_slab = stats_of(&heap, "kmalloc-256").objects_per_slab;
+        let mut ptrs = [ptr::null_mut(); 16];
+        let count = per_slab + 1;
+        for p in ptrs.iter_mut().take(count) {
+            *p = unsafe { heap.alloc(layout) };
+            assert!(!p.is_null());
+            assert_eq!(*p as usize % 256, 0);
+        }
+
+        let stats = stats_of(&heap, "kmalloc-256");
+        assert_eq!(stats.slabs, 2);
+        assert_eq!(stats.active_objects, count);
+
+        for p in ptrs.iter().take(count) {
+            unsafe { heap.dealloc(*p, layout) };
+        }
+        let stats = stats_of(&heap, "kmalloc-256");
+        assert_eq!(stats.active_objects, 0);
+        // 最後の 1 枚だけ保持する
+        assert_eq!(stats.slabs, 1);
+        assert_eq!(heap.heap_stats().current_usage.as_usize(), SLAB_SIZE);
+
+        // 大きな割り当てはヒープから
+        let large = Layout::from_size_align(1024, 8).unwrap();
+        let p = unsafe { heap.alloc(large) };
+        assert!(!p.is_null());
+        assert_eq!(heap.heap_stats().current_usage.as_usize(), SLAB_SIZE + 1024);
+        unsafe { heap.dealloc(p, large) };
+    }
+
+    #[test_case]
+    fn test_oom_handler_retries_failed_allocation() {
+        use core::sync::atomic::AtomicUsize;
+
+        static mut MEM: Memory = Memory([0; 4 * SLAB_SIZE]);
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        // 1 回目だけやり直させる
+        fn handler() -> bool {
+            CALLS.fetch_add(1, Ordering::Relaxed) == 0
+        }
+
+        let heap = SlabAllocator::new();
+        unsafe {
+            let start = core::ptr::addr_of_mut!(MEM) as usize;
+            heap.init(VirtAddr::new(start), LayoutSize::new(4 * SLAB_SIZE)).unwrap();
+        }
+        heap.set_oom_handler(handler);
+
+        let small = Layout::from_size_align(64, 8).unwrap();
+        let p = unsafe { heap.alloc(small) };
+        assert!(!p.is_null());
+        assert_eq!(CALLS.load(Ordering::Relaxed), 0);
+
+        let huge = Layout::from_size_align(8 * SLAB_SIZE, 8).unwrap();
+        assert!(unsafe { heap.alloc(huge) }.is_null());
+        assert_eq!(CALLS.load(Ordering::Relaxed), 2);
+        unsafe { heap.dealloc(p, small) };
+    }
+
+    #[test_case]
+    fn test_named_cache() {
+        static mut MEM: Memory = Memory([0; 4 * SLAB_SIZE]);
+        let heap = SlabAllocator::new();
+        unsafe {
+            let start = core::ptr::addr_of_mut!(MEM) as usize;
+            heap.init(VirtAddr::new(start), LayoutSize::new(4 * SLAB_SIZE)).unwrap();
+        }
+
+        let layout = Layout::from_size_align(40, 8).unwrap();
+        // 登録前の割り当ては kmalloc-64 から
+        let before = unsafe { heap.alloc(layout) };
+        heap.register_cache("object40", layout).unwrap();
+        assert!(heap.register_cache("object40", layout).is_err());
+
+        let after = unsafe { heap.alloc(layout) };
+        assert_eq!(stats_of(&heap, "object40").active_objects, 1);
+        assert_eq!(stats_of(&heap, "object40").object_size, 40);
+        assert_eq!(stats_of(&heap, "kmalloc-64").active_objects, 1);
+
+        unsafe {
+            heap.dealloc(before, layout);
+            heap.dealloc(after, layout);
+        }
+        assert_eq!(stats_of(&heap, "object40").active_objects, 0);
+        assert_eq!(stats_of(&heap, "kmalloc-64").active_objects, 0);
+    }
+}
@@ -17,8 +17,11 @@ use x86_64::{
     VirtAddr,
 };
 use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use crate::kernel::mm::BootInfoFrameAllocator;
-use crate::kernel::mm::paging::COW_FLAG;
+use crate::kernel::mm::vma::{Vma, VmaKind};
+use crate::abi::mman::{MMAP_BASE, MMAP_END, PROT_READ, PROT_WRITE};
+use crate::kernel::security::random::random_below;
 
 /// User memory layout constants
 ///
@@ -42,9 +45,116 @@ pub const USER_RING_CONTEXT_BASE: u64 = 0x0000_1000_0000_0000;  // 16 TiB
 /// Size of RingContext mapping (rounded up to 64KB for future expansion)
 pub const USER_RING_CONTEXT_SIZE: usize = 64 * 1024;
 
+/// Load address of position-independent executables (64 TiB)
+pub const USER_PIE_BASE: u64 = 0x0000_4000_0000_0000;  // 64 TiB
+
+/// Pages the stack top may be moved down by (16 GiB)
+const STACK_RANDOM_PAGES: u64 = 1 << 22;
+/// Pages the mmap window, ring context and PIE base may be moved up by (1 TiB)
+const BASE_RANDOM_PAGES: u64 = 1 << 28;
+
 /// Default user stack size (1 MiB - increased for deeper call stacks)
+///
+/// This much of the stack is mapped when a program is loaded; the rest of
+/// the stack area up to [`user_stack_limit`] is filled on demand.
 pub const DEFAULT_USER_STACK_SIZE: usize = 1024 * 1024;
 
+/// Default maximum user stack size (8 MiB)
+pub const DEFAULT_USER_STACK_LIMIT: usize = 8 * 1024 * 1024;
+
+/// Largest accepted user stack limit (1 GiB, well clear of the mmap window)
+pub const MAX_USER_STACK_LIMIT: usize = 1024 * 1024 * 1024;
+
+/// 4 KiB frames behind one 2 MiB page
+const HUGE_PAGE_FRAMES: u64 = 512;
+
+/// Per-process placement of the stack, mmap window, ring context and
+/// position-independent executables
+///
+/// Each randomised base is moved by a random number of pages away from
+/// its fixed default, in the direction that keeps it clear of the
+/// neighbouring areas: the stack top moves down by up to 16 GiB, the
+/// other bases move up by up to 1 TiB. Non-PIE executables are always
+/// loaded at their link address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UserLayout {
+    /// Top of the initial user stack
+    pub stack_top: u64,
+    /// Start of the range searched for mappings without a fixed address
+    pub mmap_base: u64,
+    /// End (exclusive) of the range searched for mappings
+    pub mmap_end: u64,
+    /// Address the syscall ring context is mapped at
+    pub ring_base: u64,
+    /// Load address of position-independent executables
+    pub pie_base: u64,
+}
+
+impl UserLayout {
+    /// Layout with every base at its default, used when randomisation is off
+    pub const FIXED: Self = Self {
+        stack_top: USER_STACK_TOP,
+        mmap_base: MMAP_BASE,
+        mmap_end: MMAP_END,
+        ring_base: USER_RING_CONTEXT_BASE,
+        pie_base: USER_PIE_BASE,
+    };
+
+    /// Layout with randomised bases drawn from the kernel entropy source
+    #[must_use]
+    pub fn randomized() -> Self {
+        let pages = |count: u64| random_below(count) * 4096;
+        Self {
+            stack_top: USER_STACK_TOP - pages(STACK_RANDOM_PAGES),
+            mmap_base: MMAP_BASE + pages(BASE_RANDOM_PAGES),
+            mmap_end: MMAP_END,
+            ring_base: USER_RING_CONTEXT_BASE + pages(BASE_RANDOM_PAGES),
+            pie_base: USER_PIE_BASE + pages(BASE_RANDOM_PAGES),
+        }
+    }
+
+    /// Randomised layout, or [`UserLayout::FIXED`] if `randomize` is false
+    #[must_use]
+    pub fn new(randomize: bool) -> Self {
+        if randomize { Self::randomized() } else { Self::FIXED }
+    }
+}
+
+impl Default for UserLayout {
+    fn default() -> Self {
+        Self::FIXED
+    }
+}
+
+/// Maximum stack size of newly loaded programs
+static USER_STACK_LIMIT: AtomicUsize = AtomicUsize::new(DEFAULT_USER_STACK_LIMIT);
+
+/// Maximum size the user stack of newly loaded programs may grow to
+#[must_use]
+pub fn user_stack_limit() -> usize {
+    USER_STACK_LIMIT.load(Ordering::Relaxed)
+}
+
+/// Set the maximum user stack size for programs loaded from now on
+///
+/// The limit is rounded up to whole pages. Running processes keep the
+/// stack area they were started with.
+///
+/// # Errors
+/// Fails if the limit is smaller than the initially mapped stack
+/// ([`DEFAULT_USER_STACK_SIZE`]) or larger than [`MAX_USER_STACK_LIMIT`].
+pub fn set_user_stack_limit(limit: usize) -> Result<(), &'static str> {
+    let limit = limit.checked_next_multiple_of(4096).ok_or("stack limit too large")?;
+    if limit < DEFAULT_USER_STACK_SIZE {
+        return Err("stack limit below the initial stack size");
+    }
+    if limit > MAX_USER_STACK_LIMIT {
+        return Err("stack limit too large");
+    }
+    USER_STACK_LIMIT.store(limit, Ordering::Relaxed);
+    Ok(())
+}
+
 /// Map user program code into user page table
 ///
 /// This function maps the provided code into the user's address space
@@ -142,19 +252,34 @@ where
     Ok(allocated_frames)
 }
 
+/// Memory area covering a user stack of `stack_size` bytes below `stack_top`
+///
+/// The stack may grow on demand until it fills the area. The guard gap
+/// below it ([`STACK_GUARD_SIZE`](crate::kernel::mm::vma::STACK_GUARD_SIZE))
+/// is not part of the area and stays unmapped.
+#[must_use]
+pub fn user_stack_area(stack_top: u64, stack_size: usize) -> Vma {
+    let size = (stack_size as u64 + 4095) & !4095;
+    Vma::new(stack_top - size, stack_top, PROT_READ | PROT_WRITE, VmaKind::Stack)
+}
+
 /// Map user stack
 ///
-/// This function allocates and maps a user stack starting from `USER_STACK_TOP`
-/// and growing downward. The stack is mapped with NO_EXECUTE for security.
+/// This function allocates and maps a user stack starting from `stack_top`
+/// (see [`UserLayout::stack_top`]) and growing downward. The stack is mapped with NO_EXECUTE for security.
+///
+/// Only the top `stack_size` bytes are mapped; the stack area recorded by
+/// [`user_stack_area`] may be larger and is filled on demand.
 ///
 /// **Security Feature: Stack Guard Page**
 /// 
-/// A guard page (unmapped page) is placed at the bottom of the stack to detect
-/// stack overflow. If the stack grows too large and touches the guard page,
-/// a page fault will occur, preventing silent memory corruption.
+/// The guard gap below the stack area is kept free of other mappings. If
+/// the stack grows into it, the page fault handler reports a stack
+/// overflow instead of silently running into other memory.
 ///
 /// # Arguments
 /// * `mapper` - Page table mapper for the user address space
+/// * `stack_top` - Page-aligned top of the stack
 /// * `stack_size` - Size of stack in bytes (will be rounded up to page boundary)
 /// * `frame_allocator` - Physical frame allocator
 ///
@@ -172,34 +297,26 @@ where
 #[allow(clippy::missing_panics_doc)]
 pub unsafe fn map_user_stack<A>(
     mapper: &mut OffsetPageTable,
+    stack_top: VirtAddr,
     stack_size: usize,
     frame_allocator: &mut A,
 ) -> Result<VirtAddr, MapError>
 where
     A: FrameAllocator<Size4KiB>,
 {
-    // Add one extra page for the guard page
-    let guard_page_size = 4096;
-    let total_size = stack_size + guard_page_size;
-    
-    let stack_bottom = USER_STACK_TOP - total_size as u64;
     let num_pages = (stack_size + 4095) / 4096;
+    let stack_bottom = stack_top.as_u64() - (num_pages * 4096) as u64;
     
     crate::debug_println!(
         "[User Paging] Mapping stack: {} bytes ({} pages) at 0x{:x}",
         stack_size,
         num_pages,
-        stack_bottom + guard_page_size as u64
-    );
-    crate::debug_println!(
-        "[User Paging] Guard page at 0x{:x} (unmapped)",
         stack_bottom
     );
     
-    // Map stack pages (skip the first page - that's the guard page)
+    // Map stack pages
     for i in 0..num_pages {
-        // Start mapping from guard_page_size offset
-        let page_addr = VirtAddr::new(stack_bottom + guard_page_size as u64 + (i * 4096) as u64);
+        let page_addr = VirtAddr::new(stack_bottom + (i * 4096) as u64);
         let page: Page<Size4KiB> = Page::containing_address(page_addr);
         
         let frame = frame_allocator
@@ -228,15 +345,15 @@ where
         }
     }
     
-    // Note: The guard page (at stack_bottom) is intentionally left UNMAPPED
-    // Any access to it will cause a page fault, catching stack overflow
+    // Note: Pages below stack_bottom stay UNMAPPED; the stack area fills
+    // them on demand down to the guard gap, which catches stack overflow
     
     crate::debug_println!(
         "[User Paging] Stack mapped successfully, top=0x{:x}",
-        USER_STACK_TOP
+        stack_top.as_u64()
     );
     
-    Ok(VirtAddr::new(USER_STACK_TOP))
+    Ok(stack_top)
 }
 
 /// Map user heap region (placeholder for Phase 2.5)
@@ -344,207 +461,6 @@ impl fmt::Display for MapError {
     }
 }
 
-/// Duplicate a user page table (Deep Copy)
-///
-/// This function creates a new page table and copies all user-space mappings
-/// from the source page table. New physical frames are allocated for data.
-///
-/// # Arguments
-/// * `src_mapper` - Mapper for the source page table
-/// * `frame_allocator` - Frame allocator
-/// * `physical_memory_offset` - Physical memory offset
-///
-/// # Returns
-/// Physical frame of the new page table
-/// Duplicate a user page table (Deep Copy)
-///
-/// This function creates a new page table and copies all user-space mappings
-/// from the source page table. New physical frames are allocated for data.
-///
-/// # Arguments
-/// * `_src_mapper` - Mapper for the source page table (unused, we use CR3)
-/// * `frame_allocator` - Frame allocator
-/// * `physical_memory_offset` - Physical memory offset
-///
-/// # Returns
-/// Physical frame of the new page table
-pub unsafe fn duplicate_user_page_table(
-    _src_mapper: &mut OffsetPageTable,
-    frame_allocator: &mut BootInfoFrameAllocator,
-    physical_memory_offset: VirtAddr,
-) -> Result<PhysFrame, MapError>
-{
-    // 1. Allocate new PML4 frame
-    let pml4_frame = frame_allocator
-        .allocate_frame()
-        .ok_or(MapError::FrameAllocationFailed)?;
-        
-    let pml4_ptr = (physical_memory_offset + pml4_frame.start_address().as_u64()).as_mut_ptr::<x86_64::structures::paging::PageTable>();
-    let pml4 = unsafe { &mut *pml4_ptr };
-    pml4.zero();
-    
-    // 2. Copy kernel mappings (upper half)
-    let (kernel_frame, _) = x86_64::registers::control::Cr3::read();
-    let kernel_pt_ptr = (physical_memory_offset + kernel_frame.start_address().as_u64()).as_ptr::<x86_64::structures::paging::PageTable>();
-    let kernel_pt = unsafe { &*kernel_pt_ptr };
-    
-    for i in 256..512 {
-        pml4[i] = kernel_pt[i].clone();
-    }
-    
-    // 3. Deep copy user mappings (lower half)
-    let (src_pml4_frame, _) = x86_64::registers::control::Cr3::read();
-    let src_pml4_ptr = (physical_memory_offset + src_pml4_frame.start_address().as_u64()).as_mut_ptr::<x86_64::structures::paging::PageTable>();
-    let src_pml4 = unsafe { &mut *src_pml4_ptr };
-    
-    for i in 0..256 {
-        if !src_pml4[i].is_unused() {
-            unsafe {
-                // Get mutable reference to source entry to update flags (CoW)
-                copy_pml4_entry(
-                    &mut src_pml4[i],
-                    &mut pml4[i],
-                    frame_allocator,
-                    physical_memory_offset
-                )?;
-            }
-        }
-    }
-    
-    Ok(pml4_frame)
-}
-
-// Helper to copy page table entries recursively
-unsafe fn copy_pml4_entry(
-    src_entry: &mut PageTableEntry,
-    dst_entry: &mut PageTableEntry,
-    frame_allocator: &mut BootInfoFrameAllocator,
-    phys_offset: VirtAddr,
-) -> Result<(), MapError>
-{
-    // Allocate new PDPT frame
-    let frame = frame_allocator.allocate_frame().ok_or(MapError::FrameAllocationFailed)?;
-    dst_entry.set_frame(frame, src_entry.flags());
-    
-    let src_table_ptr = (phys_offset + src_entry.addr().as_u64()).as_mut_ptr::<x86_64::structures::paging::PageTable>();
-    let dst_table_ptr = (phys_offset + frame.start_address().as_u64()).as_mut_ptr::<x86_64::structures::paging::PageTable>();
-    // SAFETY: We just allocated the frame and checked the source entry is present
-    let src_table = unsafe { &mut *src_table_ptr };
-    let dst_table = unsafe { &mut *dst_table_ptr };
-    dst_table.zero();
-    
-    for i in 0..512 {
-        if !src_table[i].is_unused() {
-            unsafe {
-                copy_pdpt_entry(&mut src_table[i], &mut dst_table[i], frame_allocator, phys_offset)?;
-            }
-        }
-    }
-    
-    Ok(())
-}
-
-unsafe fn copy_pdpt_entry(
-    src_entry: &mut PageTableEntry,
-    dst_entry: &mut PageTableEntry,
-    frame_allocator: &mut BootInfoFrameAllocator,
-    phys_offset: VirtAddr,
-) -> Result<(), MapError>
-{
-    // Check for huge page (1GB) - Not supported yet
-    if src_entry.flags().contains(PageTableFlags::HUGE_PAGE) {
-        return Err(MapError::MappingFailed("Huge pages not supported in fork".into()));
-    }
-
-    let frame = frame_allocator.allocate_frame().ok_or(MapError::FrameAllocationFailed)?;
-    dst_entry.set_frame(frame, src_entry.flags());
-    
-    let src_table_ptr = (phys_offset + src_entry.addr().as_u64()).as_mut_ptr::<x86_64::structures::paging::PageTable>();
-    let dst_table_ptr = (phys_offset + frame.start_address().as_u64()).as_mut_ptr::<x86_64::structures::paging::PageTable>();
-    let src_table = unsafe { &mut *src_table_ptr };
-    let dst_table = unsafe { &mut *dst_table_ptr };
-    dst_table.zero();
-    
-    for i in 0..512 {
-        if !src_table[i].is_unused() {
-            unsafe {
-                copy_pd_entry(&mut src_table[i], &mut dst_table[i], frame_allocator, phys_offset)?;
-            }
-        }
-    }
-    
-    Ok(())
-}
-
-unsafe fn copy_pd_entry(
-    src_entry: &mut PageTableEntry,
-    dst_entry: &mut PageTableEntry,
-    frame_allocator: &mut BootInfoFrameAllocator,
-    phys_offset: VirtAddr,
-) -> Result<(), MapError>
-{
-    // Check for huge page (2MB) - Not supported yet
-    if src_entry.flags().contains(PageTableFlags::HUGE_PAGE) {
-        return Err(MapError::MappingFailed("Huge pages not supported in fork".into()));
-    }
-
-    let frame = frame_allocator.allocate_frame().ok_or(MapError::FrameAllocationFailed)?;
-    dst_entry.set_frame(frame, src_entry.flags());
-    
-    let src_table_ptr = (phys_offset + src_entry.addr().as_u64()).as_mut_ptr::<x86_64::structures::paging::PageTable>();
-    let dst_table_ptr = (phys_offset + frame.start_address().as_u64()).as_mut_ptr::<x86_64::structures::paging::PageTable>();
-    let src_table = unsafe { &mut *src_table_ptr };
-    let dst_table = unsafe { &mut *dst_table_ptr };
-    dst_table.zero();
-    
-    for i in 0..512 {
-        if !src_table[i].is_unused() {
-            unsafe {
-                copy_pt_entry(&mut src_table[i], &mut dst_table[i], frame_allocator, phys_offset)?;
-            }
-        }
-    }
-    
-    Ok(())
-}
-
-unsafe fn copy_pt_entry(
-    src_entry: &mut PageTableEntry,
-    dst_entry: &mut PageTableEntry,
-    frame_allocator: &mut BootInfoFrameAllocator,
-    _phys_offset: VirtAddr,
-) -> Result<(), MapError>
-{
-    // This is the leaf level. Implement Copy-on-Write.
-    
-    let flags = src_entry.flags();
-    let frame = src_entry.frame().map_err(|_| MapError::InvalidAddress)?;
-    
-    if flags.contains(PageTableFlags::WRITABLE) {
-        // If writable, mark both as Read-Only + CoW
-        let new_flags = (flags - PageTableFlags::WRITABLE) | COW_FLAG;
-        
-        // Update source entry
-        src_entry.set_flags(new_flags);
-        
-        // Map destination to the SAME frame with new flags
-        dst_entry.set_frame(frame, new_flags);
-        
-        // Increment reference count
-        frame_allocator.add_reference(frame);
-    } else {
-        // If already read-only (e.g. code), just share it
-        // We can optionally add CoW flag, but it's not strictly necessary if we never write.
-        // Let's just share it as is.
-        dst_entry.set_frame(frame, flags);
-        
-        // Increment reference count
-        frame_allocator.add_reference(frame);
-    }
-    
-    Ok(())
-}
-
 /// Free all user-space resources in a page table
 pub unsafe fn free_user_page_table(
     pml4_frame: PhysFrame,
@@ -633,7 +549,9 @@ unsafe fn free_pd_entry(
     phys_offset: VirtAddr,
 ) {
     if entry.flags().contains(PageTableFlags::HUGE_PAGE) {
-        if let Ok(frame) = entry.frame() {
+        // `frame()` rejects huge entries; drop the reference on each frame
+        let first = PhysFrame::<Size4KiB>::containing_address(entry.addr());
+        for frame in PhysFrame::range(first, first + HUGE_PAGE_FRAMES) {
             unsafe {
                 frame_allocator.deallocate_frame(frame);
             }
@@ -827,4 +745,32 @@ pub unsafe fn map_kernel_to_user(
     }
     
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_randomized_layout_stays_in_bounds() {
+        for _ in 0..16 {
+            let layout = UserLayout::randomized();
+            for base in [layout.stack_top, layout.mmap_base, layout.ring_base, layout.pie_base] {
+                assert_eq!(base % 4096, 0);
+            }
+            assert!(layout.stack_top <= USER_STACK_TOP);
+            assert!(layout.stack_top - MAX_USER_STACK_LIMIT as u64 > layout.mmap_end);
+            assert!(layout.mmap_base >= MMAP_BASE && layout.mmap_base < layout.mmap_end);
+            assert!(layout.ring_base >= USER_RING_CONTEXT_BASE && layout.ring_base < USER_PIE_BASE);
+            assert!(layout.pie_base >= USER_PIE_BASE && layout.pie_base < MMAP_BASE);
+        }
+    }
+
+    #[test_case]
+    fn test_fixed_layout_uses_defaults() {
+        let layout = UserLayout::new(false);
+        assert_eq!(layout, UserLayout::FIXED);
+        assert_eq!(layout.stack_top, USER_STACK_TOP);
+        assert_eq!(layout.mmap_base, MMAP_BASE);
+    }
+}
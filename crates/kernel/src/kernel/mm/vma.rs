@@ -0,0 +1,476 @@
+// kernel/src/kernel/mm/vma.rs
+//! Virtual memory areas of a user address space
+//!
+//! Every process owns a [`VmaTree`]: an ordered map of non-overlapping,
+//! page-aligned regions describing what each part of the user address
+//! space is (program image, stack, anonymous memory, shared memory and file
+//! mappings, kernel-shared rings), its `PROT_*` permissions and whether it
+//! is shared.
+//!
+//! The tree is the source of truth for user mappings:
+//!
+//! - `mmap` / `munmap` / `mprotect` update it before touching page tables,
+//!   and free ranges (including holes left by `munmap`) are found in it
+//! - the page fault handler looks up the faulting address to decide
+//!   between demand-filling a page and delivering a segfault
+//! - `/proc/<pid>/maps` lists it
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use crate::abi::mman::{PROT_EXEC, PROT_MASK, PROT_READ, PROT_WRITE};
+
+/// Page size used for area alignment
+const PAGE_SIZE: u64 = 4096;
+
+/// Unmapped gap kept below every stack area
+///
+/// Faults in the gap are stack overflows, and no other area may be
+/// placed in it.
+pub const STACK_GUARD_SIZE: u64 = PAGE_SIZE;
+
+/// What backs a virtual memory area
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmaKind {
+    /// Loaded program segments
+    Image,
+    /// User stack
+    Stack,
+    /// Anonymous memory from `mmap`
+    Anonymous,
+    /// Shared memory object mapped through its capability
+    Shmem,
+    /// File pages mapped through a file capability (initrd, tmpfs)
+    File,
+    /// Kernel-owned frames shared with user space (syscall ring, doorbell)
+    ///
+    /// These frames are never freed through the process and cannot be
+    /// unmapped, replaced or reprotected by user space.
+    Ring,
+}
+
+impl VmaKind {
+    /// Whether the backing frames belong to the kernel rather than the process
+    #[must_use]
+    pub const fn is_kernel_owned(self) -> bool {
+        matches!(self, Self::Ring)
+    }
+
+    /// Whether missing pages are filled with zeroes on first access
+    #[must_use]
+    pub const fn is_demand_zero(self) -> bool {
+        matches!(self, Self::Stack | Self::Anonymous)
+    }
+
+    /// Name shown in `/proc/<pid>/maps`
+    #[must_use]
+    pub const fn label(self) -> &'static str {
+        match self {
+            Self::Image => "[image]",
+            Self::Stack => "[stack]",
+            Self::Anonymous => "",
+            Self::Shmem => "[shmem]",
+            Self::File => "[file]",
+            Self::Ring => "[ring]",
+        }
+    }
+}
+
+/// One virtual memory area `[start, end)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Vma {
+    /// First address (page-aligned)
+    pub start: u64,
+    /// End address, exclusive (page-aligned)
+    pub end: u64,
+    /// `PROT_*` permission bits
+    pub prot: u64,
+    /// `PROT_*` bits `mprotect` may grant, limited by the backing object
+    pub max_prot: u64,
+    /// Backing of the area
+    pub kind: VmaKind,
+    /// Whether the pages are shared rather than private (copy-on-write)
+    pub shared: bool,
+    /// Whether 2 MiB pages are used where the area covers an aligned block
+    pub huge: bool,
+}
+
+impl Vma {
+    /// Create a private area
+    ///
+    /// # Panics
+    /// Panics if the bounds are not page-aligned or the area is empty.
+    #[must_use]
+    pub fn new(start: u64, end: u64, prot: u64, kind: VmaKind) -> Self {
+        assert!(start.is_multiple_of(PAGE_SIZE) && end.is_multiple_of(PAGE_SIZE) && start < end);
+        Self { start, end, prot, max_prot: PROT_MASK, kind, shared: false, huge: false }
+    }
+
+    /// Limit the permissions `mprotect` may grant
+    #[must_use]
+    pub const fn with_max_prot(mut self, max_prot: u64) -> Self {
+        self.max_prot = max_prot;
+        self
+    }
+
+    /// Mark the area as shared
+    #[must_use]
+    pub const fn with_shared(mut self, shared: bool) -> Self {
+        self.shared = shared;
+        self
+    }
+
+    /// Back the area with 2 MiB pages where alignment allows
+    #[must_use]
+    pub const fn with_huge(mut self, huge: bool) -> Self {
+        self.huge = huge;
+        self
+    }
+
+    /// Size in bytes
+    #[must_use]
+    pub const fn size(&self) -> u64 {
+        self.end - self.start
+    }
+
+    /// Whether `addr` lies inside the area
+    #[must_use]
+    pub const fn contains(&self, addr: u64) -> bool {
+        self.start <= addr && addr < self.end
+    }
+
+    /// Whether the area may be read
+    #[must_use]
+    pub const fn readable(&self) -> bool {
+        self.prot & (PROT_READ | PROT_WRITE | PROT_EXEC) != 0
+    }
+
+    /// Whether the area may be written
+    #[must_use]
+    pub const fn writable(&self) -> bool {
+        self.prot & PROT_WRITE != 0
+    }
+
+    /// Whether the area may be executed
+    #[must_use]
+    pub const fn executable(&self) -> bool {
+        self.prot & PROT_EXEC != 0
+    }
+
+    /// Whether `other` directly follows this area with identical attributes
+    const fn can_merge(&self, other: &Self) -> bool {
+        self.end == other.start
+            && self.prot == other.prot
+            && self.max_prot == other.max_prot
+            && self.shared == other.shared
+            && self.huge == other.huge
+            && matches!(
+                (self.kind, other.kind),
+                (VmaKind::Anonymous, VmaKind::Anonymous) | (VmaKind::Image, VmaKind::Image)
+            )
+    }
+}
+
+/// Errors from [`VmaTree`] updates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmaError {
+    /// The range overlaps an existing area
+    Overlap,
+    /// Part of the range is not covered by any area
+    NotMapped,
+    /// The range touches a kernel-owned area
+    KernelOwned,
+    /// The protection exceeds what the backing object allows
+    PermissionDenied,
+}
+
+/// Ordered set of the virtual memory areas of one address space
+#[derive(Debug, Default)]
+pub struct VmaTree {
+    /// Areas keyed by start address
+    areas: BTreeMap<u64, Vma>,
+}
+
+impl VmaTree {
+    /// Create an empty tree
+    #[must_use]
+    pub const fn new() -> Self {
+        Self { areas: BTreeMap::new() }
+    }
+
+    /// Area containing `addr`
+    #[must_use]
+    pub fn find(&self, addr: u64) -> Option<&Vma> {
+        self.areas
+            .range(..=addr)
+            .next_back()
+            .map(|(_, vma)| vma)
+            .filter(|vma| vma.contains(addr))
+    }
+
+    /// Areas intersecting `[start, end)`, in address order
+    pub fn overlapping(&self, start: u64, end: u64) -> impl Iterator<Item = &Vma> {
+        // An area starting before `start` can still reach into the range
+        let first = self.find(start).map_or(start, |vma| vma.start);
+        self.areas.range(first..end).map(|(_, vma)| vma)
+    }
+
+    /// Whether no area intersects `[start, end)`
+    #[must_use]
+    pub fn is_free(&self, start: u64, end: u64) -> bool {
+        self.overlapping(start, end).next().is_none()
+    }
+
+    /// Whether `[start, end)` is completely covered by areas
+    #[must_use]
+    pub fn is_covered(&self, start: u64, end: u64) -> bool {
+        let mut cursor = start;
+        for vma in self.overlapping(start, end) {
+            if vma.start > cursor {
+                return false;
+            }
+            cursor = vma.end;
+        }
+        cursor >= end
+    }
+
+    /// Whether any kernel-owned area intersects `[start, end)`
+    #[must_use]
+    pub fn touches_kernel_owned(&self, start: u64, end: u64) -> bool {
+        self.overlapping(start, end).any(|vma| vma.kind.is_kernel_owned())
+    }
+
+    /// Whether `addr` lies in the guard gap below a stack area
+    #[must_use]
+    pub fn in_stack_guard(&self, addr: u64) -> bool {
+        self.touches_stack_guard(addr, addr.saturating_add(1))
+    }
+
+    /// Whether `[start, end)` intersects the guard gap below a stack area
+    #[must_use]
+    pub fn touches_stack_guard(&self, start: u64, end: u64) -> bool {
+        // A stack whose guard reaches into the range starts at most one
+        // guard size above its end
+        self.areas
+            .range(start..end.saturating_add(STACK_GUARD_SIZE))
+            .map(|(_, vma)| vma)
+            .filter(|vma| vma.kind == VmaKind::Stack)
+            .any(|vma| start < vma.start && vma.start.saturating_sub(STACK_GUARD_SIZE) < end)
+    }
+
+    /// Lowest free, page-aligned range of `len` bytes inside `[lo, hi)`
+    ///
+    /// Holes left by earlier unmaps are reused.
+    #[must_use]
+    pub fn find_free(&self, len: u64, lo: u64, hi: u64) -> Option<u64> {
+        self.find_free_aligned(len, PAGE_SIZE, lo, hi)
+    }
+
+    /// Lowest free range of `len` bytes inside `[lo, hi)` starting at a
+    /// multiple of `align` (a power of two)
+    #[must_use]
+    pub fn find_free_aligned(&self, len: u64, align: u64, lo: u64, hi: u64) -> Option<u64> {
+        let mut candidate = lo.checked_next_multiple_of(align)?;
+        for vma in self.overlapping(lo, hi) {
+            if vma.start >= candidate.checked_add(len)? {
+                break;
+            }
+            candidate = candidate.max(vma.end.checked_next_multiple_of(align)?);
+        }
+        let end = candidate.checked_add(len)?;
+        (end <= hi).then_some(candidate)
+    }
+
+    /// Insert a new area
+    ///
+    /// Adjacent anonymous or image areas with identical attributes are
+    /// merged.
+    pub fn insert(&mut self, vma: Vma) -> Result<(), VmaError> {
+        if !self.is_free(vma.start, vma.end) {
+            return Err(VmaError::Overlap);
+        }
+        self.areas.insert(vma.start, vma);
+        self.merge_around(vma.start, vma.end);
+        Ok(())
+    }
+
+    /// Remove `[start, end)` from the tree, splitting partially covered areas
+    ///
+    /// Returns the removed pieces so that the caller can release their
+    /// pages. Kernel-owned areas are never removed.
+    pub fn remove(&mut self, start: u64, end: u64) -> Result<Vec<Vma>, VmaError> {
+        if self.touches_kernel_owned(start, end) {
+            return Err(VmaError::KernelOwned);
+        }
+        self.split_at(start);
+        self.split_at(end);
+        let keys: Vec<u64> = self.areas.range(start..end).map(|(&k, _)| k).collect();
+        Ok(keys.into_iter().filter_map(|k| self.areas.remove(&k)).collect())
+    }
+
+    /// Change the permissions of `[start, end)`
+    ///
+    /// The whole range must be covered by areas that are not kernel-owned
+    /// and whose `max_prot` includes `prot`.
+    pub fn protect(&mut self, start: u64, end: u64, prot: u64) -> Result<(), VmaError> {
+        if !self.is_covered(start, end) {
+            return Err(VmaError::NotMapped);
+        }
+        if self.touches_kernel_owned(start, end) {
+            return Err(VmaError::KernelOwned);
+        }
+        if self.overlapping(start, end).any(|vma| prot & !vma.max_prot != 0) {
+            return Err(VmaError::PermissionDenied);
+        }
+        self.split_at(start);
+        self.split_at(end);
+        for (_, vma) in self.areas.range_mut(start..end) {
+            vma.prot = prot;
+        }
+        self.merge_around(start, end);
+        Ok(())
+    }
+
+    /// All areas in address order
+    pub fn iter(&self) -> impl Iterator<Item = &Vma> {
+        self.areas.values()
+    }
+
+    /// Split the area containing `addr` so that an area starts at `addr`
+    fn split_at(&mut self, addr: u64) {
+        let Some(&vma) = self.find(addr) else { return };
+        if vma.start == addr {
+            return;
+        }
+        self.areas.insert(vma.start, Vma { end: addr, ..vma });
+        self.areas.insert(addr, Vma { start: addr, ..vma });
+    }
+
+    /// Merge mergeable neighbours of the areas in `[start, end)`
+    fn merge_around(&mut self, start: u64, end: u64) {
+        let first = self
+            .areas
+            .range(..start)
+            .next_back()
+            .map_or(start, |(&k, _)| k);
+        let keys: Vec<u64> = self.areas.range(first..=end).map(|(&k, _)| k).collect();
+
+        let mut current = None::<u64>;
+        for key in keys {
+            let Some(&next) = self.areas.get(&key) else { continue };
+            if let Some(prev_key) = current {
+                let prev = self.areas[&prev_key];
+                if prev.can_merge(&next) {
+                    self.areas.remove(&key);
+                    self.areas.insert(prev_key, Vma { end: next.end, ..prev });
+                    continue;
+                }
+            }
+            current = Some(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::abi::mman::PROT_NONE;
+
+    const RW: u64 = PROT_READ | PROT_WRITE;
+
+    fn anon(start: u64, end: u64) -> Vma {
+        Vma::new(start, end, RW, VmaKind::Anonymous)
+    }
+
+    #[test_case]
+    fn test_insert_rejects_overlap_and_merges_neighbours() {
+        let mut tree = VmaTree::new();
+        tree.insert(anon(0x1000, 0x3000)).unwrap();
+        assert_eq!(tree.insert(anon(0x2000, 0x4000)), Err(VmaError::Overlap));
+
+        tree.insert(anon(0x3000, 0x5000)).unwrap();
+        assert_eq!(tree.iter().count(), 1);
+        assert_eq!(tree.find(0x4fff).map(|v| (v.start, v.end)), Some((0x1000, 0x5000)));
+        assert!(tree.find(0x5000).is_none());
+    }
+
+    #[test_case]
+    fn test_remove_splits_and_find_free_reuses_hole() {
+        let mut tree = VmaTree::new();
+        tree.insert(anon(0x10000, 0x20000)).unwrap();
+
+        let removed = tree.remove(0x14000, 0x16000).unwrap();
+        assert_eq!(removed.len(), 1);
+        assert_eq!((removed[0].start, removed[0].end), (0x14000, 0x16000));
+        assert_eq!(tree.iter().count(), 2);
+
+        assert_eq!(tree.find_free(0x2000, 0x10000, 0x40000), Some(0x14000));
+        assert_eq!(tree.find_free(0x3000, 0x10000, 0x40000), Some(0x20000));
+        assert_eq!(tree.find_free(0x3000, 0x10000, 0x22000), None);
+    }
+
+    #[test_case]
+    fn test_find_free_aligned_skips_to_boundary() {
+        const HUGE: u64 = 0x20_0000;
+        let mut tree = VmaTree::new();
+        tree.insert(anon(HUGE, HUGE + 0x1000)).unwrap();
+
+        assert_eq!(tree.find_free_aligned(HUGE, HUGE, HUGE, 8 * HUGE), Some(2 * HUGE));
+        assert_eq!(tree.find_free_aligned(0x1000, HUGE, 0x1000, 8 * HUGE), Some(2 * HUGE));
+        assert_eq!(tree.find_free_aligned(HUGE, HUGE, HUGE, 2 * HUGE), None);
+
+        // Areas of different page sizes are not merged
+        tree.insert(anon(HUGE + 0x1000, 2 * HUGE).with_huge(true)).unwrap();
+        assert_eq!(tree.iter().count(), 2);
+    }
+
+    #[test_case]
+    fn test_protect_requires_full_coverage() {
+        let mut tree = VmaTree::new();
+        tree.insert(anon(0x1000, 0x2000)).unwrap();
+        tree.insert(anon(0x3000, 0x4000)).unwrap();
+        assert_eq!(tree.protect(0x1000, 0x4000, PROT_READ), Err(VmaError::NotMapped));
+
+        tree.protect(0x3000, 0x4000, PROT_READ).unwrap();
+        assert!(!tree.find(0x3000).unwrap().writable());
+        assert!(tree.find(0x1000).unwrap().writable());
+    }
+
+    #[test_case]
+    fn test_kernel_owned_areas_are_protected() {
+        let mut tree = VmaTree::new();
+        tree.insert(Vma::new(0x1000, 0x2000, RW, VmaKind::Ring)).unwrap();
+        tree.insert(anon(0x2000, 0x3000)).unwrap();
+
+        assert_eq!(tree.remove(0x1000, 0x3000), Err(VmaError::KernelOwned));
+        assert_eq!(tree.protect(0x1000, 0x2000, PROT_READ), Err(VmaError::KernelOwned));
+    }
+
+    #[test_case]
+    fn test_protect_respects_max_prot() {
+        let mut tree = VmaTree::new();
+        let read_only = Vma::new(0x1000, 0x3000, PROT_READ, VmaKind::Shmem).with_max_prot(PROT_READ);
+        tree.insert(read_only.with_shared(true)).unwrap();
+
+        assert_eq!(tree.protect(0x1000, 0x2000, RW), Err(VmaError::PermissionDenied));
+        tree.protect(0x1000, 0x2000, PROT_NONE).unwrap();
+        assert_eq!(tree.find(0x1000).unwrap().max_prot, PROT_READ);
+    }
+
+    #[test_case]
+    fn test_stack_guard_gap() {
+        let mut tree = VmaTree::new();
+        tree.insert(Vma::new(0x10000, 0x20000, RW, VmaKind::Stack)).unwrap();
+        tree.insert(anon(0x30000, 0x31000)).unwrap();
+
+        assert!(tree.in_stack_guard(0x10000 - STACK_GUARD_SIZE));
+        assert!(tree.in_stack_guard(0xFFFF));
+        assert!(!tree.in_stack_guard(0x10000));
+        assert!(!tree.in_stack_guard(0x10000 - STACK_GUARD_SIZE - 1));
+        // Anonymous areas have no guard
+        assert!(!tree.in_stack_guard(0x2FFFF));
+
+        assert!(tree.touches_stack_guard(0x8000, 0x10000));
+        assert!(!tree.touches_stack_guard(0x8000, 0x10000 - STACK_GUARD_SIZE));
+    }
+}
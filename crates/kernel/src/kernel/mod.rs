@@ -12,6 +12,9 @@
 //! - `process`: プロセス管理
 //! - `syscall`: システムコールハンドラ
 //! - `scheduler`: タスクスケジューラ
+//! - `power`: 再起動・電源断
+//! - `ksyms`: バックトレース用のカーネルシンボルテーブル
+//! - `klog`: カーネルログリングバッファ
 
 pub mod core;
 pub mod driver;
@@ -31,3 +34,6 @@ pub mod loader;
 pub mod io_uring;  // io_uring-style async I/O
 pub mod capability; // Next-gen capability-based resource management
 pub mod ipc;  // Inter-process communication (Phase 1/3)
+pub mod power;  // Reboot / power-off
+pub mod ksyms;  // Kernel symbol table for backtraces
+pub mod klog;  // Kernel log ring buffer (log crate backend)
@@ -0,0 +1,94 @@
+// kernel/src/kernel/power.rs
+//! システムの再起動・電源断
+//!
+//! `sys_reboot` から呼ばれ、順序立てたシャットダウンを行ってから
+//! `arch::x86_64::power` でハードウェアを止めます。
+//!
+//! # シャットダウン手順
+//!
+//! ```text
+//! 1. SQPOLL ワーカーを停止（新しい SQE を拾わない）
+//! 2. ファイルシステムを sync（現状は書き込み可能な FS がマウントされないため何もしない）
+//! 3. 呼び出し元以外のプロセスを終了させ、wait 中の init を起こす
+//! 4. (PowerOff のみ) QEMU の isa-debug-exit に終了ステータスを書く
+//! 5. FADT リセット / ACPI S5 / KBC リセット
+//! ```
+//!
+//! # 権限
+//!
+//! 呼び出しには `SystemResource` capability（`Rights::POWER`）が必要です。
+//! これは初期プロセス (`/bin/init`) にだけ [`SYSTEM_CAP_ID`] で付与されます。
+
+use crate::arch::x86_64::{power, qemu};
+use crate::debug_println;
+use crate::kernel::process::{self, ProcessId, PROCESS_TABLE};
+
+/// システム制御 capability の固定 ID
+///
+/// stdio (0..=2) と同様に generation 0 で登録されます。通常の capability
+/// 割り当てと衝突しにくいよう、`FIRST_USER_CAP_ID` から離しています。
+pub const SYSTEM_CAP_ID: u64 = 15;
+
+/// シャットダウン時に他プロセスへ設定する終了コード (128 + SIGTERM)
+const SHUTDOWN_EXIT_CODE: i32 = 128 + 15;
+
+/// 電源操作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerAction {
+    /// CPU を停止するだけ（電源は切らない）
+    Halt,
+    /// ACPI S5 で電源を切る
+    PowerOff,
+    /// 再起動
+    Reboot,
+}
+
+impl PowerAction {
+    /// `sys_reboot` のコマンド値から変換
+    #[must_use]
+    pub const fn from_u64(cmd: u64) -> Option<Self> {
+        match cmd {
+            0 => Some(Self::Halt),
+            1 => Some(Self::PowerOff),
+            2 => Some(Self::Reboot),
+            _ => None,
+        }
+    }
+}
+
+/// 呼び出し元以外の全プロセスを終了させる
+///
+/// `terminate_process` は親が `Blocked` なら `Ready` に戻すため、
+/// 子の終了を wait している init はここで通知を受け取ります。
+fn terminate_others(caller: Option<ProcessId>) {
+    let pids = PROCESS_TABLE.lock().live_pids();
+    for pid in pids.into_iter().filter(|&pid| Some(pid) != caller) {
+        process::terminate_process(pid, SHUTDOWN_EXIT_CODE);
+    }
+}
+
+/// 順序立ててシャットダウンし、指定された電源操作を行う
+///
+/// `status` は QEMU の isa-debug-exit に書き込む終了ステータスです
+/// (`PowerOff` のみ)。デバイスがない環境では書き込みは無視され、
+/// ACPI S5 にフォールスルーします。
+pub fn shutdown(action: PowerAction, caller: Option<ProcessId>, status: u32) -> ! {
+    debug_println!("[POWER] Shutdown requested: {:?}", action);
+
+    crate::kernel::scheduler::stop_sqpoll_async();
+    crate::kernel::io_uring::sqpoll::stop();
+
+    // initrd は読み取り専用で、tmpfs はメモリ上にしかないため書き戻すべき
+    // FS は存在しない。ディスク上の FS をマウントするようになったらここで sync する。
+
+    terminate_others(caller);
+
+    match action {
+        PowerAction::Halt => power::halt(),
+        PowerAction::PowerOff => {
+            qemu::exit_qemu(status);
+            power::power_off()
+        }
+        PowerAction::Reboot => power::reboot(),
+    }
+}
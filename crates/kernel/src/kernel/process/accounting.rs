@@ -0,0 +1,212 @@
+// kernel/src/kernel/process/accounting.rs
+//! Per-process CPU time and event accounting
+//!
+//! CPU time is measured with the TSC and split into user and kernel time.
+//! Each CPU tracks which mode it is in and the TSC of the last transition:
+//!
+//! ```text
+//!   user ──syscall / IRQ from ring 3──> kernel     enter_kernel(): charge user time
+//! kernel ──sysret / iretq to ring 3───> user       return_to_user(): charge kernel time
+//! ```
+//!
+//! Time and event counts are first accumulated in per-CPU pending counters,
+//! because the transition points (syscall entry, interrupt handlers) must not
+//! take `PROCESS_TABLE`. The pending counters are moved into the current
+//! process's [`ProcessUsage`] when the process table is already held:
+//!
+//! - on every context switch (`schedule_next`)
+//! - on timer ticks, if the process table lock is free
+//! - before reading usage (`getrusage`, `/proc/<pid>/stat`)
+
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crate::abi::rusage::RUsage;
+use crate::arch::x86_64::read_timestamp;
+use crate::arch::x86_64::smp::{self, MAX_CPUS};
+
+use super::{Process, PROCESS_TABLE};
+
+/// Accumulated resource usage of a single process
+///
+/// All counters are atomics so that they can be updated through `&Process`.
+#[derive(Debug, Default)]
+pub struct ProcessUsage {
+    user_tsc: AtomicU64,
+    kernel_tsc: AtomicU64,
+    context_switches: AtomicU64,
+    page_faults: AtomicU64,
+    syscalls: AtomicU64,
+    io_uring_sqes: AtomicU64,
+}
+
+impl ProcessUsage {
+    /// Create zeroed usage counters
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            user_tsc: AtomicU64::new(0),
+            kernel_tsc: AtomicU64::new(0),
+            context_switches: AtomicU64::new(0),
+            page_faults: AtomicU64::new(0),
+            syscalls: AtomicU64::new(0),
+            io_uring_sqes: AtomicU64::new(0),
+        }
+    }
+
+    /// Record a context switch away from this process
+    pub fn add_context_switch(&self) {
+        self.context_switches.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record io_uring submissions processed on behalf of this process
+    pub fn add_io_uring_sqes(&self, count: u64) {
+        self.io_uring_sqes.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Copy the counters into the ABI structure
+    #[must_use]
+    pub fn snapshot(&self) -> RUsage {
+        RUsage {
+            user_tsc: self.user_tsc.load(Ordering::Relaxed),
+            kernel_tsc: self.kernel_tsc.load(Ordering::Relaxed),
+            context_switches: self.context_switches.load(Ordering::Relaxed),
+            page_faults: self.page_faults.load(Ordering::Relaxed),
+            syscalls: self.syscalls.load(Ordering::Relaxed),
+            io_uring_sqes: self.io_uring_sqes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Per-CPU accounting state
+struct CpuAccount {
+    /// TSC at the last mode transition
+    last_tsc: AtomicU64,
+    /// Whether this CPU is currently executing user code
+    in_user: AtomicBool,
+    /// Pending counters not yet charged to a process
+    user_tsc: AtomicU64,
+    kernel_tsc: AtomicU64,
+    page_faults: AtomicU64,
+    syscalls: AtomicU64,
+}
+
+impl CpuAccount {
+    const fn new() -> Self {
+        Self {
+            last_tsc: AtomicU64::new(0),
+            in_user: AtomicBool::new(false),
+            user_tsc: AtomicU64::new(0),
+            kernel_tsc: AtomicU64::new(0),
+            page_faults: AtomicU64::new(0),
+            syscalls: AtomicU64::new(0),
+        }
+    }
+
+    /// Charge the time since the last transition to the current mode
+    fn checkpoint(&self) {
+        let now = read_timestamp();
+        let last = self.last_tsc.swap(now, Ordering::Relaxed);
+        // The first checkpoint only establishes the baseline
+        if last == 0 {
+            return;
+        }
+        let elapsed = now.saturating_sub(last);
+        if self.in_user.load(Ordering::Relaxed) {
+            self.user_tsc.fetch_add(elapsed, Ordering::Relaxed);
+        } else {
+            self.kernel_tsc.fetch_add(elapsed, Ordering::Relaxed);
+        }
+    }
+}
+
+static CPU_ACCOUNTS: [CpuAccount; MAX_CPUS] = [const { CpuAccount::new() }; MAX_CPUS];
+
+fn this_cpu() -> &'static CpuAccount {
+    &CPU_ACCOUNTS[smp::current_cpu_id() as usize % MAX_CPUS]
+}
+
+/// The CPU entered the kernel from user mode (syscall, interrupt, exception)
+#[inline]
+pub fn enter_kernel() {
+    let cpu = this_cpu();
+    cpu.checkpoint();
+    cpu.in_user.store(false, Ordering::Relaxed);
+}
+
+/// The CPU is about to return to user mode
+#[inline]
+pub fn return_to_user() {
+    let cpu = this_cpu();
+    cpu.checkpoint();
+    cpu.in_user.store(true, Ordering::Relaxed);
+}
+
+/// Count a syscall for the current process
+#[inline]
+pub fn count_syscall() {
+    this_cpu().syscalls.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Count a page fault for the current process
+#[inline]
+pub fn count_page_fault() {
+    this_cpu().page_faults.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Move this CPU's pending counters into `process`
+///
+/// The caller must hold `PROCESS_TABLE` and `process` must be the process
+/// that has been running on this CPU since the last flush.
+pub fn flush_into(process: &Process) {
+    let cpu = this_cpu();
+    cpu.checkpoint();
+
+    let usage = process.usage();
+    usage.user_tsc.fetch_add(cpu.user_tsc.swap(0, Ordering::Relaxed), Ordering::Relaxed);
+    usage.kernel_tsc.fetch_add(cpu.kernel_tsc.swap(0, Ordering::Relaxed), Ordering::Relaxed);
+    usage.page_faults.fetch_add(cpu.page_faults.swap(0, Ordering::Relaxed), Ordering::Relaxed);
+    usage.syscalls.fetch_add(cpu.syscalls.swap(0, Ordering::Relaxed), Ordering::Relaxed);
+}
+
+/// Flush pending counters into the current process from interrupt context
+///
+/// Does nothing if the process table is locked; the counters stay pending
+/// until the next tick or context switch.
+pub fn flush_current_from_interrupt() {
+    if let Some(table) = PROCESS_TABLE.try_lock() {
+        if let Some(process) = table.current_process() {
+            flush_into(process);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_usage_snapshot() {
+        let usage = ProcessUsage::new();
+        usage.add_context_switch();
+        usage.add_io_uring_sqes(3);
+        let snapshot = usage.snapshot();
+        assert_eq!(snapshot.context_switches, 1);
+        assert_eq!(snapshot.io_uring_sqes, 3);
+        assert_eq!(snapshot.user_tsc, 0);
+    }
+
+    #[test_case]
+    fn test_checkpoint_charges_current_mode() {
+        let cpu = CpuAccount::new();
+        // The first checkpoint only establishes the baseline
+        cpu.checkpoint();
+        cpu.in_user.store(true, Ordering::Relaxed);
+        cpu.checkpoint();
+        assert_eq!(cpu.kernel_tsc.load(Ordering::Relaxed), 0);
+
+        let user = cpu.user_tsc.load(Ordering::Relaxed);
+        cpu.in_user.store(false, Ordering::Relaxed);
+        cpu.checkpoint();
+        assert_eq!(cpu.user_tsc.load(Ordering::Relaxed), user);
+    }
+}
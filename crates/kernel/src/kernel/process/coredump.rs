@@ -0,0 +1,500 @@
+// kernel/src/kernel/process/coredump.rs
+//! ELF core dumps for fatal user faults
+//!
+//! When a user process dies from an unhandled page fault or general
+//! protection fault, the exception handler writes an ELF64 `ET_CORE` image
+//! of the process before terminating it. The image can be loaded into gdb on
+//! the host (`gdb <program> core.<pid>`).
+//!
+//! # Layout
+//!
+//! ```text
+//! ELF header
+//! Program headers   PT_NOTE, PT_LOAD x N
+//! PT_NOTE           NT_PRSTATUS  (signal, pid, ppid, general registers)
+//!                   NT_FPREGSET  (FXSAVE area)
+//! PT_LOAD           contents of the mapped user pages, page aligned
+//! ```
+//!
+//! # Output
+//!
+//! The VFS is not part of the build yet, so there is no writable path such
+//! as `/tmp/core.<pid>` to store the image in. The only target is the serial
+//! port, where the image is hex encoded between marker lines:
+//!
+//! ```text
+//! [CORE] begin pid=3 size=20480
+//! 7f454c4602010100000000000000000004003e00...
+//! [CORE] end pid=3
+//! ```
+//!
+//! On the host:
+//!
+//! ```text
+//! sed -n '/\[CORE\] begin pid=3/,/\[CORE\] end/{/CORE/d;p}' serial.log | xxd -r -p > core.3
+//! ```
+//!
+//! # Registers
+//!
+//! `x86-interrupt` handlers do not expose the interrupted general purpose
+//! registers. RIP, RSP, RFLAGS, CS and SS are taken from the exception frame;
+//! the other registers are the values last saved in the process's
+//! [`RegisterState`].
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::sync::atomic::{AtomicU8, Ordering};
+use x86_64::structures::idt::InterruptStackFrame;
+use x86_64::structures::paging::{PageTable, PageTableFlags};
+
+use crate::arch::x86_64::fpu;
+use crate::debug_println;
+use crate::kernel::driver::serial::SERIAL1;
+use crate::kernel::mm::paging::COW_FLAG;
+use crate::kernel::mm::PHYS_MEM_OFFSET;
+
+use super::{RegisterState, PROCESS_TABLE};
+
+/// Signal number recorded for segmentation faults (SIGSEGV)
+pub const SIGSEGV: i32 = 11;
+
+/// Upper bound on the number of user pages written to one core file
+///
+/// Hex streaming over the serial port takes about a second per page, so
+/// large processes are truncated rather than stalling the system.
+pub const MAX_CORE_PAGES: usize = 4096;
+
+const PAGE_SIZE: u64 = 4096;
+
+const ELF_HEADER_SIZE: usize = 64;
+const PROGRAM_HEADER_SIZE: usize = 56;
+const ET_CORE: u16 = 4;
+const EM_X86_64: u16 = 62;
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+const NT_PRSTATUS: u32 = 1;
+const NT_FPREGSET: u32 = 2;
+const NOTE_NAME: &[u8; 8] = b"CORE\0\0\0\0";
+const NOTE_NAME_LEN: u32 = 5;
+
+/// Size of the Linux x86_64 `elf_prstatus` structure
+const PRSTATUS_SIZE: usize = 336;
+const PRSTATUS_PID_OFFSET: usize = 32;
+const PRSTATUS_REGS_OFFSET: usize = 112;
+const PRSTATUS_FPVALID_OFFSET: usize = 328;
+/// Number of registers in `user_regs_struct`
+const USER_REGS_COUNT: usize = 27;
+
+/// Size of the legacy FXSAVE region stored in NT_FPREGSET
+const FXSAVE_SIZE: usize = 512;
+/// Buffer size for XSAVE, which also writes the header and AVX state
+const XSAVE_AREA_SIZE: usize = 4096;
+
+/// Where core dumps are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum CoreDumpTarget {
+    /// Do not write core dumps
+    Disabled = 0,
+    /// Stream hex-encoded images over COM1
+    Serial = 1,
+}
+
+static TARGET: AtomicU8 = AtomicU8::new(CoreDumpTarget::Serial as u8);
+
+/// Select where core dumps are written
+pub fn set_target(target: CoreDumpTarget) {
+    TARGET.store(target as u8, Ordering::Relaxed);
+}
+
+/// The current core dump target
+#[must_use]
+pub fn target() -> CoreDumpTarget {
+    match TARGET.load(Ordering::Relaxed) {
+        1 => CoreDumpTarget::Serial,
+        _ => CoreDumpTarget::Disabled,
+    }
+}
+
+/// CPU state at the time of a fatal user fault
+#[derive(Debug, Clone, Copy)]
+pub struct FaultInfo {
+    /// Signal number reported in NT_PRSTATUS
+    pub signal: i32,
+    /// Faulting instruction pointer
+    pub rip: u64,
+    /// User stack pointer
+    pub rsp: u64,
+    /// RFLAGS at the time of the fault
+    pub rflags: u64,
+    /// Code segment selector
+    pub cs: u64,
+    /// Stack segment selector
+    pub ss: u64,
+}
+
+impl FaultInfo {
+    /// Capture the state saved by the CPU in the exception frame
+    #[must_use]
+    pub fn from_frame(signal: i32, frame: &InterruptStackFrame) -> Self {
+        Self {
+            signal,
+            rip: frame.instruction_pointer.as_u64(),
+            rsp: frame.stack_pointer.as_u64(),
+            rflags: frame.cpu_flags.bits(),
+            cs: u64::from(frame.code_segment.0),
+            ss: u64::from(frame.stack_segment.0),
+        }
+    }
+}
+
+/// A run of virtually contiguous user pages with the same permissions
+#[derive(Debug)]
+struct Segment {
+    vaddr: u64,
+    flags: u32,
+    /// Physical address of each 4 KiB page in the segment
+    frames: Vec<u64>,
+}
+
+impl Segment {
+    fn end(&self) -> u64 {
+        self.vaddr + self.frames.len() as u64 * PAGE_SIZE
+    }
+}
+
+/// Collects mapped user pages into coalesced segments
+#[derive(Debug, Default)]
+struct SegmentCollector {
+    segments: Vec<Segment>,
+    pages: usize,
+}
+
+impl SegmentCollector {
+    /// Add one 4 KiB page; returns false once the page limit is reached
+    fn push_page(&mut self, vaddr: u64, phys: u64, flags: u32) -> bool {
+        if self.pages >= MAX_CORE_PAGES {
+            return false;
+        }
+        self.pages += 1;
+        if let Some(last) = self.segments.last_mut() {
+            if last.end() == vaddr && last.flags == flags {
+                last.frames.push(phys);
+                return true;
+            }
+        }
+        self.segments.push(Segment { vaddr, flags, frames: alloc::vec![phys] });
+        true
+    }
+
+    /// Add a leaf mapping of `size` bytes as individual 4 KiB pages
+    fn push_leaf(&mut self, vaddr: u64, phys: u64, size: u64, flags: u32) -> bool {
+        (0..size / PAGE_SIZE).all(|i| self.push_page(vaddr + i * PAGE_SIZE, phys + i * PAGE_SIZE, flags))
+    }
+}
+
+fn is_user_entry(flags: PageTableFlags) -> bool {
+    flags.contains(PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE)
+}
+
+fn segment_flags(flags: PageTableFlags) -> u32 {
+    let mut p_flags = PF_R;
+    if flags.intersects(PageTableFlags::WRITABLE | COW_FLAG) {
+        p_flags |= PF_W;
+    }
+    if !flags.contains(PageTableFlags::NO_EXECUTE) {
+        p_flags |= PF_X;
+    }
+    p_flags
+}
+
+/// Walk the lower half of a user page table and collect the user pages
+///
+/// # Safety
+///
+/// `l4_phys` must be a valid PML4 and all physical memory must be mapped at
+/// `phys_offset`.
+unsafe fn collect_segments(l4_phys: u64, phys_offset: u64) -> Vec<Segment> {
+    // SAFETY: page tables are reachable through the physical memory mapping
+    let table = |phys: u64| unsafe { &*((phys_offset + phys) as *const PageTable) };
+    let mut collector = SegmentCollector::default();
+
+    'walk: for (i4, e4) in table(l4_phys).iter().enumerate().take(256) {
+        if !is_user_entry(e4.flags()) {
+            continue;
+        }
+        for (i3, e3) in table(e4.addr().as_u64()).iter().enumerate() {
+            if !is_user_entry(e3.flags()) {
+                continue;
+            }
+            let base3 = ((i4 as u64) << 39) | ((i3 as u64) << 30);
+            if e3.flags().contains(PageTableFlags::HUGE_PAGE) {
+                if !collector.push_leaf(base3, e3.addr().as_u64(), 1 << 30, segment_flags(e3.flags())) {
+                    break 'walk;
+                }
+                continue;
+            }
+            for (i2, e2) in table(e3.addr().as_u64()).iter().enumerate() {
+                if !is_user_entry(e2.flags()) {
+                    continue;
+                }
+                let base2 = base3 | ((i2 as u64) << 21);
+                if e2.flags().contains(PageTableFlags::HUGE_PAGE) {
+                    if !collector.push_leaf(base2, e2.addr().as_u64(), 1 << 21, segment_flags(e2.flags())) {
+                        break 'walk;
+                    }
+                    continue;
+                }
+                for (i1, e1) in table(e2.addr().as_u64()).iter().enumerate() {
+                    if !is_user_entry(e1.flags()) {
+                        continue;
+                    }
+                    let vaddr = base2 | ((i1 as u64) << 12);
+                    if !collector.push_page(vaddr, e1.addr().as_u64(), segment_flags(e1.flags())) {
+                        break 'walk;
+                    }
+                }
+            }
+        }
+    }
+
+    if collector.pages >= MAX_CORE_PAGES {
+        debug_println!("[CORE] Page limit reached, core file truncated to {} pages", MAX_CORE_PAGES);
+    }
+    collector.segments
+}
+
+/// Registers in `user_regs_struct` order
+fn user_regs(saved: &RegisterState, fault: &FaultInfo) -> [u64; USER_REGS_COUNT] {
+    [
+        saved.r15, saved.r14, saved.r13, saved.r12, saved.rbp, saved.rbx,
+        saved.r11, saved.r10, saved.r9, saved.r8, saved.rax, saved.rcx,
+        saved.rdx, saved.rsi, saved.rdi,
+        u64::MAX, // orig_rax: not in a syscall
+        fault.rip, fault.cs, fault.rflags, fault.rsp, fault.ss,
+        0, 0, // fs_base, gs_base
+        0, 0, 0, 0, // ds, es, fs, gs
+    ]
+}
+
+/// Build the `elf_prstatus` note descriptor
+fn prstatus(signal: i32, pid: u32, ppid: u32, regs: &[u64; USER_REGS_COUNT], fpvalid: bool) -> [u8; PRSTATUS_SIZE] {
+    let mut desc = [0u8; PRSTATUS_SIZE];
+    // pr_info.si_signo and pr_cursig
+    desc[0..4].copy_from_slice(&signal.to_le_bytes());
+    desc[12..14].copy_from_slice(&(signal as u16).to_le_bytes());
+    desc[PRSTATUS_PID_OFFSET..PRSTATUS_PID_OFFSET + 4].copy_from_slice(&pid.to_le_bytes());
+    desc[PRSTATUS_PID_OFFSET + 4..PRSTATUS_PID_OFFSET + 8].copy_from_slice(&ppid.to_le_bytes());
+    for (i, reg) in regs.iter().enumerate() {
+        let offset = PRSTATUS_REGS_OFFSET + i * 8;
+        desc[offset..offset + 8].copy_from_slice(&reg.to_le_bytes());
+    }
+    desc[PRSTATUS_FPVALID_OFFSET..PRSTATUS_FPVALID_OFFSET + 4]
+        .copy_from_slice(&u32::from(fpvalid).to_le_bytes());
+    desc
+}
+
+fn push_note(buf: &mut Vec<u8>, note_type: u32, desc: &[u8]) {
+    buf.extend_from_slice(&NOTE_NAME_LEN.to_le_bytes());
+    buf.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+    buf.extend_from_slice(&note_type.to_le_bytes());
+    buf.extend_from_slice(NOTE_NAME);
+    buf.extend_from_slice(desc);
+    buf.resize(buf.len().next_multiple_of(4), 0);
+}
+
+fn push_program_header(
+    buf: &mut Vec<u8>,
+    p_type: u32,
+    p_flags: u32,
+    offset: u64,
+    vaddr: u64,
+    size: u64,
+    align: u64,
+) {
+    buf.extend_from_slice(&p_type.to_le_bytes());
+    buf.extend_from_slice(&p_flags.to_le_bytes());
+    buf.extend_from_slice(&offset.to_le_bytes());
+    buf.extend_from_slice(&vaddr.to_le_bytes()); // p_vaddr
+    buf.extend_from_slice(&0u64.to_le_bytes()); // p_paddr
+    buf.extend_from_slice(&size.to_le_bytes()); // p_filesz
+    buf.extend_from_slice(&size.to_le_bytes()); // p_memsz
+    buf.extend_from_slice(&align.to_le_bytes());
+}
+
+/// Build everything that precedes the page contents: ELF header, program
+/// headers, notes and padding up to the first PT_LOAD offset
+fn build_prefix(notes: &[u8], segments: &[Segment]) -> Vec<u8> {
+    let phnum = 1 + segments.len();
+    let notes_offset = ELF_HEADER_SIZE + phnum * PROGRAM_HEADER_SIZE;
+    let data_offset = (notes_offset + notes.len()).next_multiple_of(PAGE_SIZE as usize);
+
+    let mut buf = Vec::with_capacity(data_offset);
+    buf.extend_from_slice(&[0x7F, b'E', b'L', b'F', 2, 1, 1, 0]); // ELFCLASS64, LSB, EV_CURRENT
+    buf.extend_from_slice(&[0; 8]);
+    buf.extend_from_slice(&ET_CORE.to_le_bytes());
+    buf.extend_from_slice(&EM_X86_64.to_le_bytes());
+    buf.extend_from_slice(&1u32.to_le_bytes()); // e_version
+    buf.extend_from_slice(&0u64.to_le_bytes()); // e_entry
+    buf.extend_from_slice(&(ELF_HEADER_SIZE as u64).to_le_bytes()); // e_phoff
+    buf.extend_from_slice(&0u64.to_le_bytes()); // e_shoff
+    buf.extend_from_slice(&0u32.to_le_bytes()); // e_flags
+    buf.extend_from_slice(&(ELF_HEADER_SIZE as u16).to_le_bytes());
+    buf.extend_from_slice(&(PROGRAM_HEADER_SIZE as u16).to_le_bytes());
+    buf.extend_from_slice(&(phnum as u16).to_le_bytes());
+    buf.extend_from_slice(&[0; 6]); // e_shentsize, e_shnum, e_shstrndx
+
+    push_program_header(&mut buf, PT_NOTE, 0, notes_offset as u64, 0, notes.len() as u64, 4);
+    let mut offset = data_offset as u64;
+    for segment in segments {
+        let size = segment.end() - segment.vaddr;
+        push_program_header(&mut buf, PT_LOAD, segment.flags, offset, segment.vaddr, size, PAGE_SIZE);
+        offset += size;
+    }
+
+    buf.extend_from_slice(notes);
+    buf.resize(data_offset, 0);
+    buf
+}
+
+/// Total size of the core file
+fn core_size(prefix: &[u8], segments: &[Segment]) -> usize {
+    prefix.len() + segments.iter().map(|s| s.frames.len() * PAGE_SIZE as usize).sum::<usize>()
+}
+
+/// Hex-encodes the core image onto COM1
+struct SerialHexSink<'a> {
+    serial: spin::MutexGuard<'a, crate::kernel::driver::serial::SerialPort>,
+    column: usize,
+}
+
+impl SerialHexSink<'_> {
+    /// Bytes per output line
+    const LINE_BYTES: usize = 32;
+
+    fn write(&mut self, bytes: &[u8]) {
+        const HEX: &[u8; 16] = b"0123456789abcdef";
+        for &byte in bytes {
+            let _ = self.serial.write_char(char::from(HEX[usize::from(byte >> 4)]));
+            let _ = self.serial.write_char(char::from(HEX[usize::from(byte & 0xF)]));
+            self.column += 1;
+            if self.column == Self::LINE_BYTES {
+                let _ = self.serial.write_char('\n');
+                self.column = 0;
+            }
+        }
+    }
+
+    fn finish(mut self) {
+        if self.column != 0 {
+            let _ = self.serial.write_char('\n');
+        }
+    }
+}
+
+/// Write a core dump of the current process
+///
+/// Called from exception handlers before the process is terminated. Does
+/// nothing if dumps are disabled or the process table is locked.
+pub fn dump_current(fault: &FaultInfo) {
+    if target() == CoreDumpTarget::Disabled {
+        return;
+    }
+
+    // Snapshot the live FPU state before anything else touches it
+    #[repr(C, align(64))]
+    struct XsaveArea([u8; XSAVE_AREA_SIZE]);
+    let mut xsave = Box::new(XsaveArea([0; XSAVE_AREA_SIZE]));
+    // SAFETY: the buffer is 64-byte aligned and large enough for XSAVE
+    unsafe { fpu::save_fpu_state(xsave.0.as_mut_ptr()) };
+
+    let Some(table) = PROCESS_TABLE.try_lock() else {
+        debug_println!("[CORE] Process table busy, core dump skipped");
+        return;
+    };
+    let Some(process) = table.current_process() else {
+        return;
+    };
+    let pid = process.pid().as_u64();
+    let ppid = process.parent_pid().map_or(0, |p| p.as_u64());
+    let regs = user_regs(process.registers(), fault);
+    let phys_offset = PHYS_MEM_OFFSET.load(Ordering::Relaxed);
+    // SAFETY: the process's page table is valid while it is the current process
+    let segments = unsafe { collect_segments(process.page_table_phys_addr(), phys_offset) };
+    drop(table);
+
+    let mut notes = Vec::new();
+    push_note(&mut notes, NT_PRSTATUS, &prstatus(fault.signal, pid as u32, ppid as u32, &regs, true));
+    push_note(&mut notes, NT_FPREGSET, &xsave.0[..FXSAVE_SIZE]);
+    let prefix = build_prefix(&notes, &segments);
+
+    let mut serial = SERIAL1.lock();
+    let _ = writeln!(serial, "[CORE] begin pid={} size={}", pid, core_size(&prefix, &segments));
+    let mut sink = SerialHexSink { serial, column: 0 };
+    sink.write(&prefix);
+    for frame in segments.iter().flat_map(|s| s.frames.iter()) {
+        // SAFETY: the frame is mapped in the process and reachable through
+        // the physical memory mapping; the process has not been freed yet
+        let page = unsafe {
+            core::slice::from_raw_parts((phys_offset + frame) as *const u8, PAGE_SIZE as usize)
+        };
+        sink.write(page);
+    }
+    sink.finish();
+    let mut serial = SERIAL1.lock();
+    let _ = writeln!(serial, "[CORE] end pid={}", pid);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_collector_coalesces_contiguous_pages() {
+        let mut collector = SegmentCollector::default();
+        assert!(collector.push_page(0x40_0000, 0x1000, PF_R | PF_X));
+        assert!(collector.push_page(0x40_1000, 0x5000, PF_R | PF_X));
+        // Permission change starts a new segment
+        assert!(collector.push_page(0x40_2000, 0x6000, PF_R | PF_W));
+        // Gap starts a new segment
+        assert!(collector.push_page(0x50_0000, 0x7000, PF_R | PF_W));
+        assert_eq!(collector.segments.len(), 3);
+        assert_eq!(collector.segments[0].frames.len(), 2);
+        assert_eq!(collector.segments[0].end(), 0x40_2000);
+    }
+
+    #[test_case]
+    fn test_prefix_layout() {
+        let mut collector = SegmentCollector::default();
+        collector.push_page(0x40_0000, 0x1000, PF_R);
+        let mut notes = Vec::new();
+        let regs = [0u64; USER_REGS_COUNT];
+        push_note(&mut notes, NT_PRSTATUS, &prstatus(SIGSEGV, 7, 1, &regs, false));
+        assert_eq!(notes.len(), 12 + 8 + PRSTATUS_SIZE);
+
+        let prefix = build_prefix(&notes, &collector.segments);
+        assert_eq!(&prefix[0..4], b"\x7fELF");
+        assert_eq!(u16::from_le_bytes([prefix[16], prefix[17]]), ET_CORE);
+        assert_eq!(u16::from_le_bytes([prefix[56], prefix[57]]), 2); // e_phnum
+        assert_eq!(prefix.len() % PAGE_SIZE as usize, 0);
+        assert_eq!(core_size(&prefix, &collector.segments), prefix.len() + PAGE_SIZE as usize);
+    }
+
+    #[test_case]
+    fn test_prstatus_fields() {
+        let mut regs = [0u64; USER_REGS_COUNT];
+        regs[16] = 0x40_1234; // rip
+        let desc = prstatus(SIGSEGV, 7, 1, &regs, true);
+        assert_eq!(desc[0], SIGSEGV as u8);
+        assert_eq!(desc[PRSTATUS_PID_OFFSET], 7);
+        assert_eq!(desc[PRSTATUS_PID_OFFSET + 4], 1);
+        let rip_offset = PRSTATUS_REGS_OFFSET + 16 * 8;
+        assert_eq!(u64::from_le_bytes(desc[rip_offset..rip_offset + 8].try_into().unwrap()), 0x40_1234);
+        assert_eq!(desc[PRSTATUS_FPVALID_OFFSET], 1);
+    }
+}
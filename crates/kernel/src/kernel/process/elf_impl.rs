@@ -6,6 +6,10 @@
 use super::elf_loader::*;
 use x86_64::{VirtAddr, PhysAddr, structures::paging::{OffsetPageTable, FrameAllocator, Size4KiB, Page, Mapper, Translate, PhysFrame}};
 use x86_64::structures::paging::mapper::TranslateResult;
+use alloc::vec::Vec;
+use crate::abi::mman::{PROT_EXEC, PROT_READ, PROT_WRITE};
+use crate::kernel::mm::user_paging::UserLayout;
+use crate::kernel::mm::vma::{Vma, VmaKind};
 
 /// Information about a loaded ELF program
 #[derive(Debug)]
@@ -18,14 +22,29 @@ pub struct LoadedProgram {
     pub base_addr: VirtAddr,
     /// Total size of program in memory
     pub size: u64,
+    /// Memory areas of the loaded segments and the stack
+    pub areas: Vec<Vma>,
 }
 
 /// Load an ELF binary into memory
 ///
+/// `ET_EXEC` images are loaded at their link addresses. `ET_DYN`
+/// (position-independent) images are moved to `layout.pie_base` and their
+/// `R_X86_64_RELATIVE` relocations are applied. The stack is placed below
+/// `layout.stack_top`.
+///
+/// With `file_frames`, read-only segments are mapped from the frames
+/// holding the file instead of being copied (see [`maps_from_file`]).
+/// The pages are copy-on-write, so neither a later `mprotect` nor a
+/// debugger write reaches the file. The frames must lie outside the frame
+/// allocator (the initrd), as the mappings take no references on them.
+///
 /// # Arguments
 /// * `elf_data` - Raw ELF file bytes
+/// * `file_frames` - Frames holding `elf_data` in page order, if it is page aligned
 /// * `mapper` - Page table mapper
 /// * `frame_allocator` - Frame allocator
+/// * `layout` - Address-space layout of the new process
 ///
 /// # Returns
 /// Information about the loaded program
@@ -35,10 +54,13 @@ pub struct LoadedProgram {
 /// - ELF file is invalid
 /// - Segments cannot be mapped
 /// - Out of memory
+/// - The image needs relocations other than `R_X86_64_RELATIVE`
 pub fn load_elf<A>(
     elf_data: &[u8],
+    file_frames: Option<&[PhysFrame<Size4KiB>]>,
     mapper: &mut OffsetPageTable,
     frame_allocator: &mut A,
+    layout: &UserLayout,
 ) -> Result<LoadedProgram, ElfError>
 where
     A: FrameAllocator<Size4KiB>,
@@ -56,39 +78,299 @@ where
     // 3. Calculate memory requirements
     let (min_addr, max_addr) = calculate_memory_range(phdrs)?;
     let total_size = max_addr - min_addr;
+    let bias = load_bias(header, phdrs, min_addr, layout.pie_base)?;
     
-    crate::debug_println!("[ELF] Memory range: 0x{:x} - 0x{:x} ({} bytes)", 
-        min_addr, max_addr, total_size);
+    crate::debug_println!("[ELF] Memory range: 0x{:x} - 0x{:x} ({} bytes), bias=0x{:x}", 
+        min_addr, max_addr, total_size, bias);
     
     // 4. Load each LOAD segment
+    let file_frames = file_frames.filter(|_| !has_text_relocations(phdrs, elf_data));
     for (i, phdr) in phdrs.iter().enumerate() {
-        if phdr.is_load() {
-            load_segment(phdr, elf_data, mapper, frame_allocator, i)?;
+        if !phdr.is_load() {
+            continue;
         }
+        match file_frames {
+            Some(frames) if maps_from_file(phdr, phdrs, frames.len()) => {
+                map_segment_from_file(phdr, bias, frames, mapper, frame_allocator, i)?;
+            }
+            _ => load_segment(phdr, bias, elf_data, mapper, frame_allocator, i)?,
+        }
+    }
+    if bias != 0 {
+        apply_relocations(phdrs, elf_data, bias, mapper)?;
     }
     
-    // 5. Setup stack (using configured default size, growing downward from high address)
-    use crate::kernel::mm::user_paging::DEFAULT_USER_STACK_SIZE;
+    // 5. Setup stack (map the default size now; the area spans the stack
+    // limit and grows downward on demand)
+    use crate::kernel::mm::user_paging::{user_stack_limit, DEFAULT_USER_STACK_SIZE};
     
     let stack_top = unsafe {
         crate::kernel::mm::user_paging::map_user_stack(
             mapper,
+            VirtAddr::new(layout.stack_top),
             DEFAULT_USER_STACK_SIZE,
             frame_allocator,
         ).map_err(|_| ElfError::MapFailed)?
     };
     
+    let mut areas = segment_areas(phdrs, bias);
+    areas.push(crate::kernel::mm::user_paging::user_stack_area(layout.stack_top, user_stack_limit()));
+
     Ok(LoadedProgram {
-        entry: VirtAddr::new(header.e_entry),
+        entry: VirtAddr::new(header.e_entry + bias),
         stack_top,
-        base_addr: VirtAddr::new(min_addr),
+        base_addr: VirtAddr::new(min_addr + bias),
         size: total_size,
+        areas,
     })
 }
 
-/// Load a single segment into memory
+/// Offset added to every address of the image
+///
+/// `ET_EXEC` images run at their link address (bias 0). `ET_DYN` images
+/// are moved so that their first page lands at `pie_base`, rounded down
+/// to the largest segment alignment so that every segment keeps its
+/// alignment.
+fn load_bias(
+    header: &Elf64Header,
+    phdrs: &[Elf64ProgramHeader],
+    min_addr: u64,
+    pie_base: u64,
+) -> Result<u64, ElfError> {
+    if header.e_type != ElfType::Dyn as u16 {
+        return Ok(0);
+    }
+    let align = phdrs
+        .iter()
+        .filter(|p| p.is_load() && p.p_align.is_power_of_two())
+        .map(|p| p.p_align)
+        .fold(0x1000, u64::max);
+    (pie_base & !(align - 1))
+        .checked_sub(min_addr & !0xFFF)
+        .ok_or(ElfError::InvalidProgramHeader)
+}
+
+/// Apply the relocations of a position-independent image loaded at `bias`
+///
+/// The table is located through `DT_RELA` in the `PT_DYNAMIC` segment and
+/// read from the file; relocated values are written through the new
+/// mappings. Statically linked PIE images only carry
+/// `R_X86_64_RELATIVE` entries, so no symbol lookup is needed.
+fn apply_relocations(
+    phdrs: &[Elf64ProgramHeader],
+    elf_data: &[u8],
+    bias: u64,
+    mapper: &OffsetPageTable,
+) -> Result<(), ElfError> {
+    use super::elf_loader::dynamic_tags::{DT_NULL, DT_RELA, DT_RELAENT, DT_RELASZ};
+    use super::elf_loader::reloc_types::{R_X86_64_NONE, R_X86_64_RELATIVE};
+
+    let Some(dynamic) = phdrs.iter().find(|p| p.p_type == ProgramHeaderType::Dynamic as u32) else {
+        return Ok(());
+    };
+
+    let (mut rela, mut rela_size, mut rela_ent) = (None, 0, 24);
+    for i in 0..dynamic.p_filesz / 16 {
+        let entry = dynamic.p_offset + i * 16;
+        let tag = read_u64(elf_data, entry)? as i64;
+        let value = read_u64(elf_data, entry + 8)?;
+        match tag {
+            DT_NULL => break,
+            DT_RELA => rela = Some(value),
+            DT_RELASZ => rela_size = value,
+            DT_RELAENT => rela_ent = value,
+            _ => {}
+        }
+    }
+    let Some(rela) = rela else {
+        return Ok(());
+    };
+    if rela_ent < 24 {
+        return Err(ElfError::InvalidHeader);
+    }
+    let table = file_offset(phdrs, rela).ok_or(ElfError::InvalidHeader)?;
+
+    for i in 0..rela_size / rela_ent {
+        let entry = table + i * rela_ent;
+        let offset = read_u64(elf_data, entry)?;
+        let kind = read_u64(elf_data, entry + 8)? as u32;
+        let addend = read_u64(elf_data, entry + 16)?;
+        match kind {
+            R_X86_64_NONE => {}
+            R_X86_64_RELATIVE => {
+                write_image(mapper, offset.wrapping_add(bias), &bias.wrapping_add(addend).to_le_bytes())?;
+            }
+            _ => return Err(ElfError::UnsupportedRelocation),
+        }
+    }
+
+    crate::debug_println!("[ELF] Applied {} relocations", rela_size / rela_ent);
+    Ok(())
+}
+
+/// Whether relocations may write to read-only segments (`DT_TEXTREL`)
+fn has_text_relocations(phdrs: &[Elf64ProgramHeader], elf_data: &[u8]) -> bool {
+    use super::elf_loader::dynamic_tags::{DF_TEXTREL, DT_FLAGS, DT_NULL, DT_TEXTREL};
+
+    let Some(dynamic) = phdrs.iter().find(|p| p.p_type == ProgramHeaderType::Dynamic as u32) else {
+        return false;
+    };
+    for i in 0..dynamic.p_filesz / 16 {
+        let entry = dynamic.p_offset + i * 16;
+        let (Ok(tag), Ok(value)) = (read_u64(elf_data, entry), read_u64(elf_data, entry + 8)) else {
+            // Malformed tables fail later in `apply_relocations`
+            return true;
+        };
+        match tag as i64 {
+            DT_NULL => break,
+            DT_TEXTREL => return true,
+            DT_FLAGS if value & DF_TEXTREL != 0 => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Whether a LOAD segment can be mapped from the file's pages
+///
+/// The segment must be read-only, have no zero-filled tail, keep its file
+/// offset and address congruent modulo the page size, lie within the
+/// `file_pages` pages of the file, and share no page with another LOAD
+/// segment (which would copy its own data into the file's frame).
+fn maps_from_file(phdr: &Elf64ProgramHeader, phdrs: &[Elf64ProgramHeader], file_pages: usize) -> bool {
+    let (_, write, _) = phdr.permissions();
+    let pages = |p: &Elf64ProgramHeader| (p.p_vaddr & !0xFFF, (p.p_vaddr + p.p_memsz + 0xFFF) & !0xFFF);
+    let (start, end) = pages(phdr);
+
+    !write
+        && phdr.p_filesz > 0
+        && phdr.p_memsz == phdr.p_filesz
+        && phdr.p_offset % 0x1000 == phdr.p_vaddr % 0x1000
+        && phdr
+            .p_offset
+            .checked_add(phdr.p_filesz)
+            .is_some_and(|end| end.div_ceil(0x1000) <= file_pages as u64)
+        && phdrs
+            .iter()
+            .filter(|p| p.is_load() && !core::ptr::eq(*p, phdr))
+            .all(|p| {
+                let (other_start, other_end) = pages(p);
+                other_end <= start || other_start >= end
+            })
+}
+
+/// Map a segment chosen by [`maps_from_file`] from the file's frames
+fn map_segment_from_file<A>(
+    phdr: &Elf64ProgramHeader,
+    bias: u64,
+    file_frames: &[PhysFrame<Size4KiB>],
+    mapper: &mut OffsetPageTable,
+    frame_allocator: &mut A,
+    index: usize,
+) -> Result<(), ElfError>
+where
+    A: FrameAllocator<Size4KiB>,
+{
+    use crate::kernel::mm::paging::COW_FLAG;
+    use x86_64::structures::paging::PageTableFlags;
+
+    let vaddr = phdr.p_vaddr.checked_add(bias).ok_or(ElfError::InvalidProgramHeader)?;
+    if vaddr.saturating_add(phdr.p_memsz) > 0x0000_8000_0000_0000 {
+        return Err(ElfError::InvalidProgramHeader);
+    }
+    let start_page = Page::<Size4KiB>::containing_address(VirtAddr::new(vaddr));
+    let first = (phdr.p_offset / 0x1000) as usize;
+    let count = ((vaddr + phdr.p_memsz + 0xFFF) / 0x1000 - vaddr / 0x1000) as usize;
+    let flags = (phdr.to_page_flags() - PageTableFlags::WRITABLE) | COW_FLAG;
+
+    for (i, &frame) in file_frames[first..first + count].iter().enumerate() {
+        // SAFETY: The frame holds the file and outlives the mapping; the
+        // copy-on-write flag keeps user writes away from it
+        unsafe {
+            mapper
+                .map_to(start_page + i as u64, frame, flags, frame_allocator)
+                .map_err(|_| ElfError::MapFailed)?
+                .flush();
+        }
+    }
+
+    crate::debug_println!("[ELF] Mapped segment {} from the file ({} pages)", index, count);
+    Ok(())
+}
+
+/// File offset of the link-time address `addr`, if a LOAD segment holds it
+fn file_offset(phdrs: &[Elf64ProgramHeader], addr: u64) -> Option<u64> {
+    phdrs
+        .iter()
+        .find(|p| p.is_load() && addr >= p.p_vaddr && addr < p.p_vaddr + p.p_filesz)
+        .map(|p| p.p_offset + (addr - p.p_vaddr))
+}
+
+/// Read a little-endian `u64` at `offset` of the file
+fn read_u64(elf_data: &[u8], offset: u64) -> Result<u64, ElfError> {
+    let start = usize::try_from(offset).map_err(|_| ElfError::FileTooSmall)?;
+    let bytes = elf_data
+        .get(start..start.checked_add(8).ok_or(ElfError::FileTooSmall)?)
+        .ok_or(ElfError::FileTooSmall)?;
+    Ok(u64::from_le_bytes(bytes.try_into().unwrap_or_default()))
+}
+
+/// Write `bytes` to the mapped image at user address `addr`
+///
+/// Goes through the physical memory mapping, so read-only segments can be
+/// relocated as well. Pages mapped from the file are refused, as the
+/// write would modify the file.
+fn write_image(mapper: &OffsetPageTable, addr: u64, bytes: &[u8]) -> Result<(), ElfError> {
+    use crate::kernel::mm::paging::COW_FLAG;
+
+    let phys_mem_offset = crate::kernel::mm::PHYS_MEM_OFFSET.load(core::sync::atomic::Ordering::Relaxed);
+    for (i, byte) in bytes.iter().enumerate() {
+        let virt = VirtAddr::try_new(addr.wrapping_add(i as u64)).map_err(|_| ElfError::InvalidHeader)?;
+        let TranslateResult::Mapped { frame, offset, flags } = mapper.translate(virt) else {
+            return Err(ElfError::InvalidHeader);
+        };
+        if flags.contains(COW_FLAG) {
+            return Err(ElfError::UnsupportedRelocation);
+        }
+        let phys = frame.start_address() + offset;
+        // SAFETY: The page belongs to the image that was just loaded and
+        // the physical memory mapping covers all frames
+        unsafe {
+            *((phys_mem_offset + phys.as_u64()) as *mut u8) = *byte;
+        }
+    }
+    Ok(())
+}
+
+/// Page-aligned memory areas of the LOAD segments
+///
+/// Segments sharing a page are clipped so that the shared page belongs to
+/// the earlier segment, keeping the areas disjoint.
+fn segment_areas(phdrs: &[Elf64ProgramHeader], bias: u64) -> Vec<Vma> {
+    let mut areas: Vec<Vma> = Vec::new();
+    for phdr in phdrs.iter().filter(|p| p.is_load() && p.p_memsz > 0) {
+        let (read, write, exec) = phdr.permissions();
+        let prot = (if read { PROT_READ } else { 0 })
+            | (if write { PROT_WRITE } else { 0 })
+            | (if exec { PROT_EXEC } else { 0 });
+
+        let vaddr = phdr.p_vaddr + bias;
+        let mut start = vaddr & !0xFFF;
+        let end = (vaddr + phdr.p_memsz + 0xFFF) & !0xFFF;
+        if let Some(prev) = areas.last() {
+            start = start.max(prev.end);
+        }
+        if start < end {
+            areas.push(Vma::new(start, end, prot, VmaKind::Image));
+        }
+    }
+    areas
+}
+
+/// Load a single segment into memory at `p_vaddr + bias`
 fn load_segment<A>(
     phdr: &Elf64ProgramHeader,
+    bias: u64,
     elf_data: &[u8],
     mapper: &mut OffsetPageTable,
     frame_allocator: &mut A,
@@ -98,11 +380,12 @@ where
     A: FrameAllocator<Size4KiB>,
 {
     let (read, write, exec) = phdr.permissions();
+    let vaddr = phdr.p_vaddr.checked_add(bias).ok_or(ElfError::InvalidProgramHeader)?;
     
     crate::debug_println!(
         "[ELF] Loading segment {}: vaddr=0x{:x}, size=0x{:x}, flags={}{}{}",
         index,
-        phdr.p_vaddr,
+        vaddr,
         phdr.p_memsz,
         if read { "R" } else { "-" },
         if write { "W" } else { "-" },
@@ -110,15 +393,15 @@ where
     );
     
     // Security: Verify segment is in user space
-    if phdr.p_vaddr >= 0x0000_8000_0000_0000 {
+    if vaddr >= 0x0000_8000_0000_0000 {
         return Err(ElfError::InvalidProgramHeader);
     }
     
     // Calculate page-aligned range
-    let start_addr = VirtAddr::new(phdr.p_vaddr);
+    let start_addr = VirtAddr::new(vaddr);
     let start_page = Page::<Size4KiB>::containing_address(start_addr);
     
-    let end_addr = VirtAddr::new(phdr.p_vaddr + phdr.p_memsz);
+    let end_addr = VirtAddr::new(vaddr + phdr.p_memsz);
     let end_page = Page::<Size4KiB>::containing_address(end_addr);
     
     // Get page flags from ELF permissions
@@ -169,7 +452,7 @@ where
         
         // Copy data to each page via physical address
         let mut bytes_copied = 0usize;
-        let segment_offset = (phdr.p_vaddr & 0xFFF) as usize; // Offset within first page
+        let segment_offset = (vaddr & 0xFFF) as usize; // Offset within first page
         
         for (i, (page, frame)) in frames.iter().enumerate() {
             let page_phys_addr = frame.start_address().as_u64();
@@ -201,7 +484,7 @@ where
     
     // Zero-fill BSS (uninitialized data)
     if phdr.p_memsz > phdr.p_filesz {
-        let bss_start = phdr.p_vaddr + phdr.p_filesz;
+        let bss_start = vaddr + phdr.p_filesz;
         let bss_size = (phdr.p_memsz - phdr.p_filesz) as usize;
         
         crate::debug_println!("[ELF] Zero-filling BSS: 0x{:x} ({} bytes)", bss_start, bss_size);
@@ -377,4 +660,75 @@ mod tests {
         assert_eq!(min, 0x400000);
         assert_eq!(max, 0x401000);
     }
+    
+    #[test]
+    fn test_pie_load_bias() {
+        let phdrs = [Elf64ProgramHeader {
+            p_type: ProgramHeaderType::Load as u32,
+            p_flags: phdr_flags::PF_R | phdr_flags::PF_X,
+            p_offset: 0,
+            p_vaddr: 0,
+            p_paddr: 0,
+            p_filesz: 0x1000,
+            p_memsz: 0x1000,
+            p_align: 0x20_0000,
+        }];
+        let mut header = Elf64Header {
+            e_ident: [0; 16],
+            e_type: ElfType::Dyn as u16,
+            e_machine: ElfMachine::X86_64 as u16,
+            e_version: 1,
+            e_entry: 0x100,
+            e_phoff: 0,
+            e_shoff: 0,
+            e_flags: 0,
+            e_ehsize: 0,
+            e_phentsize: 0,
+            e_phnum: 1,
+            e_shentsize: 0,
+            e_shnum: 0,
+            e_shstrndx: 0,
+        };
+        
+        // Rounded down to the 2 MiB segment alignment
+        let bias = load_bias(&header, &phdrs, 0, 0x4000_0012_3000).unwrap();
+        assert_eq!(bias, 0x4000_0000_0000);
+        assert_eq!(segment_areas(&phdrs, bias)[0].start, 0x4000_0000_0000);
+        
+        header.e_type = ElfType::Exec as u16;
+        assert_eq!(load_bias(&header, &phdrs, 0, 0x4000_0012_3000).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_maps_from_file() {
+        let segment = |flags, offset, vaddr, filesz, memsz| Elf64ProgramHeader {
+            p_type: ProgramHeaderType::Load as u32,
+            p_flags: flags,
+            p_offset: offset,
+            p_vaddr: vaddr,
+            p_paddr: vaddr,
+            p_filesz: filesz,
+            p_memsz: memsz,
+            p_align: 0x1000,
+        };
+        let text = segment(phdr_flags::PF_R | phdr_flags::PF_X, 0, 0x400000, 0x1800, 0x1800);
+        let data = segment(phdr_flags::PF_R | phdr_flags::PF_W, 0x2000, 0x402000, 0x100, 0x300);
+        let phdrs = [text, data];
+
+        assert!(maps_from_file(&phdrs[0], &phdrs, 3));
+        // Writable segments are always copied
+        assert!(!maps_from_file(&phdrs[1], &phdrs, 3));
+        // The file must hold every page of the segment
+        assert!(!maps_from_file(&phdrs[0], &phdrs, 1));
+
+        // A segment sharing the last text page would write into the file
+        let shared = [text, segment(phdr_flags::PF_R | phdr_flags::PF_W, 0x1800, 0x401800, 0x100, 0x100)];
+        assert!(!maps_from_file(&shared[0], &shared, 3));
+
+        // Zero-filled tails and incongruent offsets need a private copy
+        let bss = [segment(phdr_flags::PF_R, 0, 0x400000, 0x100, 0x2000)];
+        assert!(!maps_from_file(&bss[0], &bss, 3));
+        let shifted = [segment(phdr_flags::PF_R, 0x10, 0x400000, 0x100, 0x100)];
+        assert!(!maps_from_file(&shifted[0], &shifted, 3));
+    }
 }
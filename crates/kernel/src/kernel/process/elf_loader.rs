@@ -166,6 +166,32 @@ pub mod phdr_flags {
     pub const PF_R: u32 = 1 << 2;
 }
 
+/// Dynamic section tags used by the loader
+pub mod dynamic_tags {
+    /// End of the dynamic section
+    pub const DT_NULL: i64 = 0;
+    /// Address of the `Elf64_Rela` relocation table
+    pub const DT_RELA: i64 = 7;
+    /// Total size of the relocation table in bytes
+    pub const DT_RELASZ: i64 = 8;
+    /// Size of one relocation entry
+    pub const DT_RELAENT: i64 = 9;
+    /// Relocations may modify non-writable segments
+    pub const DT_TEXTREL: i64 = 22;
+    /// Flag bits (`DF_*`)
+    pub const DT_FLAGS: i64 = 30;
+    /// `DT_FLAGS` bit equivalent to `DT_TEXTREL`
+    pub const DF_TEXTREL: u64 = 0x4;
+}
+
+/// x86-64 relocation types
+pub mod reloc_types {
+    /// No relocation
+    pub const R_X86_64_NONE: u32 = 0;
+    /// Load bias plus addend
+    pub const R_X86_64_RELATIVE: u32 = 8;
+}
+
 /// ELF parsing errors
 #[derive(Debug)]
 pub enum ElfError {
@@ -189,6 +215,8 @@ pub enum ElfError {
     AlignmentError,
     /// Memory mapping failed
     MapFailed,
+    /// Relocation type the loader cannot apply
+    UnsupportedRelocation,
 }
 
 impl Elf64Header {
@@ -6,14 +6,15 @@ use alloc::vec::Vec;
 use alloc::sync::Arc;
 use x86_64::structures::paging::{
     PageTable, OffsetPageTable, FrameAllocator, PhysFrame, Size4KiB, PageTableFlags,
-    Mapper, Translate, Page
 };
-use x86_64::structures::paging::mapper::TranslateResult;
 use x86_64::{VirtAddr, PhysAddr};
 use crate::kernel::process::{Process, ProcessId, ProcessState, PROCESS_TABLE};
+use crate::kernel::fs::vfs::VFS;
 use crate::kernel::loader::load_user_program;
 use crate::kernel::mm::allocator::BOOT_INFO_ALLOCATOR;
 use crate::kernel::mm::PHYS_MEM_OFFSET;
+use crate::kernel::mm::user_paging::UserLayout;
+use crate::abi::personality::ADDR_NO_RANDOMIZE;
 
 /// Error creating a process
 #[derive(Debug)]
@@ -38,6 +39,19 @@ impl From<crate::kernel::loader::LoadError> for CreateError {
 /// This is the main entry point for creating processes in Phase 2.
 /// It creates a new process, loads the program from the filesystem, and adds it to the process table.
 pub fn create_user_process(path: &str, args: &[&str]) -> Result<(ProcessId, VirtAddr, VirtAddr, u64), CreateError> {
+    // The child inherits the caller's personality, which decides whether
+    // its layout is randomised
+    let personality = PROCESS_TABLE.lock().current_process().map_or(0, |p| p.personality());
+    let layout = UserLayout::new(personality & ADDR_NO_RANDOMIZE == 0);
+
+    // The program comes from the initrd; without one, the embedded image runs
+    let vfs = VFS.lock();
+    let (program_data, file_frames) = match vfs.read_file(path) {
+        Some(data) => (data, vfs.file_frames(path)),
+        None if vfs.is_mounted() => return Err(CreateError::FileNotFound),
+        None => (&include_bytes!("../../shell.bin")[..], None),
+    };
+
     let mut allocator_lock = BOOT_INFO_ALLOCATOR.lock();
     let frame_allocator = allocator_lock.as_mut().ok_or(CreateError::FrameAllocationFailed)?;
     
@@ -52,9 +66,13 @@ pub fn create_user_process(path: &str, args: &[&str]) -> Result<(ProcessId, Virt
     ).map_err(CreateError::PageTableCreationError)?;
     
     let pid = process.pid();
+    process.set_name(path.rsplit('/').next().unwrap_or(path));
+    process.set_personality(personality);
+    process.set_layout(layout);
     
     // 2. Load program into the process's address space
     // We need to temporarily access the process's page table
+    let stack_top;
     {
         let l4_table_ptr = (phys_mem_offset + process.page_table_frame().start_address().as_u64())
             .as_mut_ptr::<PageTable>();
@@ -67,9 +85,6 @@ pub fn create_user_process(path: &str, args: &[&str]) -> Result<(ProcessId, Virt
         let loaded_program = {
             let mut mapper = unsafe { OffsetPageTable::new(l4_table, phys_mem_offset) };
             
-            // Get embedded program binary (TODO: Use VFS when implemented)
-            let program_data = include_bytes!("../../shell.bin");
-            
             // Try ELF loader first, fallback to legacy loader
             match crate::kernel::process::elf_impl::validate_elf(program_data) {
                 Ok(_) => {
@@ -87,104 +102,39 @@ pub fn create_user_process(path: &str, args: &[&str]) -> Result<(ProcessId, Virt
                     
                     let loaded = crate::kernel::process::elf_impl::load_elf(
                         program_data,
+                        file_frames.as_deref(),
                         &mut mapper,
                         frame_allocator,
+                        &layout,
                     ).map_err(|_| CreateError::PageTableCreationError("ELF load failed"))?;
                     
                     // Convert to LoadedProgram format
                     crate::kernel::loader::LoadedProgram {
                         entry_point: loaded.entry,
                         stack_top: loaded.stack_top,
+                        areas: loaded.areas,
                     }
                 },
                 Err(_) => {
                     crate::debug_println!("[create] Using legacy flat binary loader");
-                    load_user_program(program_data, &mut mapper, frame_allocator)?
+                    load_user_program(program_data, &mut mapper, frame_allocator, &layout)?
                 }
             }
         }; // mapper dropped here
+        drop(vfs);
         
         crate::debug_println!("[create_user_process] PML4 Entry 0 after load: {:?}", l4_table[0]);
         
-        // [PHASE 3 CRITICAL] Set USER_ACCESSIBLE on user page table hierarchy for 0x400000
-        // This is needed because the ELF loader uses existing kernel PDPT/PD entries
-        // which don't have USER_ACCESSIBLE flag
-        unsafe {
-            use x86_64::structures::paging::PageTableFlags;
-            
-            fn add_user_flag_to_entry(entry: &mut x86_64::structures::paging::page_table::PageTableEntry) {
-                if !entry.is_unused() {
-                    let old_flags = entry.flags();
-                    if !old_flags.contains(PageTableFlags::USER_ACCESSIBLE) {
-                        if let Ok(frame) = entry.frame() {
-                            let new_flags = old_flags | PageTableFlags::USER_ACCESSIBLE;
-                            entry.set_addr(frame.start_address(), new_flags);
-                        }
-                    }
-                }
-            }
-            
-            let phys_offset = VirtAddr::new(PHYS_MEM_OFFSET.load(core::sync::atomic::Ordering::Relaxed));
-            
-            crate::debug_println!("[USER PT FIX] Setting USER_ACCESSIBLE on user page table hierarchy...");
-            
-            // PML4[1] for user code (0x8000000000)
-            add_user_flag_to_entry(&mut l4_table[1]);
-            
-            // PDPT level
-            if let Ok(pdpt_frame) = l4_table[1].frame() {
-                let pdpt_ptr = (phys_offset + pdpt_frame.start_address().as_u64()).as_mut_ptr::<PageTable>();
-                let pdpt = &mut *pdpt_ptr;
-                add_user_flag_to_entry(&mut pdpt[0]);
-                
-                // PD level
-                if let Ok(pd_frame) = pdpt[0].frame() {
-                    let pd_ptr = (phys_offset + pd_frame.start_address().as_u64()).as_mut_ptr::<PageTable>();
-                    let pd = &mut *pd_ptr;
-                    add_user_flag_to_entry(&mut pd[0]); // 0x8000000000 is at start of PD
-                    
-                    // PT level
-                    if let Ok(pt_frame) = pd[0].frame() {
-                        let pt_ptr = (phys_offset + pt_frame.start_address().as_u64()).as_mut_ptr::<PageTable>();
-                        let pt = &mut *pt_ptr;
-                        // Map first few pages
-                        for i in 0..16 {
-                             add_user_flag_to_entry(&mut pt[i]);
-                        }
-                    }
-                }
-            }
-            
-            // PML4[223] for user stack (0x6ffffffff000)
-            add_user_flag_to_entry(&mut l4_table[223]);
-            
-            if let Ok(pdpt_frame) = l4_table[223].frame() {
-                let pdpt_ptr = (phys_offset + pdpt_frame.start_address().as_u64()).as_mut_ptr::<PageTable>();
-                let pdpt = &mut *pdpt_ptr;
-                add_user_flag_to_entry(&mut pdpt[511]);
-                
-                if let Ok(pd_frame) = pdpt[511].frame() {
-                    let pd_ptr = (phys_offset + pd_frame.start_address().as_u64()).as_mut_ptr::<PageTable>();
-                    let pd = &mut *pd_ptr;
-                    add_user_flag_to_entry(&mut pd[511]);
-                    
-                    if let Ok(pt_frame) = pd[511].frame() {
-                        let pt_ptr = (phys_offset + pt_frame.start_address().as_u64()).as_mut_ptr::<PageTable>();
-                        let pt = &mut *pt_ptr;
-                        // [CRITICAL FIX] Update ALL stack pages, not just PT[511]
-                        for idx in 496..=511 {
-                            if !pt[idx].is_unused() {
-                                add_user_flag_to_entry(&mut pt[idx]);
-                            }
-                        }
-                    }
-                }
+        stack_top = loaded_program.stack_top.as_u64();
+        
+        // Record the image and stack areas
+        for area in &loaded_program.areas {
+            if process.vmas_mut().insert(*area).is_err() {
+                crate::debug_println!("[create] Overlapping area {:#x}-{:#x} ignored", area.start, area.end);
             }
         }
-        
+
         // Update process entry point and stack
-        let stack_top = loaded_program.stack_top.as_u64();
-        
         // Setup arguments on stack
         // Stack layout (System V ABIish):
         // [ ... ]
@@ -301,148 +251,6 @@ pub fn create_user_process(path: &str, args: &[&str]) -> Result<(ProcessId, Virt
     let user_stack = VirtAddr::new(process.registers().rsp);
     let user_cr3 = process.page_table_phys_addr();
     
-    // [PHASE 3] Map user code to kernel page table (workaround for CR3 switching)
-    unsafe {
-        let kernel_cr3: u64;
-        core::arch::asm!("mov {}, cr3", out(reg) kernel_cr3, options(nomem, nostack));
-        
-        let phys_offset = crate::kernel::mm::PHYS_MEM_OFFSET.load(core::sync::atomic::Ordering::Relaxed);
-        let phys_mem_offset = x86_64::VirtAddr::new(phys_offset);
-        
-        let kernel_l4_ptr = (phys_mem_offset + kernel_cr3).as_mut_ptr::<PageTable>();
-        let kernel_l4 = &mut *kernel_l4_ptr;
-        
-        // Ensure PML4[0] has USER_ACCESSIBLE
-        if !kernel_l4[0].is_unused() {
-            let old_flags = kernel_l4[0].flags();
-            let new_flags = old_flags | PageTableFlags::USER_ACCESSIBLE;
-            let frame_addr = kernel_l4[0].addr();
-            kernel_l4[0].set_addr(frame_addr, new_flags);
-        }
-        
-        let mut kernel_mapper = OffsetPageTable::new(kernel_l4, phys_mem_offset);
-        
-        let user_l4_ptr = (phys_mem_offset + user_cr3).as_mut_ptr::<PageTable>();
-        let user_l4 = &mut *user_l4_ptr;
-        let user_mapper = OffsetPageTable::new(user_l4, phys_mem_offset);
-        
-        // Map user code pages
-        let user_code_start = x86_64::VirtAddr::new(0x8000000000);
-        let num_code_pages = 16;
-        
-        for i in 0..num_code_pages {
-            let virt = user_code_start + (i * 4096u64);
-            
-            match user_mapper.translate(virt) {
-                TranslateResult::Mapped { frame, .. } => {
-                    let frame_addr = frame.start_address();
-                    let page: Page = Page::containing_address(virt);
-                    let phys_frame = PhysFrame::<Size4KiB>::containing_address(frame_addr);
-                    let flags = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
-                    
-                    if let TranslateResult::Mapped { .. } = kernel_mapper.translate(virt) {
-                        // Already mapped
-                    } else {
-                        let _ = kernel_mapper.map_to(page, phys_frame, flags, frame_allocator)
-                            .map(|f| f.flush());
-                    }
-                }
-                _ => break,
-            }
-        }
-        
-        // Map user stack pages
-        use crate::kernel::mm::user_paging::{DEFAULT_USER_STACK_SIZE, USER_STACK_TOP};
-        let stack_top_for_mapping = x86_64::VirtAddr::new(USER_STACK_TOP);
-        let first_mapped_page = stack_top_for_mapping - 4096u64;
-        let user_stack_pages = DEFAULT_USER_STACK_SIZE / 4096;
-        
-        for i in 0..user_stack_pages {
-            let virt = first_mapped_page - (i as u64 * 4096u64);
-            
-            match user_mapper.translate(virt) {
-                TranslateResult::Mapped { frame, .. } => {
-                    let frame_addr = frame.start_address();
-                    let page: Page = Page::containing_address(virt);
-                    let phys_frame = PhysFrame::<Size4KiB>::containing_address(frame_addr);
-                    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
-                    
-                    if let TranslateResult::Mapped { .. } = kernel_mapper.translate(virt) {
-                        // Already mapped
-                    } else {
-                        let _ = kernel_mapper.map_to(page, phys_frame, flags, frame_allocator)
-                            .map(|f| f.flush());
-                    }
-                }
-                _ => break,
-            }
-        }
-        
-        // Helper function to add USER_ACCESSIBLE to page table entry
-        fn add_user_accessible_to_entry(entry: &mut x86_64::structures::paging::page_table::PageTableEntry) {
-            use x86_64::structures::paging::PageTableFlags;
-            if !entry.is_unused() {
-                let old_flags = entry.flags();
-                if !old_flags.contains(PageTableFlags::USER_ACCESSIBLE) {
-                    if let Ok(frame) = entry.frame() {
-                        let new_flags = old_flags | PageTableFlags::USER_ACCESSIBLE;
-                        entry.set_addr(frame.start_address(), new_flags);
-                    }
-                }
-            }
-        }
-        
-        // Update page table hierarchy for User code (0x400000)
-        let pml4_idx_code = 0usize;
-        let pdpt_idx_code = 0usize;
-        let pd_idx_code = 2usize;
-        
-        add_user_accessible_to_entry(&mut kernel_l4[pml4_idx_code]);
-        if let Ok(pdpt_frame) = kernel_l4[pml4_idx_code].frame() {
-            let pdpt_ptr = (phys_mem_offset + pdpt_frame.start_address().as_u64()).as_mut_ptr::<PageTable>();
-            let pdpt = &mut *pdpt_ptr;
-            add_user_accessible_to_entry(&mut pdpt[pdpt_idx_code]);
-            if let Ok(pd_frame) = pdpt[pdpt_idx_code].frame() {
-                let pd_ptr = (phys_mem_offset + pd_frame.start_address().as_u64()).as_mut_ptr::<PageTable>();
-                let pd = &mut *pd_ptr;
-                add_user_accessible_to_entry(&mut pd[pd_idx_code]);
-                if let Ok(pt_frame) = pd[pd_idx_code].frame() {
-                    let pt_ptr = (phys_mem_offset + pt_frame.start_address().as_u64()).as_mut_ptr::<PageTable>();
-                    let pt = &mut *pt_ptr;
-                    add_user_accessible_to_entry(&mut pt[0]);
-                }
-            }
-        }
-        
-        // Update page table hierarchy for User stack (0x6ffffffff000)
-        let pml4_idx_stack = 223usize;
-        let pdpt_idx_stack = 511usize;
-        let pd_idx_stack = 511usize;
-        
-        add_user_accessible_to_entry(&mut kernel_l4[pml4_idx_stack]);
-        if let Ok(pdpt_frame) = kernel_l4[pml4_idx_stack].frame() {
-            let pdpt_ptr = (phys_mem_offset + pdpt_frame.start_address().as_u64()).as_mut_ptr::<PageTable>();
-            let pdpt = &mut *pdpt_ptr;
-            add_user_accessible_to_entry(&mut pdpt[pdpt_idx_stack]);
-            if let Ok(pd_frame) = pdpt[pdpt_idx_stack].frame() {
-                let pd_ptr = (phys_mem_offset + pd_frame.start_address().as_u64()).as_mut_ptr::<PageTable>();
-                let pd = &mut *pd_ptr;
-                add_user_accessible_to_entry(&mut pd[pd_idx_stack]);
-                if let Ok(pt_frame) = pd[pd_idx_stack].frame() {
-                    let pt_ptr = (phys_mem_offset + pt_frame.start_address().as_u64()).as_mut_ptr::<PageTable>();
-                    let pt = &mut *pt_ptr;
-                    for idx in 496..=511 {
-                        if !pt[idx].is_unused() {
-                            add_user_accessible_to_entry(&mut pt[idx]);
-                        }
-                    }
-                }
-            }
-        }
-        
-        x86_64::instructions::tlb::flush_all();
-    }
-    
     // Initialize standard I/O capabilities (stdin=0, stdout=1, stderr=2)
     if let Err(e) = process.init_stdio_capabilities() {
         crate::debug_println!("[Process] Warning: Failed to init stdio capabilities: {:?}", e);
@@ -451,6 +259,14 @@ pub fn create_user_process(path: &str, args: &[&str]) -> Result<(ProcessId, Virt
     // 3. Add to process table
     {
         let mut table = PROCESS_TABLE.lock();
+        // The calling process (none for the initial process) becomes the parent
+        if let Some(parent) = table.current_process() {
+            process.set_parent_pid(parent.pid());
+            #[cfg(feature = "syscall_trace")]
+            if parent.trace_children() {
+                process.set_trace(Some(super::trace::ProcessTrace::new()));
+            }
+        }
         table.add_process(process);
     }
     
@@ -474,6 +290,10 @@ pub fn terminate_process(pid: ProcessId, exit_code: i32) {
         if let Some(process) = table.get_process_mut(pid) {
             process.set_state(ProcessState::Terminated);
             process.set_exit_code(exit_code);
+            #[cfg(feature = "syscall_trace")]
+            if let Some(trace) = process.trace() {
+                trace.push(crate::abi::trace::TraceKind::Exit, 0, [0; 6], i64::from(exit_code), 0);
+            }
             
             let parent_pid = process.parent_pid();
             
@@ -508,6 +328,31 @@ pub fn terminate_process(pid: ProcessId, exit_code: i32) {
     }
 }
 
+/// Terminate the current process after a fatal fault and switch away
+///
+/// Called from exception handlers for faults raised in user mode. If no
+/// other process is runnable, QEMU is exited with a failure status.
+pub fn kill_current(exit_code: i32) -> ! {
+    use crate::kernel::scheduler::SCHEDULER;
+
+    let pid = PROCESS_TABLE.lock().current_process().map(Process::pid);
+    if let Some(pid) = pid {
+        terminate_process(pid, exit_code);
+
+        let has_ready_process = SCHEDULER.lock().schedule().is_some();
+        if has_ready_process {
+            crate::kernel::process::schedule_next();
+        } else {
+            crate::debug_println!("[Process] No more processes to run, exiting QEMU");
+            crate::arch::qemu::exit_qemu(0x11);
+        }
+    }
+
+    loop {
+        <crate::arch::ArchCpu as crate::arch::Cpu>::halt();
+    }
+}
+
 /// Free resources associated with a terminated process
 ///
 /// This includes:
@@ -555,20 +400,10 @@ fn free_process_resources(process: &mut crate::kernel::process::Process) {
         }
     }
     
-    // 2. Free kernel stack
-    // Note: The kernel stack was allocated from the global allocator
-    let kernel_stack_top = process.kernel_stack;
-    let kernel_stack_size = 16 * 1024; // 16KB stack
-    let kernel_stack_bottom = kernel_stack_top.as_u64() - kernel_stack_size;
-    
-    unsafe {
-        use alloc::alloc::{dealloc, Layout};
-        if let Ok(kernel_stack_layout) = Layout::from_size_align(kernel_stack_size as usize, 16) {
-            dealloc(kernel_stack_bottom as *mut u8, kernel_stack_layout);
-        }
-    }
+    // The kernel stack is still in use here (we may be exiting on it); it
+    // is released by `Drop for Process` once the process is reaped
     
-    // 3. Clear capability table (closes all resources)
+    // 2. Clear capability table (closes all resources)
     process.capability_table().clear();
     
     crate::debug_println!("[Process] Freed resources for PID={}", process.pid().as_u64());
@@ -10,17 +10,26 @@ use x86_64::VirtAddr;
 use alloc::vec::Vec;
 use alloc::sync::Arc;
 use alloc::boxed::Box;
+use alloc::string::String;
 use alloc::alloc::{alloc_zeroed, Layout};
 use spin::{Mutex, Lazy};
 use crate::kernel::io_uring::IoUringContext;
 use crate::kernel::capability::table::CapabilityTable;
 use crate::arch::x86_64::syscall_ring::RingContext;
+use crate::kernel::mm::user_paging::UserLayout;
+use crate::kernel::mm::vma::{Vma, VmaKind, VmaTree};
+use crate::kernel::mm::pcid::{self, AddressSpaceTag};
+use crate::kernel::mm::{kstack, BootInfoFrameAllocator};
 
 pub mod lifecycle;
 pub mod switch;
 pub mod elf_loader;
 pub mod elf_impl;
 pub mod binary_reader;
+pub mod accounting;
+pub mod coredump;
+#[cfg(feature = "syscall_trace")]
+pub mod trace;
 
 pub use lifecycle::{create_user_process, terminate_process};
 pub use switch::switch_to_process;
@@ -116,13 +125,20 @@ pub struct Process {
     pid: ProcessId,
     state: ProcessState,
     page_table_frame: PhysFrame,
+    /// PCID assigned to the address space
+    address_space: AddressSpaceTag,
     kernel_stack: VirtAddr,
     user_stack: VirtAddr,
     saved_registers: RegisterState,
     context_rsp: u64,
     parent_pid: Option<ProcessId>,
     exit_code: Option<i32>,
-    mmap_top: VirtAddr,
+    /// User memory areas (mappings, stack, image)
+    vmas: VmaTree,
+    /// Placement of the stack, mmap window, ring context and PIE image
+    layout: UserLayout,
+    /// Personality flags, inherited by spawned children
+    personality: u64,
     fpu_state: FpuState,
     /// io_uring context for async I/O (optional, created on demand)
     io_uring_ctx: Option<Box<IoUringContext>>,
@@ -132,13 +148,22 @@ pub struct Process {
     ring_doorbell_kern_ptr: Option<u64>,
     /// Capability table for V2 resource management (Next-gen)
     capability_table: CapabilityTable,
+    /// Program name (basename of the executable path)
+    name: String,
+    /// CPU time and event counters
+    usage: accounting::ProcessUsage,
+    /// Syscall trace buffer, if this process is traced
+    #[cfg(feature = "syscall_trace")]
+    trace: Option<Arc<trace::ProcessTrace>>,
+    /// Whether children spawned from now on are traced
+    #[cfg(feature = "syscall_trace")]
+    trace_children: bool,
 }
 
 impl Drop for Process {
     fn drop(&mut self) {
         use crate::kernel::mm::allocator::BOOT_INFO_ALLOCATOR;
         use crate::kernel::mm::PHYS_MEM_OFFSET;
-        use alloc::alloc::{dealloc, Layout};
 
         let mut allocator_lock = BOOT_INFO_ALLOCATOR.lock();
         if let Some(frame_allocator) = allocator_lock.as_mut() {
@@ -150,15 +175,12 @@ impl Drop for Process {
                     frame_allocator,
                     phys_mem_offset
                 );
+                // SAFETY: A process is only dropped after it was reaped, so
+                // nothing runs on its kernel stack anymore
+                kstack::free(self.kernel_stack, frame_allocator);
             }
         }
         
-        let layout = Layout::from_size_align(16 * 1024, 16).unwrap();
-        unsafe {
-            let stack_ptr = (self.kernel_stack.as_u64() - 16 * 1024) as *mut u8;
-            dealloc(stack_ptr, layout);
-        }
-        
         // Clear capability table (drops all resources)
         self.capability_table.clear();
         
@@ -208,18 +230,27 @@ impl Process {
             pid,
             state: ProcessState::Ready,
             page_table_frame,
+            address_space: AddressSpaceTag::default(),
             kernel_stack,
             user_stack,
             saved_registers: registers,
             context_rsp: 0,
             parent_pid: None,
             exit_code: None,
-            mmap_top: VirtAddr::new(0x0000_6000_0000_0000),
+            vmas: VmaTree::new(),
+            layout: UserLayout::FIXED,
+            personality: 0,
             fpu_state: FpuState::default(),
             io_uring_ctx: None,
             ring_ctx: None,
             ring_doorbell_kern_ptr: None,
             capability_table: CapabilityTable::new(),
+            name: String::new(),
+            usage: accounting::ProcessUsage::new(),
+            #[cfg(feature = "syscall_trace")]
+            trace: None,
+            #[cfg(feature = "syscall_trace")]
+            trace_children: false,
         }
     }
     
@@ -276,9 +307,30 @@ impl Process {
 
     pub fn update_image(&mut self, page_table_frame: PhysFrame, user_stack: VirtAddr, _entry_point: VirtAddr) {
         self.page_table_frame = page_table_frame;
+        // The old PCID may still cache translations of the old page table
+        self.address_space = AddressSpaceTag::default();
         self.user_stack = user_stack;
     }
 
+    /// CR3 value that loads this address space
+    ///
+    /// Carries the address space's PCID and the no-flush bit when PCIDs
+    /// are enabled; otherwise it is the page table address alone.
+    pub fn cr3_value(&mut self) -> u64 {
+        pcid::cr3_value(self.page_table_frame, &mut self.address_space)
+    }
+
+    /// Load this process's page table into CR3
+    ///
+    /// # Safety
+    /// The caller must be running on a stack and code mapped in the kernel
+    /// half, which every address space shares.
+    pub unsafe fn activate_address_space(&mut self) {
+        // SAFETY: The page table was built by `create_user_page_table` and
+        // maps the kernel half; the caller guarantees the rest
+        unsafe { pcid::switch_to(self.page_table_frame, &mut self.address_space) };
+    }
+
     pub fn parent_pid(&self) -> Option<ProcessId> {
         self.parent_pid
     }
@@ -295,12 +347,89 @@ impl Process {
         self.exit_code = Some(code);
     }
 
-    pub fn mmap_top(&self) -> VirtAddr {
-        self.mmap_top
+    /// Program name (basename of the executable path)
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Set the program name
+    pub fn set_name(&mut self, name: &str) {
+        self.name = String::from(name);
     }
 
-    pub fn set_mmap_top(&mut self, addr: VirtAddr) {
-        self.mmap_top = addr;
+    /// CPU time and event counters
+    #[must_use]
+    pub const fn usage(&self) -> &accounting::ProcessUsage {
+        &self.usage
+    }
+
+    /// Syscall trace buffer, if this process is traced
+    #[cfg(feature = "syscall_trace")]
+    #[must_use]
+    pub fn trace(&self) -> Option<&Arc<trace::ProcessTrace>> {
+        self.trace.as_ref()
+    }
+
+    /// Start or stop tracing this process
+    ///
+    /// The buffer is shared with the io_uring context so that SQEs are
+    /// recorded as well.
+    #[cfg(feature = "syscall_trace")]
+    pub fn set_trace(&mut self, trace: Option<Arc<trace::ProcessTrace>>) {
+        if let Some(ctx) = self.io_uring_ctx.as_mut() {
+            ctx.set_trace(trace.clone());
+        }
+        self.trace = trace;
+    }
+
+    /// Whether children spawned from now on are traced
+    #[cfg(feature = "syscall_trace")]
+    #[must_use]
+    pub const fn trace_children(&self) -> bool {
+        self.trace_children
+    }
+
+    /// Set whether children spawned from now on are traced
+    #[cfg(feature = "syscall_trace")]
+    pub fn set_trace_children(&mut self, enabled: bool) {
+        self.trace_children = enabled;
+    }
+
+    /// User memory areas of this process
+    #[must_use]
+    pub fn vmas(&self) -> &VmaTree {
+        &self.vmas
+    }
+
+    /// Mutable access to the user memory areas
+    pub fn vmas_mut(&mut self) -> &mut VmaTree {
+        &mut self.vmas
+    }
+
+    /// Address-space layout of this process
+    #[must_use]
+    pub const fn layout(&self) -> &UserLayout {
+        &self.layout
+    }
+
+    /// Set the address-space layout
+    ///
+    /// Must be called before the program is loaded; the bases are not
+    /// moved once mapped.
+    pub fn set_layout(&mut self, layout: UserLayout) {
+        self.layout = layout;
+    }
+
+    /// Personality flags (`crate::abi::personality`)
+    #[must_use]
+    pub const fn personality(&self) -> u64 {
+        self.personality
+    }
+
+    /// Set the personality flags inherited by children spawned from now on
+    pub fn set_personality(&mut self, personality: u64) {
+        self.personality = personality;
     }
 
     /// Get mutable pointer to FPU state data for saving
@@ -331,6 +460,10 @@ impl Process {
         if self.io_uring_ctx.is_none() {
             let ctx = IoUringContext::new_with_allocator(allocator)?;
             self.io_uring_ctx = Some(Box::new(ctx));
+            #[cfg(feature = "syscall_trace")]
+            if let Some(ctx) = self.io_uring_ctx.as_mut() {
+                ctx.set_trace(self.trace.clone());
+            }
             crate::debug_println!("[Process] Created io_uring context for PID={}", self.pid.as_u64());
         }
         Some(self.io_uring_ctx.as_mut().unwrap())
@@ -347,15 +480,16 @@ impl Process {
         self.io_uring_ctx.as_mut().map(|b| &mut **b)
     }
     
-    /// Get mutable io_uring context and capability table reference simultaneously
+    /// Run `f` with the io_uring context detached from the process
     ///
-    /// This is needed for io_uring enter operations which need to modify the
-    /// context while also reading the capability table for I/O operations.
-    pub fn io_uring_with_capabilities(&mut self) -> Option<(&mut IoUringContext, &CapabilityTable)> {
-        match &mut self.io_uring_ctx {
-            Some(ctx) => Some((&mut **ctx, &self.capability_table)),
-            None => None,
-        }
+    /// The context is moved out while `f` runs, so handlers can borrow the
+    /// whole process mutably (e.g. to map memory for `OpCode::Mmap`) next to
+    /// the context. Returns `None` if io_uring is not set up.
+    pub fn with_io_uring<R>(&mut self, f: impl FnOnce(&mut IoUringContext, &mut Self) -> R) -> Option<R> {
+        let mut ctx = self.io_uring_ctx.take()?;
+        let result = f(&mut ctx, self);
+        self.io_uring_ctx = Some(ctx);
+        Some(result)
     }
     
     /// Check if io_uring is initialized
@@ -419,6 +553,19 @@ impl Process {
                 self.pid.as_u64(), enable_sqpoll);
         }
         
+        // The ring window must not collide with user mappings
+        let ring_base = self.layout.ring_base;
+        let ring_area = Vma::new(
+            ring_base,
+            ring_base + crate::kernel::mm::user_paging::USER_RING_CONTEXT_SIZE as u64,
+            crate::abi::mman::PROT_READ | crate::abi::mman::PROT_WRITE,
+            VmaKind::Ring,
+        );
+        let ring_registered = self.vmas.find(ring_area.start) == Some(&ring_area);
+        if !ring_registered && !self.vmas.is_free(ring_area.start, ring_area.end) {
+            return Err(-17); // EEXIST
+        }
+
         // 2. Get mapper for user page table
         let (l4_frame, _) = x86_64::registers::control::Cr3::read();
         let l4_ptr = (phys_offset + l4_frame.start_address().as_u64()).as_mut_ptr();
@@ -432,9 +579,9 @@ impl Process {
         let user_addr = unsafe {
             crate::arch::x86_64::syscall_ring::map_ring_to_user(
                 ctx,
+                ring_base,
                 &mut mapper,
                 frame_allocator,
-                phys_offset,
             )?
         };
         
@@ -447,7 +594,6 @@ impl Process {
         // Allocate and map the doorbell page for this ring (zero-syscall mode)
         // -----------------------------------------------------------------
         // use crate::kernel::io_uring::doorbell::manager as doorbell_manager;
-        use crate::kernel::mm::user_paging::USER_RING_CONTEXT_BASE;
         use x86_64::structures::paging::{Page, PageTableFlags, Mapper, PhysFrame, Size4KiB};
         use x86_64::VirtAddr as X64VirtAddr;
         use x86_64::PhysAddr as X64PhysAddr;
@@ -469,7 +615,7 @@ impl Process {
             }
         };
 
-        let user_doorbell_addr = USER_RING_CONTEXT_BASE + DOORBELL_OFFSET;
+        let user_doorbell_addr = ring_base + DOORBELL_OFFSET;
 
         // Map the kernel doorbell page into the user's address space
         let phys_addr = (kernel_doorbell_ptr as u64).wrapping_sub(phys_offset.as_u64());
@@ -494,6 +640,9 @@ impl Process {
 
         // Store kernel doorbell pointer for cleanup and for SQPOLL registration
         self.ring_doorbell_kern_ptr = Some(kernel_doorbell_ptr as u64);
+        if !ring_registered {
+            let _ = self.vmas.insert(ring_area);
+        }
 
         // Register ring with SQPOLL if requested
         if enable_sqpoll {
@@ -510,12 +659,13 @@ impl Process {
     
     /// Get the user-space address of the ring context
     ///
-    /// Returns the fixed user-space address where the ring context is mapped.
-    /// This can be used to pass the address to user programs.
+    /// Returns the user-space address where the ring context is mapped
+    /// ([`UserLayout::ring_base`]). This can be used to pass the address to
+    /// user programs.
     #[must_use]
     pub fn ring_user_address(&self) -> Option<u64> {
         if self.ring_ctx.is_some() {
-            Some(crate::kernel::mm::user_paging::USER_RING_CONTEXT_BASE)
+            Some(self.layout.ring_base)
         } else {
             None
         }
@@ -540,9 +690,13 @@ impl Process {
     
     /// Poll the ring buffer and process pending operations
     ///
+    /// `mapper` must map this process's address space, which the caller
+    /// keeps locked (`PROCESS_TABLE`).
+    ///
     /// Returns the number of completions generated.
-    pub fn ring_poll(&mut self) -> u32 {
-        self.ring_ctx.as_mut().map(|ctx| ctx.poll()).unwrap_or(0)
+    pub fn ring_poll(&mut self, mapper: &mut x86_64::structures::paging::OffsetPageTable<'_>) -> u32 {
+        let vmas = &self.vmas;
+        self.ring_ctx.as_mut().map(|ctx| ctx.poll(Some((vmas, mapper)))).unwrap_or(0)
     }
     
     /// Check if exit was requested via ring
@@ -665,6 +819,28 @@ impl Process {
         
         Ok(())
     }
+
+    /// Grant the system control capability at its well-known ID
+    ///
+    /// Only the initial process receives this; it allows `sys_reboot`.
+    /// Like stdio, it is inserted with generation=0 so userland can pass
+    /// [`crate::kernel::power::SYSTEM_CAP_ID`] directly.
+    pub fn grant_system_capability(&self) -> Result<(), crate::abi::error::SyscallError> {
+        use crate::kernel::capability::{ResourceKind, SystemResource};
+        use crate::kernel::power::SYSTEM_CAP_ID;
+
+        let handle = self.capability_table.insert_at_index::<SystemResource, _>(
+            SYSTEM_CAP_ID as u32,
+            Arc::new(SystemResource),
+            SystemResource::DEFAULT_RIGHTS,
+        )?;
+        crate::debug_println!(
+            "[Process] Registered system capability: index={}", handle.index()
+        );
+        core::mem::forget(handle);
+
+        Ok(())
+    }
 }
 
 /// Process table
@@ -719,6 +895,11 @@ impl ProcessTable {
         self.current_pid = Some(pid);
     }
     
+    /// All processes, including terminated ones not yet reaped
+    pub fn processes(&self) -> impl Iterator<Item = &Process> {
+        self.processes.iter()
+    }
+
     pub fn ready_processes(&self) -> impl Iterator<Item = &Process> {
         self.processes.iter().filter(|p| p.state() == ProcessState::Ready)
     }
@@ -729,6 +910,14 @@ impl ProcessTable {
             .map(|p| (p.pid(), p.exit_code().unwrap_or(0)))
     }
 
+    /// PIDs of all processes that have not terminated yet
+    pub fn live_pids(&self) -> Vec<ProcessId> {
+        self.processes.iter()
+            .filter(|p| p.state() != ProcessState::Terminated)
+            .map(Process::pid)
+            .collect()
+    }
+
     pub fn has_children(&self, parent_pid: ProcessId) -> bool {
         self.processes.iter().any(|p| p.parent_pid() == Some(parent_pid))
     }
@@ -743,11 +932,8 @@ impl ProcessTable {
 pub static PROCESS_TABLE: Lazy<Mutex<ProcessTable>> = Lazy::new(|| Mutex::new(ProcessTable::new()));
 
 const USER_STACK_SIZE: usize = 64 * 1024;
-const KERNEL_STACK_SIZE: usize = 16 * 1024;
 #[allow(dead_code)]
 const USER_STACK_TOP: u64 = 0x0000_7FFF_FFFF_F000;
-#[allow(dead_code)]
-const KERNEL_STACK_BASE: u64 = 0xFFFF_8000_0000_0000;
 
 fn allocate_user_stack() -> VirtAddr {
     let layout = Layout::from_size_align(USER_STACK_SIZE, 16)
@@ -757,14 +943,6 @@ fn allocate_user_stack() -> VirtAddr {
     VirtAddr::new(ptr as u64 + USER_STACK_SIZE as u64)
 }
 
-fn allocate_kernel_stack() -> VirtAddr {
-    let layout = Layout::from_size_align(KERNEL_STACK_SIZE, 16)
-        .expect("Invalid stack layout");
-    let ptr = unsafe { alloc_zeroed(layout) };
-    assert!(!ptr.is_null(), "Failed to allocate kernel stack");
-    VirtAddr::new(ptr as u64 + KERNEL_STACK_SIZE as u64)
-}
-
 fn create_user_page_table<A>(
     frame_allocator: &mut A,
     physical_memory_offset: VirtAddr,
@@ -785,32 +963,17 @@ where
     let kernel_pt_ptr = (physical_memory_offset + kernel_pt_frame.start_address().as_u64()).as_ptr::<PageTable>();
     let kernel_pt = unsafe { &*kernel_pt_ptr };
     
-    crate::debug_println!("[create_user_page_table] Scanning ALL kernel entries (0-511)...");
-    let mut count = 0;
-    for i in 0..512 {
-        if !kernel_pt[i].is_unused() {
-            crate::debug_println!("  Kernel entry {}: addr={:#x}, flags={:?}", 
-                i, kernel_pt[i].addr().as_u64(), kernel_pt[i].flags());
-            count += 1;
-        }
-    }
-    crate::debug_println!("[create_user_page_table] Found {} kernel entries", count);
-    
-    crate::debug_println!("[create_user_page_table] Copying kernel entries (0-511)");
+    // The kernel image is linked at KERNEL_BASE (linker.ld) and the
+    // bootloader's dynamic mappings start at the bottom of the upper half,
+    // so the kernel lives entirely in the upper half. The lower half is left
+    // empty so that user mappings get top-level entries of their own and
+    // never share (or add USER_ACCESSIBLE to) the kernel's tables
+    crate::debug_println!("[create_user_page_table] Copying kernel entries (256-511)");
     let mut copied_entries = 0;
-    // Include entry 0 for kernel code during iretq transition
-    for i in 0..512 {
+    for i in 256..512 {
         if !kernel_pt[i].is_unused() {
             page_table[i] = kernel_pt[i].clone();
             copied_entries += 1;
-            if i <= 6 || i == 511 {
-                crate::debug_println!(
-                    "  [COPY] Entry {}: {:#x} -> flags: {:?}",
-                    i,
-                    kernel_pt[i].addr().as_u64(),
-                    kernel_pt[i].flags()
-                );
-            }
         }
     }
     crate::debug_println!("[create_user_page_table] Copied {} entries", copied_entries);
@@ -828,20 +991,24 @@ where
     Ok(frame)
 }
 
-pub fn create_process<A>(
+pub fn create_process(
     entry_point: VirtAddr,
-    frame_allocator: &mut A,
+    frame_allocator: &mut BootInfoFrameAllocator,
     physical_memory_offset: VirtAddr,
-) -> Result<ProcessId, &'static str>
-where
-    A: FrameAllocator<Size4KiB>,
-{
+) -> Result<ProcessId, &'static str> {
     let mut table = PROCESS_TABLE.lock();
     let pid = table.allocate_pid();
     
     let page_table_frame = create_user_page_table(frame_allocator, physical_memory_offset)?;
     
-    let kernel_stack = allocate_kernel_stack();
+    let kernel_stack = match kstack::allocate(pid.as_u64(), frame_allocator) {
+        Ok(top) => top,
+        Err(e) => {
+            // SAFETY: The page table was created above and is not in use
+            unsafe { frame_allocator.deallocate_frame(page_table_frame) };
+            return Err(e);
+        }
+    };
     let user_stack = allocate_user_stack();
     
     let process = Process::new(pid, page_table_frame, kernel_stack, user_stack, entry_point);
@@ -850,14 +1017,11 @@ where
     Ok(pid)
 }
 
-pub fn create_process_with_context<A>(
+pub fn create_process_with_context(
     entry_point: VirtAddr,
-    frame_allocator: &mut A,
+    frame_allocator: &mut BootInfoFrameAllocator,
     physical_memory_offset: VirtAddr,
-) -> Result<Process, &'static str>
-where
-    A: FrameAllocator<Size4KiB>,
-{
+) -> Result<Process, &'static str> {
     let mut table = PROCESS_TABLE.lock();
     let pid = table.allocate_pid();
     
@@ -866,7 +1030,14 @@ where
     crate::debug_println!("[create_process] PID={}, page_table_frame={:#x}", 
         pid.as_u64(), page_table_frame.start_address().as_u64());
     
-    let kernel_stack = allocate_kernel_stack();
+    let kernel_stack = match kstack::allocate(pid.as_u64(), frame_allocator) {
+        Ok(top) => top,
+        Err(e) => {
+            // SAFETY: The page table was created above and is not in use
+            unsafe { frame_allocator.deallocate_frame(page_table_frame) };
+            return Err(e);
+        }
+    };
     let user_stack = allocate_user_stack();
     
     debug_assert!(
@@ -884,16 +1055,14 @@ where
 }
 
 #[allow(dead_code)]
-pub unsafe fn switch_to_single_process(process: &Process) {
-    use x86_64::registers::control::{Cr3, Cr3Flags};
-    let flags = Cr3Flags::empty();
+pub unsafe fn switch_to_single_process(process: &mut Process) {
     unsafe {
-        Cr3::write(process.page_table_frame(), flags);
+        process.activate_address_space();
     }
 }
 
 #[allow(dead_code)]
-pub unsafe fn jump_to_usermode_with_process(process: &Process) -> ! {
+pub unsafe fn jump_to_usermode_with_process(process: &mut Process) -> ! {
     unsafe {
         switch_to_single_process(process);
     }
@@ -901,7 +1070,7 @@ pub unsafe fn jump_to_usermode_with_process(process: &Process) -> ! {
     PROCESS_TABLE.lock().set_current(process.pid());
     
     let entry = VirtAddr::new(process.registers().rip);
-    let user_cr3 = process.page_table_phys_addr();
+    let user_cr3 = process.cr3_value();
     unsafe {
         jump_to_usermode(entry, process.user_stack(), user_cr3)
     }
@@ -921,10 +1090,20 @@ pub fn schedule_next() {
                 None
             } else {
                 let current = table.current_process_mut().expect("Current process invalid");
+                accounting::flush_into(current);
+                current.usage().add_context_switch();
                 let current_ctx_ptr = current.context_rsp_mut() as *mut u64;
                 
-                let next = table.get_process(next_pid).expect("Next process invalid");
+                let next = table.get_process_mut(next_pid).expect("Next process invalid");
                 let next_ctx_val = next.context_rsp();
+
+                // Interrupts and syscalls of the next process use its own
+                // kernel stack. Kernel stacks live in the shared kernel half,
+                // so the address space can change before the stack does
+                crate::arch::x86_64::tss::update_kernel_stack(next.kernel_stack());
+                crate::arch::x86_64::syscall::set_kernel_stack(next.kernel_stack());
+                // SAFETY: Running on a kernel stack in the shared kernel half
+                unsafe { next.activate_address_space() };
                 
                 table.set_current(next_pid);
                 
@@ -948,7 +1127,8 @@ pub unsafe fn jump_to_usermode(entry_point: VirtAddr, user_stack: VirtAddr, user
         crate::debug_println!("[ERROR] user_cr3 is NULL! Cannot switch page tables.");
         loop { x86_64::instructions::hlt(); }
     }
-    if user_cr3 & 0xFFF != 0 {
+    // The low 12 bits carry the PCID when PCIDs are enabled
+    if !pcid::is_enabled() && user_cr3 & 0xFFF != 0 {
         crate::debug_println!("[ERROR] user_cr3 not page-aligned: {:#x}", user_cr3);
         loop { x86_64::instructions::hlt(); }
     }
@@ -1018,6 +1198,7 @@ pub unsafe fn jump_to_usermode(entry_point: VirtAddr, user_stack: VirtAddr, user
     }
     
     crate::debug_println!("[jump_to_usermode] Using external NASM function with IRETQ");
+    accounting::return_to_user();
     unsafe {
         jump_to_usermode_asm(
             entry_point.as_u64(),
@@ -1054,6 +1235,7 @@ pub unsafe fn jump_to_usermode_with_ring(
         fn jump_to_usermode_asm(entry_point: u64, user_stack: u64, user_cr3: u64, rflags: u64, ring_context_addr: u64) -> !;
     }
     
+    accounting::return_to_user();
     unsafe {
         jump_to_usermode_asm(
             entry_point.as_u64(),
@@ -49,12 +49,12 @@ pub unsafe extern "C" fn switch_context_asm(prev_ctx: *mut u64, next_ctx: u64) {
 unsafe extern "C" fn process_entry_trampoline() -> ! {
     // 1. Get current process information
     let (entry_point, user_stack, user_cr3) = {
-        let table = crate::kernel::process::PROCESS_TABLE.lock();
-        let process = table.current_process().expect("[Trampoline] No current process");
+        let mut table = crate::kernel::process::PROCESS_TABLE.lock();
+        let process = table.current_process_mut().expect("[Trampoline] No current process");
         (
             x86_64::VirtAddr::new(process.registers().rip),
             x86_64::VirtAddr::new(process.registers().rsp),
-            process.page_table_phys_addr()
+            process.cr3_value()
         )
     }; // Lock released here
     
@@ -118,18 +118,19 @@ pub fn setup_process_context(process: &mut Process) {
 /// # Safety
 /// - Both processes must have valid page tables
 #[allow(dead_code)]
-pub unsafe fn context_switch(from: &mut Process, to: &Process) {
+pub unsafe fn context_switch(from: &mut Process, to: &mut Process) {
     // 1. Update TSS kernel stack for syscalls/interrupts
     crate::arch::x86_64::tss::update_kernel_stack(to.kernel_stack());
     
     // Also update legacy syscall stack for backward compatibility
     set_kernel_stack(to.kernel_stack());
     
-    // 2. Switch page table (if different)
-    let (current_frame, flags) = Cr3::read();
+    // 2. Switch page table (if different). With PCIDs the TLB entries of
+    // `from` survive the switch
+    let (current_frame, _) = Cr3::read();
     if current_frame != to.page_table_frame() {
         unsafe {
-            Cr3::write(to.page_table_frame(), flags);
+            to.activate_address_space();
         }
     }
     
@@ -170,16 +171,13 @@ pub unsafe fn context_switch(from: &mut Process, to: &Process) {
 ///
 /// This is a convenience wrapper that can be used when you have a single process
 /// to switch to, without needing a "from" process.
-pub fn switch_to_process(process: &Process) {
-    use x86_64::registers::control::{Cr3, Cr3Flags};
-    
+pub fn switch_to_process(process: &mut Process) {
     // Update kernel stack for syscalls
     crate::arch::x86_64::tss::update_kernel_stack(process.kernel_stack());
     set_kernel_stack(process.kernel_stack());
     
     // Switch page table
-    let flags = Cr3Flags::empty();
     unsafe {
-        Cr3::write(process.page_table_frame(), flags);
+        process.activate_address_space();
     }
 }
\ No newline at end of file
@@ -0,0 +1,159 @@
+// kernel/src/kernel/process/trace.rs
+//! Per-process syscall tracing (`syscall_trace` feature)
+//!
+//! A traced process owns a [`ProcessTrace`] buffer that records every
+//! syscall it makes, every io_uring SQE processed on its behalf, and its
+//! exit. Events use the raw [`TraceEvent`] ABI layout; decoding into names
+//! is left to user space (`strace`).
+//!
+//! ```text
+//! strace ──trace_ctl(self, TRACE_CHILDREN)──> kernel
+//!        ──spawn(prog)─────────────────────> child inherits a trace buffer
+//!        ──trace_read(child, buf)──────────> drains the child's events
+//! ```
+//!
+//! The buffer is shared through an `Arc` so that io_uring contexts can
+//! record SQEs without going back through the process table. When no
+//! process is traced, the syscall path only pays for one atomic load.
+
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use spin::Mutex;
+
+use crate::abi::io_uring_v2::{CompletionEntryV2, SubmissionEntryV2};
+use crate::abi::trace::{TraceEvent, TraceKind, TRACE_CAPACITY};
+use crate::arch::x86_64::read_timestamp;
+
+use super::PROCESS_TABLE;
+
+/// Number of live trace buffers
+static TRACED: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether any process is being traced
+#[inline]
+pub fn is_active() -> bool {
+    TRACED.load(Ordering::Relaxed) != 0
+}
+
+/// Bounded event buffer of a traced process
+pub struct ProcessTrace {
+    inner: Mutex<TraceRing>,
+}
+
+struct TraceRing {
+    next_seq: u64,
+    events: VecDeque<TraceEvent>,
+}
+
+impl ProcessTrace {
+    /// Create an empty trace buffer
+    #[must_use]
+    pub fn new() -> Arc<Self> {
+        TRACED.fetch_add(1, Ordering::Relaxed);
+        Arc::new(Self {
+            inner: Mutex::new(TraceRing {
+                next_seq: 0,
+                events: VecDeque::with_capacity(TRACE_CAPACITY),
+            }),
+        })
+    }
+
+    /// Append an event, dropping the oldest one if the buffer is full
+    pub fn push(&self, kind: TraceKind, number: u32, args: [u64; 6], result: i64, duration_tsc: u64) {
+        let mut ring = self.inner.lock();
+        if ring.events.len() == TRACE_CAPACITY {
+            ring.events.pop_front();
+        }
+        let seq = ring.next_seq;
+        ring.next_seq += 1;
+        ring.events.push_back(TraceEvent {
+            seq,
+            kind: kind as u32,
+            number,
+            args,
+            result,
+            duration_tsc,
+        });
+    }
+
+    /// Move up to `out.len()` of the oldest events into `out`
+    ///
+    /// Returns the number of events copied.
+    pub fn drain_into(&self, out: &mut [TraceEvent]) -> usize {
+        let mut ring = self.inner.lock();
+        let count = out.len().min(ring.events.len());
+        for (slot, event) in out.iter_mut().zip(ring.events.drain(..count)) {
+            *slot = event;
+        }
+        count
+    }
+}
+
+impl Drop for ProcessTrace {
+    fn drop(&mut self) {
+        TRACED.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Record a completed syscall of the current process
+///
+/// Called from the syscall entry path after dispatch, without
+/// `PROCESS_TABLE` held.
+pub fn record_syscall(number: u64, args: [u64; 6], result: i64, start_tsc: u64) {
+    if !is_active() {
+        return;
+    }
+    let duration = read_timestamp().saturating_sub(start_tsc);
+    let table = PROCESS_TABLE.lock();
+    if let Some(trace) = table.current_process().and_then(|p| p.trace()) {
+        trace.push(TraceKind::Syscall, number as u32, args, result, duration);
+    }
+}
+
+/// Record a processed io_uring submission
+pub fn record_sqe(trace: &ProcessTrace, sqe: &SubmissionEntryV2, cqe: &CompletionEntryV2, start_tsc: u64) {
+    let result = match cqe.result.into_result() {
+        Ok(value) => i64::from(value),
+        Err(e) => -i64::from(e.to_u32()),
+    };
+    let args = [
+        sqe.capability_id,
+        sqe.off,
+        u64::from(sqe.buf_index),
+        u64::from(sqe.len),
+        u64::from(sqe.op_flags),
+        sqe.user_data,
+    ];
+    let duration = read_timestamp().saturating_sub(start_tsc);
+    trace.push(TraceKind::Sqe, u32::from(sqe.opcode), args, result, duration);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn test_trace_ring_drops_oldest() {
+        let trace = ProcessTrace::new();
+        for i in 0..=TRACE_CAPACITY as u64 {
+            trace.push(TraceKind::Syscall, 3, [i, 0, 0, 0, 0, 0], 0, 0);
+        }
+
+        let mut out = [TraceEvent::default(); 2];
+        assert_eq!(trace.drain_into(&mut out), 2);
+        // Event 0 was dropped when the buffer overflowed
+        assert_eq!(out[0].seq, 1);
+        assert_eq!(out[1].args[0], 2);
+    }
+
+    #[test_case]
+    fn test_active_count_follows_buffers() {
+        let before = TRACED.load(Ordering::Relaxed);
+        let trace = ProcessTrace::new();
+        assert!(is_active());
+        drop(trace);
+        assert_eq!(TRACED.load(Ordering::Relaxed), before);
+    }
+}
@@ -247,7 +247,8 @@ pub fn kernel_idle() {
     }
     
     // No work - halt until next interrupt
-    x86_64::instructions::hlt();
+    // LAPIC タイマー使用時は次のタイマー期限までティック割り込みを止める (tickless idle)
+    crate::arch::x86_64::apic::idle_halt(crate::kernel::r#async::next_deadline());
 }
 
 /// Run async runtime until idle
@@ -11,29 +11,32 @@
 //! layers of validation:
 //!
 //! 1. **Address range validation** - Basic checks that pointers are in user space
-//! 2. **Page mapping validation** - Verifies pages are actually mapped
-//! 3. **Permission validation** - Checks read/write/execute permissions
-//! 4. **Size validation** - Prevents integer overflow and excessive allocations
+//! 2. **Size validation** - Prevents integer overflow and excessive allocations
+//! 3. **Fault-tolerant copies** - Copies user memory with faults turned into
+//!    errors instead of kernel panics
 //!
 //! # Usage
 //!
-//! System call handlers should validate all user-provided pointers:
+//! System call handlers access user memory only through the primitives in
+//! [`usercopy`], which validate the range and turn faults into `EFAULT`:
 //!
 //! ```rust,ignore
-//! pub fn sys_write(fd: u64, buf: u64, len: u64) -> SyscallResult {
-//!     // Validate user buffer
-//!     validate_user_read(buf, len)?;
-//!     
-//!     // Safe to use buffer
-//!     let slice = unsafe { core::slice::from_raw_parts(buf as *const u8, len as usize) };
+//! pub fn sys_getrusage(who: u64, usage_ptr: u64) -> SyscallResult {
+//!     let usage = current_usage();
+//!     if let Err(e) = write_user(usage_ptr, &usage) {
+//!         return e;
+//!     }
 //!     // ...
 //! }
 //! ```
+//!
+//! There is deliberately no "validate, then dereference" helper: a check
+//! made before the access does not survive a concurrent `munmap`.
+
+pub mod random;
+pub mod usercopy;
 
-use x86_64::structures::paging::{OffsetPageTable, PageTableFlags, Mapper, Page, Size4KiB, PageTable};
-use x86_64::{VirtAddr, registers::control::Cr3};
-use core::sync::atomic::Ordering;
-use crate::kernel::mm::PHYS_MEM_OFFSET;
+pub use usercopy::{copy_from_user, copy_to_user, read_user, strncpy_from_user, write_user};
 
 // Type alias for syscall results
 type SyscallResult<T> = Result<T, i64>;
@@ -107,289 +110,6 @@ pub fn is_user_range(addr: u64, len: u64) -> bool {
     is_user_address(addr) && is_user_address(end.saturating_sub(1))
 }
 
-/// Verify that a memory range is mapped with the required permissions
-///
-/// # Arguments
-/// * `ptr` - Start address
-/// * `len` - Length in bytes
-/// * `_check_read` - Whether to check read permission
-/// * `check_write` - Whether to check write permission
-///
-/// # Returns
-/// * `Ok(())` - All pages are mapped with required permissions
-/// * `Err(EFAULT)` - At least one page is not mapped or lacks permissions
-///
-/// # Safety
-///
-/// This function accesses page tables which requires proper synchronization.
-/// It should only be called with valid user-space addresses.
-fn verify_page_mapping(ptr: u64, len: u64, _check_read: bool, check_write: bool) -> SyscallResult<()> {
-    if len == 0 {
-        return Ok(());
-    }
-    
-    // Get physical memory offset
-    let phys_mem_offset = VirtAddr::new(PHYS_MEM_OFFSET.load(Ordering::Relaxed));
-    
-    // Get current page table
-    let (level_4_table_frame, _) = Cr3::read();
-    let phys = level_4_table_frame.start_address();
-    let virt = phys_mem_offset + phys.as_u64();
-    let page_table_ptr = virt.as_mut_ptr();
-    
-    // Safety: We're reading from the active page table pointed to by CR3
-    let level_4_table = unsafe { &mut *page_table_ptr };
-    let mapper = unsafe { OffsetPageTable::new(level_4_table, phys_mem_offset) };
-    
-    // Check each page in the range
-    let start_page = Page::<Size4KiB>::containing_address(VirtAddr::new(ptr));
-    let end_addr = ptr + len - 1;
-    let end_page = Page::<Size4KiB>::containing_address(VirtAddr::new(end_addr));
-    
-    let page_range = Page::range_inclusive(start_page, end_page);
-    
-    for page in page_range {
-        // Try to translate the page
-        match mapper.translate_page(page) {
-            Ok(_frame) => {
-                // Page is mapped, now check permissions
-                // We need to get the flags for this page
-                let flags = get_page_flags(&mapper, page)?;
-                
-                // Check if page is present and user-accessible
-                if !flags.contains(PageTableFlags::PRESENT) {
-                    return Err(EFAULT);
-                }
-                
-                if !flags.contains(PageTableFlags::USER_ACCESSIBLE) {
-                    return Err(EFAULT);
-                }
-                
-                // Check read permission (on x86_64, if page is present and user-accessible, it's readable)
-                // No additional check needed for read
-                
-                // Check write permission if required
-                if check_write && !flags.contains(PageTableFlags::WRITABLE) {
-                    return Err(EFAULT);
-                }
-            }
-            Err(_) => {
-                // Page is not mapped
-                return Err(EFAULT);
-            }
-        }
-    }
-    
-    Ok(())
-}
-
-/// Get page table flags for a specific page
-///
-/// # Arguments
-/// * `mapper` - Page table mapper
-/// * `page` - Page to get flags for
-///
-/// # Returns
-/// * `Ok(flags)` - Page table flags
-/// * `Err(EFAULT)` - Failed to get flags
-fn get_page_flags(_mapper: &OffsetPageTable, page: Page<Size4KiB>) -> SyscallResult<PageTableFlags> {
-    // Walk page table manually to get flags
-    let addr = page.start_address();
-    unsafe {
-        let (l4_table_frame, _) = Cr3::read();
-        let phys_mem_offset = VirtAddr::new(PHYS_MEM_OFFSET.load(Ordering::Relaxed));
-        let l4_table_ptr = (phys_mem_offset + l4_table_frame.start_address().as_u64()).as_mut_ptr();
-        let l4_table = &*(l4_table_ptr as *const PageTable);
-        
-        let l4_index = (addr.as_u64() >> 39) & 0o777;
-        let l3_index = (addr.as_u64() >> 30) & 0o777;
-        let l2_index = (addr.as_u64() >> 21) & 0o777;
-        let l1_index = (addr.as_u64() >> 12) & 0o777;
-        
-        let l4_entry = &l4_table[l4_index as usize];
-        if l4_entry.is_unused() {
-            return Err(EFAULT);
-        }
-        let l4_flags = l4_entry.flags();
-        
-        let l3_table_ptr = (phys_mem_offset + l4_entry.addr().as_u64()).as_ptr();
-        let l3_table = &*(l3_table_ptr as *const PageTable);
-        let l3_entry = &l3_table[l3_index as usize];
-        if l3_entry.is_unused() {
-            return Err(EFAULT);
-        }
-        let l3_flags = l3_entry.flags();
-        
-        let l2_table_ptr = (phys_mem_offset + l3_entry.addr().as_u64()).as_ptr();
-        let l2_table = &*(l2_table_ptr as *const PageTable);
-        let l2_entry = &l2_table[l2_index as usize];
-        if l2_entry.is_unused() {
-            return Err(EFAULT);
-        }
-        let l2_flags = l2_entry.flags();
-        
-        let l1_table_ptr = (phys_mem_offset + l2_entry.addr().as_u64()).as_ptr();
-        let l1_table = &*(l1_table_ptr as *const PageTable);
-        let l1_entry = &l1_table[l1_index as usize];
-        if l1_entry.is_unused() {
-            return Err(EFAULT);
-        }
-        let l1_flags = l1_entry.flags();
-        
-        // Combine flags from all levels (AND operation for restrictive flags)
-        let combined_flags = l4_flags & l3_flags & l2_flags & l1_flags;
-        Ok(combined_flags)
-    }
-}
-
-/// Validate that a user pointer can be read from
-///
-/// # Arguments
-/// * `ptr` - User-provided pointer
-/// * `len` - Number of bytes to read
-///
-/// # Returns
-/// * `Ok(())` - Pointer is valid for reading
-/// * `Err(EFAULT)` - Invalid pointer or not mapped
-/// * `Err(EINVAL)` - Invalid length
-///
-/// # Security
-///
-/// Checks:
-/// 1. Pointer is in user space
-/// 2. Range doesn't overflow
-/// 3. Verify pages are mapped
-/// 4. Verify pages have read permission
-pub fn validate_user_read(ptr: u64, len: u64) -> SyscallResult<()> {
-    // Check for zero-length (allowed, no-op)
-    if len == 0 {
-        return Ok(());
-    }
-    
-    // Check null pointer
-    if ptr == 0 {
-        return Err(EFAULT);
-    }
-    
-    // Check range is in user space
-    if !is_user_range(ptr, len) {
-        return Err(EFAULT);
-    }
-    
-    // Verify pages are actually mapped and have read permission
-    verify_page_mapping(ptr, len, true, false)?;
-    
-    Ok(())
-}
-
-/// Validate that a user pointer can be written to
-///
-/// # Arguments
-/// * `ptr` - User-provided pointer
-/// * `len` - Number of bytes to write
-///
-/// # Returns
-/// * `Ok(())` - Pointer is valid for writing
-/// * `Err(EFAULT)` - Invalid pointer or not mapped
-/// * `Err(EINVAL)` - Invalid length
-///
-/// # Security
-///
-/// Checks:
-/// 1. Pointer is in user space
-/// 2. Range doesn't overflow
-/// 3. Verify pages are mapped
-/// 4. Verify pages have write permission
-pub fn validate_user_write(ptr: u64, len: u64) -> SyscallResult<()> {
-    // Check for zero-length (allowed, no-op)
-    if len == 0 {
-        return Ok(());
-    }
-    
-    // Check null pointer
-    if ptr == 0 {
-        return Err(EFAULT);
-    }
-    
-    // Check range is in user space
-    if !is_user_range(ptr, len) {
-        return Err(EFAULT);
-    }
-    
-    // Verify pages are actually mapped and have write permission
-    verify_page_mapping(ptr, len, true, true)?;
-    
-    Ok(())
-}
-
-/// Validate a user string pointer
-///
-/// This validates that a null-terminated string is entirely in user space.
-///
-/// # Arguments
-/// * `ptr` - Pointer to null-terminated string
-/// * `max_len` - Maximum length to scan (prevents infinite loops)
-///
-/// # Returns
-/// * `Ok(len)` - String is valid, returns length (excluding null terminator)
-/// * `Err(EFAULT)` - Invalid pointer
-/// * `Err(EINVAL)` - String too long or not null-terminated
-///
-/// # Safety
-///
-/// This function must scan user memory to find the null terminator.
-/// It's inherently slower than fixed-length validation.
-pub fn validate_user_string(ptr: u64, max_len: usize) -> SyscallResult<usize> {
-    if ptr == 0 {
-        return Err(EFAULT);
-    }
-    
-    if !is_user_address(ptr) {
-        return Err(EFAULT);
-    }
-    
-    // Scan user memory for null terminator
-    let mut len = 0;
-    while len < max_len {
-        let current_ptr = ptr + len as u64;
-        
-        // Check if we can still read this byte
-        if !is_user_address(current_ptr) {
-            return Err(EFAULT);
-        }
-        
-        // Validate current byte is readable
-        validate_user_read(current_ptr, 1)?;
-        
-        // Read the byte
-        let byte = unsafe { *(current_ptr as *const u8) };
-        
-        // Check for null terminator
-        if byte == 0 {
-            return Ok(len);
-        }
-        
-        len += 1;
-    }
-    
-    // String too long or not null-terminated
-    Err(EINVAL)
-}
-
-/// Validate an array of user pointers
-///
-/// # Arguments
-/// * `ptr` - Pointer to array of pointers
-/// * `count` - Number of pointers in array
-///
-/// # Returns
-/// * `Ok(())` - All pointers are valid
-/// * `Err(EFAULT)` - Invalid pointer or array
-pub fn validate_user_pointer_array(ptr: u64, count: usize) -> SyscallResult<()> {
-    let len = count.checked_mul(8).ok_or(EINVAL)?; // 8 bytes per pointer on x86-64
-    validate_user_read(ptr, len as u64)
-}
-
 /// Maximum reasonable allocation size (1GB)
 ///
 /// This prevents:
@@ -0,0 +1,80 @@
+// kernel/src/kernel/security/random.rs
+//! Kernel entropy source
+//!
+//! Random numbers come from RDRAND when the CPU supports it. Without
+//! RDRAND, or when it keeps failing, the kernel falls back to mixing the
+//! TSC into a global state with the SplitMix64 finalizer. The fallback is
+//! only as unpredictable as the TSC and is meant for layout
+//! randomisation, not for cryptographic keys.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+use crate::arch::x86_64::{cpu_features, read_random, read_timestamp};
+
+/// RDRAND attempts before falling back (Intel recommends 10)
+const RDRAND_RETRIES: usize = 10;
+
+/// Golden-ratio increment of SplitMix64
+const SPLITMIX_GAMMA: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// State of the TSC fallback
+static FALLBACK_STATE: AtomicU64 = AtomicU64::new(0);
+
+/// Return a random 64-bit value
+#[must_use]
+pub fn random_u64() -> u64 {
+    if cpu_features::get().has_rdrand {
+        for _ in 0..RDRAND_RETRIES {
+            if let Some(value) = read_random() {
+                return value;
+            }
+        }
+    }
+    tsc_random()
+}
+
+/// Return a random value in `[0, bound)`
+///
+/// Returns 0 if `bound` is 0. The modulo bias is negligible for the
+/// bounds used by the kernel, which are far below 2^64.
+#[must_use]
+pub fn random_below(bound: u64) -> u64 {
+    if bound == 0 {
+        return 0;
+    }
+    random_u64() % bound
+}
+
+/// Fallback generator: mix the TSC into a shared SplitMix64 state
+///
+/// Every call advances the state, so two calls within the same TSC tick
+/// still return different values.
+fn tsc_random() -> u64 {
+    let seed = read_timestamp().wrapping_mul(SPLITMIX_GAMMA);
+    let state = FALLBACK_STATE.fetch_add(SPLITMIX_GAMMA, Ordering::Relaxed);
+    mix(state ^ seed)
+}
+
+/// SplitMix64 finalizer
+const fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_random_below_bound() {
+        for bound in [1, 2, 7, 4096] {
+            assert!(random_below(bound) < bound);
+        }
+        assert_eq!(random_below(0), 0);
+    }
+
+    #[test]
+    fn test_fallback_varies() {
+        assert_ne!(tsc_random(), tsc_random());
+    }
+}
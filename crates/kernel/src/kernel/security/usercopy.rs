@@ -0,0 +1,159 @@
+// kernel/src/kernel/security/usercopy.rs
+//! Copying data between the kernel and user space
+//!
+//! These are the only functions system calls should use to touch user
+//! memory. Each call checks that the range lies in user space and then
+//! copies with the routines in [`crate::arch::x86_64::usercopy`], whose
+//! faults are caught by the exception table in the page fault handler.
+//! A buffer that is unmapped, or unmapped concurrently by another thread,
+//! therefore yields `EFAULT` instead of a kernel panic, and no page-table
+//! walk is needed up front. With SMAP enabled they are also the only code
+//! that can touch user pages at all.
+//!
+//! # Demand paging
+//!
+//! Faults on untouched pages are resolved as usual, which needs
+//! `PROCESS_TABLE`. Copy before taking the table lock, or after dropping
+//! it: while the lock is held, any fault on a page that is not yet
+//! resident fails with `EFAULT`.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! pub fn sys_write(fd: u64, buf: u64, len: u64) -> SyscallResult {
+//!     let mut data = vec![0; len as usize];
+//!     copy_from_user(&mut data, buf)?;
+//!     // ...
+//! }
+//! ```
+
+use core::mem::{size_of, MaybeUninit};
+
+use super::{is_user_range, SyscallResult, EFAULT};
+use crate::arch::x86_64::usercopy::{copy_user, strncpy_user};
+
+/// Check that `len` bytes at `addr` lie in user space
+///
+/// Empty ranges are always accepted.
+fn check_range(addr: u64, len: usize) -> SyscallResult<()> {
+    if len == 0 || is_user_range(addr, len as u64) {
+        Ok(())
+    } else {
+        Err(EFAULT)
+    }
+}
+
+/// Copy `dst.len()` bytes from user address `src` into `dst`
+///
+/// # Returns
+/// * `Ok(())` - All bytes were copied
+/// * `Err(EFAULT)` - The range is not in user space or not readable.
+///   `dst` may have been partially written.
+pub fn copy_from_user(dst: &mut [u8], src: u64) -> SyscallResult<()> {
+    check_range(src, dst.len())?;
+    // SAFETY: dst is a valid kernel buffer and src was checked to be in
+    // user space
+    match unsafe { copy_user(dst.as_mut_ptr(), src as *const u8, dst.len()) } {
+        0 => Ok(()),
+        _ => Err(EFAULT),
+    }
+}
+
+/// Copy `src` to user address `dst`
+///
+/// # Returns
+/// * `Ok(())` - All bytes were copied
+/// * `Err(EFAULT)` - The range is not in user space or not writable.
+///   A prefix of the range may have been written.
+pub fn copy_to_user(dst: u64, src: &[u8]) -> SyscallResult<()> {
+    check_range(dst, src.len())?;
+    // SAFETY: src is a valid kernel buffer and dst was checked to be in
+    // user space
+    match unsafe { copy_user(dst as *mut u8, src.as_ptr(), src.len()) } {
+        0 => Ok(()),
+        _ => Err(EFAULT),
+    }
+}
+
+/// Copy a null-terminated string from user address `src` into `dst`
+///
+/// Copies up to `dst.len()` bytes, stopping after the terminator. The
+/// scan also stops at the end of user space.
+///
+/// # Returns
+/// * `Ok(len)` with `len < dst.len()` - The string is `dst[..len]` and
+///   `dst[len]` is the terminator
+/// * `Ok(dst.len())` - No terminator within `dst.len()` bytes
+/// * `Err(EFAULT)` - `src` is not in user space or not readable
+pub fn strncpy_from_user(dst: &mut [u8], src: u64) -> SyscallResult<usize> {
+    if dst.is_empty() {
+        return Ok(0);
+    }
+    check_range(src, 1)?;
+    let max = dst.len().min((super::address_space::USER_END - src) as usize);
+    // SAFETY: dst holds `max` bytes and `src..src + max` is in user space
+    let len = unsafe { strncpy_user(dst.as_mut_ptr(), src as *const u8, max) }.ok_or(EFAULT)?;
+    if len == max && max < dst.len() {
+        // Ran into the end of user space without a terminator
+        return Err(EFAULT);
+    }
+    Ok(len)
+}
+
+/// Read a value of type `T` from user address `src`
+///
+/// The address does not need to be aligned.
+///
+/// # Safety
+/// Every bit pattern must be a valid `T` (integers, arrays and `repr(C)`
+/// structures of them).
+pub unsafe fn read_user<T: Copy>(src: u64) -> SyscallResult<T> {
+    check_range(src, size_of::<T>())?;
+    let mut value = MaybeUninit::<T>::uninit();
+    // SAFETY: value has room for a T and src was checked to be in user space
+    let left = unsafe { copy_user(value.as_mut_ptr().cast(), src as *const u8, size_of::<T>()) };
+    if left != 0 {
+        return Err(EFAULT);
+    }
+    // SAFETY: All bytes were written and the caller guarantees that any
+    // bit pattern is a valid T
+    Ok(unsafe { value.assume_init() })
+}
+
+/// Write `value` to user address `dst`
+///
+/// The address does not need to be aligned.
+pub fn write_user<T: Copy>(dst: u64, value: &T) -> SyscallResult<()> {
+    check_range(dst, size_of::<T>())?;
+    // SAFETY: value is a valid T and dst was checked to be in user space
+    let left = unsafe { copy_user(dst as *mut u8, (value as *const T).cast(), size_of::<T>()) };
+    if left == 0 { Ok(()) } else { Err(EFAULT) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::security::address_space::USER_END;
+
+    #[test]
+    fn test_copy_rejects_kernel_addresses() {
+        let mut buf = [0u8; 8];
+        assert_eq!(copy_from_user(&mut buf, 0xFFFF_8000_0000_0000), Err(EFAULT));
+        assert_eq!(copy_to_user(USER_END - 4, &buf), Err(EFAULT));
+        assert_eq!(copy_from_user(&mut buf, u64::MAX - 3), Err(EFAULT));
+        assert_eq!(write_user(USER_END, &0u32), Err(EFAULT));
+    }
+
+    #[test]
+    fn test_empty_copies_succeed() {
+        assert_eq!(copy_from_user(&mut [], 0xFFFF_8000_0000_0000), Ok(()));
+        assert_eq!(copy_to_user(0, &[]), Ok(()));
+        assert_eq!(strncpy_from_user(&mut [], 0xFFFF_8000_0000_0000), Ok(0));
+    }
+
+    #[test]
+    fn test_strncpy_rejects_kernel_addresses() {
+        let mut buf = [0u8; 16];
+        assert_eq!(strncpy_from_user(&mut buf, USER_END), Err(EFAULT));
+    }
+}
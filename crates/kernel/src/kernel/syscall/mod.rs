@@ -32,13 +32,16 @@
 //!
 //! ## Pointer Validation
 //!
-//! User-provided pointers are **always** validated before use:
+//! User-provided pointers are never dereferenced directly. Handlers copy
+//! through [`crate::kernel::security::usercopy`]:
 //!
 //! - **Address range check**: Must be in user space (< 0x8000_0000_0000)
-//! - **Mapping check**: Verifies page is mapped (implemented via security module)
-//! - **Permission check**: Verifies page has correct permissions (implemented via security module)
+//! - **Fault recovery**: A page that is unmapped, or lacks the needed
+//!   permission, faults inside the copy routine and the page fault handler
+//!   resumes at its fixup
 //!
-//! Invalid pointers result in [`EFAULT`] error.
+//! Handlers copy while not holding `PROCESS_TABLE`, so that untouched pages
+//! can still be demand-paged. Invalid pointers result in [`EFAULT`] error.
 //!
 //! ## Argument Validation
 //!
@@ -55,6 +58,7 @@
 //! System calls enforce resource limits:
 //!
 //! - Maximum write size: 1MB ([`MAX_WRITE_LEN`])
+//! - Maximum read size: 1MB ([`MAX_READ_LEN`])
 //! - Process limits (TODO: Phase 4)
 //! - Memory limits (TODO: Phase 4)
 //!
@@ -114,11 +118,12 @@
 //! - `userland/libuser/src/syscall.rs` - User-space wrappers
 
 use alloc::boxed::Box;
+use alloc::vec::Vec;
 use crate::arch::Cpu;
 use crate::debug_println;
 
 use crate::kernel::core::traits::CharDevice;
-use crate::kernel::security::{validate_user_write, validate_user_read};
+use crate::kernel::security::{copy_from_user, copy_to_user, read_user, strncpy_from_user, write_user};
 
 // ============================================================================
 // Constants
@@ -132,12 +137,35 @@ use crate::kernel::security::{validate_user_write, validate_user_read};
 /// - Potential DoS attacks
 const MAX_WRITE_LEN: u64 = 1024 * 1024;
 
+/// Maximum length served by one sys_read (1MB)
+///
+/// Longer reads return at most this many bytes, which bounds the kernel
+/// buffer the data passes through.
+const MAX_READ_LEN: u64 = 1024 * 1024;
+
+/// Maximum length of a path or argument passed to sys_spawn
+const MAX_ARG_LEN: usize = 4096;
+
+/// Maximum number of arguments passed to sys_spawn
+const MAX_ARG_COUNT: u64 = 256;
+
 // ============================================================================
 // Security Utilities
 // ============================================================================
 
-// Note: Security validation functions are now in crate::kernel::security module
-// (is_user_address, is_user_range, validate_user_read, validate_user_write)
+// Note: User memory is accessed through crate::kernel::security::usercopy
+// (copy_from_user, copy_to_user, strncpy_from_user, read_user, write_user)
+
+/// Allocate a zeroed kernel buffer that user data passes through
+///
+/// Fails with ENOMEM instead of exhausting the kernel heap.
+fn bounce_buffer(len: u64) -> Result<Vec<u8>, SyscallResult> {
+    let len = usize::try_from(len).map_err(|_| ENOMEM)?;
+    let mut buf = Vec::new();
+    buf.try_reserve_exact(len).map_err(|_| ENOMEM)?;
+    buf.resize(len, 0);
+    Ok(buf)
+}
 
 // ============================================================================
 // Error Codes (Linux-compatible)
@@ -172,8 +200,14 @@ pub const ECHILD: SyscallResult = -10;
 pub const EAGAIN: SyscallResult = -11;
 /// Out of memory
 pub const ENOMEM: SyscallResult = -12;
+/// Permission denied
+pub const EACCES: SyscallResult = -13;
 /// Bad address (invalid pointer)
 pub const EFAULT: SyscallResult = -14;
+/// File exists
+pub const EEXIST: SyscallResult = -17;
+/// No such device
+pub const ENODEV: SyscallResult = -19;
 /// Invalid argument
 pub const EINVAL: SyscallResult = -22;
 /// Broken pipe
@@ -210,16 +244,16 @@ pub fn sys_write(fd: u64, buf: u64, len: u64, _arg4: u64, _arg5: u64, _arg6: u64
         return EINVAL;
     }
     
-    // Validate buffer is readable
-    if let Err(e) = validate_user_read(buf, len) {
+    // Copy the buffer in before taking the process table
+    let mut data = match bounce_buffer(len) {
+        Ok(data) => data,
+        Err(e) => return e,
+    };
+    if let Err(e) = copy_from_user(&mut data, buf) {
         debug_println!("[SYSCALL] sys_write: invalid buffer at 0x{:x}, len={}", buf, len);
         return e;
     }
     
-    let slice = unsafe {
-        core::slice::from_raw_parts(buf as *const u8, len as usize)
-    };
-    
     // Get VfsFile from capability table
     let table = PROCESS_TABLE.lock();
     let process = match table.current_process() {
@@ -244,7 +278,7 @@ pub fn sys_write(fd: u64, buf: u64, len: u64, _arg4: u64, _arg5: u64, _arg6: u64
         }
     };
     
-    let result = vfs_file.write(slice);
+    let result = vfs_file.write(&data);
     core::mem::forget(handle);
     
     match result {
@@ -270,51 +304,140 @@ pub fn sys_read(fd: u64, buf: u64, len: u64, _arg4: u64, _arg5: u64, _arg6: u64)
     use crate::kernel::capability::{FileResource, Handle, Rights};
     use crate::kernel::fs::VfsFile;
     use crate::kernel::process::PROCESS_TABLE;
+    use crate::kernel::security::is_user_range;
     
-    // Validate buffer is writable
-    if let Err(e) = validate_user_write(buf, len) {
-        return e;
+    // Reject buffers outside user space before consuming any input
+    let len = len.min(MAX_READ_LEN);
+    if len > 0 && !is_user_range(buf, len) {
+        return EFAULT;
     }
-    
-    let slice = unsafe {
-        core::slice::from_raw_parts_mut(buf as *mut u8, len as usize)
-    };
-    
-    // Get VfsFile from capability table
-    let table = PROCESS_TABLE.lock();
-    let process = match table.current_process() {
-        Some(p) => p,
-        None => return ESRCH,
-    };
-    
-    let handle: Handle<FileResource> = unsafe { Handle::from_raw(fd) };
-    let entry = match process.capability_table().get_with_rights(&handle, Rights::READ) {
-        Ok(e) => e,
-        Err(_) => {
-            core::mem::forget(handle);
-            return EBADF;
-        }
+    let mut data = match bounce_buffer(len) {
+        Ok(data) => data,
+        Err(e) => return e,
     };
     
-    let vfs_file = match entry.downcast::<VfsFile>() {
-        Some(vfs) => vfs,
-        None => {
-            core::mem::forget(handle);
-            return EBADF;
-        }
+    let result = {
+        // Get VfsFile from capability table
+        let table = PROCESS_TABLE.lock();
+        let process = match table.current_process() {
+            Some(p) => p,
+            None => return ESRCH,
+        };
+        
+        let handle: Handle<FileResource> = unsafe { Handle::from_raw(fd) };
+        let entry = match process.capability_table().get_with_rights(&handle, Rights::READ) {
+            Ok(e) => e,
+            Err(_) => {
+                core::mem::forget(handle);
+                return EBADF;
+            }
+        };
+        
+        let vfs_file = match entry.downcast::<VfsFile>() {
+            Some(vfs) => vfs,
+            None => {
+                core::mem::forget(handle);
+                return EBADF;
+            }
+        };
+        
+        let result = vfs_file.read(&mut data);
+        core::mem::forget(handle);
+        result
+        // table lock dropped here, so the copy below may demand-page
     };
     
-    let result = vfs_file.read(slice);
-    core::mem::forget(handle);
-    
     match result {
-        Ok(read) => read as SyscallResult,
+        Ok(read) => match copy_to_user(buf, &data[..read]) {
+            Ok(()) => read as SyscallResult,
+            Err(e) => e,
+        },
         Err(crate::kernel::fs::FileError::BrokenPipe) => 0, // EOF
         Err(crate::kernel::fs::FileError::WouldBlock) => EAGAIN,
         Err(_) => EIO,
     }
 }
 
+/// sys_open - Open a file by path and get a file capability
+///
+/// Arguments:
+/// - arg1: path pointer (absolute path, not NUL-terminated)
+/// - arg2: path length
+/// - arg3: requested rights (`RIGHT_*` bits)
+///
+/// Supported files:
+/// - `/proc/<pid>/stat`, `/proc/<pid>/maps` (read-only)
+/// - `/tmp/<name>` (read, write, map; created with `RIGHT_CREATE`)
+/// - any other path is looked up in the initrd (read, map, exec)
+///
+/// Returns:
+/// - Positive or zero: capability ID usable with read/write/mmap/close
+/// - Negative: ENOENT if the path does not exist, EACCES if the file
+///   system does not grant the requested rights, EMFILE if the capability
+///   table is full
+pub fn sys_open(path_ptr: u64, path_len: u64, rights: u64, _arg4: u64, _arg5: u64, _arg6: u64) -> SyscallResult {
+    use crate::kernel::capability::{FileResource, Rights};
+    use crate::kernel::fs::procfs::ProcFile;
+    use crate::kernel::fs::tmpfs::TMPFS;
+    use crate::kernel::fs::vfs::VFS;
+    use crate::kernel::fs::{VfsFile, VfsFileType};
+    use crate::kernel::process::PROCESS_TABLE;
+    use alloc::sync::Arc;
+
+    if path_len == 0 || path_len > MAX_ARG_LEN as u64 {
+        return EINVAL;
+    }
+    let mut path = alloc::vec![0u8; path_len as usize];
+    if let Err(e) = copy_from_user(&mut path, path_ptr) {
+        return e;
+    }
+    let Ok(path) = core::str::from_utf8(&path) else {
+        return EINVAL;
+    };
+
+    let shared = Rights::DUP | Rights::TRANSFER;
+    let rights = Rights(rights);
+    let tmp_name = path.strip_prefix("/tmp/");
+    let allowed = if path.starts_with("/proc/") {
+        Rights::READ | shared
+    } else if tmp_name.is_some() {
+        Rights::READ | Rights::WRITE | Rights::MAP | Rights::CREATE | shared
+    } else {
+        Rights::READ | Rights::MAP | Rights::EXEC | shared
+    };
+    // Checked first so that a refused open never creates a file
+    if !allowed.contains(rights) {
+        return EACCES;
+    }
+
+    // procfs snapshots the process table, so open it before taking the lock
+    let file = if path.starts_with("/proc/") {
+        ProcFile::open(path).map(|file| VfsFile::with_type(file, VfsFileType::Regular))
+    } else if let Some(name) = tmp_name {
+        TMPFS
+            .lock()
+            .open(name, rights.contains(Rights::CREATE))
+            .map(|file| VfsFile::with_type(file, VfsFileType::Regular))
+    } else {
+        VFS.lock().open(path).map(|file| VfsFile::from_boxed(file, VfsFileType::Regular))
+    };
+    let Some(file) = file else {
+        return ENOENT;
+    };
+
+    let mut table = PROCESS_TABLE.lock();
+    let Some(process) = table.current_process_mut() else {
+        return ESRCH;
+    };
+    match process
+        .capability_table_mut()
+        .insert::<FileResource, VfsFile>(Arc::new(file), rights)
+    {
+        Ok(handle) => handle.into_raw() as SyscallResult,
+        Err(_) => EMFILE,
+    }
+}
+
 /// sys_exit - Exit current process
 ///
 /// When the last process exits:
@@ -381,69 +504,48 @@ pub fn sys_getpid(_arg1: u64, _arg2: u64, _arg3: u64, _arg4: u64, _arg5: u64, _a
 /// - path_len: Length of path string
 /// - args_ptr: Pointer to array of string pointers (argv)
 /// - args_len: Number of arguments (argc)
+///
+/// The path and each argument are limited to [`MAX_ARG_LEN`] bytes and at
+/// most [`MAX_ARG_COUNT`] arguments are accepted (EINVAL otherwise).
 pub fn sys_spawn(path_ptr: u64, path_len: u64, args_ptr: u64, args_len: u64, _arg5: u64, _arg6: u64) -> SyscallResult {
-    use crate::kernel::security::validate_user_read;
-    use alloc::vec::Vec;
+    use alloc::string::String;
     
-    // Validate path
-    if let Err(e) = validate_user_read(path_ptr, path_len) {
+    // Copy path
+    if path_len > MAX_ARG_LEN as u64 || args_len > MAX_ARG_COUNT {
+        return EINVAL;
+    }
+    let mut path = alloc::vec![0u8; path_len as usize];
+    if let Err(e) = copy_from_user(&mut path, path_ptr) {
         return e;
     }
     
-    let path_slice = unsafe {
-        core::slice::from_raw_parts(path_ptr as *const u8, path_len as usize)
-    };
-    
-    let path_str = match core::str::from_utf8(path_slice) {
+    let path_str = match core::str::from_utf8(&path) {
         Ok(s) => s,
         Err(_) => return EINVAL,
     };
     
-    // Validate and read args
-    let mut args_vec = Vec::new();
-    if args_len > 0 {
-        // Validate args array (array of u64 pointers)
-        if let Err(e) = validate_user_read(args_ptr, args_len * 8) {
-            return e;
-        }
+    // Copy args (array of u64 pointers to null-terminated strings)
+    let mut args = Vec::new();
+    let mut arg_buf = alloc::vec![0u8; MAX_ARG_LEN];
+    for i in 0..args_len {
+        // SAFETY: Any bit pattern is a valid u64
+        let arg_ptr = match unsafe { read_user::<u64>(args_ptr.wrapping_add(i * 8)) } {
+            Ok(ptr) => ptr,
+            Err(e) => return e,
+        };
         
-        let args_ptrs = unsafe {
-            core::slice::from_raw_parts(args_ptr as *const u64, args_len as usize)
+        let len = match strncpy_from_user(&mut arg_buf, arg_ptr) {
+            Ok(len) if len < arg_buf.len() => len,
+            Ok(_) => return EINVAL, // Argument too long
+            Err(e) => return e,
         };
         
-        for &arg_ptr in args_ptrs {
-            // Scan for null terminator to determine length
-            let mut len = 0;
-            let max_len = 4096; // Reasonable limit for an argument
-            loop {
-                if len >= max_len {
-                    return EINVAL; // Argument too long
-                }
-                
-                // Check if address is valid before reading
-                if let Err(e) = validate_user_read(arg_ptr + len, 1) {
-                    return e;
-                }
-                
-                let b = unsafe { core::ptr::read((arg_ptr + len) as *const u8) };
-                if b == 0 {
-                    break;
-                }
-                len += 1;
-            }
-            
-            let arg_slice = unsafe {
-                core::slice::from_raw_parts(arg_ptr as *const u8, len as usize)
-            };
-            
-            let arg_str = match core::str::from_utf8(arg_slice) {
-                Ok(s) => s,
-                Err(_) => return EINVAL,
-            };
-            
-            args_vec.push(arg_str);
+        match core::str::from_utf8(&arg_buf[..len]) {
+            Ok(s) => args.push(String::from(s)),
+            Err(_) => return EINVAL,
         }
     }
+    let args_vec: Vec<&str> = args.iter().map(String::as_str).collect();
     
     match crate::kernel::process::lifecycle::spawn_process(path_str, &args_vec) {
         Ok(pid) => pid.as_u64() as SyscallResult,
@@ -465,26 +567,10 @@ pub fn sys_wait(_pid: u64, status_ptr: u64, _options: u64, _arg4: u64, _arg5: u6
             };
             
             if let Some((child_pid, exit_code)) = table.find_terminated_child(current_pid) {
-                // Found terminated child
-                
-                // Write exit code to user pointer if provided
-                if status_ptr != 0 {
-                    // Check validity of status_ptr (mapped and writable)
-                    if let Err(e) = validate_user_write(status_ptr, core::mem::size_of::<i32>() as u64) {
-                        debug_println!("[SYSCALL] sys_wait: invalid status_ptr 0x{:x}", status_ptr);
-                        return e;
-                    }
-                    
-                    // Safe to write exit code
-                    unsafe {
-                        *(status_ptr as *mut i32) = exit_code;
-                    }
-                }
-                
-                // Reap the child
+                // Found terminated child: reap it
                 table.remove_process(child_pid);
                 
-                Ok(child_pid.as_u64() as SyscallResult)
+                Ok((child_pid.as_u64() as SyscallResult, exit_code))
             } else if table.has_children(current_pid) {
                 // Has children but none terminated
                 // Block current process
@@ -499,7 +585,17 @@ pub fn sys_wait(_pid: u64, status_ptr: u64, _options: u64, _arg4: u64, _arg5: u6
         };
         
         match result {
-            Ok(pid) => return pid,
+            Ok((pid, exit_code)) => {
+                // Write exit code to user pointer if provided, after the
+                // table lock is dropped. The child stays reaped on failure
+                if status_ptr != 0 {
+                    if let Err(e) = write_user(status_ptr, &exit_code) {
+                        debug_println!("[SYSCALL] sys_wait: invalid status_ptr 0x{:x}", status_ptr);
+                        return e;
+                    }
+                }
+                return pid;
+            }
             Err(0) => {
                 // Block and switch
                 schedule_next();
@@ -510,250 +606,517 @@ pub fn sys_wait(_pid: u64, status_ptr: u64, _options: u64, _arg4: u64, _arg5: u6
     }
 }
 
+/// A frame allocation failed
+///
+/// Kept apart from other `ENOMEM` causes, such as running out of address
+/// space, because only this one can be helped by the OOM killer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct OutOfFrames;
+
+/// Run `f` until it stops running out of frames
+///
+/// `f` takes and drops `PROCESS_TABLE` and the frame allocator itself.
+/// After each [`OutOfFrames`] the reclaim-then-OOM-killer sequence of
+/// [`oom::out_of_memory`](crate::kernel::mm::oom::out_of_memory) runs; if
+/// it picks the calling process, the process is terminated and this does
+/// not return.
+fn retry_out_of_frames(reason: &str, mut f: impl FnMut() -> Result<SyscallResult, OutOfFrames>) -> SyscallResult {
+    use crate::kernel::mm::oom::{self, OomAction};
+    use crate::kernel::process::lifecycle;
+
+    loop {
+        match f() {
+            Ok(result) => return result,
+            Err(OutOfFrames) => {
+                if oom::out_of_memory(reason) == OomAction::KillCurrent {
+                    lifecycle::kill_current(oom::OOM_EXIT_CODE);
+                }
+            }
+        }
+    }
+}
+
 /// sys_mmap - Map memory
-pub fn sys_mmap(addr: u64, len: u64, _prot: u64, _flags: u64, _fd: u64, _offset: u64) -> SyscallResult {
+///
+/// Honours `PROT_*` with W^X enforced, and `MAP_FIXED` /
+/// `MAP_FIXED_NOREPLACE` placement. The mapping is recorded as a memory
+/// area of the process. Private anonymous mappings only reserve address
+/// space; their pages are zero-filled on first access by the page fault
+/// handler.
+///
+/// `MAP_HUGE` backs an anonymous mapping with 2 MiB pages where it covers
+/// aligned 2 MiB blocks; without a fixed address or usable hint such a
+/// mapping is placed on a 2 MiB boundary.
+///
+/// Without `MAP_ANONYMOUS`, `fd` is a shared memory or file capability
+/// and `offset` a page-aligned offset into the object (see
+/// [`crate::kernel::ipc::shmem`]). Initrd files map the ramdisk pages
+/// without copying and are never writable through a shared mapping;
+/// `/tmp` files map their own pages, so `MAP_SHARED` stores are seen by
+/// every other mapper and by `read` (see [`crate::kernel::fs::tmpfs`]).
+/// Files without pages, such as pipes, fail with `ENODEV`.
+///
+/// When no frame is left, memory is reclaimed or the OOM killer ends the
+/// largest process and the call is retried.
+pub fn sys_mmap(addr: u64, len: u64, prot: u64, flags: u64, fd: u64, offset: u64) -> SyscallResult {
+    use crate::kernel::mm::mmap;
     use crate::kernel::process::PROCESS_TABLE;
-    use crate::kernel::mm::allocator::BOOT_INFO_ALLOCATOR;
-    use crate::kernel::security::{is_user_address, is_user_range, validate_alloc_size};
 
-    // Validate length
-    if len == 0 {
-        return EINVAL;
-    }
-    
-    // Validate allocation size (prevent excessive allocations)
-    if let Err(e) = validate_alloc_size(len) {
-        debug_println!("[SYSCALL] sys_mmap: invalid allocation size {}", len);
-        return e;
-    }
-    
-    // If addr is specified (non-zero), validate it's in user space
-    if addr != 0 {
-        // Align length to page size for range check
-        let len_aligned = (len + 4095) & !4095;
-        
-        if !is_user_range(addr, len_aligned) {
-            debug_println!("[SYSCALL] sys_mmap: requested address 0x{:x} not in user space", addr);
-            return EFAULT;
+    let args = match MmapArgs::new(addr, len, prot, flags, fd, offset) {
+        Ok(args) => args,
+        Err(e) => return e,
+    };
+
+    retry_out_of_frames("mmap", || {
+        let mut table = PROCESS_TABLE.lock();
+        let Some(process) = table.current_process_mut() else {
+            return Ok(ESRCH);
+        };
+        // SAFETY: PROCESS_TABLE serializes page table updates of the current process
+        let mut mapper = unsafe { mmap::current_mapper() };
+        mmap_locked(process, &mut mapper, &args)
+    })
+}
+
+/// Arguments of [`sys_mmap`] that passed the checks not needing a process
+#[derive(Debug, Clone, Copy)]
+pub struct MmapArgs {
+    addr: u64,
+    /// Length rounded up to whole pages
+    len: u64,
+    prot: u64,
+    flags: u64,
+    fd: u64,
+    offset: u64,
+    page_flags: x86_64::structures::paging::PageTableFlags,
+}
+
+impl MmapArgs {
+    /// Check the size, flags, protection and fixed address of a request
+    ///
+    /// # Errors
+    /// `EINVAL` for bad sizes, flags or fixed addresses, `EACCES` for a
+    /// writable and executable protection.
+    pub fn new(addr: u64, len: u64, prot: u64, flags: u64, fd: u64, offset: u64) -> Result<Self, SyscallResult> {
+        use crate::abi::mman::{
+            MAP_ANONYMOUS, MAP_FIXED, MAP_FIXED_NOREPLACE, MAP_HUGE, MAP_MASK, MAP_PRIVATE, MAP_SHARED,
+            PROT_MASK,
+        };
+        use crate::kernel::mm::mmap;
+        use crate::kernel::security::validate_alloc_size;
+
+        // Validate allocation size (prevent excessive allocations)
+        if let Err(e) = validate_alloc_size(len) {
+            debug_println!("[SYSCALL] sys_mmap: invalid allocation size {}", len);
+            return Err(e);
         }
-        
-        // Fixed address mapping not supported yet
-        debug_println!("[SYSCALL] sys_mmap: fixed address mapping not supported yet");
-        return EINVAL;
+        if prot & !PROT_MASK != 0 || flags & !MAP_MASK != 0 {
+            return Err(EINVAL);
+        }
+        if flags & MAP_PRIVATE != 0 && flags & MAP_SHARED != 0 {
+            return Err(EINVAL);
+        }
+        // Only anonymous memory can be backed by huge pages
+        if flags & MAP_HUGE != 0 && flags & MAP_ANONYMOUS == 0 {
+            return Err(EINVAL);
+        }
+        let Some(page_flags) = mmap::prot_to_flags(prot) else {
+            debug_println!("[SYSCALL] sys_mmap: W^X violation (prot={:#x})", prot);
+            return Err(EACCES);
+        };
+
+        // Align length to page size
+        let len = (len + 4095) & !4095;
+        let fixed = flags & (MAP_FIXED | MAP_FIXED_NOREPLACE) != 0;
+        if fixed && (addr % 4096 != 0 || !is_mappable_range(addr, len)) {
+            debug_println!("[SYSCALL] sys_mmap: invalid fixed address 0x{:x}", addr);
+            return Err(EINVAL);
+        }
+
+        Ok(Self { addr, len, prot, flags, fd, offset, page_flags })
     }
-    
-    // Align length to page size
-    let len_aligned = (len + 4095) & !4095;
-    let num_pages = (len_aligned / 4096) as usize;
-    
-    let mut table = PROCESS_TABLE.lock();
-    let process = match table.current_process_mut() {
-        Some(p) => p,
-        None => return ESRCH,
+}
+
+/// [`sys_mmap`] on a process the caller has already locked
+///
+/// The caller holds `PROCESS_TABLE` and passes the mapper for `process`'s
+/// page table, which need not be the one loaded in CR3 (see
+/// [`crate::kernel::mm::mmap::mapper_for`]). Used by io_uring, whose
+/// handlers run with the process table held; running out of frames is
+/// reported as `ENOMEM`, since the OOM killer cannot run under the lock.
+pub fn do_mmap(
+    process: &mut crate::kernel::process::Process,
+    mapper: &mut x86_64::structures::paging::OffsetPageTable<'_>,
+    args: &MmapArgs,
+) -> SyscallResult {
+    mmap_locked(process, mapper, args).unwrap_or(ENOMEM)
+}
+
+/// [`do_mmap`] that tells running out of frames apart from other errors
+fn mmap_locked(
+    process: &mut crate::kernel::process::Process,
+    mapper: &mut x86_64::structures::paging::OffsetPageTable<'_>,
+    args: &MmapArgs,
+) -> Result<SyscallResult, OutOfFrames> {
+    use crate::abi::mman::{MAP_ANONYMOUS, MAP_FIXED, MAP_FIXED_NOREPLACE, MAP_HUGE, MAP_SHARED};
+    use crate::kernel::mm::allocator::BOOT_INFO_ALLOCATOR;
+    use crate::kernel::mm::mmap;
+    use crate::kernel::mm::vma::{Vma, VmaKind};
+    use x86_64::structures::paging::{Page, Size4KiB};
+
+    let MmapArgs { addr, len: len_aligned, prot, flags, fd, offset, page_flags } = *args;
+    let huge = flags & MAP_HUGE != 0;
+    let fixed = flags & (MAP_FIXED | MAP_FIXED_NOREPLACE) != 0;
+    let shared = flags & MAP_SHARED != 0;
+
+    // Resolve the backing object before touching the address space
+    let object = if flags & MAP_ANONYMOUS == 0 {
+        match mappable_object(process, fd, offset, len_aligned, prot, shared) {
+            Ok(object) => Some(object),
+            // Only file pages copied on first use take frames here
+            Err(ENOMEM) => return Err(OutOfFrames),
+            Err(e) => return Ok(e),
+        }
+    } else {
+        None
     };
-    
-    // Determine address
-    let start_addr = if addr == 0 {
-        process.mmap_top()
+    let layout = *process.layout();
+    let vmas = process.vmas_mut();
+
+    // Determine address: fixed, a usable hint, or the lowest free range
+    let hint = addr & !4095;
+    let start_addr = if fixed {
+        addr
+    } else if hint != 0
+        && is_mappable_range(hint, len_aligned)
+        && vmas.is_free(hint, hint + len_aligned)
+        && !vmas.touches_stack_guard(hint, hint + len_aligned)
+    {
+        hint
     } else {
-        // Fixed address request not supported yet for simplicity
-        return EINVAL;
+        let align = if huge { mmap::HUGE_PAGE_SIZE } else { 4096 };
+        match vmas.find_free_aligned(len_aligned, align, layout.mmap_base, layout.mmap_end) {
+            Some(start) => start,
+            None => return Ok(ENOMEM),
+        }
     };
-    
-    // Update mmap_top
-    let new_top = start_addr + len_aligned;
-    process.set_mmap_top(new_top);
-    
-    // Map memory
-    // We need to access the page table of the current process.
-    // But the page table is active (CR3).
-    // So we can just map into the current address space!
-    // But we need a mapper.
-    // We can create a temporary mapper using CR3.
-    
-    let phys_mem_offset = x86_64::VirtAddr::new(crate::kernel::mm::PHYS_MEM_OFFSET.load(core::sync::atomic::Ordering::Relaxed));
-    let (l4_frame, _) = x86_64::registers::control::Cr3::read();
-    let l4_table_ptr = (phys_mem_offset + l4_frame.start_address().as_u64()).as_mut_ptr();
-    let l4_table = unsafe { &mut *l4_table_ptr };
-    let mut mapper = unsafe { x86_64::structures::paging::OffsetPageTable::new(l4_table, phys_mem_offset) };
-    
+    let end_addr = start_addr + len_aligned;
+
+    if flags & MAP_FIXED_NOREPLACE != 0 && !vmas.is_free(start_addr, end_addr) {
+        return Ok(EEXIST);
+    }
+    // The gap below the stack must stay unmapped to catch overflows
+    if vmas.touches_stack_guard(start_addr, end_addr) {
+        return Ok(ENOMEM);
+    }
+
     let mut allocator_lock = BOOT_INFO_ALLOCATOR.lock();
     let frame_allocator = match allocator_lock.as_mut() {
         Some(alloc) => alloc,
-        None => return ENOMEM,
+        None => return Ok(ENOMEM),
     };
-    
-    // We use map_user_stack logic but for arbitrary range?
-    // map_user_stack allocates frames and maps them.
-    // But it assumes stack grows down?
-    // Let's check map_user_stack implementation.
-    // It maps [stack_bottom, stack_top).
-    // So we can use it, or write a loop here.
-    
-    use x86_64::structures::paging::{Page, PageTableFlags, Mapper, FrameAllocator, Size4KiB};
-    
-    let start_page = Page::<Size4KiB>::containing_address(start_addr);
-    let end_page = Page::<Size4KiB>::containing_address(start_addr + len_aligned);
-    let page_range = Page::range(start_page, end_page);
-    
-    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
-    
-    // Track allocated pages for rollback
-    // Since we don't have a Vec, we can't easily store them all if the count is large.
-    // However, we are mapping a contiguous range.
-    // If we fail at index i, we need to unmap pages 0 to i-1.
-    
-    for i in 0..num_pages {
-        let page = page_range.start + i as u64;
-        let frame = match frame_allocator.allocate_frame() {
-            Some(f) => f,
-            None => {
-                // Rollback: Unmap previously mapped pages
-                for j in 0..i {
-                    let page_to_unmap = page_range.start + j as u64;
-                    if let Ok((frame, _)) = mapper.unmap(page_to_unmap) {
-                        x86_64::instructions::tlb::flush(page_to_unmap.start_address());
-                        unsafe {
-                            frame_allocator.deallocate_frame(frame);
-                        }
-                    }
-                }
-                return ENOMEM;
-            }
-        };
-        
-        unsafe {
-            match mapper.map_to(page, frame, flags, frame_allocator) {
-                Ok(tlb) => tlb.flush(),
-                Err(_) => {
-                    // Rollback this frame and previous pages
-                    frame_allocator.deallocate_frame(frame);
-                    
-                    for j in 0..i {
-                        let page_to_unmap = page_range.start + j as u64;
-                        if let Ok((frame, _)) = mapper.unmap(page_to_unmap) {
-                            x86_64::instructions::tlb::flush(page_to_unmap.start_address());
-                            frame_allocator.deallocate_frame(frame);
-                        }
-                    }
-                    return ENOMEM;
-                }
+
+    // MAP_FIXED replaces whatever was there
+    match vmas.remove(start_addr, end_addr) {
+        Ok(replaced) => {
+            for area in replaced {
+                let start = Page::<Size4KiB>::containing_address(x86_64::VirtAddr::new(area.start));
+                mmap::unmap_range(mapper, frame_allocator, start, area.size() / 4096);
             }
         }
+        Err(_) => return Ok(EINVAL),
+    }
+
+    let area = match &object {
+        Some(object) => Vma::new(start_addr, end_addr, prot, object.kind).with_max_prot(object.max_prot),
+        None => Vma::new(start_addr, end_addr, prot, VmaKind::Anonymous).with_huge(huge),
+    }
+    .with_shared(shared);
+    if vmas.insert(area).is_err() {
+        return Ok(EEXIST);
     }
-    
-    // Zero the memory
-    // Newly allocated frames might contain garbage.
-    // Security risk! We should zero them.
-    // Since we just mapped them, we can write to them via the direct map.
-    
-    let phys_mem_offset = x86_64::VirtAddr::new(crate::kernel::mm::PHYS_MEM_OFFSET.load(core::sync::atomic::Ordering::Relaxed));
-    
-    // We need to iterate over the pages we just mapped and zero them.
-    // We can't easily get the frames again without walking the page table, 
-    // but we know the virtual addresses.
-    // However, we are in kernel mode. We can just write to the user address?
-    // No, SMAP might prevent it (if enabled).
-    // Safer to use the direct map.
-    
-    // Let's walk the range again and get the physical address.
-    // Or better, we should have zeroed them IN the allocation loop.
-    // But we didn't want to change the loop structure too much.
-    // Let's do a second pass for now.
-    
-    for page in page_range {
 
-        if let Ok(frame) = mapper.translate_page(page) {
-             let frame_ptr = (phys_mem_offset + frame.start_address().as_u64()).as_mut_ptr::<u8>();
-             unsafe {
-                 core::ptr::write_bytes(frame_ptr, 0, 4096);
-             }
+    // Private anonymous pages are only reserved and filled on first touch;
+    // shared ones and object pages are mapped now so every process mapping
+    // them sees the same frames
+    let start_page = Page::<Size4KiB>::containing_address(x86_64::VirtAddr::new(start_addr));
+    let page_count = len_aligned / 4096;
+    let mapped = match &object {
+        Some(object) => mmap::map_frames(mapper, frame_allocator, start_page, &object.frames, page_flags, !shared),
+        None if shared && huge => {
+            mmap::map_anonymous_huge(mapper, frame_allocator, start_page, page_count, page_flags)
+        }
+        None if shared => mmap::map_anonymous(mapper, frame_allocator, start_page, page_count, page_flags),
+        None => Ok(()),
+    };
+    match mapped {
+        Ok(()) => Ok(start_addr as SyscallResult),
+        Err(e) => {
+            let _ = vmas.remove(start_addr, end_addr);
+            match e {
+                mmap::MapError::OutOfMemory => Err(OutOfFrames),
+                mmap::MapError::AlreadyMapped => Ok(ENOMEM),
+            }
         }
     }
-    
-    start_addr.as_u64() as SyscallResult
 }
 
+/// Pages of a capability resolved for an `mmap`
+struct MappableObject {
+    /// Frames to map, in page order
+    frames: alloc::vec::Vec<x86_64::structures::paging::PhysFrame>,
+    /// `PROT_*` bits the mapping may ever have, from the capability rights
+    max_prot: u64,
+    /// Area kind recorded for the mapping
+    kind: crate::kernel::mm::vma::VmaKind,
+}
+
+/// Pages behind capability `handle`, checked for an `mmap` of `len` bytes
+/// at `offset` with `prot`
+///
+/// The frames stay alive while the mapping is set up: shared memory
+/// objects and opened files hold them, and the caller holds the process
+/// and thereby the capability.
+fn mappable_object(
+    process: &crate::kernel::process::Process,
+    handle: u64,
+    offset: u64,
+    len: u64,
+    prot: u64,
+    shared: bool,
+) -> Result<MappableObject, SyscallResult> {
+    use crate::abi::mman::{PROT_EXEC, PROT_READ, PROT_WRITE};
+    use crate::kernel::capability::{FileResource, ResourceKind, Rights, ShmemResource};
+    use crate::kernel::fs::{FileError, VfsFile};
+    use crate::kernel::ipc::shmem::ShmemObject;
+    use crate::kernel::mm::vma::VmaKind;
+
+    let entry = process.capability_table().get_raw(handle).map_err(|_| EBADF)?;
+    if entry.type_id != ShmemResource::TYPE_ID && entry.type_id != FileResource::TYPE_ID {
+        debug_println!("[SYSCALL] sys_mmap: capability type {} cannot be mapped", entry.type_id);
+        return Err(ENODEV);
+    }
+
+    let rights = entry.rights;
+    if !rights.contains(Rights::MAP | Rights::READ) {
+        return Err(EACCES);
+    }
+    let mut max_prot = PROT_READ;
+    if !shared || rights.contains(Rights::WRITE) {
+        max_prot |= PROT_WRITE;
+    }
+    if rights.contains(Rights::EXEC) {
+        max_prot |= PROT_EXEC;
+    }
+    if prot & !max_prot != 0 {
+        return Err(EACCES);
+    }
+    if offset % 4096 != 0 {
+        return Err(EINVAL);
+    }
+
+    if entry.type_id == FileResource::TYPE_ID {
+        let file = entry.resource.clone().downcast::<VfsFile>().map_err(|_| ENODEV)?;
+        let frames = file.pages(offset, len).map_err(|e| match e {
+            FileError::NotSupported => ENODEV,
+            FileError::OutOfMemory => ENOMEM,
+            _ => EINVAL,
+        })?;
+        return Ok(MappableObject { frames, max_prot, kind: VmaKind::File });
+    }
+
+    let object = entry.resource.clone().downcast::<ShmemObject>().map_err(|_| ENODEV)?;
+    if offset.checked_add(len).is_none_or(|end| end > object.size()) {
+        return Err(EINVAL);
+    }
+    let first = (offset / 4096) as usize;
+    let frames = object.frames()[first..first + (len / 4096) as usize].to_vec();
+    Ok(MappableObject { frames, max_prot, kind: VmaKind::Shmem })
+}
+
+/// Whether `[addr, addr + len)` may hold a fixed or hinted user mapping
+fn is_mappable_range(addr: u64, len: u64) -> bool {
+    use crate::abi::mman::MMAP_MIN_ADDR;
+    use crate::kernel::security::is_user_range;
 
+    addr >= MMAP_MIN_ADDR && is_user_range(addr, len)
+}
 
 /// sys_munmap - Unmap memory
+///
+/// Removes the memory areas in the range, splitting partially covered
+/// ones, and releases their pages. Unmapped parts of the range are
+/// ignored; kernel-shared areas cannot be unmapped (`EINVAL`). Huge pages
+/// cut by the range are split, which fails with `ENOMEM` when no frame is
+/// left for the new page table.
 pub fn sys_munmap(addr: u64, len: u64, _arg3: u64, _arg4: u64, _arg5: u64, _arg6: u64) -> SyscallResult {
-    use crate::kernel::security::is_user_range;
-    
-    // Validate length
-    if len == 0 {
+    use crate::kernel::mm::mmap;
+    use crate::kernel::process::PROCESS_TABLE;
+
+    let args = match MunmapArgs::new(addr, len) {
+        Ok(args) => args,
+        Err(e) => return e,
+    };
+
+    retry_out_of_frames("munmap", || {
+        let mut table = PROCESS_TABLE.lock();
+        let Some(process) = table.current_process_mut() else {
+            return Ok(ESRCH);
+        };
+        // SAFETY: PROCESS_TABLE serializes page table updates of the current process
+        let mut mapper = unsafe { mmap::current_mapper() };
+        munmap_locked(process, &mut mapper, &args)
+    })
+}
+
+/// Arguments of [`sys_munmap`] that passed the checks not needing a process
+#[derive(Debug, Clone, Copy)]
+pub struct MunmapArgs {
+    addr: u64,
+    /// Length rounded up to whole pages
+    len: u64,
+}
+
+impl MunmapArgs {
+    /// Check the alignment and user-space bounds of a range
+    ///
+    /// # Errors
+    /// `EINVAL` for an empty or unaligned range, `EFAULT` for a range
+    /// outside user space.
+    pub fn new(addr: u64, len: u64) -> Result<Self, SyscallResult> {
+        // Validate length and alignment
+        if len == 0 || addr % 4096 != 0 {
+            return Err(EINVAL);
+        }
+
+        // Check null pointer
+        if addr == 0 {
+            debug_println!("[SYSCALL] sys_munmap: null pointer");
+            return Err(EFAULT);
+        }
+
+        // Align length to page size
+        let len = (len + 4095) & !4095;
+
+        // Validate that address range is in user space
+        if !is_mappable_range(addr, len) {
+            debug_println!("[SYSCALL] sys_munmap: address 0x{:x} not in user space", addr);
+            return Err(EFAULT);
+        }
+        Ok(Self { addr, len })
+    }
+}
+
+/// [`sys_munmap`] on a process the caller has already locked
+///
+/// See [`do_mmap`] for the locking and mapper requirements.
+pub fn do_munmap(
+    process: &mut crate::kernel::process::Process,
+    mapper: &mut x86_64::structures::paging::OffsetPageTable<'_>,
+    args: &MunmapArgs,
+) -> SyscallResult {
+    munmap_locked(process, mapper, args).unwrap_or(ENOMEM)
+}
+
+/// [`do_munmap`] that tells running out of frames apart from other errors
+fn munmap_locked(
+    process: &mut crate::kernel::process::Process,
+    mapper: &mut x86_64::structures::paging::OffsetPageTable<'_>,
+    args: &MunmapArgs,
+) -> Result<SyscallResult, OutOfFrames> {
+    use crate::kernel::mm::allocator::BOOT_INFO_ALLOCATOR;
+    use crate::kernel::mm::mmap;
+    use x86_64::structures::paging::{Page, Size4KiB};
+
+    let MunmapArgs { addr, len: len_aligned } = *args;
+    if process.vmas().touches_kernel_owned(addr, addr + len_aligned) {
+        debug_println!("[SYSCALL] sys_munmap: range 0x{:x} is kernel-owned", addr);
+        return Ok(EINVAL);
+    }
+
+    let mut allocator_lock = BOOT_INFO_ALLOCATOR.lock();
+    let Some(frame_allocator) = allocator_lock.as_mut() else {
+        return Ok(ENOMEM);
+    };
+
+    // Huge pages cut by the range are split before anything is removed
+    let (start, end) = (x86_64::VirtAddr::new(addr), x86_64::VirtAddr::new(addr + len_aligned));
+    if mmap::split_huge_boundaries(mapper, frame_allocator, start, end).is_err() {
+        return Err(OutOfFrames);
+    }
+    let Ok(removed) = process.vmas_mut().remove(addr, addr + len_aligned) else {
+        return Ok(EINVAL);
+    };
+
+    for area in removed {
+        let start = Page::<Size4KiB>::containing_address(x86_64::VirtAddr::new(area.start));
+        mmap::unmap_range(mapper, frame_allocator, start, area.size() / 4096);
+    }
+
+    Ok(SUCCESS)
+}
+
+/// sys_mprotect - Change the protection of mapped memory
+///
+/// The whole range must be covered by memory areas (`ENOMEM` otherwise),
+/// and the new protection must not be both writable and executable
+/// (`EACCES`). Kernel-shared areas cannot be reprotected, and mapped
+/// objects cannot gain permissions their capability did not grant
+/// (`EACCES`).
+///
+/// Native ABI number `SyscallNumber::Mprotect` (0x0202).
+pub fn sys_mprotect(addr: u64, len: u64, prot: u64, _arg4: u64, _arg5: u64, _arg6: u64) -> SyscallResult {
+    use crate::abi::mman::PROT_MASK;
+    use crate::kernel::mm::allocator::BOOT_INFO_ALLOCATOR;
+    use crate::kernel::mm::mmap;
+    use crate::kernel::mm::vma::VmaError;
+    use crate::kernel::process::PROCESS_TABLE;
+    use x86_64::structures::paging::{Page, Size4KiB};
+
+    if addr % 4096 != 0 || prot & !PROT_MASK != 0 {
         return EINVAL;
     }
-    
-    // Check null pointer
-    if addr == 0 {
-        debug_println!("[SYSCALL] sys_munmap: null pointer");
-        return EFAULT;
+    if len == 0 {
+        return SUCCESS;
     }
-    
-    // Align length to page size for validation
+    let Some(page_flags) = mmap::prot_to_flags(prot) else {
+        debug_println!("[SYSCALL] sys_mprotect: W^X violation (prot={:#x})", prot);
+        return EACCES;
+    };
+
     let len_aligned = (len + 4095) & !4095;
-    
-    // Validate that address range is in user space
-    if !is_user_range(addr, len_aligned) {
-        debug_println!("[SYSCALL] sys_munmap: address 0x{:x} not in user space", addr);
-        return EFAULT;
+    if !is_mappable_range(addr, len_aligned) {
+        return ENOMEM;
     }
-    
-    // Align length
-    let len_aligned = (len + 4095) & !4095;
-    
-    // We need to unmap pages.
-    // Access mapper via CR3.
-    let phys_mem_offset = x86_64::VirtAddr::new(crate::kernel::mm::PHYS_MEM_OFFSET.load(core::sync::atomic::Ordering::Relaxed));
-    let (l4_frame, _) = x86_64::registers::control::Cr3::read();
-    let l4_table_ptr = (phys_mem_offset + l4_frame.start_address().as_u64()).as_mut_ptr();
-    let l4_table = unsafe { &mut *l4_table_ptr };
-    let mut mapper = unsafe { x86_64::structures::paging::OffsetPageTable::new(l4_table, phys_mem_offset) };
-    
-    use x86_64::structures::paging::{Page, Mapper, Size4KiB};
-    
-    let start_addr = x86_64::VirtAddr::new(addr);
-    let start_page = Page::<Size4KiB>::containing_address(start_addr);
-    let end_page = Page::<Size4KiB>::containing_address(start_addr + len_aligned);
-    let page_range = Page::range(start_page, end_page);
-    
-    // First pass: validate all pages are mapped
-    // This provides better error reporting (POSIX compliance)
-    let mut unmapped_found = false;
-    for page in page_range.clone() {
-        if mapper.translate_page(page).is_err() {
-            debug_println!("[SYSCALL] sys_munmap: page 0x{:x} is not mapped", page.start_address().as_u64());
-            unmapped_found = true;
+
+    retry_out_of_frames("mprotect", || {
+        let mut table = PROCESS_TABLE.lock();
+        let Some(process) = table.current_process_mut() else {
+            return Ok(ESRCH);
+        };
+
+        // SAFETY: PROCESS_TABLE serializes page table updates of the current process
+        let mut mapper = unsafe { mmap::current_mapper() };
+        let mut allocator_lock = BOOT_INFO_ALLOCATOR.lock();
+        let Some(frame_allocator) = allocator_lock.as_mut() else {
+            return Ok(ENOMEM);
+        };
+
+        // Huge pages cut by the range are split before anything changes
+        let (start, end) = (x86_64::VirtAddr::new(addr), x86_64::VirtAddr::new(addr + len_aligned));
+        if mmap::split_huge_boundaries(&mut mapper, frame_allocator, start, end).is_err() {
+            return Err(OutOfFrames);
         }
-    }
-    
-    // If any pages are unmapped, we could either:
-    // 1. Return error (strict POSIX) - uncomment the line below
-    // 2. Continue and unmap only mapped pages (lenient)
-    // Currently using lenient approach for compatibility
-    // if unmapped_found {
-    //     return EINVAL;
-    // }
-    
-    // Second pass: unmap pages
-    for page in page_range {
-        // Unmap
-        if let Ok((frame, _flags)) = mapper.unmap(page) {
-            // Flush TLB
-            x86_64::instructions::tlb::flush(page.start_address());
-            
-            // Free the physical frame
-            unsafe {
-                let mut allocator_lock = crate::kernel::mm::allocator::BOOT_INFO_ALLOCATOR.lock();
-                if let Some(frame_allocator) = allocator_lock.as_mut() {
-                    frame_allocator.deallocate_frame(frame);
-                }
-            }
+        match process.vmas_mut().protect(addr, addr + len_aligned, prot) {
+            Ok(()) => {}
+            Err(VmaError::NotMapped) => return Ok(ENOMEM),
+            Err(_) => return Ok(EACCES),
         }
-    }
-    
-    SUCCESS
+
+        let start_page = Page::<Size4KiB>::containing_address(x86_64::VirtAddr::new(addr));
+        mmap::protect_range(&mut mapper, frame_allocator, start_page, len_aligned / 4096, page_flags);
+        Ok(SUCCESS)
+    })
 }
 
 // ============================================================================
@@ -886,17 +1249,18 @@ pub fn sys_io_uring_enter(
         None => return ESRCH,
     };
     
-    // Get io_uring context and capability table simultaneously
-    let (ctx, cap_table) = match process.io_uring_with_capabilities() {
-        Some(pair) => pair,
-        None => {
-            debug_println!("[SYSCALL] io_uring_enter: io_uring not set up");
-            return EINVAL;
-        }
+    // Process submissions and completions with the context detached, so
+    // handlers can change the process (e.g. its mappings)
+    let entered = process.with_io_uring(|ctx, process| {
+        let submitted_before = ctx.stats().submissions_total;
+        let completed = ctx.enter(min_complete as u32, process);
+        (completed, ctx.stats().submissions_total - submitted_before)
+    });
+    let Some((completed, submitted)) = entered else {
+        debug_println!("[SYSCALL] io_uring_enter: io_uring not set up");
+        return EINVAL;
     };
-    
-    // Process submissions and completions
-    let completed = ctx.enter(min_complete as u32, cap_table);
+    process.usage().add_io_uring_sqes(submitted);
     
     debug_println!(
         "[SYSCALL] io_uring_enter: to_submit={}, min_complete={}, completed={}",
@@ -906,29 +1270,66 @@ pub fn sys_io_uring_enter(
     completed as SyscallResult
 }
 
-/// sys_io_uring_register - Register resources with io_uring
+/// sys_io_uring_register - Register resources with io_uring (ID: 2007)
 ///
-/// Used to register buffers or file descriptors for zero-copy operations.
+/// Used to register buffers for zero-copy operations. Registration faults
+/// the buffers in and backs their aligned 2 MiB blocks with huge pages.
 ///
 /// # Arguments
-/// * `fd` - io_uring file descriptor
-/// * `opcode` - Registration operation
-/// * `arg` - Operation-specific argument
+/// * `fd` - io_uring file descriptor (ignored, we use process context)
+/// * `opcode` - Registration operation (`register_op::*`)
+/// * `arg` - Operation-specific argument (iovec array for buffers)
 /// * `nr_args` - Number of arguments
 ///
 /// # Returns
-/// * Success: 0
-/// * Error: ENOSYS (not implemented)
+/// * Success: index of the first buffer for `REGISTER_BUFFERS`, else 0
+/// * Error: EINVAL if io_uring is not set up or the opcode is unknown,
+///   EFAULT for inaccessible buffers
 pub fn sys_io_uring_register(
     _fd: u64,
-    _opcode: u64,
-    _arg: u64,
-    _nr_args: u64,
+    opcode: u64,
+    arg: u64,
+    nr_args: u64,
     _arg5: u64,
     _arg6: u64,
 ) -> SyscallResult {
-    debug_println!("[SYSCALL] io_uring_register: not implemented yet");
-    ENOSYS
+    use crate::abi::io_uring_common::register_op;
+    use crate::kernel::io_uring::registered_buffers::read_iovecs;
+    use crate::kernel::mm::mmap;
+    use crate::kernel::process::PROCESS_TABLE;
+
+    // Copy the iovecs before taking the table lock
+    let iovecs = match opcode {
+        register_op::REGISTER_BUFFERS => match read_iovecs(arg, nr_args as usize) {
+            Ok(iovecs) => iovecs,
+            Err(e) => return e,
+        },
+        register_op::UNREGISTER_BUFFERS => Vec::new(),
+        _ => return EINVAL,
+    };
+
+    let mut table = PROCESS_TABLE.lock();
+    let Some(process) = table.current_process_mut() else {
+        return ESRCH;
+    };
+    // SAFETY: PROCESS_TABLE serializes page table updates of the current process
+    let mut mapper = unsafe { mmap::current_mapper() };
+    let result = process.with_io_uring(|ctx, process| {
+        if opcode == register_op::REGISTER_BUFFERS {
+            ctx.register_buffers(&iovecs, process.vmas(), &mut mapper)
+                .map(SyscallResult::from)
+        } else {
+            ctx.unregister_all_buffers().map(|()| 0)
+        }
+    });
+    match result {
+        Some(Ok(value)) => value,
+        Some(Err(e)) => e,
+        None => {
+            debug_println!("[SYSCALL] io_uring_register: io_uring not set up");
+            EINVAL
+        }
+    }
 }
 
 // ============================================================================
@@ -944,13 +1345,24 @@ pub fn sys_io_uring_register(
 /// * 1 - Read timestamp (rdtsc)
 /// * 2 - Memory fence
 /// * 3 - Check shared ring
+/// * 4 - Reload the address space the way a context switch does
+/// * 5 - Reload the address space and flush its TLB entries, as a context
+///   switch did before PCIDs
+///
+/// Modes 4 and 5 let user space measure what a context switch costs in
+/// TLB refills: touching a working set after mode 4 hits the TLB when
+/// PCIDs are enabled, after mode 5 it never does.
 ///
 /// # Returns
 /// * Mode 0: 0
 /// * Mode 1: Current timestamp
 /// * Mode 2: 0
 /// * Mode 3: Number of pending operations
+/// * Modes 4, 5: PCID of the address space (0 when PCIDs are disabled)
 pub fn sys_benchmark(mode: u64, _arg2: u64, _arg3: u64, _arg4: u64, _arg5: u64, _arg6: u64) -> SyscallResult {
+    use crate::kernel::mm::pcid;
+    use crate::kernel::process::PROCESS_TABLE;
+
     match mode {
         0 => 0, // Minimal - just return
         1 => crate::arch::x86_64::cpu::read_timestamp() as SyscallResult,
@@ -959,6 +1371,18 @@ pub fn sys_benchmark(mode: u64, _arg2: u64, _arg3: u64, _arg4: u64, _arg5: u64,
             0
         }
         3 => 0, // Ring check (placeholder)
+        4 | 5 => {
+            let mut table = PROCESS_TABLE.lock();
+            let Some(process) = table.current_process_mut() else {
+                return ESRCH;
+            };
+            // SAFETY: Syscalls run on the kernel stack, mapped in every address space
+            unsafe { process.activate_address_space() };
+            if mode == 5 {
+                pcid::flush_address_space();
+            }
+            SyscallResult::from(pcid::current_pcid())
+        }
         _ => EINVAL,
     }
 }
@@ -1067,44 +1491,48 @@ pub fn sys_io_uring_enter_v2(
     _arg5: u64,
     _arg6: u64,
 ) -> SyscallResult {
-    use crate::abi::io_uring_v2::{SubmissionEntryV2, CompletionEntryV2};
+    use crate::abi::io_uring_v2::SubmissionEntryV2;
     use crate::kernel::process::PROCESS_TABLE;
     
-    // Validate addresses (basic check - full validation done in handler)
-    if sqe_addr == 0 || cqe_addr == 0 {
-        return EFAULT;
-    }
-    
     // Read SQE from user space
-    let sqe: SubmissionEntryV2 = unsafe {
-        core::ptr::read_volatile(sqe_addr as *const SubmissionEntryV2)
+    // SAFETY: SubmissionEntryV2 consists of integers only
+    let sqe: SubmissionEntryV2 = match unsafe { read_user(sqe_addr) } {
+        Ok(sqe) => sqe,
+        Err(e) => return e,
     };
     
     // Get process and process the V2 submission while holding the lock
     let cqe = {
-        let table = PROCESS_TABLE.lock();
-        let process = match table.current_process() {
+        use crate::kernel::io_uring::handlers_v2::dispatch_sqe_v2;
+
+        let mut table = PROCESS_TABLE.lock();
+        let process = match table.current_process_mut() {
             Some(p) => p,
             None => return ESRCH,
         };
         
-        let cap_table = process.capability_table();
-        let io_uring_ctx = process.io_uring();
-        let buf_table = io_uring_ctx.map(|ctx| ctx.registered_buffer_table());
-        
-        // Process the V2 submission
-        crate::kernel::io_uring::handlers_v2::dispatch_sqe_v2(
-            &sqe,
-            cap_table,
-            buf_table,
-            false, // User mode: no raw addresses
-        )
+        process.usage().add_io_uring_sqes(1);
+
+        // Process the V2 submission (user mode: no raw addresses), using the
+        // registered buffers if io_uring is set up
+        #[cfg(feature = "syscall_trace")]
+        let start = crate::arch::x86_64::read_timestamp();
+        let cqe = process
+            .with_io_uring(|ctx, process| {
+                dispatch_sqe_v2(&sqe, process, Some(ctx.registered_buffer_table()), false)
+            })
+            .unwrap_or_else(|| dispatch_sqe_v2(&sqe, process, None, false));
+        #[cfg(feature = "syscall_trace")]
+        if let Some(trace) = process.trace() {
+            crate::kernel::process::trace::record_sqe(trace, &sqe, &cqe, start);
+        }
+        cqe
         // table lock dropped here
     };
     
     // Write CQE to user space
-    unsafe {
-        core::ptr::write_volatile(cqe_addr as *mut CompletionEntryV2, cqe);
+    if let Err(e) = write_user(cqe_addr, &cqe) {
+        return e;
     }
     
     0 // Success
@@ -1114,6 +1542,7 @@ pub fn sys_io_uring_enter_v2(
 ///
 /// Duplicate an existing capability with potentially reduced rights.
 /// This creates a new capability handle pointing to the same resource.
+/// Works for capabilities of any resource type.
 ///
 /// # Arguments
 /// * `capability_id` - Existing capability ID to duplicate
@@ -1131,7 +1560,7 @@ pub fn sys_capability_dup(
     _arg5: u64,
     _arg6: u64,
 ) -> SyscallResult {
-    use crate::kernel::capability::{Rights, FileResource, Handle};
+    use crate::kernel::capability::{Rights, FileResource};
     use crate::kernel::process::PROCESS_TABLE;
     
     let table = PROCESS_TABLE.lock();
@@ -1141,37 +1570,28 @@ pub fn sys_capability_dup(
     };
     
     // Get the original capability
-    let handle: Handle<FileResource> = unsafe { Handle::from_raw(capability_id) };
-    let entry = match process.capability_table().get(&handle) {
+    let entry = match process.capability_table().get_raw(capability_id) {
         Ok(e) => e,
-        Err(_) => {
-            core::mem::forget(handle);
-            return EBADF;
-        }
+        Err(_) => return EBADF,
     };
     
     // New rights must be a subset of original rights
     let new_rights = Rights::from_bits(rights);
     if !entry.rights.contains(new_rights) {
-        core::mem::forget(handle);
         return EPERM;
     }
     
     // Clone the resource and insert with new rights
     let resource_clone = entry.resource.clone();
-    core::mem::forget(handle);
     
-    // Insert the duplicated capability
+    // Insert the duplicated capability (the handle type only shapes the
+    // returned value; the entry keeps the original type ID)
     match process.capability_table().insert_raw::<FileResource>(
         entry.type_id,
         resource_clone,
         new_rights,
     ) {
-        Ok(new_handle) => {
-            let value = new_handle.raw();
-            core::mem::forget(new_handle);
-            value as SyscallResult
-        }
+        Ok(new_handle) => new_handle.into_raw() as SyscallResult,
         Err(_) => ENOSPC,
     }
 }
@@ -1195,7 +1615,6 @@ pub fn sys_capability_revoke(
     _arg5: u64,
     _arg6: u64,
 ) -> SyscallResult {
-    use crate::kernel::capability::FileResource;
     use crate::kernel::process::PROCESS_TABLE;
     
     let mut table = PROCESS_TABLE.lock();
@@ -1204,16 +1623,73 @@ pub fn sys_capability_revoke(
         None => return ESRCH,
     };
     
-    let cap_handle: crate::kernel::capability::Handle<FileResource> = unsafe {
-        crate::kernel::capability::Handle::from_raw(handle)
-    };
-    
-    match process.capability_table_mut().remove(cap_handle) {
+    match process.capability_table_mut().remove_raw(handle) {
         Ok(_) => 0,
         Err(_) => EINVAL,
     }
 }
 
+/// V2 capability transfer syscall (ID: 2006)
+///
+/// Move a capability into another process's table. The caller loses the
+/// handle; to keep access, duplicate it first.
+///
+/// # Arguments
+/// * `handle` - Capability handle to transfer (needs `Rights::TRANSFER`)
+/// * `pid` - Receiving process
+/// * `rights` - Rights of the transferred capability (must be a subset of
+///   the original)
+///
+/// # Returns
+/// * Success: Handle value in the receiving process, to be passed on to it
+///   (e.g. through a pipe)
+/// * EBADF: Invalid handle
+/// * EPERM: Missing `TRANSFER` right or rights not a subset
+/// * ESRCH: No such live process
+/// * ENOSPC: Receiver's capability table is full
+pub fn sys_capability_transfer(
+    handle: u64,
+    pid: u64,
+    rights: u64,
+    _arg4: u64,
+    _arg5: u64,
+    _arg6: u64,
+) -> SyscallResult {
+    use crate::kernel::capability::{FileResource, Rights};
+    use crate::kernel::process::{ProcessId, ProcessState, PROCESS_TABLE};
+
+    let table = PROCESS_TABLE.lock();
+    let Some(sender) = table.current_process() else {
+        return ESRCH;
+    };
+    let entry = match sender.capability_table().get_raw(handle) {
+        Ok(e) => e,
+        Err(_) => return EBADF,
+    };
+    let new_rights = Rights::from_bits(rights);
+    if !entry.rights.contains(Rights::TRANSFER) || !entry.rights.contains(new_rights) {
+        return EPERM;
+    }
+
+    let receiver = match table.get_process(ProcessId::new(pid)) {
+        Some(p) if p.state() != ProcessState::Terminated => p,
+        _ => return ESRCH,
+    };
+
+    // Insert first so a full receiver table leaves the sender untouched
+    let moved = match receiver.capability_table().insert_raw::<FileResource>(
+        entry.type_id,
+        entry.resource.clone(),
+        new_rights,
+    ) {
+        Ok(h) => h.into_raw(),
+        Err(_) => return ENOSPC,
+    };
+    let _ = sender.capability_table().remove_raw(handle);
+
+    moved as SyscallResult
+}
+
 /// sys_pipe - Create a pipe
 ///
 /// Arguments:
@@ -1242,49 +1718,446 @@ pub fn sys_pipe(pipefd: u64, _: u64, _: u64, _: u64, _: u64, _: u64) -> SyscallR
     let reader_vfs = Arc::new(VfsFile::with_type(reader, VfsFileType::PipeRead));
     let writer_vfs = Arc::new(VfsFile::with_type(writer, VfsFileType::PipeWrite));
 
-    // Get current process
-    let mut table = PROCESS_TABLE.lock();
-    let process = match table.current_process_mut() {
-        Some(p) => p,
-        None => return ESRCH,
-    };
+    let handles = {
+        // Get current process
+        let mut table = PROCESS_TABLE.lock();
+        let process = match table.current_process_mut() {
+            Some(p) => p,
+            None => return ESRCH,
+        };
 
-    // Insert into capability table
-    // Reader needs READ rights
-    let reader_handle = match process.capability_table_mut().insert::<FileResource, VfsFile>(
-        reader_vfs,
-        Rights::READ
-    ) {
-        Ok(h) => h,
-        Err(_) => return EMFILE,
+        // Insert into capability table
+        // Reader needs READ rights
+        let reader_handle = match process.capability_table_mut().insert::<FileResource, VfsFile>(
+            reader_vfs,
+            Rights::READ
+        ) {
+            Ok(h) => h,
+            Err(_) => return EMFILE,
+        };
+
+        // Writer needs WRITE rights
+        let writer_handle = match process.capability_table_mut().insert::<FileResource, VfsFile>(
+            writer_vfs,
+            Rights::WRITE
+        ) {
+            Ok(h) => h,
+            Err(_) => {
+                // Cleanup reader if writer fails
+                let _ = process.capability_table_mut().remove(reader_handle);
+                return EMFILE;
+            }
+        };
+
+        [reader_handle.into_raw(), writer_handle.into_raw()]
+        // table lock dropped here
     };
 
-    // Writer needs WRITE rights
-    let writer_handle = match process.capability_table_mut().insert::<FileResource, VfsFile>(
-        writer_vfs,
-        Rights::WRITE
-    ) {
-        Ok(h) => h,
-        Err(_) => {
-            // Cleanup reader if writer fails
-            let _ = process.capability_table_mut().remove(reader_handle);
-            return EMFILE;
+    // Write back to user space
+    let fds = handles.map(|raw| raw as i32);
+    if let Err(e) = write_user(pipefd, &fds) {
+        // Do not leave capabilities behind that the caller never learns of
+        let table = PROCESS_TABLE.lock();
+        if let Some(process) = table.current_process() {
+            for raw in handles {
+                let _ = process.capability_table().remove_raw(raw);
+            }
         }
+        return e;
+    }
+
+    SUCCESS
+}
+
+/// sys_reboot - Reboot, power off, or halt the machine
+///
+/// Arguments:
+/// - arg1: system capability ID (see [`crate::kernel::power::SYSTEM_CAP_ID`])
+/// - arg2: command (0=halt, 1=power off, 2=reboot)
+/// - arg3: exit status reported to QEMU's isa-debug-exit (power off only)
+///
+/// Returns:
+/// - Does not return on success
+/// - EPERM: Capability lacks `Rights::POWER`
+/// - EBADF: Not a valid system capability
+/// - EINVAL: Unknown command
+///
+/// # Security
+/// - Requires a `SystemResource` capability, granted only to `/bin/init`
+pub fn sys_reboot(cap_id: u64, cmd: u64, status: u64, _arg4: u64, _arg5: u64, _arg6: u64) -> SyscallResult {
+    use crate::kernel::capability::Rights;
+    use crate::kernel::power::{self, PowerAction};
+
+    let Some(action) = PowerAction::from_u64(cmd) else {
+        return EINVAL;
     };
 
-    // Write back to user space
-    unsafe {
-        let ptr = pipefd as *mut i32;
-        *ptr = reader_handle.into_raw() as i32;
-        *ptr.add(1) = writer_handle.into_raw() as i32;
+    let pid = match check_system_right(cap_id, Rights::POWER) {
+        Ok(pid) => pid,
+        Err(e) => return e,
+    };
+
+    debug_println!("[SYSCALL] sys_reboot: PID={} requested {:?}", pid.as_u64(), action);
+    power::shutdown(action, Some(pid), status as u32)
+}
+
+/// Check that the current process holds the system capability `cap_id`
+/// with `right`
+///
+/// Returns the caller's PID, or EPERM if the capability lacks the right
+/// and EBADF if `cap_id` is not a system capability.
+fn check_system_right(
+    cap_id: u64,
+    right: crate::kernel::capability::Rights,
+) -> Result<crate::kernel::process::ProcessId, SyscallResult> {
+    use crate::abi::error::SyscallError;
+    use crate::kernel::capability::{Handle, SystemResource};
+    use crate::kernel::process::PROCESS_TABLE;
+
+    let table = PROCESS_TABLE.lock();
+    let Some(process) = table.current_process() else {
+        return Err(ESRCH);
+    };
+
+    // SAFETY: The handle is only used for lookup and is forgotten below
+    let handle: Handle<SystemResource> = unsafe { Handle::from_raw(cap_id) };
+    let result = process.capability_table().get_with_rights(&handle, right).map(|_| ());
+    core::mem::forget(handle);
+    match result {
+        Ok(()) => Ok(process.pid()),
+        Err(SyscallError::InsufficientRights) => Err(EPERM),
+        Err(_) => Err(EBADF),
+    }
+}
+
+/// sys_getrusage - Get resource usage of the calling process
+///
+/// Arguments:
+/// - arg1: who (only `RUSAGE_SELF` = 0 is supported)
+/// - arg2: pointer to a `RUsage` structure to fill
+///
+/// Returns:
+/// - 0: Success
+/// - EINVAL: Unsupported `who`
+/// - EFAULT: Invalid pointer
+pub fn sys_getrusage(who: u64, usage_ptr: u64, _arg3: u64, _arg4: u64, _arg5: u64, _arg6: u64) -> SyscallResult {
+    use crate::abi::rusage::{RUsage, RUSAGE_SELF};
+    use crate::kernel::process::{accounting, PROCESS_TABLE};
+
+    if who != RUSAGE_SELF {
+        return EINVAL;
+    }
+
+    let usage: RUsage = {
+        let table = PROCESS_TABLE.lock();
+        let Some(process) = table.current_process() else {
+            return ESRCH;
+        };
+        accounting::flush_into(process);
+        process.usage().snapshot()
+    };
+
+    match write_user(usage_ptr, &usage) {
+        Ok(()) => SUCCESS,
+        Err(e) => e,
+    }
+}
+
+/// sys_debug_set_level - Set the kernel log levels
+///
+/// Native ABI number `SyscallNumber::DebugSetLevel` (0xFF01).
+///
+/// Arguments:
+/// - arg1: system capability ID (see [`crate::kernel::power::SYSTEM_CAP_ID`])
+/// - arg2: level recorded into the kernel log ring (`KLOG_*`)
+/// - arg3: level also echoed to the serial console (`KLOG_*`)
+///
+/// Either level may be `KLOG_UNCHANGED`.
+///
+/// Returns:
+/// - 0: Success
+/// - EINVAL: Unknown level
+/// - EPERM: The capability lacks `Rights::SYSLOG`
+/// - EBADF: `arg1` is not a system capability
+pub fn sys_debug_set_level(cap_id: u64, record: u64, console: u64, _arg4: u64, _arg5: u64, _arg6: u64) -> SyscallResult {
+    use crate::abi::klog::KLOG_UNCHANGED;
+    use crate::kernel::capability::Rights;
+    use crate::kernel::klog;
+
+    if let Err(e) = check_system_right(cap_id, Rights::SYSLOG) {
+        return e;
+    }
+
+    let parse = |level: u64| match level {
+        KLOG_UNCHANGED => Ok(None),
+        _ => klog::level_filter_from_u64(level).map(Some).ok_or(EINVAL),
+    };
+    let (record, console) = match (parse(record), parse(console)) {
+        (Ok(record), Ok(console)) => (record, console),
+        _ => return EINVAL,
+    };
+
+    klog::set_levels(record, console);
+    SUCCESS
+}
+
+/// sys_dmesg - Read the kernel log
+///
+/// Arguments:
+/// - arg1: buffer pointer
+/// - arg2: buffer length
+/// - arg3: pointer to a u64 cursor (sequence number to start from)
+///
+/// Copies whole lines (see [`crate::abi::klog`]) and advances the cursor
+/// past the last copied record.
+///
+/// Returns:
+/// - Positive or zero: Number of bytes copied (0 when no new records)
+/// - EINVAL: Buffer too small for a single line
+/// - EFAULT: Invalid pointer
+pub fn sys_dmesg(buf: u64, len: u64, cursor_ptr: u64, _arg4: u64, _arg5: u64, _arg6: u64) -> SyscallResult {
+    use crate::kernel::klog::{self, DMESG_MIN_BUFFER, KERNEL_LOG};
+
+    if len < DMESG_MIN_BUFFER as u64 {
+        return EINVAL;
+    }
+    let len = len.min(MAX_WRITE_LEN);
+    // SAFETY: Any bit pattern is a valid u64
+    let mut cursor: u64 = match unsafe { read_user(cursor_ptr) } {
+        Ok(cursor) => cursor,
+        Err(e) => return e,
+    };
+
+    // Format into a kernel buffer and copy the lines out afterwards
+    let mut out = match bounce_buffer(len) {
+        Ok(out) => out,
+        Err(e) => return e,
+    };
+    let written = klog::read_lines(&KERNEL_LOG, &mut cursor, &mut out);
+
+    // Advance the caller's cursor only once the lines have arrived
+    if let Err(e) = copy_to_user(buf, &out[..written]) {
+        return e;
+    }
+    if let Err(e) = write_user(cursor_ptr, &cursor) {
+        return e;
+    }
+
+    written as SyscallResult
+}
+
+/// Look up a process that the caller may trace: itself (`pid == 0` or its
+/// own PID) or one of its children
+#[cfg(feature = "syscall_trace")]
+fn traceable_process(
+    table: &mut crate::kernel::process::ProcessTable,
+    pid: u64,
+) -> Result<&mut crate::kernel::process::Process, SyscallResult> {
+    use crate::kernel::process::ProcessId;
+
+    let caller = table.current_process().map(|p| p.pid()).ok_or(ESRCH)?;
+    let target = if pid == 0 { caller } else { ProcessId::new(pid) };
+    let process = table.get_process_mut(target).ok_or(ESRCH)?;
+    if target != caller && process.parent_pid() != Some(caller) {
+        return Err(EPERM);
     }
+    Ok(process)
+}
+
+/// sys_trace_ctl - Enable or disable syscall tracing
+///
+/// Arguments:
+/// - arg1: target PID (0 = calling process)
+/// - arg2: mode (`TRACE_OFF`, `TRACE_ON`, `TRACE_CHILDREN`)
+///
+/// `TRACE_CHILDREN` traces processes the target spawns from now on, which
+/// lets `strace` trace a program from its first syscall. `TRACE_OFF`
+/// discards pending events and stops tracing children as well.
+///
+/// Returns:
+/// - 0: Success
+/// - ESRCH: No such process
+/// - EPERM: Target is neither the caller nor its child
+/// - EINVAL: Unknown mode
+///
+/// Only available with the `syscall_trace` feature (ENOSYS otherwise).
+#[cfg(feature = "syscall_trace")]
+pub fn sys_trace_ctl(pid: u64, mode: u64, _arg3: u64, _arg4: u64, _arg5: u64, _arg6: u64) -> SyscallResult {
+    use crate::abi::trace::{TRACE_CHILDREN, TRACE_OFF, TRACE_ON};
+    use crate::kernel::process::{trace::ProcessTrace, PROCESS_TABLE};
 
+    let mut table = PROCESS_TABLE.lock();
+    let process = match traceable_process(&mut table, pid) {
+        Ok(p) => p,
+        Err(e) => return e,
+    };
+
+    match mode {
+        TRACE_OFF => {
+            process.set_trace(None);
+            process.set_trace_children(false);
+        }
+        TRACE_ON => {
+            if process.trace().is_none() {
+                process.set_trace(Some(ProcessTrace::new()));
+            }
+        }
+        TRACE_CHILDREN => process.set_trace_children(true),
+        _ => return EINVAL,
+    }
     SUCCESS
 }
 
+/// sys_trace_read - Drain trace events of a traced process
+///
+/// Arguments:
+/// - arg1: target PID (0 = calling process)
+/// - arg2: pointer to an array of `TraceEvent`
+/// - arg3: capacity of the array (in events)
+///
+/// Events stay readable after the target exits, until it is reaped by
+/// `wait`. The last event of an exited process is a `TraceKind::Exit`.
+///
+/// Returns:
+/// - Positive or zero: Number of events copied
+/// - ESRCH / EPERM: See [`sys_trace_ctl`]
+/// - EINVAL: Target is not traced
+/// - EFAULT: Invalid buffer
+///
+/// Only available with the `syscall_trace` feature (ENOSYS otherwise).
+#[cfg(feature = "syscall_trace")]
+pub fn sys_trace_read(pid: u64, buf: u64, count: u64, _arg4: u64, _arg5: u64, _arg6: u64) -> SyscallResult {
+    use crate::abi::trace::{TraceEvent, TRACE_CAPACITY};
+    use crate::kernel::process::PROCESS_TABLE;
+    use crate::kernel::security::is_user_range;
+
+    let count = count.min(TRACE_CAPACITY as u64) as usize;
+    let size = count * core::mem::size_of::<TraceEvent>();
+    // Check the range up front so that events are not drained for nothing
+    if size > 0 && !is_user_range(buf, size as u64) {
+        return EFAULT;
+    }
+
+    // Drain into a kernel-side array while the table is held
+    let mut events = alloc::vec![TraceEvent::default(); count];
+    let n = {
+        let mut table = PROCESS_TABLE.lock();
+        let process = match traceable_process(&mut table, pid) {
+            Ok(p) => p,
+            Err(e) => return e,
+        };
+        let Some(trace) = process.trace() else {
+            return EINVAL;
+        };
+        trace.drain_into(&mut events)
+    };
+
+    // Copy event by event, so the user buffer does not need to be aligned
+    for (i, event) in events[..n].iter().enumerate() {
+        if let Err(e) = write_user(buf + (i * core::mem::size_of::<TraceEvent>()) as u64, event) {
+            return e;
+        }
+    }
+
+    n as SyscallResult
+}
+
+/// sys_shm_create - Create a shared memory object
+///
+/// Arguments:
+/// - arg1: size in bytes (rounded up to whole pages)
+///
+/// The object is zero-filled and reachable through the returned
+/// `ShmemResource` capability, which carries `READ`, `WRITE`, `MAP`, `DUP`
+/// and `TRANSFER`. Map it with `mmap` (without `MAP_ANONYMOUS`, passing
+/// the handle as `fd`) and hand it to other processes with
+/// [`sys_capability_transfer`]. The memory is freed once the last mapping
+/// and the last capability are gone.
+///
+/// Returns:
+/// - Positive: Capability handle
+/// - EINVAL: Size is zero or too large
+/// - ENOMEM: Out of memory
+/// - ENOSPC: Capability table is full
+pub fn sys_shm_create(size: u64, _arg2: u64, _arg3: u64, _arg4: u64, _arg5: u64, _arg6: u64) -> SyscallResult {
+    use crate::kernel::capability::{ResourceKind, ShmemHandle, ShmemResource};
+    use crate::kernel::ipc::shmem::ShmemObject;
+    use crate::kernel::mm::allocator::BOOT_INFO_ALLOCATOR;
+    use crate::kernel::process::PROCESS_TABLE;
+    use crate::kernel::security::validate_alloc_size;
+    use alloc::sync::Arc;
+
+    if let Err(e) = validate_alloc_size(size) {
+        return e;
+    }
+
+    retry_out_of_frames("shm_create", || {
+        let table = PROCESS_TABLE.lock();
+        let Some(process) = table.current_process() else {
+            return Ok(ESRCH);
+        };
+
+        let object = {
+            let mut allocator_lock = BOOT_INFO_ALLOCATOR.lock();
+            let Some(frame_allocator) = allocator_lock.as_mut() else {
+                return Ok(ENOMEM);
+            };
+            Arc::new(ShmemObject::new(size, frame_allocator).ok_or(OutOfFrames)?)
+        };
+
+        let handle: ShmemHandle = match process
+            .capability_table()
+            .insert::<ShmemResource, ShmemObject>(object, ShmemResource::DEFAULT_RIGHTS)
+        {
+            Ok(h) => h,
+            Err(_) => return Ok(ENOSPC),
+        };
+        Ok(handle.into_raw() as SyscallResult)
+    })
+}
+
+/// sys_personality - Set or query the personality flags
+///
+/// Arguments:
+/// - arg1: new flags (`ADDR_NO_RANDOMIZE`), or `PERSONALITY_QUERY` to only
+///   read the current ones
+///
+/// The flags apply to processes the caller spawns from now on; the
+/// caller's own address space keeps its layout. With `ADDR_NO_RANDOMIZE`
+/// those processes get the fixed default layout instead of randomised
+/// stack, mmap, ring and PIE bases.
+///
+/// Returns:
+/// - Positive or zero: Previous flags
+/// - EINVAL: Unknown flag
+/// - ESRCH: No current process
+pub fn sys_personality(persona: u64, _arg2: u64, _arg3: u64, _arg4: u64, _arg5: u64, _arg6: u64) -> SyscallResult {
+    use crate::abi::personality::{PERSONALITY_MASK, PERSONALITY_QUERY};
+    use crate::kernel::process::PROCESS_TABLE;
+
+    let mut table = PROCESS_TABLE.lock();
+    let Some(process) = table.current_process_mut() else {
+        return ESRCH;
+    };
+    let old = process.personality();
+    if persona != PERSONALITY_QUERY {
+        if persona & !PERSONALITY_MASK != 0 {
+            return EINVAL;
+        }
+        process.set_personality(persona);
+    }
+    old as SyscallResult
+}
+
 /// Syscall handler function type
 type SyscallHandler = fn(u64, u64, u64, u64, u64, u64) -> SyscallResult;
 
+/// Tracing syscalls, present only with the `syscall_trace` feature
+#[cfg(feature = "syscall_trace")]
+const TRACE_HANDLERS: [SyscallHandler; 2] = [sys_trace_ctl, sys_trace_read];
+#[cfg(not(feature = "syscall_trace"))]
+const TRACE_HANDLERS: [SyscallHandler; 2] = [sys_ni_syscall, sys_ni_syscall];
+
 /// Syscall dispatch table
 static SYSCALL_TABLE: &[SyscallHandler] = &[
     sys_write,    // 0
@@ -1300,8 +2173,15 @@ static SYSCALL_TABLE: &[SyscallHandler] = &[
     sys_munmap,   // 10
     sys_pipe,     // 11
     sys_ni_syscall,         // 12 - sys_io_uring_setup (removed)
-    sys_ni_syscall,         // 13 - reserved
-    sys_ni_syscall,         // 14 - reserved
+    sys_reboot,   // 13
+    sys_getrusage, // 14
+    sys_ni_syscall, // 15 - sys_debug_set_level (moved to 0xFF01)
+    sys_dmesg,    // 16
+    TRACE_HANDLERS[0], // 17 - sys_trace_ctl
+    TRACE_HANDLERS[1], // 18 - sys_trace_read
+    sys_ni_syscall, // 19 - sys_mprotect (moved to 0x0202)
+    sys_shm_create, // 20
+    sys_personality, // 21
 ];
 
 /// Not implemented syscall handler
@@ -1319,12 +2199,6 @@ pub fn dispatch(
     arg5: u64,
     arg6: u64,
 ) -> SyscallResult {
-    // Always log syscall entry for Phase 2.5 debugging
-    crate::debug_println!(
-        "[SYSCALL-ENTRY] num={}, args=({:#x}, {:#x}, {:#x}, {:#x}, {:#x}, {:#x})",
-        syscall_num, arg1, arg2, arg3, arg4, arg5, arg6
-    );
-    
     let num = syscall_num as usize;
     
     if num >= SYSCALL_TABLE.len() {
@@ -1333,6 +2207,10 @@ pub fn dispatch(
             1000 => sys_benchmark(arg1, arg2, arg3, arg4, arg5, arg6),
             1001 => sys_fast_poll(arg1, arg2, arg3, arg4, arg5, arg6),
             1002 => ENOSYS, // sys_fast_io_setup removed
+            // Native ABI numbers (`rany_os_abi::native::SyscallNumber`)
+            0x0100 => sys_open(arg1, arg2, arg3, arg4, arg5, arg6),
+            0x0202 => sys_mprotect(arg1, arg2, arg3, arg4, arg5, arg6),
+            0xFF01 => sys_debug_set_level(arg1, arg2, arg3, arg4, arg5, arg6),
             // Ring-based syscall system (2000+)
             2000 => ENOSYS, // sys_ring_enter removed
             2001 => ENOSYS, // sys_ring_register removed
@@ -1341,25 +2219,17 @@ pub fn dispatch(
             2003 => sys_io_uring_enter_v2(arg1, arg2, arg3, arg4, arg5, arg6),
             2004 => sys_capability_dup(arg1, arg2, arg3, arg4, arg5, arg6),
             2005 => sys_capability_revoke(arg1, arg2, arg3, arg4, arg5, arg6),
+            2006 => sys_capability_transfer(arg1, arg2, arg3, arg4, arg5, arg6),
+            2007 => sys_io_uring_register(arg1, arg2, arg3, arg4, arg5, arg6),
             _ => {
-                debug_println!("[SYSCALL] Invalid syscall number: {}", syscall_num);
+                log::debug!(target: "syscall", "invalid syscall number: {}", syscall_num);
                 ENOSYS
             }
         };
     }
     
-    debug_println!(
-        "[SYSCALL] Dispatching syscall {} with args=({}, {}, {}, {}, {}, {})",
-        syscall_num, arg1, arg2, arg3, arg4, arg5, arg6
-    );
-    
     let handler = SYSCALL_TABLE[num];
-    let result = handler(arg1, arg2, arg3, arg4, arg5, arg6);
-    
-    // Log syscall result
-    crate::debug_println!("[SYSCALL-RESULT] num={} returned {}", syscall_num, result);
-    
-    result
+    handler(arg1, arg2, arg3, arg4, arg5, arg6)
 }
 
 /// Test syscall mechanism from kernel space
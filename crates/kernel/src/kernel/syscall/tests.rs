@@ -191,3 +191,59 @@ fn test_sys_munmap_unmapped_pages() {
     // Both behaviors are acceptable depending on policy
     assert_eq!(result, SUCCESS, "Current policy: lenient unmapping");
 }
+
+/// Test sys_open with null and kernel path pointers
+#[test_case]
+fn test_sys_open_invalid_path() {
+    assert_eq!(sys_open(0, 10, 0, 0, 0, 0), EFAULT, "Should reject null path");
+    assert_eq!(sys_open(address_space::KERNEL_START, 10, 0, 0, 0, 0), EFAULT, "Should reject kernel path");
+    assert_eq!(sys_open(0x1000, 0, 0, 0, 0, 0), EINVAL, "Should reject empty path");
+}
+
+/// DebugSetLevel is only reachable through its native ABI number and
+/// requires the system capability
+#[test_case]
+fn test_debug_set_level_routing() {
+    use crate::abi::klog::KLOG_UNCHANGED;
+
+    assert_eq!(dispatch(15, 0, 0, 0, 0, 0, 0), ENOSYS, "Legacy number is retired");
+    let result = dispatch(0xFF01, u64::MAX, KLOG_UNCHANGED, KLOG_UNCHANGED, 0, 0, 0);
+    assert_ne!(result, SUCCESS, "Should require the system capability");
+    assert_ne!(result, ENOSYS, "Should reach sys_debug_set_level");
+}
+
+/// mprotect is reachable through its native ABI number only
+#[test_case]
+fn test_mprotect_routing() {
+    assert_eq!(dispatch(19, 0x1000, 4096, 0, 0, 0, 0), ENOSYS, "Legacy number is retired");
+    assert_eq!(
+        dispatch(0x0202, address_space::KERNEL_START, 4096, 0, 0, 0, 0),
+        sys_mprotect(address_space::KERNEL_START, 4096, 0, 0, 0, 0),
+        "Native number should reach sys_mprotect"
+    );
+}
+
+/// io_uring_register has its own number and does not fall into the table
+#[test_case]
+fn test_io_uring_register_routing() {
+    use crate::abi::io_uring_common::register_op;
+
+    let pid = sys_getpid(0, 0, 0, 0, 0, 0);
+    assert_eq!(dispatch(3, 0, 0, 0, 0, 0, 0), pid, "Table slot 3 stays getpid");
+    let result = dispatch(2007, 0, register_op::UNREGISTER_BUFFERS + 1, 0, 0, 0, 0);
+    assert_ne!(result, pid, "Must not reach getpid");
+    assert_eq!(result, EINVAL, "Unknown opcodes should reach sys_io_uring_register");
+}
+
+/// mmap and munmap arguments are checked before any process is looked up,
+/// so io_uring can validate them the same way
+#[test_case]
+fn test_mmap_args_validation() {
+    use crate::abi::mman::{MAP_ANONYMOUS, MAP_PRIVATE, PROT_EXEC, PROT_READ, PROT_WRITE};
+
+    let private = MAP_PRIVATE | MAP_ANONYMOUS;
+    assert_eq!(MmapArgs::new(0, 100, PROT_READ, private, 0, 0).unwrap().len, 4096);
+    assert_eq!(MmapArgs::new(0, 4096, PROT_WRITE | PROT_EXEC, private, 0, 0).unwrap_err(), EACCES);
+    assert_eq!(MunmapArgs::new(0x1001, 4096).unwrap_err(), EINVAL);
+    assert_eq!(MunmapArgs::new(0x1000, 1).unwrap().len, 4096);
+}
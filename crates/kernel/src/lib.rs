@@ -38,7 +38,7 @@ use crate::arch::{Cpu, ArchCpu};
 
 // グローバルヒープアロケータ
 #[global_allocator]
-static ALLOCATOR: kernel::mm::LockedHeap = kernel::mm::LockedHeap::new();
+static ALLOCATOR: kernel::mm::SlabAllocator = kernel::mm::SlabAllocator::new();
 
 /// ヒープ初期化エラー
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -77,6 +77,45 @@ pub unsafe fn init_heap(
     }
 }
 
+/// ヒープの拡張を有効にする
+///
+/// [`init_heap`] の後に呼び出します。以後、空きが足りない割り当ては
+/// `grow` でヒープ末尾を拡張してから再試行されます。それでも失敗した
+/// 割り当ては、ロックを手放してから `oom` を呼び、`true` なら再試行されます。
+pub fn enable_heap_growth(
+    grow: kernel::mm::allocator::HeapGrowHandler,
+    oom: kernel::mm::allocator::HeapOomHandler,
+) {
+    ALLOCATOR.set_grow_handler(grow);
+    ALLOCATOR.set_oom_handler(oom);
+}
+
+/// ヒープ統計情報を取得
+#[must_use]
+pub fn heap_stats() -> kernel::mm::HeapStats {
+    ALLOCATOR.heap_stats()
+}
+
+/// スラブキャッシュごとの統計情報を `f` に渡す
+pub fn slab_stats(f: impl FnMut(&kernel::mm::SlabStats)) {
+    ALLOCATOR.for_each_cache(f);
+}
+
+/// 未解放のヒープ割り当てを呼び出し元ごとに出力（リーク調査用）
+#[cfg(feature = "heap_debug")]
+pub fn dump_heap_allocations() {
+    ALLOCATOR.dump_allocations();
+}
+
+/// 頻繁に割り当てる型のレイアウトに名前付きスラブキャッシュを登録
+///
+/// # Errors
+///
+/// [`kernel::mm::SlabAllocator::register_cache`] を参照
+pub fn register_slab_cache(name: &'static str, layout: core::alloc::Layout) -> Result<(), &'static str> {
+    ALLOCATOR.register_cache(name, layout)
+}
+
 pub use qemu::{exit_qemu, QemuExitCode};
 
 /// `console_print!` マクロ - ユーザー向け画面出力
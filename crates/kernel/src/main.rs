@@ -21,6 +21,9 @@ pub static BOOTLOADER_CONFIG: BootloaderConfig = {
     // フレームバッファと全物理メモリを仮想アドレス空間に動的にマッピングするよう要求
     config.mappings.framebuffer = Mapping::Dynamic;
     config.mappings.physical_memory = Some(Mapping::Dynamic);
+    // 動的なマッピング（ダイレクトマップ、ブートスタックなど）を上位半分に置き、
+    // 下位半分の PML4 エントリをすべてユーザー空間に残す
+    config.mappings.dynamic_range_start = Some(0xFFFF_8000_0000_0000);
     config
 };
 
@@ -28,6 +31,8 @@ entry_point!(kernel_main, config = &BOOTLOADER_CONFIG);
 
 fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
     debug_println!("[KERNEL] Entry point reached");
+    // log クレートのバックエンド（ヒープ不要）
+    tiny_os::kernel::klog::init();
     
     // GDT/IDT初期化
     tiny_os::arch::x86_64::init_gdt();
@@ -35,6 +40,7 @@ fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
     debug_println!("[OK] GDT initialized");
     tiny_os::arch::x86_64::init_idt();
     debug_println!("[OK] IDT initialized");
+    tiny_os::kernel::driver::register_irq_handlers();
     
     // システムコール機構初期化
     tiny_os::arch::x86_64::syscall::init();
@@ -66,52 +72,95 @@ fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
     let virt_mem_offset = x86_64::VirtAddr::new(phys_mem_offset);
     
     let _mapper = unsafe { tiny_os::kernel::mm::paging::init(virt_mem_offset) };
+
+    // PCID でアドレス空間を区別し、CR3 の切り替えで TLB を捨てないようにする
+    tiny_os::kernel::mm::pcid::init();
+
+    // カーネルからユーザーページの実行・アクセスを禁止する
+    tiny_os::arch::x86_64::protection::init();
+
+    // ダイレクトマップを可能な範囲で 2 MiB ページにまとめる（TLB の節約）
+    let phys_end = boot_info.memory_regions.iter().map(|region| region.end).max().unwrap_or(0);
+    let collapsed = tiny_os::kernel::mm::paging::collapse_direct_map(phys_end);
+    debug_println!("[OK] Direct map: {} blocks collapsed into 2 MiB pages", collapsed);
     
     // グローバルフレームアロケータの初期化 (Phase 2)
     // 注意: BootInfoFrameAllocatorは一度しか初期化してはならない（同じ領域を指すため）
     let frame_allocator = unsafe {
-        tiny_os::kernel::mm::frame::BootInfoFrameAllocator::init(&boot_info.memory_regions)
+        tiny_os::kernel::mm::frame::BootInfoFrameAllocator::init(&boot_info.memory_regions, &[])
     };
+    let frame_stats = frame_allocator.stats();
     {
         let mut allocator = tiny_os::kernel::mm::allocator::BOOT_INFO_ALLOCATOR.lock();
         *allocator = Some(frame_allocator);
     }
-    debug_println!("[OK] Paging & Global Frame Allocator initialized");
+    debug_println!(
+        "[OK] Paging & Global Frame Allocator initialized ({} frames, {} free, {} reserved, {} free in DMA32)",
+        frame_stats.total, frame_stats.free, frame_stats.reserved, frame_stats.dma32_free
+    );
 
-    // ヒープ初期化
-    let (heap_start_phys, heap_size) = tiny_os::kernel::mm::init_heap(&boot_info.memory_regions)
-        .expect("Heap initialization failed");
-    
-    let heap_start_virt = tiny_os::kernel::mm::VirtAddr::new((heap_start_phys.as_u64() + phys_mem_offset) as usize);
-    
+    // ヒープ初期化（専用の仮想領域に初期サイズ分をマップし、以後は必要に応じて拡張）
+    let (heap_start, heap_size) = {
+        let mut allocator = tiny_os::kernel::mm::allocator::BOOT_INFO_ALLOCATOR.lock();
+        let frame_allocator = allocator.as_mut().expect("frame allocator not initialized");
+        tiny_os::kernel::mm::heap::init(frame_allocator).expect("Heap initialization failed")
+    };
     unsafe {
-        tiny_os::init_heap(heap_start_virt, heap_size)
+        tiny_os::init_heap(heap_start, heap_size)
             .expect("Heap initialization failed");
     }
-    debug_println!("[OK] Heap initialized at 0x{:x} (Size: {} bytes)", heap_start_virt.as_usize(), heap_size.as_usize());
+    tiny_os::enable_heap_growth(tiny_os::kernel::mm::heap::grow, tiny_os::kernel::mm::heap::out_of_memory);
+    debug_println!(
+        "[OK] Heap initialized at 0x{:x} (Size: {} bytes, ceiling: {} bytes)",
+        heap_start.as_usize(), heap_size.as_usize(), tiny_os::kernel::mm::heap::heap_ceiling()
+    );
+
+    // 頻繁に割り当てる型の名前付きスラブキャッシュ
+    if let Err(e) = tiny_os::register_slab_cache(
+        "capability_entry",
+        core::alloc::Layout::new::<tiny_os::kernel::capability::table::CapabilityEntry>(),
+    ) {
+        debug_println!("[WARNING] capability_entry slab cache: {}", e);
+    }
+
+    // カーネルスタック領域（最初のプロセスより前に PML4 エントリを用意する）
+    {
+        let mut allocator = tiny_os::kernel::mm::allocator::BOOT_INFO_ALLOCATOR.lock();
+        let frame_allocator = allocator.as_mut().expect("frame allocator not initialized");
+        tiny_os::kernel::mm::kstack::init(frame_allocator)
+            .expect("Kernel stack region initialization failed");
+    }
+    debug_println!("[OK] Kernel stack region initialized");
+
+    // ACPI テーブル解析と割り込みコントローラ設定 (8259 PIC -> LAPIC/IOAPIC)
+    // NOTE: MADT の解析に Vec を使うため、ヒープ初期化後に行う
+    match tiny_os::arch::x86_64::acpi::init(boot_info.rsdp_addr.into_option(), phys_mem_offset) {
+        Ok(()) => debug_println!("[OK] ACPI tables parsed"),
+        Err(e) => debug_println!("[WARNING] ACPI unavailable: {:?}", e),
+    }
+    match tiny_os::arch::x86_64::apic::init(phys_mem_offset) {
+        Ok(()) => debug_println!("[OK] LAPIC/IOAPIC initialized"),
+        Err(e) => debug_println!("[WARNING] APIC init failed, staying on 8259 PIC: {:?}", e),
+    }
+    if !tiny_os::arch::x86_64::gdbstub::init() {
+        debug_println!("[INFO] COM2 not present, GDB stub disabled");
+    }
     
     // Initrd Setup
     if let Some(ramdisk_addr) = boot_info.ramdisk_addr.into_option() {
         let ramdisk_len = boot_info.ramdisk_len;
         debug_println!("[Initrd] Found at {:#x}, size: {} bytes", ramdisk_addr, ramdisk_len);
-        
-        let ramdisk_slice = unsafe {
-            core::slice::from_raw_parts(
-                (ramdisk_addr + phys_mem_offset) as *const u8,
-                ramdisk_len as usize
-            )
+
+        // ブートローダはラムディスクを仮想アドレス ramdisk_addr にマップして渡す。
+        // 領域は Bootloader 種別のまま解放されないので 'static として扱える
+        let ramdisk_slice: &'static [u8] = unsafe {
+            core::slice::from_raw_parts(ramdisk_addr as *const u8, ramdisk_len as usize)
         };
-        
-        // TODO: Implement initrd and VFS
-        /*
-        // SAFETY: ramdisk memory is valid and static (loaded by bootloader)
-        let static_slice = unsafe { core::mem::transmute::<&[u8], &'static [u8]>(ramdisk_slice) };
-        let initrd = unsafe { tiny_os::kernel::fs::initrd::InitrdFs::new(static_slice) };
-        
+        // SAFETY: ラムディスクはカーネルの生存期間中ずっと有効
+        let initrd = unsafe { tiny_os::kernel::fs::initrd::InitrdFs::new(ramdisk_slice) };
+
         tiny_os::kernel::fs::vfs::VFS.lock().mount("/", initrd);
         debug_println!("[OK] Initrd mounted at /");
-        */
-        debug_println!("[TODO] Initrd support not implemented yet");
     } else {
         debug_println!("[WARNING] No initrd found!");
     }
@@ -143,6 +192,10 @@ fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
                 table.set_current(pid);
                 if let Some(process) = table.get_process_mut(pid) {
                     process.set_state(tiny_os::kernel::process::ProcessState::Running);
+                    // init だけが再起動・電源断の権限を持つ
+                    if let Err(e) = process.grant_system_capability() {
+                        debug_println!("[Process] Warning: Failed to grant system capability: {:?}", e);
+                    }
                     debug_println!("[Process] Set PID={} as Running", pid.as_u64());
                 } else {
                     panic!("Failed to get process after creation");
@@ -157,40 +210,32 @@ fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
             // This is expected to cause a page fault at 0x400000
             debug_println!("[PHASE 2.5] User mode transition test (CR3 switch skipped)");
             
-            // TEMP: Disable timer and interrupts for testing User mode transition
-            // This helps isolate the problem (timer interrupt vs user code)
-            // TODO: Re-enable after User mode works
-            // // NOW enable timer and interrupts (after releasing PROCESS_TABLE lock)
-            // // ハードウェアタイマー初期化
-            // // SAFETY: PICの初期化はカーネル起動時に1回だけ実行される
-            // unsafe {
-            //     tiny_os::arch::x86_64::pic::PICS.lock().initialize();
-            //     // Enable timer interrupt (IRQ0)
-            //     tiny_os::arch::x86_64::pic::PICS.lock().unmask_irq(0);
-            // }
-            // debug_println!("[OK] Hardware Timer initialized and enabled");
- 
-            // // 割り込み有効化
-            // ArchCpu::enable_interrupts();
-            // debug_println!("[OK] Interrupts enabled");
-            
-            debug_println!("[TEMP] Interrupts DISABLED for User mode testing");
+            // ハードウェアタイマー初期化 (LAPIC タイマー、周期モード)
+            // 割り込みは iretq で RFLAGS.IF が立ったときに初めて有効になるため、
+            // この先で PROCESS_TABLE を取ってもタイマー割り込みとは競合しない
+            if tiny_os::arch::x86_64::apic::is_enabled() {
+                tiny_os::arch::x86_64::apic::start_periodic();
+                debug_println!("[OK] LAPIC timer started ({} ms ticks)", tiny_os::kernel::r#async::timer::TICK_MS);
+            }
             
             // [CRITICAL] Update TSS kernel stack before jumping to user mode
             // This is required so that interrupts/syscalls from Ring 3 use the correct kernel stack
-            {
-                let table = tiny_os::kernel::process::PROCESS_TABLE.lock();
-                if let Some(process) = table.get_process(pid) {
+            let user_cr3 = {
+                let mut table = tiny_os::kernel::process::PROCESS_TABLE.lock();
+                if let Some(process) = table.get_process_mut(pid) {
                     let kernel_stack = process.kernel_stack();
                     debug_println!("[TSS] Setting kernel stack to {:#x}", kernel_stack.as_u64());
                     tiny_os::arch::x86_64::tss::update_kernel_stack(kernel_stack);
                     
                     // Also set syscall kernel stack (for sysret path)
                     tiny_os::arch::x86_64::syscall::set_kernel_stack(kernel_stack);
+
+                    // PCID が有効なら PCID 付きの CR3 値
+                    process.cr3_value()
                 } else {
                     panic!("Failed to get process {} for TSS setup", pid.as_u64());
                 }
-            }
+            };
             
             debug_println!("[Kernel] Jumping to first user process...");
             
@@ -223,6 +268,9 @@ fn panic(info: &PanicInfo) -> ! {
             // NOTE: format_args! はスタック上で動作するため、
             // ヒープアロケーションは発生しない（安全）
             debug_println!("[KERNEL PANIC] {}", info);
+            tiny_os::arch::x86_64::backtrace::print_current();
+            // GDB スタブが有効ならパニック時点の状態を調べられるようにする
+            tiny_os::arch::x86_64::gdbstub::breakpoint();
         }
         FIRST_PANIC => {
             // 二重パニック: 最小限の情報のみ
@@ -24,28 +24,113 @@
 //!
 //! # Implementation Note
 //!
-//! This is a minimal allocator suitable for Phase 1-2.
-//! A more sophisticated allocator will be implemented in Phase 5.
+//! Small allocations (up to [`MAX_SMALL`] bytes) are rounded up to a
+//! power-of-two size class and carved out of large arenas reserved with a
+//! single `mmap`. The kernel only backs arena pages when they are first
+//! touched, so reserving [`ARENA_SIZE`] bytes up front costs nothing.
+//! Freed small blocks go onto a per-class free list and are reused; arena
+//! memory is never returned to the kernel.
+//!
+//! Larger allocations get their own mapping and are unmapped on `dealloc`.
 
 use crate::syscall;
 use crate::mem::{PROT_READ, PROT_WRITE, MAP_PRIVATE, MAP_ANONYMOUS};
+use crate::sync::Mutex;
 use core::alloc::{GlobalAlloc, Layout};
 use core::ptr::null_mut;
 
+/// Smallest size class in bytes (room for the free list link)
+const MIN_CLASS: usize = 16;
+/// Largest request served from the arenas
+pub const MAX_SMALL: usize = 2048;
+/// Number of size classes from [`MIN_CLASS`] to [`MAX_SMALL`]
+const CLASS_COUNT: usize = (MAX_SMALL / MIN_CLASS).trailing_zeros() as usize + 1;
+/// Address space reserved per arena
+pub const ARENA_SIZE: usize = 64 * 1024 * 1024;
+/// Page size used for direct mappings
+const PAGE_SIZE: usize = 4096;
+
+/// Arena state shared by all `MmapAllocator` instances
+///
+/// Addresses are stored as `usize` (0 meaning none) so the state is `Send`.
+struct Heap {
+    /// Next unused byte of the current arena
+    next: usize,
+    /// End of the current arena
+    end: usize,
+    /// Heads of the per-class free lists
+    free: [usize; CLASS_COUNT],
+}
+
+static HEAP: Mutex<Heap> = Mutex::new(Heap {
+    next: 0,
+    end: 0,
+    free: [0; CLASS_COUNT],
+});
+
+impl Heap {
+    /// Take a block of size class `class` from the free list or the arena
+    fn alloc_small(&mut self, class: usize) -> *mut u8 {
+        let head = self.free[class];
+        if head != 0 {
+            // SAFETY: Free blocks hold the address of the next free block
+            self.free[class] = unsafe { *(head as *const usize) };
+            return head as *mut u8;
+        }
+
+        // Blocks are aligned to their own size
+        let size = MIN_CLASS << class;
+        let mut start = (self.next + size - 1) & !(size - 1);
+        if self.next == 0 || start + size > self.end {
+            match syscall::mmap(0, ARENA_SIZE as u64, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS) {
+                Ok(addr) => {
+                    self.next = addr as usize;
+                    self.end = self.next + ARENA_SIZE;
+                }
+                Err(_) => return null_mut(),
+            }
+            start = (self.next + size - 1) & !(size - 1);
+        }
+        self.next = start + size;
+        start as *mut u8
+    }
+
+    /// Put a block of size class `class` back on its free list
+    fn free_small(&mut self, ptr: *mut u8, class: usize) {
+        // SAFETY: The block is at least MIN_CLASS bytes and no longer in use
+        unsafe { *(ptr as *mut usize) = self.free[class] };
+        self.free[class] = ptr as usize;
+    }
+}
+
+/// Size class serving `layout`, or `None` for a direct mapping
+fn size_class(layout: &Layout) -> Option<usize> {
+    let size = layout.size().max(layout.align()).max(MIN_CLASS);
+    if size > MAX_SMALL {
+        return None;
+    }
+    Some((size.next_power_of_two() / MIN_CLASS).trailing_zeros() as usize)
+}
+
 /// Global allocator using mmap
 ///
-/// This allocator directly maps each allocation request to a `mmap` call.
-/// This is simple but not very efficient for small allocations.
+/// Small allocations are served from lazily backed arenas, large ones
+/// map their own pages. Alignments above the page size are only
+/// supported for small allocations.
 pub struct MmapAllocator;
 
 unsafe impl GlobalAlloc for MmapAllocator {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        let size = layout.size();
-        
-        // Directly use mmap for all allocations
+        if let Some(class) = size_class(&layout) {
+            return HEAP.lock().alloc_small(class);
+        }
+        if layout.align() > PAGE_SIZE {
+            return null_mut();
+        }
+
         match syscall::mmap(
             0,
-            size as u64,
+            layout.size() as u64,
             PROT_READ | PROT_WRITE,
             MAP_PRIVATE | MAP_ANONYMOUS,
         ) {
@@ -55,7 +140,11 @@ unsafe impl GlobalAlloc for MmapAllocator {
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        let size = layout.size();
-        let _ = syscall::munmap(ptr as u64, size as u64);
+        match size_class(&layout) {
+            Some(class) => HEAP.lock().free_small(ptr, class),
+            None => {
+                let _ = syscall::munmap(ptr as u64, layout.size() as u64);
+            }
+        }
     }
 }
@@ -110,11 +110,12 @@
 //! - `alloc()`, `dealloc()` - Simple allocation
 //! - `mmap()` - Flexible memory mapping
 //! - `MemoryRegion` - RAII memory handle
+//! - `shm_create()`, `map_shared()`, `share()` - Shared memory between processes
 //!
 //! ## [`alloc`]
 //!
 //! Global allocator for userland programs:
-//! - `MmapAllocator` - Uses mmap/munmap for heap allocation
+//! - `MmapAllocator` - Size-class arenas over lazily backed mmap regions
 //! - Automatic integration with Rust's `alloc` crate
 //!
 //! ## [`constants`]
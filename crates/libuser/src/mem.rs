@@ -4,23 +4,11 @@
 
 use crate::syscall::{self, SyscallResult};
 
-/// Memory protection flags
-/// No memory protection
-pub const PROT_NONE: u64 = 0;
-/// Memory can be read
-pub const PROT_READ: u64 = 1;
-/// Memory can be written
-pub const PROT_WRITE: u64 = 2;
-/// Memory can be executed
-pub const PROT_EXEC: u64 = 4;
-
-/// Memory mapping flags
-/// Create a private copy-on-write mapping
-pub const MAP_PRIVATE: u64 = 1;
-/// Map anonymous memory (not backed by a file)
-pub const MAP_ANONYMOUS: u64 = 2;
-/// Share mapping with other processes
-pub const MAP_SHARED: u64 = 4;
+pub use crate::abi::mman::{
+    MAP_ANONYMOUS, MAP_FIXED, MAP_FIXED_NOREPLACE, MAP_HUGE, MAP_PRIVATE, MAP_SHARED, PROT_EXEC,
+    PROT_NONE, PROT_READ, PROT_WRITE,
+};
+pub use crate::abi::rights::{RIGHT_DUP, RIGHT_EXEC, RIGHT_MAP, RIGHT_READ, RIGHT_TRANSFER, RIGHT_WRITE};
 
 /// Allocate memory using mmap
 ///
@@ -107,7 +95,9 @@ pub fn dealloc(addr: u64, size: u64) -> SyscallResult<()> {
 /// Address of mapped memory
 ///
 /// # Errors
-/// * `EINVAL` - Invalid arguments
+/// * `EINVAL` - Invalid arguments, or `MAP_HUGE` without `MAP_ANONYMOUS`
+/// * `EACCES` - `prot` is both writable and executable
+/// * `EEXIST` - `MAP_FIXED_NOREPLACE` range is in use
 /// * `ENOMEM` - Out of memory
 ///
 /// # Examples
@@ -124,6 +114,81 @@ pub fn mmap(addr: u64, len: u64, prot: u64, flags: u64) -> SyscallResult<u64> {
     syscall::mmap(addr, len, prot, flags)
 }
 
+/// Change the protection of mapped memory
+///
+/// # Arguments
+/// * `addr` - Page-aligned start address
+/// * `len` - Length in bytes (will be rounded up to page size)
+/// * `prot` - New protection flags (PROT_*)
+///
+/// # Errors
+/// * `EINVAL` - Invalid arguments
+/// * `EACCES` - `prot` is both writable and executable
+/// * `ENOMEM` - Part of the range is not mapped
+///
+/// # Examples
+/// ```no_run
+/// use libuser::mem::{alloc, protect, PROT_READ};
+///
+/// let addr = alloc(4096).unwrap();
+/// // Make the page read-only
+/// protect(addr, 4096, PROT_READ).unwrap();
+/// ```
+pub fn protect(addr: u64, len: u64, prot: u64) -> SyscallResult<()> {
+    syscall::mprotect(addr, len, prot)
+}
+
+/// Create a zero-filled shared memory object
+///
+/// Returns a capability handle with `RIGHT_READ | RIGHT_WRITE | RIGHT_MAP
+/// | RIGHT_DUP | RIGHT_TRANSFER`. Close it with
+/// [`syscall::capability_revoke`]; the memory stays alive while mapped.
+///
+/// # Errors
+/// * `EINVAL` - Size is zero or too large
+/// * `ENOMEM` - Out of memory
+pub fn shm_create(size: u64) -> SyscallResult<u64> {
+    syscall::shm_create(size)
+}
+
+/// Map the first `len` bytes of a shared memory object
+///
+/// Writes through the mapping are visible to every process that maps the
+/// same object.
+///
+/// # Errors
+/// * `EBADF` - Not a valid capability
+/// * `EACCES` - The capability lacks `RIGHT_MAP`/`RIGHT_READ`, or
+///   `RIGHT_WRITE` for `PROT_WRITE`
+/// * `EINVAL` - `len` exceeds the object
+///
+/// # Examples
+/// ```no_run
+/// use libuser::mem::{map_shared, shm_create, PROT_READ, PROT_WRITE};
+///
+/// let shm = shm_create(65536).unwrap();
+/// let addr = map_shared(shm, 65536, PROT_READ | PROT_WRITE).unwrap();
+/// ```
+pub fn map_shared(handle: u64, len: u64, prot: u64) -> SyscallResult<u64> {
+    syscall::mmap_object(0, len, prot, MAP_SHARED, handle, 0)
+}
+
+/// Give another process its own handle to a shared memory object
+///
+/// The caller keeps `handle`. The receiver gets a capability with
+/// `rights` (a subset of the caller's), whose value is returned and must
+/// be passed on to it, e.g. through a pipe.
+///
+/// # Errors
+/// * `EPERM` - `handle` lacks `RIGHT_TRANSFER` or `rights` exceed it
+/// * `ESRCH` - No such process
+pub fn share(handle: u64, pid: u64, rights: u64) -> SyscallResult<u64> {
+    let copy = syscall::capability_dup(handle, rights | RIGHT_TRANSFER)?;
+    syscall::capability_transfer(copy, pid, rights).inspect_err(|_| {
+        let _ = syscall::capability_revoke(copy);
+    })
+}
+
 /// Memory region handle (RAII wrapper)
 ///
 /// Automatically unmaps the memory when dropped.
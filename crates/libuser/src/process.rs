@@ -2,6 +2,7 @@
 
 use crate::syscall::{self, SyscallResult};
 use crate::abi::error::SyscallError;
+pub use crate::abi::personality::{ADDR_NO_RANDOMIZE, PERSONALITY_QUERY};
 
 /// Exit the current process with the given exit code
 ///
@@ -85,3 +86,28 @@ pub fn wait(pid: i64, status: Option<&mut i32>) -> SyscallResult<u64> {
 pub fn spawn(path: &str, args: &[&str]) -> SyscallResult<u64> {
     syscall::spawn(path, args)
 }
+
+/// Get the personality flags of the current process
+pub fn personality() -> u64 {
+    // Querying cannot fail
+    syscall::personality(PERSONALITY_QUERY).unwrap_or(0)
+}
+
+/// Set the personality flags inherited by processes spawned from now on
+///
+/// Returns the previous flags.
+///
+/// # Errors
+/// * `EINVAL` - Unknown flag
+///
+/// # Examples
+/// ```no_run
+/// use libuser::process::{personality, set_personality, spawn, ADDR_NO_RANDOMIZE};
+///
+/// // Run a program with a reproducible address-space layout
+/// set_personality(personality() | ADDR_NO_RANDOMIZE).unwrap();
+/// let pid = spawn("/bin/test", &[]).unwrap();
+/// ```
+pub fn set_personality(persona: u64) -> SyscallResult<u64> {
+    syscall::personality(persona)
+}
@@ -18,43 +18,62 @@ pub const SYS_WAIT: u64 = 8;
 pub const SYS_MMAP: u64 = 9;
 pub const SYS_MUNMAP: u64 = 10;
 pub const SYS_PIPE: u64 = 11;
-
-
+pub const SYS_REBOOT: u64 = 13;
+pub const SYS_GETRUSAGE: u64 = 14;
+pub const SYS_DMESG: u64 = 16;
+pub const SYS_TRACE_CTL: u64 = 17;
+pub const SYS_TRACE_READ: u64 = 18;
+pub const SYS_SHM_CREATE: u64 = 20;
+pub const SYS_PERSONALITY: u64 = 21;
+/// Native ABI number of `SyscallNumber::CapOpen`
+pub const SYS_OPEN: u64 = 0x0100;
+/// Native ABI number of `SyscallNumber::Mprotect`
+pub const SYS_MPROTECT: u64 = 0x0202;
+/// Native ABI number of `SyscallNumber::DebugSetLevel`
+pub const SYS_DEBUG_SET_LEVEL: u64 = 0xFF01;
+
+/// Well-known ID of the system control capability (granted to init only)
+pub const SYSTEM_CAP_ID: u64 = 15;
+
+/// sys_reboot commands
+pub const REBOOT_CMD_HALT: u64 = 0;
+pub const REBOOT_CMD_POWER_OFF: u64 = 1;
+pub const REBOOT_CMD_REBOOT: u64 = 2;
+
+
+
+// Benchmark syscalls
+pub const SYS_BENCHMARK: u64 = 1000;
+pub const SYS_FAST_POLL: u64 = 1001;
+
+/// `sys_benchmark` modes
+pub mod benchmark_mode {
+    /// Return immediately
+    pub const MINIMAL: u64 = 0;
+    /// Return the kernel's timestamp counter
+    pub const TIMESTAMP: u64 = 1;
+    /// Execute a full memory fence
+    pub const FENCE: u64 = 2;
+    /// Reload the address space as a context switch does
+    pub const SWITCH_MM: u64 = 4;
+    /// Reload the address space and flush its TLB entries
+    pub const SWITCH_MM_FLUSH: u64 = 5;
+}
 
 // V2 io_uring syscalls
 pub const SYS_IO_URING_SETUP: u64 = 2002;
 pub const SYS_IO_URING_ENTER: u64 = 2003;
 pub const SYS_CAPABILITY_DUP: u64 = 2004;
 pub const SYS_CAPABILITY_REVOKE: u64 = 2005;
+pub const SYS_CAPABILITY_TRANSFER: u64 = 2006;
+pub const SYS_IO_URING_REGISTER: u64 = 2007;
 
 /// System call result type
 pub type SyscallResult<T> = Result<T, SyscallError>;
 
 /// Convert errno (negative) to SyscallError
 fn errno_to_syscall_error(errno: i64) -> SyscallError {
-    match errno {
-        -1 => SyscallError::PermissionDenied,  // EPERM
-        -2 => SyscallError::NotFound,          // ENOENT
-        -3 => SyscallError::NoSuchProcess,     // ESRCH
-        -4 => SyscallError::Interrupted,       // EINTR
-        -5 => SyscallError::IoError,           // EIO
-        -9 => SyscallError::InvalidCapability, // EBADF
-        -11 => SyscallError::WouldBlock,       // EAGAIN
-        -12 => SyscallError::OutOfMemory,      // ENOMEM
-        -13 => SyscallError::PermissionDenied, // EACCES
-        -14 => SyscallError::InvalidAddress,   // EFAULT
-        -16 => SyscallError::Busy,             // EBUSY
-        -17 => SyscallError::AlreadyExists,    // EEXIST
-        -22 => SyscallError::InvalidArgument,  // EINVAL
-        -24 => SyscallError::TooManyOpen,      // EMFILE
-        -32 => SyscallError::BrokenPipe,       // EPIPE
-        -34 => SyscallError::InvalidArgument,  // ERANGE
-        -38 => SyscallError::NotImplemented,   // ENOSYS
-        -104 => SyscallError::ConnectionReset, // ECONNRESET
-        -110 => SyscallError::Timeout,         // ETIMEDOUT
-        -111 => SyscallError::ConnectionRefused, // ECONNREFUSED
-        _ => SyscallError::InternalError,      // Fallback
-    }
+    SyscallError::from_errno(errno)
 }
 
 /// Helper to convert syscall result to Result type
@@ -210,6 +229,14 @@ pub fn mmap(addr: u64, len: u64, prot: u64, flags: u64) -> SyscallResult<u64> {
     syscall_result(ret).map(|addr| addr as u64)
 }
 
+/// sys_mmap - Map a capability-backed object (shared memory or a file)
+pub fn mmap_object(addr: u64, len: u64, prot: u64, flags: u64, handle: u64, offset: u64) -> SyscallResult<u64> {
+    let ret = unsafe {
+        syscall6(SYS_MMAP, addr, len, prot, flags, handle, offset)
+    };
+    syscall_result(ret).map(|addr| addr as u64)
+}
+
 /// sys_munmap - Unmap memory
 pub fn munmap(addr: u64, len: u64) -> SyscallResult<()> {
     let ret = unsafe {
@@ -218,6 +245,28 @@ pub fn munmap(addr: u64, len: u64) -> SyscallResult<()> {
     syscall_result(ret).map(|_| ())
 }
 
+/// sys_mprotect - Change the protection of mapped memory
+pub fn mprotect(addr: u64, len: u64, prot: u64) -> SyscallResult<()> {
+    let ret = unsafe {
+        syscall6(SYS_MPROTECT, addr, len, prot, 0, 0, 0)
+    };
+    syscall_result(ret).map(|_| ())
+}
+
+/// sys_open - Open a file by absolute path
+///
+/// `rights` is a combination of `RIGHT_*` bits; the returned capability
+/// carries exactly these rights. Supports `/proc/<pid>/stat` and
+/// `/proc/<pid>/maps`, `/tmp/<name>` files (created with `RIGHT_CREATE`)
+/// and the files of the initrd. Initrd and `/tmp` files can be mapped
+/// with [`mmap_object`] given `RIGHT_MAP`.
+pub fn open(path: &str, rights: u64) -> SyscallResult<u64> {
+    let ret = unsafe {
+        syscall6(SYS_OPEN, path.as_ptr() as u64, path.len() as u64, rights, 0, 0, 0)
+    };
+    syscall_result(ret).map(|cap| cap as u64)
+}
+
 /// sys_pipe - Create a pipe
 pub fn pipe(fds: &mut [i32; 2]) -> SyscallResult<()> {
     let ret = unsafe {
@@ -226,6 +275,69 @@ pub fn pipe(fds: &mut [i32; 2]) -> SyscallResult<()> {
     syscall_result(ret).map(|_| ())
 }
 
+/// sys_getrusage - Get resource usage of the calling process
+pub fn getrusage() -> SyscallResult<crate::abi::rusage::RUsage> {
+    let mut usage = crate::abi::rusage::RUsage::default();
+    let ret = unsafe {
+        syscall6(SYS_GETRUSAGE, crate::abi::rusage::RUSAGE_SELF, &mut usage as *mut _ as u64, 0, 0, 0, 0)
+    };
+    syscall_result(ret).map(|_| usage)
+}
+
+/// sys_debug_set_level - Set the kernel log levels
+///
+/// `record` is the level kept in the kernel log ring, `console` the level
+/// also echoed to the serial console. Pass `KLOG_UNCHANGED` to keep one.
+/// `cap` must be the system capability (`SYSTEM_CAP_ID`, granted to init).
+pub fn debug_set_level(cap: u64, record: u64, console: u64) -> SyscallResult<()> {
+    let ret = unsafe {
+        syscall6(SYS_DEBUG_SET_LEVEL, cap, record, console, 0, 0, 0)
+    };
+    syscall_result(ret).map(|_| ())
+}
+
+/// sys_dmesg - Read kernel log lines starting at `*cursor`
+///
+/// Returns the number of bytes copied and advances `cursor`.
+pub fn dmesg(buf: &mut [u8], cursor: &mut u64) -> SyscallResult<usize> {
+    let ret = unsafe {
+        syscall6(SYS_DMESG, buf.as_mut_ptr() as u64, buf.len() as u64, cursor as *mut u64 as u64, 0, 0, 0)
+    };
+    syscall_result(ret).map(|n| n as usize)
+}
+
+/// sys_trace_ctl - Enable or disable syscall tracing of `pid` (0 = self)
+///
+/// `mode` is one of `TRACE_OFF`, `TRACE_ON`, `TRACE_CHILDREN`.
+/// Fails with `NotImplemented` unless the kernel has the `syscall_trace` feature.
+pub fn trace_ctl(pid: u64, mode: u64) -> SyscallResult<()> {
+    let ret = unsafe {
+        syscall6(SYS_TRACE_CTL, pid, mode, 0, 0, 0, 0)
+    };
+    syscall_result(ret).map(|_| ())
+}
+
+/// sys_trace_read - Drain trace events of `pid` (0 = self) into `events`
+///
+/// Returns the number of events copied.
+pub fn trace_read(pid: u64, events: &mut [crate::abi::trace::TraceEvent]) -> SyscallResult<usize> {
+    let ret = unsafe {
+        syscall6(SYS_TRACE_READ, pid, events.as_mut_ptr() as u64, events.len() as u64, 0, 0, 0)
+    };
+    syscall_result(ret).map(|n| n as usize)
+}
+
+/// sys_reboot - Halt, power off, or reboot the machine
+///
+/// `status` is the exit status reported to QEMU when powering off.
+/// Only returns on failure.
+pub fn reboot(cap: u64, cmd: u64, status: u32) -> SyscallError {
+    let ret = unsafe {
+        syscall6(SYS_REBOOT, cap, cmd, status as u64, 0, 0, 0)
+    };
+    errno_to_syscall_error(ret)
+}
+
 
 
 // ============================================================================
@@ -250,6 +362,25 @@ pub fn io_uring_enter(sqe_addr: u64, cqe_addr: u64) -> SyscallResult<()> {
     syscall_result(ret).map(|_| ())
 }
 
+/// sys_io_uring_register - Register `(base, len)` buffers for fixed-buffer I/O
+///
+/// Returns the index of the first buffer. Aligned 2 MiB blocks of
+/// anonymous buffers are backed by huge pages.
+pub fn io_uring_register_buffers(iovecs: &[[u64; 2]]) -> SyscallResult<u32> {
+    let ret = unsafe {
+        syscall6(
+            SYS_IO_URING_REGISTER,
+            0,
+            crate::abi::io_uring_common::register_op::REGISTER_BUFFERS,
+            iovecs.as_ptr() as u64,
+            iovecs.len() as u64,
+            0,
+            0,
+        )
+    };
+    syscall_result(ret).map(|index| index as u32)
+}
+
 /// sys_capability_dup - Duplicate capability
 pub fn capability_dup(capability_id: u64, rights: u64) -> SyscallResult<u64> {
     let ret = unsafe {
@@ -266,6 +397,67 @@ pub fn capability_revoke(handle: u64) -> SyscallResult<()> {
     syscall_result(ret).map(|_| ())
 }
 
+/// sys_capability_transfer - Move a capability to another process
+///
+/// Returns the handle value in the receiving process.
+pub fn capability_transfer(handle: u64, pid: u64, rights: u64) -> SyscallResult<u64> {
+    let ret = unsafe {
+        syscall6(SYS_CAPABILITY_TRANSFER, handle, pid, rights, 0, 0, 0)
+    };
+    syscall_result(ret).map(|cap| cap as u64)
+}
+
+/// sys_shm_create - Create a shared memory object
+pub fn shm_create(size: u64) -> SyscallResult<u64> {
+    let ret = unsafe {
+        syscall6(SYS_SHM_CREATE, size, 0, 0, 0, 0, 0)
+    };
+    syscall_result(ret).map(|cap| cap as u64)
+}
+
+/// sys_personality - Set or query the personality flags
+///
+/// Returns the previous personality.
+pub fn personality(persona: u64) -> SyscallResult<u64> {
+    let ret = unsafe {
+        syscall6(SYS_PERSONALITY, persona, 0, 0, 0, 0, 0)
+    };
+    syscall_result(ret).map(|old| old as u64)
+}
+
+/// sys_benchmark - Minimal syscall for overhead measurements
+///
+/// See [`benchmark_mode`] for the modes. The address-space modes return
+/// the PCID in use, 0 when the kernel runs without PCIDs.
+pub fn benchmark(mode: u64) -> SyscallResult<u64> {
+    let ret = unsafe {
+        syscall6(SYS_BENCHMARK, mode, 0, 0, 0, 0, 0)
+    };
+    syscall_result(ret).map(|value| value as u64)
+}
+
+/// sys_fast_poll - Process the submissions of every SQPOLL ring
+///
+/// Returns the number of operations processed.
+pub fn fast_poll() -> SyscallResult<u64> {
+    let ret = unsafe {
+        syscall6(SYS_FAST_POLL, 0, 0, 0, 0, 0, 0)
+    };
+    syscall_result(ret).map(|count| count as u64)
+}
+
+/// Read the timestamp counter
+#[inline]
+#[must_use]
+pub fn rdtsc() -> u64 {
+    let low: u32;
+    let high: u32;
+    unsafe {
+        core::arch::asm!("rdtsc", out("eax") low, out("edx") high, options(nomem, nostack));
+    }
+    (u64::from(high) << 32) | u64::from(low)
+}
+
 // ============================================================================
 // Convenience Macros
 // ============================================================================
@@ -5,14 +5,43 @@
 //! 1. **Standard syscall** - Traditional syscall via `syscall` instruction
 //! 2. **Benchmark syscall (ID: 1000)** - Minimal overhead syscall
 //! 3. **Fast I/O ring** - Syscall-less I/O via shared memory
+//! 4. **Address-space switch** - TLB refill cost of a context switch, with
+//!    the kernel's PCID-tagged CR3 reload against a flushing one
 //!
 //! This helps evaluate the effectiveness of syscall optimization strategies.
 
 #![no_std]
 #![no_main]
 
-use libuser::{println, process};
-use libuser::syscall::{benchmark, benchmark_mode, fast_io_setup, fast_poll, fast_io_flags, rdtsc};
+use libuser::{mem, println, process};
+use libuser::constants::PAGE_SIZE_U64;
+use libuser::ring_io::Ring;
+use libuser::syscall::{benchmark, benchmark_mode, fast_poll, rdtsc};
+
+/// Pages touched after every address-space switch
+const WORKING_SET_PAGES: u64 = 64;
+/// Address-space switches per measurement
+const SWITCH_ITERATIONS: u64 = 10_000;
+
+/// Average cycles of one address-space switch in `mode` followed by a
+/// read of every page in the working set at `base`
+fn measure_switch(mode: u64, base: u64) -> u64 {
+    let start = rdtsc();
+    for _ in 0..SWITCH_ITERATIONS {
+        let _ = benchmark(mode);
+        touch_pages(base);
+    }
+    rdtsc().saturating_sub(start) / SWITCH_ITERATIONS
+}
+
+/// Read one byte from every page of the working set
+fn touch_pages(base: u64) {
+    for page in 0..WORKING_SET_PAGES {
+        let ptr = (base + page * PAGE_SIZE_U64) as *const u8;
+        // SAFETY: The working set is mapped readable for the whole run
+        unsafe { core::ptr::read_volatile(ptr) };
+    }
+}
 
 #[no_mangle]
 pub extern "C" fn _start() -> ! {
@@ -23,6 +52,7 @@ pub extern "C" fn _start() -> ! {
     println!("║  1. Standard syscall (getpid)                              ║");
     println!("║  2. Benchmark syscall (ID: 1000, minimal)                  ║");
     println!("║  3. Fast I/O ring (syscall-less)                           ║");
+    println!("║  4. Address-space switch (PCID vs. TLB flush)              ║");
     println!("╚════════════════════════════════════════════════════════════╝\n");
 
     // Warmup phase
@@ -118,12 +148,11 @@ pub extern "C" fn _start() -> ! {
     println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
     println!("TEST 5: Fast I/O Setup (syscall-less mode)");
     println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
-    
-    match fast_io_setup(fast_io_flags::SQPOLL) {
-        Ok(ctx) => {
-            println!("  Fast I/O context: 0x{:x}", ctx);
+
+    match Ring::setup(true) {
+        Ok(_ring) => {
             println!("  SQPOLL mode enabled");
-            
+
             // Test fast_poll
             let start = rdtsc();
             for _ in 0..iterations {
@@ -132,7 +161,7 @@ pub extern "C" fn _start() -> ! {
             let end = rdtsc();
             let total_cycles_poll = end.saturating_sub(start);
             let avg_cycles_poll = total_cycles_poll / iterations;
-            
+
             println!("  Fast poll avg:    {} cycles", avg_cycles_poll);
         }
         Err(e) => {
@@ -141,6 +170,35 @@ pub extern "C" fn _start() -> ! {
     }
     println!();
 
+    // Test 6: Address-space switch with and without a TLB flush
+    println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+    println!("TEST 6: Address-space switch ({} pages touched)", WORKING_SET_PAGES);
+    println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+
+    let switch = match mem::alloc(WORKING_SET_PAGES * PAGE_SIZE_U64) {
+        Ok(base) => {
+            // Fault the working set in before measuring
+            touch_pages(base);
+            let pcid = benchmark(benchmark_mode::SWITCH_MM).unwrap_or(0);
+            let kept = measure_switch(benchmark_mode::SWITCH_MM, base);
+            let flushed = measure_switch(benchmark_mode::SWITCH_MM_FLUSH, base);
+
+            if pcid == 0 {
+                println!("  PCID:             disabled (every switch flushes)");
+            } else {
+                println!("  PCID:             {}", pcid);
+            }
+            println!("  Switch (kept TLB):    {} cycles", kept);
+            println!("  Switch (flushed TLB): {} cycles", flushed);
+            Some((kept, flushed))
+        }
+        Err(e) => {
+            println!("  Working set allocation failed: {}", e.description());
+            None
+        }
+    };
+    println!();
+
     // Summary
     println!("╔════════════════════════════════════════════════════════════╗");
     println!("║                        SUMMARY                             ║");
@@ -157,6 +215,12 @@ pub extern "C" fn _start() -> ! {
     
     println!("║ Syscall overhead:          {:>8} cycles                 ║", syscall_overhead);
     println!("║ Benchmark overhead:        {:>8} cycles                 ║", bench_overhead);
+
+    if let Some((kept, flushed)) = switch {
+        println!("║ Switch, TLB kept:          {:>8} cycles                 ║", kept);
+        println!("║ Switch, TLB flushed:       {:>8} cycles                 ║", flushed);
+        println!("║ Saved per switch:          {:>8} cycles                 ║", flushed.saturating_sub(kept));
+    }
     
     if bench_overhead > 0 && syscall_overhead > bench_overhead {
         let improvement = ((syscall_overhead - bench_overhead) * 100) / syscall_overhead;
@@ -0,0 +1,124 @@
+//! strace - trace the syscalls of a program
+//!
+//! Usage: `strace <path> [args...]`
+//!
+//! Spawns the program with tracing enabled and prints every syscall and
+//! io_uring submission it makes until it exits. Requires a kernel built
+//! with the `syscall_trace` feature.
+
+#![no_std]
+#![no_main]
+
+use libuser::abi::trace::{TraceEvent, TraceKind, TRACE_CHILDREN, TRACE_OFF};
+use libuser::{println, process, syscall};
+
+/// Maximum number of arguments passed through to the traced program
+const MAX_ARGS: usize = 16;
+
+#[unsafe(no_mangle)]
+#[unsafe(link_section = ".text.entry")]
+pub extern "C" fn _start(argc: u64, argv: *const *const u8) -> ! {
+    let mut args = [""; MAX_ARGS];
+    let argc = (argc as usize).min(MAX_ARGS);
+    for (i, arg) in args.iter_mut().enumerate().take(argc) {
+        // SAFETY: The kernel passes `argc` valid NUL-terminated strings
+        *arg = unsafe { c_str(*argv.add(i)) };
+    }
+    if argc == 0 {
+        println!("usage: strace <path> [args...]");
+        process::exit(1);
+    }
+
+    if let Err(e) = syscall::trace_ctl(0, TRACE_CHILDREN) {
+        println!("strace: cannot enable tracing: {} (kernel built without syscall_trace?)", e);
+        process::exit(1);
+    }
+    let spawned = process::spawn(args[0], &args[1..argc]);
+    let _ = syscall::trace_ctl(0, TRACE_OFF);
+    let pid = match spawned {
+        Ok(pid) => pid,
+        Err(e) => {
+            println!("strace: {}: {}", args[0], e);
+            process::exit(1);
+        }
+    };
+
+    let mut events = [TraceEvent::default(); 32];
+    let mut expected_seq = 0;
+    'outer: loop {
+        let count = match syscall::trace_read(pid, &mut events) {
+            Ok(count) => count,
+            Err(e) => {
+                println!("strace: trace_read failed: {}", e);
+                break;
+            }
+        };
+        if count == 0 {
+            for _ in 0..100_000 {
+                core::hint::spin_loop();
+            }
+            continue;
+        }
+
+        for event in &events[..count] {
+            if event.seq != expected_seq {
+                println!("... {} events dropped ...", event.seq - expected_seq);
+            }
+            expected_seq = event.seq + 1;
+
+            print_event(event);
+            if event.kind() == Some(TraceKind::Exit) {
+                break 'outer;
+            }
+        }
+    }
+
+    let mut status = 0;
+    let _ = process::wait(pid as i64, Some(&mut status));
+    process::exit(status);
+}
+
+/// Print one event in strace style
+fn print_event(event: &TraceEvent) {
+    match event.kind() {
+        Some(TraceKind::Exit) => {
+            println!("+++ exited with {} +++", event.result);
+            return;
+        }
+        Some(TraceKind::Sqe) => libuser::print!("io_uring:"),
+        _ => {}
+    }
+
+    libuser::print!("{}(", event.name());
+    for (i, arg) in event.args.iter().take(event.arg_count()).enumerate() {
+        if i > 0 {
+            libuser::print!(", ");
+        }
+        libuser::print!("{:#x}", arg);
+    }
+    match event.error() {
+        Some(e) => println!(") = {} {:?} ({}) <{} cycles>", event.result, e, e, event.duration_tsc),
+        None => println!(") = {} <{} cycles>", event.result, event.duration_tsc),
+    }
+}
+
+/// Borrow a NUL-terminated string
+///
+/// # Safety
+/// `ptr` must point to a NUL-terminated string that lives for the whole program.
+unsafe fn c_str(ptr: *const u8) -> &'static str {
+    let mut len = 0;
+    // SAFETY: Guaranteed by the caller
+    unsafe {
+        while *ptr.add(len) != 0 {
+            len += 1;
+        }
+        core::str::from_utf8(core::slice::from_raw_parts(ptr, len)).unwrap_or("")
+    }
+}
+
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    println!("[strace] PANIC: {}", info);
+    process::exit(1);
+}
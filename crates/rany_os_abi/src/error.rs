@@ -274,6 +274,39 @@ impl SyscallError {
         }
     }
 
+    /// Convert a negative Linux-style errno returned by a syscall
+    ///
+    /// Unknown codes map to [`SyscallError::InternalError`].
+    #[must_use]
+    pub const fn from_errno(errno: i64) -> Self {
+        match errno {
+            -1 => Self::PermissionDenied,   // EPERM
+            -2 => Self::NotFound,           // ENOENT
+            -3 => Self::NoSuchProcess,      // ESRCH
+            -4 => Self::Interrupted,        // EINTR
+            -5 => Self::IoError,            // EIO
+            -9 => Self::InvalidCapability,  // EBADF
+            -10 => Self::NoSuchProcess,     // ECHILD
+            -11 => Self::WouldBlock,        // EAGAIN
+            -12 => Self::OutOfMemory,       // ENOMEM
+            -13 => Self::PermissionDenied,  // EACCES
+            -14 => Self::InvalidAddress,    // EFAULT
+            -16 => Self::Busy,              // EBUSY
+            -17 => Self::AlreadyExists,     // EEXIST
+            -19 => Self::NotImplemented,    // ENODEV
+            -22 => Self::InvalidArgument,   // EINVAL
+            -24 => Self::TooManyOpen,       // EMFILE
+            -28 => Self::FilesystemFull,    // ENOSPC
+            -32 => Self::BrokenPipe,        // EPIPE
+            -34 => Self::InvalidArgument,   // ERANGE
+            -38 => Self::NotImplemented,    // ENOSYS
+            -104 => Self::ConnectionReset,  // ECONNRESET
+            -110 => Self::Timeout,          // ETIMEDOUT
+            -111 => Self::ConnectionRefused, // ECONNREFUSED
+            _ => Self::InternalError,
+        }
+    }
+
     /// Get the raw u32 value
     #[must_use]
     pub const fn to_u32(self) -> u32 {
@@ -80,6 +80,29 @@ impl OpCode {
             _ => None,
         }
     }
+
+    /// Uppercase name used in traces (`IORING_OP_*` style)
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Nop => "NOP",
+            Self::Read => "READ",
+            Self::Write => "WRITE",
+            Self::Open => "OPEN",
+            Self::Close => "CLOSE",
+            Self::Fsync => "FSYNC",
+            Self::Poll => "POLL",
+            Self::Cancel => "CANCEL",
+            Self::LinkTimeout => "LINK_TIMEOUT",
+            Self::Connect => "CONNECT",
+            Self::Accept => "ACCEPT",
+            Self::Send => "SEND",
+            Self::Recv => "RECV",
+            Self::Mmap => "MMAP",
+            Self::Munmap => "MUNMAP",
+            Self::Exit => "EXIT",
+        }
+    }
 }
 
 /// Submission entry flags
@@ -125,4 +148,13 @@ impl core::ops::BitAnd for IoUringFlags {
     fn bitand(self, rhs: Self) -> Self::Output {
         Self(self.0 & rhs.0)
     }
-}
\ No newline at end of file
+}
+/// `io_uring_register` operations (`opcode` argument)
+pub mod register_op {
+    /// Register the `nr_args` buffers of the iovec array at `arg`
+    ///
+    /// Returns the index of the first buffer.
+    pub const REGISTER_BUFFERS: u64 = 0;
+    /// Unregister all buffers
+    pub const UNREGISTER_BUFFERS: u64 = 1;
+}
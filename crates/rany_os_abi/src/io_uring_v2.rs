@@ -255,6 +255,8 @@ impl SubmissionEntryV2 {
     }
 
     /// Create an mmap entry
+    ///
+    /// The mapped address is returned in the completion's `aux` field.
     #[must_use]
     pub const fn mmap(addr_hint: u64, len: u32, user_data: u64) -> Self {
         Self {
@@ -322,6 +324,7 @@ pub struct CompletionEntryV2 {
     /// Auxiliary data (operation-specific)
     /// - For accept: peer address info
     /// - For recv: message flags
+    /// - For mmap: the mapped address
     pub aux: u64,
 
     /// Padding to maintain alignment
@@ -0,0 +1,33 @@
+// rany_os_abi/src/klog.rs
+//! Kernel log levels and the `dmesg` interface
+//!
+//! Levels are shared by `debug_set_level` and the records returned by
+//! `dmesg`. They match the `log` crate's `LevelFilter` ordering.
+//!
+//! # dmesg Output
+//!
+//! `dmesg` copies complete text lines into the user buffer:
+//!
+//! ```text
+//! <seq> [<seconds>.<millis>] <LEVEL> <tag>: <message>\n
+//! ```
+//!
+//! The caller passes a cursor (sequence number) that the kernel advances
+//! past the last copied record, so repeated calls read the log incrementally.
+//! Records overwritten before they were read are skipped.
+
+/// Logging disabled
+pub const KLOG_OFF: u64 = 0;
+/// Errors only
+pub const KLOG_ERROR: u64 = 1;
+/// Warnings and above
+pub const KLOG_WARN: u64 = 2;
+/// Informational messages and above
+pub const KLOG_INFO: u64 = 3;
+/// Debug messages and above
+pub const KLOG_DEBUG: u64 = 4;
+/// Everything, including per-syscall traces
+pub const KLOG_TRACE: u64 = 5;
+
+/// Leave the level unchanged (`debug_set_level` argument)
+pub const KLOG_UNCHANGED: u64 = u64::MAX;
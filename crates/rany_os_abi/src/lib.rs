@@ -19,6 +19,12 @@
 //! - [`result`]: ABI-safe Result types
 //! - [`io_uring_common`]: Common io_uring constants and opcodes
 //! - [`io_uring_v2`]: V2 io_uring entry structures
+//! - [`rusage`]: Per-process resource usage
+//! - [`klog`]: Kernel log levels and `dmesg`
+//! - [`mman`]: `mmap` / `mprotect` protection and flag bits
+//! - [`personality`]: Process personality flags (ASLR opt-out)
+//! - [`rights`]: Capability rights bits
+//! - [`trace`]: Per-process syscall tracing
 
 #![no_std]
 #![warn(missing_docs)]
@@ -27,8 +33,14 @@
 pub mod error;
 pub mod io_uring_common;
 pub mod io_uring_v2;
+pub mod klog;
+pub mod mman;
 pub mod native;
+pub mod personality;
 pub mod result;
+pub mod rights;
+pub mod rusage;
+pub mod trace;
 
 // Re-export commonly used types
 pub use error::{ErrorCategory, SyscallError, SyscallResult};
@@ -39,4 +51,5 @@ pub use native::{
     PipeHandle, PipeMarker, ResourceId, ResourceMarker, SocketHandle, SocketMarker,
     SyscallCategory, SyscallNumber,
 };
+pub use rusage::{RUsage, RUSAGE_SELF};
 pub use result::{AbiResult, AbiResultI32, AbiResultI64, AbiResultU64, AbiResultUnit, AbiResultUsize, CompactResult};
@@ -0,0 +1,89 @@
+// rany_os_abi/src/mman.rs
+//! Memory mapping protection and flag bits for `mmap` and `mprotect`
+//!
+//! # Protection
+//!
+//! `PROT_*` bits select the page permissions. A mapping may be writable or
+//! executable, but never both (W^X): requests combining [`PROT_WRITE`] and
+//! [`PROT_EXEC`] fail with `EACCES`. Everything not marked [`PROT_EXEC`] is
+//! mapped non-executable.
+//!
+//! # Placement
+//!
+//! Without [`MAP_FIXED`] the address argument is only a hint; the kernel
+//! uses it when the range is free and otherwise picks the lowest free
+//! range in the process's mmap window, reusing holes left by `munmap`.
+//! The window ends at [`MMAP_END`] and starts at [`MMAP_BASE`] plus a
+//! random offset of up to 1 TiB chosen when the process is created (see
+//! [`crate::personality`] to disable this). [`MAP_FIXED`] replaces whatever was mapped at the address,
+//! while [`MAP_FIXED_NOREPLACE`] fails with `EEXIST` instead.
+//!
+//! Fixed and hinted addresses must lie in user space at or above
+//! [`MMAP_MIN_ADDR`]. Kernel-shared areas such as the syscall ring cannot
+//! be replaced, unmapped or reprotected. The guard page below the user
+//! stack stays unmapped so that stack overflows fault; mappings over it
+//! fail with `ENOMEM`.
+//!
+//! # Backing
+//!
+//! [`MAP_PRIVATE`] anonymous mappings only reserve address space; each
+//! page is zero-filled when it is first accessed, so large reservations
+//! are cheap. [`MAP_SHARED`] mappings are backed immediately.
+//!
+//! With [`MAP_HUGE`], an anonymous mapping is backed by 2 MiB pages
+//! wherever it covers a whole, 2 MiB-aligned block, and by 4 KiB pages at
+//! the unaligned edges. Without a fixed address the kernel places such a
+//! mapping on a 2 MiB boundary. Huge pages are split transparently when
+//! part of one is unmapped, reprotected or written after a fork, and the
+//! kernel falls back to 4 KiB pages when no 2 MiB block is free.
+//!
+//! # Shared memory objects
+//!
+//! Without [`MAP_ANONYMOUS`], the `fd` argument is a shared memory
+//! capability from `shm_create` and `offset` a page-aligned offset into
+//! it. Mapping needs the `MAP` and `READ` rights; [`PROT_WRITE`] on a
+//! [`MAP_SHARED`] mapping needs `WRITE`, and [`PROT_EXEC`] needs `EXEC`
+//! (see [`crate::rights`]). `mprotect` cannot go beyond these rights.
+//! [`MAP_PRIVATE`] mappings of an object are copy-on-write.
+
+/// Pages may not be accessed
+pub const PROT_NONE: u64 = 0;
+/// Pages may be read
+pub const PROT_READ: u64 = 1;
+/// Pages may be written
+pub const PROT_WRITE: u64 = 2;
+/// Pages may be executed
+pub const PROT_EXEC: u64 = 4;
+/// All valid protection bits
+pub const PROT_MASK: u64 = PROT_READ | PROT_WRITE | PROT_EXEC;
+
+/// Private copy-on-write mapping
+pub const MAP_PRIVATE: u64 = 1;
+/// Anonymous memory, not backed by a file (`fd` and `offset` are ignored)
+pub const MAP_ANONYMOUS: u64 = 2;
+/// Mapping shared with other processes
+pub const MAP_SHARED: u64 = 4;
+/// Place the mapping exactly at `addr`, replacing existing pages
+pub const MAP_FIXED: u64 = 8;
+/// Place the mapping exactly at `addr`, failing if any page is in use
+pub const MAP_FIXED_NOREPLACE: u64 = 16;
+/// Back an anonymous mapping with 2 MiB pages where alignment allows
+pub const MAP_HUGE: u64 = 32;
+/// All valid mapping flags
+pub const MAP_MASK: u64 =
+    MAP_PRIVATE | MAP_ANONYMOUS | MAP_SHARED | MAP_FIXED | MAP_FIXED_NOREPLACE | MAP_HUGE;
+
+/// Lowest address accepted for fixed or hinted mappings
+pub const MMAP_MIN_ADDR: u64 = 0x1_0000;
+/// Start of the range searched for mappings without a fixed address,
+/// before layout randomisation
+pub const MMAP_BASE: u64 = 0x0000_6000_0000_0000;
+/// End (exclusive) of the range searched for mappings without a fixed
+/// address, leaving room below the user stack
+pub const MMAP_END: u64 = 0x0000_6F00_0000_0000;
+
+/// Whether `prot` requests both write and execute access
+#[must_use]
+pub const fn violates_wx(prot: u64) -> bool {
+    prot & (PROT_WRITE | PROT_EXEC) == PROT_WRITE | PROT_EXEC
+}
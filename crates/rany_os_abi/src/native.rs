@@ -101,6 +101,32 @@ impl SyscallNumber {
         self as u16
     }
 
+    /// Lowercase name used in traces
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::IoUringSetup => "io_uring_setup",
+            Self::IoUringEnter => "io_uring_enter",
+            Self::IoUringRegister => "io_uring_register",
+            Self::CapOpen => "cap_open",
+            Self::CapClose => "cap_close",
+            Self::CapDup => "cap_dup",
+            Self::CapTransfer => "cap_transfer",
+            Self::CapQuery => "cap_query",
+            Self::CapRestrict => "cap_restrict",
+            Self::Mmap => "mmap",
+            Self::Munmap => "munmap",
+            Self::Mprotect => "mprotect",
+            Self::Exit => "exit",
+            Self::Exec => "exec",
+            Self::Wait => "wait",
+            Self::GetPid => "getpid",
+            Self::Yield => "yield",
+            Self::DebugPrint => "debug_print",
+            Self::DebugSetLevel => "debug_set_level",
+        }
+    }
+
     /// Get the category of this syscall
     #[must_use]
     pub const fn category(&self) -> SyscallCategory {
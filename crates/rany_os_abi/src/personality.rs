@@ -0,0 +1,22 @@
+// rany_os_abi/src/personality.rs
+//! Process personality flags (`personality`)
+//!
+//! A process's personality is inherited by every process it spawns
+//! afterwards. The caller's own address space is not changed; to run a
+//! program with a fixed layout, set [`ADDR_NO_RANDOMIZE`] and then spawn
+//! it.
+//!
+//! # Address-space layout randomisation
+//!
+//! By default the kernel moves the user stack, the mmap window, the
+//! syscall ring context and position-independent executables to random,
+//! page-aligned addresses when a process is created. With
+//! [`ADDR_NO_RANDOMIZE`] they stay at their fixed defaults, so addresses
+//! repeat from run to run, which helps with debugging.
+
+/// Disable address-space layout randomisation for spawned processes
+pub const ADDR_NO_RANDOMIZE: u64 = 0x0004_0000;
+/// All valid personality flags
+pub const PERSONALITY_MASK: u64 = ADDR_NO_RANDOMIZE;
+/// Argument that only queries the current personality
+pub const PERSONALITY_QUERY: u64 = 0xFFFF_FFFF;
@@ -0,0 +1,21 @@
+// rany_os_abi/src/rights.rs
+//! Capability rights bits visible to user space
+//!
+//! Rights travel with a capability handle and can only be narrowed: by
+//! `cap_dup`, `cap_transfer`, or when the kernel hands out a handle. The
+//! values match the kernel's `Rights` type.
+
+/// Read data (required for any mapping of a shared-memory object)
+pub const RIGHT_READ: u64 = 1 << 0;
+/// Write data (required for `PROT_WRITE` on a `MAP_SHARED` mapping)
+pub const RIGHT_WRITE: u64 = 1 << 1;
+/// Memory-map the resource
+pub const RIGHT_MAP: u64 = 1 << 3;
+/// Duplicate the capability
+pub const RIGHT_DUP: u64 = 1 << 4;
+/// Transfer the capability to another process
+pub const RIGHT_TRANSFER: u64 = 1 << 5;
+/// Create the file if it does not exist (`open` of a `/tmp` file)
+pub const RIGHT_CREATE: u64 = 1 << 8;
+/// Map the resource executable (`PROT_EXEC`)
+pub const RIGHT_EXEC: u64 = 1 << 24;
@@ -0,0 +1,41 @@
+// rany_os_abi/src/rusage.rs
+//! Resource usage reporting (`getrusage`)
+//!
+//! # Memory Layout
+//!
+//! ```text
+//! RUsage (48 bytes, repr(C)):
+//! +0   user_tsc (8)
+//! +8   kernel_tsc (8)
+//! +16  context_switches (8)
+//! +24  page_faults (8)
+//! +32  syscalls (8)
+//! +40  io_uring_sqes (8)
+//! = 48 bytes
+//! ```
+
+/// Report usage of the calling process
+pub const RUSAGE_SELF: u64 = 0;
+
+/// Resource usage of a process
+///
+/// CPU time is reported in raw TSC cycles; the kernel does not convert
+/// it to wall-clock units.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RUsage {
+    /// TSC cycles spent executing user code
+    pub user_tsc: u64,
+    /// TSC cycles spent in the kernel on behalf of the process
+    pub kernel_tsc: u64,
+    /// Number of times the process was switched out
+    pub context_switches: u64,
+    /// Number of page faults taken
+    pub page_faults: u64,
+    /// Number of syscalls made
+    pub syscalls: u64,
+    /// Number of `io_uring` submission entries processed
+    pub io_uring_sqes: u64,
+}
+
+const _: () = assert!(core::mem::size_of::<RUsage>() == 48);
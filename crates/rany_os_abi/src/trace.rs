@@ -0,0 +1,198 @@
+// rany_os_abi/src/trace.rs
+//! Per-process syscall tracing (`strace`)
+//!
+//! Available when the kernel is built with the `syscall_trace` feature;
+//! otherwise the trace syscalls return `ENOSYS`.
+//!
+//! The kernel records raw numbers and arguments; decoding into names is done
+//! in user space with [`syscall_name`], [`OpCode::name`] and
+//! [`SyscallError::from_errno`].
+//!
+//! # Memory Layout
+//!
+//! ```text
+//! TraceEvent (80 bytes, repr(C)):
+//! +0   seq (8)
+//! +8   kind (4)
+//! +12  number (4)
+//! +16  args (48)
+//! +64  result (8)
+//! +72  duration_tsc (8)
+//! = 80 bytes
+//! ```
+
+use crate::error::SyscallError;
+use crate::io_uring_common::OpCode;
+use crate::native::SyscallNumber;
+
+/// Stop tracing the process
+pub const TRACE_OFF: u64 = 0;
+/// Trace the process
+pub const TRACE_ON: u64 = 1;
+/// Trace processes spawned by the process from now on (not the process itself)
+pub const TRACE_CHILDREN: u64 = 2;
+
+/// Number of events kept per process; older events are dropped
+pub const TRACE_CAPACITY: usize = 256;
+
+/// What a [`TraceEvent`] describes
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceKind {
+    /// A syscall; `number` is the syscall number
+    Syscall = 0,
+    /// An `io_uring` submission; `number` is the [`OpCode`]
+    Sqe = 1,
+    /// The process exited; `result` is the exit code
+    Exit = 2,
+}
+
+impl TraceKind {
+    /// Convert from raw u32 value
+    #[must_use]
+    pub const fn from_u32(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(Self::Syscall),
+            1 => Some(Self::Sqe),
+            2 => Some(Self::Exit),
+            _ => None,
+        }
+    }
+}
+
+/// A single traced event
+///
+/// For [`TraceKind::Sqe`] the arguments are
+/// `(capability_id, off, buf_index, len, op_flags, user_data)`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TraceEvent {
+    /// Per-process sequence number; gaps mean events were dropped
+    pub seq: u64,
+    /// Raw [`TraceKind`]
+    pub kind: u32,
+    /// Syscall number or `io_uring` opcode
+    pub number: u32,
+    /// Raw arguments
+    pub args: [u64; 6],
+    /// Return value (negative on failure, see [`TraceEvent::error`])
+    pub result: i64,
+    /// TSC cycles spent in the kernel
+    pub duration_tsc: u64,
+}
+
+const _: () = assert!(core::mem::size_of::<TraceEvent>() == 80);
+
+impl TraceEvent {
+    /// Event kind, if known
+    #[must_use]
+    pub const fn kind(&self) -> Option<TraceKind> {
+        TraceKind::from_u32(self.kind)
+    }
+
+    /// Error returned by the call, if it failed
+    ///
+    /// Syscalls fail with a negative errno; SQEs carry the negated
+    /// [`SyscallError`] code of their completion. A code that does not fit
+    /// a [`SyscallError`] is reported as [`SyscallError::Unknown`].
+    #[must_use]
+    pub fn error(&self) -> Option<SyscallError> {
+        if self.result >= 0 {
+            return None;
+        }
+        match self.kind() {
+            Some(TraceKind::Syscall) => Some(SyscallError::from_errno(self.result)),
+            Some(TraceKind::Sqe) => Some(
+                u32::try_from(self.result.unsigned_abs()).map_or(SyscallError::Unknown, SyscallError::from_u32),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Name of the syscall or operation
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        match self.kind() {
+            Some(TraceKind::Syscall) => syscall_name(u64::from(self.number)),
+            Some(TraceKind::Sqe) => u8::try_from(self.number).ok().and_then(OpCode::from_u8).map_or("?", OpCode::name),
+            Some(TraceKind::Exit) => "exit",
+            None => "?",
+        }
+    }
+
+    /// Number of meaningful arguments
+    #[must_use]
+    pub const fn arg_count(&self) -> usize {
+        match self.kind() {
+            Some(TraceKind::Syscall) => syscall_arg_count(self.number as u64),
+            Some(TraceKind::Sqe) => 6,
+            _ => 0,
+        }
+    }
+}
+
+/// Name of a kernel syscall number
+#[must_use]
+pub const fn syscall_name(number: u64) -> &'static str {
+    match number {
+        0 => "write",
+        1 => "read",
+        2 => SyscallNumber::Exit.name(),
+        3 => SyscallNumber::GetPid.name(),
+        6 => "spawn",
+        8 => SyscallNumber::Wait.name(),
+        9 => SyscallNumber::Mmap.name(),
+        10 => SyscallNumber::Munmap.name(),
+        11 => "pipe",
+        13 => "reboot",
+        14 => "getrusage",
+        16 => "dmesg",
+        17 => "trace_ctl",
+        18 => "trace_read",
+        20 => "shm_create",
+        21 => "personality",
+        0x0100 => SyscallNumber::CapOpen.name(),
+        0x0202 => SyscallNumber::Mprotect.name(),
+        0xFF01 => SyscallNumber::DebugSetLevel.name(),
+        1000 => "benchmark",
+        1001 => "fast_poll",
+        2002 => SyscallNumber::IoUringSetup.name(),
+        2003 => SyscallNumber::IoUringEnter.name(),
+        2004 => SyscallNumber::CapDup.name(),
+        2005 => "cap_revoke",
+        2006 => SyscallNumber::CapTransfer.name(),
+        2007 => SyscallNumber::IoUringRegister.name(),
+        _ => "unknown",
+    }
+}
+
+/// Number of arguments taken by a kernel syscall
+#[must_use]
+pub const fn syscall_arg_count(number: u64) -> usize {
+    match number {
+        3 => 0,
+        2 | 11 | 20 | 21 => 1,
+        10 | 14 | 17 | 2005 => 2,
+        0 | 1 | 8 | 13 | 16 | 18 | 0x0100 | 0x0202 | 0xFF01 | 2004 | 2006 => 3,
+        6 | 2007 => 4,
+        _ => 6,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sqe(number: u32, result: i64) -> TraceEvent {
+        TraceEvent { kind: TraceKind::Sqe as u32, number, result, ..TraceEvent::default() }
+    }
+
+    #[test]
+    fn test_out_of_range_sqe_is_not_truncated() {
+        // 0x100 would truncate to opcode 0
+        assert_eq!(sqe(0x100, 0).name(), "?");
+        // -(1 << 32 | 1) would truncate to InvalidArgument
+        assert_eq!(sqe(0, -((1 << 32) | 1)).error(), Some(SyscallError::Unknown));
+        assert_eq!(sqe(0, -1).error(), Some(SyscallError::InvalidArgument));
+    }
+}
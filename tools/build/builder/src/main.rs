@@ -157,7 +157,7 @@ fn quick_image_mode(args: QuickImageArgs) {
     
     // 1. Build Userland Programs
     println!("Building userland programs...");
-    let user_programs = ["shell", "init", "syscall_test"];
+    let user_programs = ["shell", "init", "syscall_test", "strace"];
     
     for prog in user_programs {
         println!("  Building {}...", prog);
@@ -231,6 +231,21 @@ fn quick_image_mode(args: QuickImageArgs) {
 
     // カーネルバイナリのパス
     let kernel_binary_path = root_dir.join("target/x86_64-rany_os/debug/tiny_os");
+
+    // 5. Embed kernel symbol table (for backtraces)
+    println!("Embedding kernel symbols...");
+    let mksyms_dir = root_dir.join("tools/build/mksyms");
+    let status = Command::new("cargo")
+        .current_dir(&mksyms_dir)
+        .env("CARGO_BUILD_TARGET", "") // Override workspace default target
+        .args(&["run", "--release", "--", kernel_binary_path.to_str().unwrap()])
+        .status()
+        .expect("Failed to run mksyms");
+
+    if !status.success() {
+        eprintln!("Failed to embed kernel symbols");
+        std::process::exit(1);
+    }
     
     // ブートイメージの出力先
     let out_dir = root_dir.join("target/x86_64-rany_os/debug");
@@ -16,6 +16,8 @@ fn main() -> io::Result<()> {
 
     let mut out = File::create(output_file)?;
     let mut inode = 1;
+    // Bytes written so far, to page-align file contents
+    let mut offset = 0u64;
 
     println!("Creating CPIO archive: {} -> {}", source_dir, output_file);
 
@@ -48,7 +50,7 @@ fn main() -> io::Result<()> {
 
         println!("  Adding: {} (mode={:o}, size={})", rel_path, mode, size);
 
-        write_cpio_header(&mut out, &rel_path, mode, size as u32, inode)?;
+        offset += write_cpio_header(&mut out, offset, &rel_path, mode, size as u32, inode, size > 0)?;
         inode += 1;
 
         if !metadata.is_dir() {
@@ -59,18 +61,39 @@ fn main() -> io::Result<()> {
             for _ in 0..padding {
                 out.write_all(&[0])?;
             }
+            offset += copied + padding;
         }
     }
 
     // Write TRAILER!!!
-    write_cpio_header(&mut out, "TRAILER!!!", 0, 0, 0)?;
+    write_cpio_header(&mut out, offset, "TRAILER!!!", 0, 0, 0, false)?;
 
     Ok(())
 }
 
-fn write_cpio_header<W: Write>(out: &mut W, name: &str, mode: u32, size: u32, inode: u32) -> io::Result<()> {
+/// Page size the kernel maps initrd files with
+const PAGE_SIZE: u64 = 4096;
+
+/// Write a newc header at archive offset `offset` and return its length
+///
+/// With `page_align`, the name is padded with NUL bytes so that the file
+/// contents start on a page boundary; the kernel then maps the ramdisk
+/// pages directly instead of copying them. Readers stop at the first NUL.
+fn write_cpio_header<W: Write>(
+    out: &mut W,
+    offset: u64,
+    name: &str,
+    mode: u32,
+    size: u32,
+    inode: u32,
+    page_align: bool,
+) -> io::Result<u64> {
     let header_size = 110;
-    let name_len = name.len() + 1;
+    let mut name_len = name.len() + 1;
+    if page_align {
+        let contents = offset + (header_size + name_len) as u64;
+        name_len += ((PAGE_SIZE - contents % PAGE_SIZE) % PAGE_SIZE) as usize;
+    }
     
     // Newc format
     write!(out, "070701")?;
@@ -89,7 +112,7 @@ fn write_cpio_header<W: Write>(out: &mut W, name: &str, mode: u32, size: u32, in
     write!(out, "{:08X}", 0)?; // Checksum
 
     out.write_all(name.as_bytes())?;
-    out.write_all(&[0])?; 
+    out.write_all(&vec![0; name_len - name.len()])?;
 
     let total_written = header_size + name_len;
     let padding = (4 - (total_written % 4)) % 4;
@@ -97,5 +120,5 @@ fn write_cpio_header<W: Write>(out: &mut W, name: &str, mode: u32, size: u32, in
         out.write_all(&[0])?;
     }
     
-    Ok(())
+    Ok((total_written + padding) as u64)
 }
@@ -0,0 +1,135 @@
+//! Embed the kernel symbol table into the `.ksyms` section of a linked kernel.
+//!
+//! The format is documented in `crates/kernel/src/kernel/ksyms.rs`.
+
+use std::env;
+use std::fs;
+use std::io;
+
+use object::{Object, ObjectSection, ObjectSymbol, SectionKind, SymbolKind};
+
+const MAGIC: &[u8; 4] = b"KSYM";
+const MAX_NAME_LEN: usize = 255;
+
+fn main() -> io::Result<()> {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 2 {
+        eprintln!("Usage: mksyms <kernel_elf>");
+        std::process::exit(1);
+    }
+
+    let path = &args[1];
+    let mut data = fs::read(path)?;
+
+    let (offset, capacity, table, count) = {
+        let file = object::File::parse(&*data).map_err(invalid)?;
+
+        let section = file
+            .section_by_name(".ksyms")
+            .ok_or_else(|| invalid("no .ksyms section in kernel image"))?;
+        let (offset, capacity) = section
+            .file_range()
+            .ok_or_else(|| invalid(".ksyms section has no file data"))?;
+
+        let mut symbols: Vec<(u64, String)> = file
+            .symbols()
+            .filter(|sym| sym.kind() == SymbolKind::Text && sym.is_definition() && sym.address() != 0)
+            .filter_map(|sym| {
+                let name = sym.name().ok()?;
+                Some((sym.address(), truncate(format!("{:#}", rustc_demangle::demangle(name)))))
+            })
+            .collect();
+        symbols.sort();
+        symbols.dedup_by_key(|(addr, _)| *addr);
+
+        // Terminator marking the end of the code so that addresses past the
+        // last function are not attributed to it
+        let text_end = file
+            .sections()
+            .filter(|s| s.kind() == SectionKind::Text)
+            .map(|s| s.address() + s.size())
+            .max()
+            .unwrap_or(0);
+        if symbols.last().is_some_and(|(addr, _)| *addr < text_end) {
+            symbols.push((text_end, String::new()));
+        }
+
+        let count = symbols.len();
+        (offset as usize, capacity as usize, encode(&symbols), count)
+    };
+
+    if table.len() > capacity {
+        return Err(invalid(format!(
+            "symbol table ({} bytes) does not fit in .ksyms ({} bytes)",
+            table.len(),
+            capacity
+        )));
+    }
+
+    data[offset..offset + capacity].fill(0);
+    data[offset..offset + table.len()].copy_from_slice(&table);
+    fs::write(path, &data)?;
+
+    println!("Embedded {} symbols ({} bytes) into {}", count, table.len(), path);
+    Ok(())
+}
+
+/// Truncate a name to `MAX_NAME_LEN` bytes on a character boundary
+fn truncate(mut name: String) -> String {
+    if name.len() > MAX_NAME_LEN {
+        let mut end = MAX_NAME_LEN;
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+        name.truncate(end);
+    }
+    name
+}
+
+/// Encode sorted symbols with address deltas and front-coded names
+fn encode(symbols: &[(u64, String)]) -> Vec<u8> {
+    let base = symbols.first().map_or(0, |(addr, _)| *addr);
+
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&(symbols.len() as u32).to_le_bytes());
+    out.extend_from_slice(&base.to_le_bytes());
+
+    let mut prev_addr = base;
+    let mut prev_name: &[u8] = &[];
+    for (addr, name) in symbols {
+        let name = name.as_bytes();
+        let shared = prev_name
+            .iter()
+            .zip(name)
+            .take_while(|(a, b)| a == b)
+            .count()
+            .min(MAX_NAME_LEN);
+        let suffix = &name[shared..];
+
+        write_uleb128(&mut out, addr - prev_addr);
+        out.push(shared as u8);
+        out.push(suffix.len() as u8);
+        out.extend_from_slice(suffix);
+
+        prev_addr = *addr;
+        prev_name = name;
+    }
+    out
+}
+
+fn write_uleb128(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn invalid<E: Into<Box<dyn std::error::Error + Send + Sync>>>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}